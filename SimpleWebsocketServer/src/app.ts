@@ -5,15 +5,68 @@ interface User {
     ws: WebSocket;
     nick: String;
     isAlive: boolean;
+    status: String;
+    // When this user's recent messages were accepted, oldest first.
+    sent: number[];
+    // When they registered, and when they last sent a message or came back from away.
+    since: number;
+    lastActive: number;
 }
 
 interface Message {
     messageType: String;
     data: String;
     dataArray: String[];
+    id?: String;
+    replyTo?: String;
+    attachment?: unknown;
 }
 
+// A file shared by a message, as uploaded by its sender.
+interface Attachment {
+    name: string;
+    size: number;
+    url: string;
+}
+
+interface ChatMessage {
+    from: String;
+    message: String;
+    id?: String;
+    time: number;
+    // Emoji to the nicks that reacted with it, in the order they did.
+    reactions?: { [emoji: string]: String[] };
+    read_by?: String[];
+    // The id of the message this one replies to.
+    reply_to?: String;
+    attachment?: Attachment;
+}
+
+// Only the most recent messages are kept; clients page through them with `history`.
+const HISTORY_LIMIT = 1000;
+const HISTORY_PAGE_SIZE = 50;
+// A history request for a message by `id` also gets this many messages from before it.
+const HISTORY_CONTEXT = 10;
+// Everyone shares one set of pinned messages, up to this many.
+const PIN_LIMIT = 10;
+// Nicks that are always admins, from a comma-separated `ADMINS`. The owner is one as well.
+const ADMINS = (process.env.ADMINS || '')
+    .split(',')
+    .map((nick) => nick.trim())
+    .filter((nick) => nick);
+// Each user may send this many messages in any `RATE_WINDOW_MS`; any more are refused.
+const RATE_LIMIT = 10;
+const RATE_WINDOW_MS = 10000;
+// Words messages may not contain, from a comma-separated `BANNED_WORDS`, in any case.
+const BANNED_WORDS = (process.env.BANNED_WORDS || '')
+    .split(',')
+    .map((word) => word.trim().toLowerCase())
+    .filter((word) => word);
+
 let users: User[] = [];
+let history: ChatMessage[] = [];
+// Ids of the pinned messages, oldest pin first.
+let pins: String[] = [];
 
 console.log(`Listening on port ${PORT}`);
 const wss = new WebSocketServer({ port: PORT });
@@ -27,23 +80,210 @@ wss.on('connection', (ws: WebSocket) => {
             const parsed_data: Message = JSON.parse(raw_data);
             switch (parsed_data.messageType) {
                 case 'register':
-                    users.push({ ws, nick: parsed_data.data, isAlive: true });
-                    broadcast(JSON.stringify({ messageType: 'users', dataArray: users.map((u) => u.nick) }));
+                    const nick = typeof parsed_data.data === 'string' ? parsed_data.data.trim() : '';
+                    const problem = usernameProblem(nick, ws);
+                    if (problem) {
+                        ws.send(JSON.stringify({ messageType: 'registerError', data: problem }));
+                        break;
+                    }
+                    // Registering again on the same socket, after a reconnect, replaces the old entry.
+                    users = users.filter((u) => u.ws !== ws);
+                    const joined = Date.now();
+                    users.push({ ws, nick, isAlive: true, status: 'active', sent: [], since: joined, lastActive: joined });
+                    broadcastUsers();
+                    // Pins made since the client last heard: it takes this list in place of its own.
+                    ws.send(JSON.stringify({ messageType: 'pin', data: null, dataArray: pins }));
+                    break;
+                case 'users':
+                    // Asked for after a reconnect, when the client's list may be out of date.
+                    ws.send(usersFrame());
                     break;
                 case 'message':
                     const sender = users.find((u) => u.ws === ws);
                     if (sender) {
+                        const refusal = messageProblem(sender, parsed_data.data);
+                        if (refusal) {
+                            sendError(ws, refusal, parsed_data.id);
+                            break;
+                        }
+                        sender.lastActive = Date.now();
+                        const chatMessage: ChatMessage = {
+                            from: sender.nick,
+                            message: parsed_data.data,
+                            id: parsed_data.id,
+                            time: Date.now(),
+                            reply_to: typeof parsed_data.replyTo === 'string' ? parsed_data.replyTo : undefined,
+                            attachment: readAttachment(parsed_data.attachment),
+                        };
+                        history.push(chatMessage);
+                        if (history.length > HISTORY_LIMIT) {
+                            history.shift();
+                        }
                         broadcast(
                             JSON.stringify({
                                 messageType: 'message',
-                                data: JSON.stringify({
-                                    from: sender.nick,
-                                    message: parsed_data.data,
-                                    time: Date.now(),
-                                }),
+                                data: JSON.stringify(chatMessage),
+                            })
+                        );
+                    }
+                    break;
+                case 'delete':
+                    // Only the author may delete a message; `data` is its id.
+                    const author = users.find((u) => u.ws === ws);
+                    const target = history.find((m) => m.id === parsed_data.data);
+                    if (author && target && target.from === author.nick) {
+                        history = history.filter((m) => m !== target);
+                        broadcast(JSON.stringify({ messageType: 'delete', data: parsed_data.data }));
+                        if (pins.includes(parsed_data.data)) {
+                            pins = pins.filter((p) => p !== parsed_data.data);
+                            broadcastPin(parsed_data.data, false, author.nick);
+                        }
+                    }
+                    break;
+                case 'clearRoom':
+                    // Only the owner, the longest-connected user, may clear the room for everyone.
+                    const clearer = users.find((u) => u.ws === ws);
+                    if (clearer && users[0] === clearer) {
+                        history = [];
+                        pins = [];
+                        broadcast(JSON.stringify({ messageType: 'clearRoom', data: clearer.nick }));
+                    }
+                    break;
+                case 'kick':
+                    // `data` is the nick to remove. Only admins may kick, and never another admin.
+                    const kicker = users.find((u) => u.ws === ws);
+                    const kicked = users.find((u) => u.nick === parsed_data.data);
+                    if (kicker && kicked && isAdmin(kicker) && !isAdmin(kicked)) {
+                        broadcast(
+                            JSON.stringify({ messageType: 'kick', data: JSON.stringify({ nick: kicked.nick, by: kicker.nick }) })
+                        );
+                        users = users.filter((u) => u !== kicked);
+                        kicked.ws.close();
+                        broadcastUsers();
+                    }
+                    break;
+                case 'pin':
+                    // `data` is `{ id, pinned }`. Anyone may pin or unpin a message we still have,
+                    // while there's room; a request that changes nothing is dropped.
+                    const pinner = users.find((u) => u.ws === ws);
+                    const pin = JSON.parse(parsed_data.data);
+                    const pinTarget = history.find((m) => m.id === pin.id);
+                    if (!pinner || !pinTarget) {
+                        break;
+                    }
+                    if (pin.pinned === true && !pins.includes(pin.id) && pins.length < PIN_LIMIT) {
+                        pins.push(pin.id);
+                        broadcastPin(pin.id, true, pinner.nick);
+                    } else if (pin.pinned === false && pins.includes(pin.id)) {
+                        pins = pins.filter((p) => p !== pin.id);
+                        broadcastPin(pin.id, false, pinner.nick);
+                    }
+                    break;
+                case 'reaction':
+                    // `data` is `{ id, emoji }`; reacting again with the same emoji takes it back.
+                    const reactor = users.find((u) => u.ws === ws);
+                    const { id, emoji } = JSON.parse(parsed_data.data);
+                    const reacted = history.find((m) => m.id === id);
+                    if (reactor && reacted && typeof emoji === 'string' && emoji.length > 0) {
+                        const reactions = (reacted.reactions = reacted.reactions || {});
+                        const nicks = reactions[emoji] || [];
+                        reactions[emoji] = nicks.includes(reactor.nick)
+                            ? nicks.filter((n) => n !== reactor.nick)
+                            : [...nicks, reactor.nick];
+                        if (reactions[emoji].length === 0) {
+                            delete reactions[emoji];
+                        }
+                        broadcast(
+                            JSON.stringify({
+                                messageType: 'reaction',
+                                data: JSON.stringify({ id, from: reactor.nick, emoji }),
+                            })
+                        );
+                    }
+                    break;
+                case 'read':
+                    // `data` is `{ ids }`, the messages the sender has now seen. Only the first
+                    // receipt per reader counts, and nobody reads their own messages.
+                    const reader = users.find((u) => u.ws === ws);
+                    const { ids } = JSON.parse(parsed_data.data);
+                    const changed: String[] = [];
+                    if (reader && Array.isArray(ids)) {
+                        for (const m of history) {
+                            const readBy = m.read_by || [];
+                            if (ids.includes(m.id) && m.from !== reader.nick && !readBy.includes(reader.nick)) {
+                                m.read_by = [...readBy, reader.nick];
+                                changed.push(m.id);
+                            }
+                        }
+                    }
+                    if (reader && changed.length > 0) {
+                        broadcast(
+                            JSON.stringify({
+                                messageType: 'read',
+                                data: JSON.stringify({ ids: changed, from: reader.nick }),
+                            })
+                        );
+                    }
+                    break;
+                case 'typing':
+                    // `data` is `{ room, typing }`; everyone else hears it with the sender filled in.
+                    const typist = users.find((u) => u.ws === ws);
+                    const notice = JSON.parse(parsed_data.data);
+                    if (typist && typeof notice.room === 'string') {
+                        broadcast(
+                            JSON.stringify({
+                                messageType: 'typing',
+                                data: JSON.stringify({ room: notice.room, from: typist.nick, typing: notice.typing === true }),
+                            }),
+                            ws
+                        );
+                    }
+                    break;
+                case 'history':
+                    // With an `id`, the client wants that message: send it, a few before it and
+                    // everything since, so there's no gap up to what it has. The reply says which
+                    // message it was for in `around`, and is the latest page if it's gone.
+                    const around = parsed_data.id !== undefined ? history.findIndex((m) => m.id === parsed_data.id) : -1;
+                    if (around >= 0) {
+                        const start = Math.max(0, around - HISTORY_CONTEXT);
+                        ws.send(
+                            JSON.stringify({
+                                messageType: 'history',
+                                data: JSON.stringify({ messages: history.slice(start), hasMore: start > 0, around: parsed_data.id }),
                             })
                         );
+                        break;
+                    }
+                    // `data` is the timestamp to page back from; without one, send the latest page.
+                    const before = parsed_data.data ? Number(parsed_data.data) : Infinity;
+                    const older = history.filter((m) => m.time < before);
+                    const page = older.slice(-HISTORY_PAGE_SIZE);
+                    ws.send(
+                        JSON.stringify({
+                            messageType: 'history',
+                            data: JSON.stringify({
+                                messages: page,
+                                hasMore: older.length > page.length,
+                                around: parsed_data.id,
+                            }),
+                        })
+                    );
+                    break;
+                case 'status':
+                    const user = users.find((u) => u.ws === ws);
+                    if (user) {
+                        user.status = parsed_data.data;
+                        if (user.status === 'active') {
+                            user.lastActive = Date.now();
+                        }
+                        broadcastUsers();
                     }
+                    break;
+                case 'ping':
+                    // Echo the ping's timestamp so the client can measure the round trip, and
+                    // say what time it is here so it can line its clock up with ours.
+                    ws.send(JSON.stringify({ messageType: 'pong', data: parsed_data.data, time: Date.now() }));
+                    break;
             }
         } catch (e) {
             console.log('Error in message', e);
@@ -56,13 +296,96 @@ const interval = setInterval(function ping() {
     const updated_users = users.filter((u) => current_clients.includes(u.ws));
     if (updated_users.length !== users.length) {
         users = updated_users;
-        broadcast(JSON.stringify({ messageType: 'users', dataArray: users.map((u) => u.nick) }));
+        broadcastUsers();
     }
 }, 5000);
 
-const broadcast = (data: any) => {
+// An attachment as a client sent it, if it's well formed, with only the fields we know.
+const readAttachment = (value: any): Attachment | undefined => {
+    if (
+        !value ||
+        typeof value.name !== 'string' ||
+        !value.name.trim() ||
+        typeof value.url !== 'string' ||
+        !/^https?:\/\//i.test(value.url) ||
+        !Number.isSafeInteger(value.size) ||
+        value.size < 0
+    ) {
+        return undefined;
+    }
+    return { name: value.name, size: value.size, url: value.url };
+};
+
+// Why the text `sender` sent can't be posted, as an error code, or null if it can. A message
+// that's posted counts towards the rate limit.
+const messageProblem = (sender: User, text: unknown): string | null => {
+    if (typeof text !== 'string') {
+        return 'rejected';
+    }
+    const lower = text.toLowerCase();
+    if (BANNED_WORDS.some((word) => lower.includes(word))) {
+        return 'banned_word';
+    }
+    const now = Date.now();
+    sender.sent = sender.sent.filter((time) => now - time < RATE_WINDOW_MS);
+    if (sender.sent.length >= RATE_LIMIT) {
+        return 'rate_limited';
+    }
+    sender.sent.push(now);
+    return null;
+};
+
+// Tells one client the server refused what it sent, by a code the client puts in its own
+// words, and the id of the message it's about, if any.
+const sendError = (ws: WebSocket, code: string, id?: String) => {
+    ws.send(JSON.stringify({ messageType: 'error', data: JSON.stringify({ code, id }) }));
+};
+
+// The same rules as the client's `username::validate`, plus the one only the server can check.
+const usernameProblem = (nick: string, ws: WebSocket): string | null => {
+    const length = [...nick].length;
+    if (length < 2 || length > 24 || !/^[\p{L}\p{N}._-]+$/u.test(nick)) {
+        return "That username isn't allowed";
+    }
+    if (users.some((u) => u.nick === nick && u.ws !== ws)) {
+        return `"${nick}" is already taken; pick another`;
+    }
+    return null;
+};
+
+// The owner is whoever has been connected longest, the first in `users`.
+const isAdmin = (user: User) => users[0] === user || ADMINS.includes(`${user.nick}`);
+
+// `data` carries each user's presence, when they joined and were last active, and who the
+// admins are, alongside the plain list of nicks.
+const usersFrame = () => {
+    const statuses: { [nick: string]: String } = {};
+    const activity: { [nick: string]: { since: number; lastActive: number } } = {};
+    users.forEach((u) => {
+        statuses[`${u.nick}`] = u.status;
+        activity[`${u.nick}`] = { since: u.since, lastActive: u.lastActive };
+    });
+    const admins = users.filter(isAdmin).map((u) => u.nick);
+    return JSON.stringify({
+        messageType: 'users',
+        dataArray: users.map((u) => u.nick),
+        data: JSON.stringify({ statuses, admins, activity }),
+    });
+};
+
+const broadcastUsers = () => {
+    broadcast(usersFrame());
+};
+
+// Tells everyone that `from` pinned or unpinned the message with this id.
+const broadcastPin = (id: String, pinned: boolean, from: String) => {
+    broadcast(JSON.stringify({ messageType: 'pin', data: JSON.stringify({ id, pinned, from }) }));
+};
+
+// Sends `data` to every open client, apart from `except` if it's given.
+const broadcast = (data: any, except?: WebSocket) => {
     wss.clients.forEach((client) => {
-        if (client.readyState === WebSocket.OPEN) {
+        if (client !== except && client.readyState === WebSocket.OPEN) {
             client.send(data);
         }
     });
@@ -0,0 +1,144 @@
+//! Locally generated avatars, used when the remote avatar service can't be reached, and
+//! the color each user's name is shown in.
+
+use web_sys::HtmlImageElement;
+use yew::{Callback, Event, TargetCast};
+
+use crate::sanitize::escape_html;
+
+/// `s` escaped for use in a URL, the way JavaScript's `encodeURIComponent` does it, but
+/// without needing a browser.
+fn encode_uri_component(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for b in s.bytes() {
+        match b {
+            b'A'..=b'Z'
+            | b'a'..=b'z'
+            | b'0'..=b'9'
+            | b'-'
+            | b'_'
+            | b'.'
+            | b'!'
+            | b'~'
+            | b'*'
+            | b'\''
+            | b'('
+            | b')' => out.push(b as char),
+            _ => out.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    out
+}
+
+/// The remote avatar for `name`.
+pub fn avatar_url(name: &str) -> String {
+    format!(
+        "https://avatars.dicebear.com/api/adventurer-neutral/{}.svg",
+        encode_uri_component(name)
+    )
+}
+
+/// Name colors as Tailwind classes: a 700 shade, at least 4.5:1 against the white the
+/// names sit on, and a 300 shade for the same against a dark theme's gray-900.
+const NAME_COLORS: [&str; 12] = [
+    "text-red-700 dark:text-red-300",
+    "text-orange-700 dark:text-orange-300",
+    "text-amber-700 dark:text-amber-300",
+    "text-green-700 dark:text-green-300",
+    "text-emerald-700 dark:text-emerald-300",
+    "text-teal-700 dark:text-teal-300",
+    "text-cyan-700 dark:text-cyan-300",
+    "text-sky-700 dark:text-sky-300",
+    "text-blue-700 dark:text-blue-300",
+    "text-violet-700 dark:text-violet-300",
+    "text-fuchsia-700 dark:text-fuchsia-300",
+    "text-pink-700 dark:text-pink-300",
+];
+
+/// FNV-1a over `name`'s bytes: stable across sessions and clients, unlike `Hash`.
+fn name_hash(name: &str) -> u32 {
+    name.bytes().fold(0x811c_9dc5_u32, |hash, b| {
+        (hash ^ b as u32).wrapping_mul(0x0100_0193)
+    })
+}
+
+/// A hue derived from `name`, so each user keeps the same color everywhere.
+pub fn name_hue(name: &str) -> u32 {
+    name_hash(name) % 360
+}
+
+/// The text color classes for `name`'s name, the same for them on every client and in
+/// every session, as it comes of their name alone.
+pub fn user_color(name: &str) -> &'static str {
+    NAME_COLORS[name_hash(name) as usize % NAME_COLORS.len()]
+}
+
+/// A data URL for a colored circle showing the first letter of `name`.
+pub fn initials_avatar(name: &str) -> String {
+    let initial: String = name
+        .trim()
+        .chars()
+        .next()
+        .map_or_else(|| "?".into(), |c| c.to_uppercase().collect());
+    let svg = format!(
+        "<svg xmlns='http://www.w3.org/2000/svg' viewBox='0 0 40 40'>\
+         <circle cx='20' cy='20' r='20' fill='hsl({}, 55%, 50%)'/>\
+         <text x='50%' y='50%' dy='.35em' text-anchor='middle' font-family='Inter, sans-serif' \
+         font-size='18' font-weight='600' fill='white'>{}</text></svg>",
+        name_hue(name),
+        escape_html(&initial)
+    );
+    format!("data:image/svg+xml,{}", encode_uri_component(&svg))
+}
+
+/// An `onerror` handler for avatar images that swaps in the initials avatar for `name`.
+pub fn fallback_on_error(name: &str) -> Callback<Event> {
+    let fallback = initials_avatar(name);
+    Callback::from(move |e: Event| {
+        let img: HtmlImageElement = e.target_unchecked_into();
+        // The fallback can't fail to load, but don't loop if it somehow does.
+        if img.src() != fallback {
+            img.set_src(&fallback);
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn the_hash_is_fnv_1a() {
+        // So the colors don't shift with the standard library's hasher, or between clients.
+        assert_eq!(name_hash(""), 0x811c_9dc5);
+        assert_eq!(name_hash("a"), 0xe40c_292c);
+    }
+
+    #[test]
+    fn a_name_always_gets_the_same_color() {
+        assert_eq!(user_color("ann"), user_color("ann"));
+        assert_eq!(user_color("ann"), NAME_COLORS[8]);
+        assert_eq!(user_color("José"), NAME_COLORS[7]);
+        assert_eq!(name_hue("a"), 340);
+        let spread: HashSet<_> = ["ann", "bob", "carol", "dave", "erin", "frank"]
+            .into_iter()
+            .map(user_color)
+            .collect();
+        assert!(spread.len() > 1);
+    }
+
+    #[test]
+    fn initials_avatars_are_escaped_data_urls() {
+        let url = initials_avatar(" émile");
+        assert!(url.starts_with("data:image/svg+xml,%3Csvg"));
+        assert!(url.contains("%C3%89"), "{}", url);
+        assert!(initials_avatar("<b>").contains("%26lt%3B"));
+        assert!(initials_avatar("  ").contains("%3E%3F%3C"));
+        assert_eq!(
+            avatar_url("a b/c"),
+            "https://avatars.dicebear.com/api/adventurer-neutral/a%20b%2Fc.svg"
+        );
+    }
+}
@@ -0,0 +1,163 @@
+//! The rendering benchmark, built with the `bench` feature. `/bench?n=5000` mounts the chat
+//! as `bench`, feeds it a page of `n` made-up messages through the event bus, then a few
+//! single changes, and `Chat` logs how long each one took to reach the DOM.
+
+use std::collections::BTreeMap;
+
+use gloo_timers::callback::Timeout;
+use serde::Deserialize;
+use web_sys::Element;
+use yew::functional::*;
+use yew::prelude::*;
+use yew_agent::Dispatched;
+use yew_router::prelude::{use_location, Location};
+
+use crate::components::chat::Chat;
+use crate::protocol::{DeliveryStatus, HistoryPage, MessageData, Reaction};
+use crate::services::event_bus::{BusEvent, EventBus, Request};
+use crate::User;
+
+const BENCH_USER: &str = "bench";
+const DEFAULT_COUNT: usize = 1000;
+const SENDERS: usize = 20;
+// Time between steps, so each update renders on its own.
+const STEP_MS: u32 = 1000;
+// A mix of what the message renderer handles; no links, so nothing goes to the network.
+const SAMPLES: [&str; 6] = [
+    "Morning! Anyone around?",
+    "That's **really** neat, try `cargo clippy` on it",
+    "Sounds good :smile: see you then",
+    "First line\nsecond line\nthird line",
+    "```\nfn main() {\n    println!(\"hi\");\n}\n```",
+    "The ending is ||they were friends all along||",
+];
+
+/// Times one update from `FlushIncoming` to the end of `rendered`.
+#[derive(Default)]
+pub struct Timer {
+    started: Option<(f64, &'static str)>,
+}
+
+impl Timer {
+    pub fn start(&mut self, event: &BusEvent) {
+        let what = match event {
+            BusEvent::HistoryReceived(_) => "history page",
+            BusEvent::MessageReceived(_) => "new message",
+            BusEvent::ReactionToggled(_) => "reaction",
+            BusEvent::UsersUpdated { .. } => "user list",
+            _ => "other event",
+        };
+        self.started = Some((now(), what));
+    }
+
+    /// Logs the time since `start`, if it was called since the last render.
+    pub fn finish(&mut self, container: &NodeRef, total: usize, rendered: usize) {
+        let Some((started, what)) = self.started.take() else {
+            return;
+        };
+        // Asking for the layout makes the browser do it now, so it's counted too.
+        if let Some(container) = container.cast::<Element>() {
+            let _ = container.scroll_height();
+        }
+        log::info!(
+            "bench: {} with {} messages ({} rendered): {:.1} ms",
+            what,
+            total,
+            rendered,
+            now() - started
+        );
+    }
+}
+
+fn now() -> f64 {
+    gloo_utils::window()
+        .performance()
+        .map_or_else(js_sys::Date::now, |p| p.now())
+}
+
+#[derive(Deserialize)]
+struct BenchQuery {
+    n: usize,
+}
+
+fn message(i: usize, time: u64) -> MessageData {
+    let from = if i.is_multiple_of(7) {
+        BENCH_USER.to_string()
+    } else {
+        format!("user{}", i % SENDERS)
+    };
+    let mut reactions = BTreeMap::new();
+    if i.is_multiple_of(10) {
+        reactions.insert("👍".to_string(), vec!["user1".to_string()]);
+    }
+    MessageData {
+        id: format!("bench-{}", i),
+        from,
+        message: SAMPLES[i % SAMPLES.len()].to_string(),
+        time,
+        reactions,
+        read_by: vec![],
+        reply_to: None,
+        attachment: None,
+        status: DeliveryStatus::Delivered,
+    }
+}
+
+/// What the chat is fed, in order: who's online, the page of `n` messages, then one new
+/// message, a reaction on it and another message.
+fn steps(n: usize) -> Vec<BusEvent> {
+    let now = js_sys::Date::now() as u64;
+    let messages = (0..n)
+        .map(|i| message(i, now - (n - i) as u64 * 1000))
+        .collect();
+    let mut users: Vec<String> = (0..SENDERS).map(|i| format!("user{}", i)).collect();
+    users.push(BENCH_USER.to_string());
+    vec![
+        BusEvent::UsersUpdated {
+            users,
+            statuses: Default::default(),
+            admins: Default::default(),
+            activity: Default::default(),
+        },
+        BusEvent::HistoryReceived(Ok(HistoryPage {
+            messages,
+            has_more: false,
+            around: None,
+        })),
+        BusEvent::MessageReceived(message(n + 1, now)),
+        BusEvent::ReactionToggled(Reaction {
+            id: format!("bench-{}", n + 1),
+            from: "user2".to_string(),
+            emoji: "🎉".to_string(),
+        }),
+        BusEvent::MessageReceived(message(n + 2, now + 1)),
+    ]
+}
+
+#[function_component(Bench)]
+pub fn bench() -> Html {
+    let user = use_context::<User>().expect("No context found.");
+    *user.username.borrow_mut() = BENCH_USER.to_string();
+    let n = use_location()
+        .and_then(|l| l.query::<BenchQuery>().ok())
+        .map_or(DEFAULT_COUNT, |q| q.n);
+
+    use_effect_with_deps(
+        |&n| {
+            log::info!("bench: feeding {} messages", n);
+            let timeouts: Vec<Timeout> = steps(n)
+                .into_iter()
+                .enumerate()
+                .map(|(i, event)| {
+                    Timeout::new(STEP_MS * (i as u32 + 1), move || {
+                        EventBus::dispatcher().send(Request::EventBusMsg(event))
+                    })
+                })
+                .collect();
+            move || drop(timeouts)
+        },
+        n,
+    );
+
+    html! { <Chat/> }
+}
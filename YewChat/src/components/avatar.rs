@@ -0,0 +1,95 @@
+use yew::prelude::*;
+
+use crate::avatar::{avatar_url, fallback_on_error};
+use crate::i18n::I18n;
+use crate::protocol::Presence;
+
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum AvatarSize {
+    /// For cards and other small spaces.
+    Small,
+    /// Beside a message.
+    #[default]
+    Medium,
+    /// In the user list.
+    Large,
+}
+
+impl AvatarSize {
+    fn class(self) -> &'static str {
+        match self {
+            AvatarSize::Small => "w-8 h-8",
+            AvatarSize::Medium => "w-10 h-10",
+            AvatarSize::Large => "w-12 h-12",
+        }
+    }
+}
+
+/// Which dot an avatar wears in its corner.
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum AvatarStatus {
+    /// None, where presence is shown some other way.
+    #[default]
+    Hidden,
+    /// Gray: not connected at all.
+    Offline,
+    /// Green when active, amber when away.
+    Present(Presence),
+}
+
+impl From<Option<Presence>> for AvatarStatus {
+    fn from(presence: Option<Presence>) -> Self {
+        presence.map_or(AvatarStatus::Offline, AvatarStatus::Present)
+    }
+}
+
+#[derive(Properties, PartialEq)]
+pub struct AvatarProps {
+    pub name: String,
+    /// The picture; the avatar service's for `name` when not given.
+    #[prop_or_default]
+    pub url: Option<String>,
+    #[prop_or_default]
+    pub size: AvatarSize,
+    #[prop_or_default]
+    pub status: AvatarStatus,
+    /// For the status dot's label.
+    #[prop_or_default]
+    pub i18n: I18n,
+    /// For the box, such as how it's placed among its neighbours.
+    #[prop_or_default]
+    pub class: Classes,
+    /// Leaves the picture out of what screen readers say, where the name is right beside
+    /// it anyway.
+    #[prop_or(false)]
+    pub decorative: bool,
+}
+
+/// A user's picture, in a circle, with a dot for whether they're here. If the picture
+/// can't be loaded, their initial is shown instead.
+#[function_component(Avatar)]
+pub fn avatar(props: &AvatarProps) -> Html {
+    let src = props.url.clone().unwrap_or_else(|| avatar_url(&props.name));
+    let alt = if props.decorative {
+        String::new()
+    } else {
+        format!("{}'s avatar", props.name)
+    };
+    let dot = match props.status {
+        AvatarStatus::Hidden => None,
+        AvatarStatus::Offline => Some(("bg-gray-400", props.i18n.t("offline"))),
+        AvatarStatus::Present(Presence::Active) => Some(("bg-green-500", props.i18n.t("online"))),
+        AvatarStatus::Present(Presence::Away) => Some(("bg-amber-400", props.i18n.t("away"))),
+    };
+    html! {
+        <div class={classes!("relative", "flex-none", props.class.clone())}>
+            <img class={classes!(props.size.class(), "rounded-full", "user-avatar")} {src} {alt}
+                 loading="lazy" onerror={fallback_on_error(&props.name)}/>
+            if let Some((color, label)) = dot {
+                <span role="img" aria-label={label} title={label}
+                    class={classes!("absolute", "bottom-0", "end-0", "w-3", "h-3", "rounded-full", "ring-2", "ring-white", "presence-dot", color)}>
+                </span>
+            }
+        </div>
+    }
+}
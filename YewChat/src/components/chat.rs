@@ -1,247 +1,5001 @@
-use serde::{Deserialize, Serialize};
-use web_sys::HtmlInputElement;
+use std::cell::Cell;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::rc::Rc;
+
+use gloo_events::{EventListener, EventListenerOptions};
+use gloo_timers::callback::{Interval, Timeout};
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::spawn_local;
+use web_sys::{
+    DataTransfer, Element, EventTarget, File, HtmlElement, HtmlInputElement, HtmlSelectElement,
+    HtmlTextAreaElement, ScrollBehavior, ScrollIntoViewOptions, ScrollLogicalPosition,
+};
+use yew::context::ContextHandle;
 use yew::prelude::*;
 use yew_agent::{Bridge, Bridged};
+use yew_router::prelude::{History, RouterScopeExt};
+
+use crate::avatar::user_color;
+use crate::components::avatar::{Avatar, AvatarSize, AvatarStatus};
+use crate::components::code_block::CodeBlock;
+use crate::components::confirm_modal::ConfirmModal;
+use crate::components::connection_status::ConnectionStatus;
+use crate::components::context_menu::{ContextMenu, MenuAction};
+use crate::components::emoji_picker::{EmojiPicker, PickerMode};
+use crate::components::gif_picker::GifPicker;
+use crate::components::hover_card::HoverCard;
+use crate::components::link_preview::LinkPreviewCard;
+use crate::components::message_toolbar::MessageToolbar;
+use crate::components::overlay::Overlay;
+use crate::components::settings_modal::SettingsModal;
+use crate::components::spoiler::Spoiler;
+use crate::emoji::{expand_shortcodes, trailing_shortcode};
+use crate::i18n::I18n;
+use crate::ids::new_id;
+use crate::input_history::InputHistory;
+use crate::markup::{classify_message, fold, Block, Fold, Inline};
+use crate::protocol::{
+    Activity, Attachment, DeliveryStatus, KickNotice, MessageData, MsgTypes, PinNotice, Presence,
+    Reaction, ReadReceipt, ServerError, TypingNotice, WebSocketMessage, PIN_LIMIT,
+};
+use crate::sanitize::{image_url, looks_like_image, safe_url, DEFAULT_IMAGE_HOSTS};
+use crate::services::clipboard::copy_text;
+use crate::services::coalesce::{next_frame, Coalescer, NextFrame};
+use crate::services::density::{self, Density};
+use crate::services::drafts;
+use crate::services::event_bus::{BusEvent, Topic, DEFAULT_ROOM};
+use crate::services::export::{self, ExportFormat};
+use crate::services::gif::GifProvider;
+use crate::services::history_store;
+use crate::services::last_read;
+use crate::services::link_preview::{first_url, DEFAULT_UNFURL_ENDPOINT};
+use crate::services::mutes;
+use crate::services::notify;
+use crate::services::outbox;
+use crate::services::receipts::{ReceiptQueue, VisibleMessages};
+use crate::services::retention::{self, Retention};
+use crate::services::sidebar;
+use crate::services::stars;
+use crate::services::upload::{self, UploadError, DEFAULT_UPLOAD_ENDPOINT};
+use crate::services::websocket::{
+    ConnState, SendError, ServiceConfig, Subscription, WebsocketService, WsEvent,
+    DEFAULT_QUEUE_CAPACITY, MAX_RECONNECT_ATTEMPTS,
+};
+use crate::settings::{Clock, Settings, SettingsContext};
+use crate::store::{AppState, Slice, Store, StoreAction, StoreRequest, StoreUpdate};
+use crate::toasts::{Toast, ToastContext};
+use crate::{Route, User};
 
-use crate::services::event_bus::EventBus;
-use crate::{services::websocket::WebsocketService, User};
+// Upper bound on messages waiting for the server, so a long outage can't grow it forever.
+const OUTBOX_CAPACITY: usize = 50;
+// How soon the outbox tries again when the socket's queue was too full to take more.
+const OUTBOX_RETRY_MS: u32 = 250;
+// How long a sent message may go without its echo before it's marked as failed.
+pub(crate) const ECHO_TIMEOUT_MS: u32 = 10_000;
+// Scrolling within this many pixels of the top asks the server for older messages.
+const HISTORY_SCROLL_THRESHOLD: i32 = 40;
+// How often to check whether the idle threshold has passed.
+const IDLE_CHECK_MS: u32 = 10_000;
+// Document events that count as the user being at the keyboard.
+const ACTIVITY_EVENTS: [&str; 5] = ["mousemove", "mousedown", "keydown", "wheel", "touchstart"];
+// Round-trip times (ms) up to which the latency pill shows green, then yellow; above is red.
+const RTT_GOOD_MS: u32 = 150;
+const RTT_FAIR_MS: u32 = 400;
+// The message box grows with its content up to this height, then scrolls.
+const INPUT_MAX_HEIGHT_PX: i32 = 160;
+// How many messages to page in from the local store at a time.
+const STORED_PAGE_SIZE: usize = 50;
+// How long the search query has to sit still before the local store is searched too.
+const STORED_SEARCH_DEBOUNCE_MS: u32 = 300;
+// How long typing has to pause before the draft is saved.
+const DRAFT_SAVE_MS: u32 = 500;
+// At most this many hits are listed from the local store.
+const STORED_SEARCH_LIMIT: usize = 20;
+// How many older messages are loaded along with a hit from the local store.
+const STORED_CONTEXT: usize = 10;
+// Length of the highlight on the sidebar's online count when someone joins or leaves.
+const COUNT_FLASH_MS: u32 = 600;
+// How long a new message's entrance and a deleted one's exit take; see the
+// `message-enter` and `message-leave` animations in the stylesheet.
+const MESSAGE_ENTER_MS: u32 = 250;
+const MESSAGE_LEAVE_MS: u32 = 200;
+// Within this many pixels of the bottom, the list keeps up with new messages.
+const FOLLOW_THRESHOLD_PX: i32 = 48;
+// How long a message jumped to stays highlighted.
+const JUMP_HIGHLIGHT_MS: u32 = 2_000;
+// A permalink's hash fragment is this followed by the message id.
+const PERMALINK_PREFIX: &str = "#msg-";
+// How often messages past the retention setting are looked for and cleared.
+const RETENTION_SWEEP_MS: u32 = 60_000;
+// How many of the newest messages are rendered to begin with, and how many more each
+// scroll to the top adds before older pages are fetched. Keeps a long conversation from
+// costing a full-list render on every change.
+const RENDER_WINDOW: usize = 200;
+// While we keep typing, the room is reminded this often that we still are.
+const TYPING_REFRESH_MS: u32 = 3_000;
+// Someone not heard from for this long has stopped typing. Longer than the refresh, so a
+// steady typist never flickers out.
+const TYPING_TIMEOUT_MS: u32 = 5_000;
+// How often relative message times ("2m", "1h") are brought up to date.
+const TIME_TICK_MS: u32 = 30_000;
+// Messages at least this old show their clock time instead of how long ago they were.
+const RELATIVE_LIMIT_MS: u64 = 6 * 60 * 60 * 1000;
+// How much of a message is quoted above a reply to it.
+const REPLY_SNIPPET_CHARS: usize = 80;
 
 pub enum Msg {
-    HandleMsg(String),
+    HandleMsg(BusEvent),
+    /// The `Store`'s copy of our room, after a change.
+    Stored(StoreUpdate),
     SubmitMessage,
+    ConnState(ConnState),
+    EchoTimeout(String),
+    Retry(String),
+    /// Starts reconnecting again after automatic reconnection gave up.
+    Reconnect,
+    /// The browser went online (`true`) or offline.
+    NetworkChanged(bool),
+    MessagesScrolled,
+    Activity,
+    CheckIdle,
+    Rtt(u32),
+    /// Time to bring relative message times up to date.
+    Tick,
+    ToggleGifPicker,
+    ToggleSettings,
+    SettingsChanged(SettingsContext),
+    SendGif(String),
+    CountFlashed,
+    StoredPage(Vec<MessageData>),
+    /// Empties the conversation on this device only, after asking.
+    ClearHistory,
+    /// Empties the conversation for everyone, after asking. Only the owner gets to.
+    ClearRoom,
+    OpenClearMenu(i32, i32),
+    CloseClearMenu,
+    /// The user went ahead with what they were asked to confirm.
+    Confirmed,
+    CancelConfirm,
+    ToggleSearch,
+    SearchInput(String),
+    /// Moves the current search match by this many hits, wrapping around.
+    SearchStep(isize),
+    /// The query has settled at this; look for it in the local store.
+    SearchStored(String),
+    /// Stored messages not loaded yet that match this query, newest first.
+    StoredHits(String, Vec<MessageData>),
+    /// Loads this stored hit with the messages around it, and goes to it.
+    OpenStoredHit(MessageData),
+    /// The stored messages from around the hit with this id on.
+    StoredContext(String, Vec<MessageData>),
+    /// Shows only this user's messages, or everyone's again with `None`.
+    FilterSender(Option<String>),
+    OpenMenu(MenuTarget),
+    CloseMenu,
+    /// Puts this quoted text at the start of the message box.
+    Quote(String),
+    /// Types an @-mention of this user where the cursor is in the message box.
+    Mention(String),
+    /// Makes the next message sent a reply to the one with this id.
+    ReplyTo(String),
+    CancelReply,
+    /// Deletes one of our own messages, for everyone.
+    DeleteMessage(String),
+    ToggleStar(String),
+    /// Shows the whole of a long message, or cuts it short again.
+    ToggleExpanded(String),
+    ToggleSaved,
+    /// Hides (true) or shows again everything from this user, on this device only.
+    SetMuted(String, bool),
+    /// Asks the server to remove this user, after asking us. Only admins get to.
+    Kick(String),
+    /// Shows muted users' messages after all, or hides them again.
+    ToggleShowMuted,
+    /// Asks the server to pin (true) or unpin the message with this id, for everyone.
+    SetPinned(String, bool),
+    TogglePins,
+    /// Closes the saved list and scrolls to the message with this id.
+    JumpTo(String),
+    /// A frame's worth of frames from the server are waiting in `incoming`.
+    FlushIncoming,
+    /// The socket's queue may have room for the outbox again.
+    FlushOutbox,
+    /// The highlight on the message jumped to has had its time.
+    JumpHighlightDone,
+    /// The messages that just came in have finished animating in.
+    Entered,
+    /// The deleted message with this id has faded out, and can go.
+    FadedOut(String),
+    /// The page's address points at the message with this id; find it and go to it.
+    SeekPermalink(String),
+    /// What the local store has under the id a permalink points at.
+    PermalinkStored(String, Option<MessageData>),
+    /// Copies the permalink to the message with this id.
+    CopyLink(String),
+    SortUsers(UserSort),
+    TogglePinSelf,
+    SetRetention(Retention),
+    SetDensity(Density),
+    /// The sidebar's edge was grabbed.
+    StartResize,
+    /// Makes the sidebar this wide, within bounds. It's saved when the drag ends.
+    ResizeSidebar(i32),
+    EndResize,
+    SweepExpired,
+    /// Opens the emoji picker for this purpose, or closes it if it's already open for it.
+    ToggleEmojiPicker(PickerMode),
+    CloseEmojiPicker,
+    /// An emoji was picked; where it goes depends on the picker's mode.
+    PickEmoji(String),
+    /// Toggles our reaction with this emoji on the message with this id.
+    React(String, String),
+    /// Opens the export menu at this point in the viewport.
+    OpenExportMenu(i32, i32),
+    CloseExportMenu,
+    Export(ExportFormat),
+    /// A message came into view, or the tab came back into focus: whatever's on screen
+    /// now counts as read.
+    MarkVisibleRead,
+    /// Renders another window of the older loaded messages.
+    ShowEarlier,
+    /// Focus moved onto or into the row of the message at this index.
+    RowFocused(usize),
+    /// This key was pressed on the focused row of the message at this index.
+    RowKey(usize, String),
+    /// Back to the message box, e.g. on Escape from the message list.
+    FocusInput,
+    /// The message box was edited; whether it now has anything in it.
+    Typed(bool),
+    /// Puts an earlier send (`true`) or a later one back in the message box.
+    Recall(bool),
+    /// Typing paused; keeps what's in the message box as the room's draft.
+    SaveDraft,
+    /// Nothing more was heard from this user typing in this room.
+    TypingExpired(String, String),
+    /// Images pasted into the message box or files dropped on the chat, to upload and send.
+    ShareFiles(Vec<File>),
+    /// Files are being dragged into the chat (`true`) or a drag has left part of it.
+    DragFiles(bool),
+    /// This much of the upload with this id has gone, from 0 to 1.
+    UploadProgress(String, f64),
+    /// The upload with this id is done, with the link to send or why it failed.
+    Uploaded(String, Result<String, UploadError>),
+    /// Takes away the failed upload with this id.
+    DismissUpload(String),
+}
+
+#[derive(Properties, PartialEq)]
+pub struct ChatProps {
+    /// Hosts whose image links are shown inline (subdomains included); an empty list
+    /// allows any https host. Other image links are shown as plain links. Link previews
+    /// only show images from these hosts too.
+    #[prop_or_else(default_image_hosts)]
+    pub image_hosts: Vec<String>,
+    /// Which service the GIF picker searches.
+    #[prop_or_default]
+    pub gif_provider: GifProvider,
+    /// Language of the interface, like `id` or `en-GB`; the browser's when not given.
+    #[prop_or_default]
+    pub lang: Option<String>,
+    /// The unfurl proxy link previews are fetched through, which the link is appended
+    /// to, percent-encoded. An empty endpoint turns previews off.
+    #[prop_or_else(default_unfurl_endpoint)]
+    pub unfurl_endpoint: String,
+    /// Where images pasted into the message box are uploaded, as described in
+    /// `services::upload`. Its links are shown inline like any other image link, so
+    /// they need to be https and on one of `image_hosts`. An empty endpoint leaves
+    /// pasting to the browser.
+    #[prop_or_else(default_upload_endpoint)]
+    pub upload_endpoint: String,
+    /// How long a message gets before it's cut short, with a "Show more" button for the
+    /// rest.
+    #[prop_or_default]
+    pub fold: Fold,
+    /// How many frames may wait to be written to the socket; see
+    /// `ServiceConfig::queue_capacity`.
+    #[prop_or(DEFAULT_QUEUE_CAPACITY)]
+    pub queue_capacity: usize,
+}
+
+fn default_upload_endpoint() -> String {
+    DEFAULT_UPLOAD_ENDPOINT.to_string()
+}
+
+fn default_unfurl_endpoint() -> String {
+    DEFAULT_UNFURL_ENDPOINT.to_string()
 }
 
-#[derive(Deserialize)]
-struct MessageData {
+fn default_image_hosts() -> Vec<String> {
+    DEFAULT_IMAGE_HOSTS.iter().map(|h| h.to_string()).collect()
+}
+
+/// The message a context menu was opened on. It's a copy, so the menu keeps working if
+/// the list shifts underneath it.
+#[derive(Clone)]
+pub struct MenuTarget {
+    x: i32,
+    y: i32,
+    id: String,
     from: String,
-    message: String,
+    text: String,
+}
+
+/// Something that can't be undone, waiting on the user to confirm it.
+enum Confirm {
+    ClearHistory,
+    ClearRoom,
+    /// Deleting our message with this id.
+    Delete(String),
+    /// Removing this user from the chat.
+    Kick(String),
+}
+
+impl Confirm {
+    fn question(&self, i18n: I18n) -> String {
+        match self {
+            Confirm::ClearHistory => i18n.t("confirm_clear_history").to_string(),
+            Confirm::ClearRoom => i18n.t("confirm_clear_room").to_string(),
+            Confirm::Delete(_) => i18n.t("confirm_delete").to_string(),
+            Confirm::Kick(nick) => i18n.fill("confirm_kick", &[nick]),
+        }
+    }
+
+    fn label(&self, i18n: I18n) -> &'static str {
+        i18n.t(match self {
+            Confirm::ClearHistory | Confirm::ClearRoom => "clear",
+            Confirm::Delete(_) => "delete",
+            Confirm::Kick(_) => "remove",
+        })
+    }
+}
+
+/// A pasted or dropped file on its way to the upload service, or that didn't make it.
+struct Upload {
+    id: String,
+    name: String,
+    size: f64,
+    /// Images are sent as their link, to be shown inline; anything else as a named link.
+    image: bool,
+    /// How much has gone, from 0 to 1.
+    progress: f64,
+    error: Option<String>,
+}
+
+/// How the sidebar orders users. Either way the sort is stable, so users that compare
+/// equal keep the order the server sent them in.
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum UserSort {
+    #[default]
+    Name,
+    /// Most recent message first; users who haven't said anything go last.
+    Recent,
+}
+
+#[derive(Clone)]
+pub(crate) struct UserProfile {
+    pub(crate) name: String,
+    pub(crate) status: Presence,
+    /// Admins may remove other users; the server checks, this only decides what's shown.
+    pub(crate) admin: bool,
+    /// When they joined and were last active, if the server said.
+    pub(crate) activity: Option<Activity>,
+}
+
+/// Whether `viewer` gets a kick button on `target`'s entry: admins do, on anyone but
+/// themselves and other admins, whom the server won't let them kick anyway.
+fn can_kick(viewer: Option<&UserProfile>, target: &UserProfile) -> bool {
+    viewer.is_some_and(|v| v.admin && v.name != target.name) && !target.admin
+}
+
+fn unstore(id: String) {
+    spawn_local(async move {
+        if let Err(e) = history_store::remove(&id).await {
+            log::error!("couldn't remove stored message: {:?}", e);
+        }
+    });
+}
+
+fn clear_store() {
+    spawn_local(async {
+        if let Err(e) = history_store::clear().await {
+            log::error!("couldn't clear stored history: {:?}", e);
+        }
+    });
+}
+
+/// Looks for `query` among the stored messages, under the same filters as the loaded
+/// ones.
+async fn search_stored(
+    query: String,
+    sender: Option<String>,
+    muted: Option<HashSet<String>>,
+) -> Msg {
+    let needle = query.trim().to_lowercase();
+    let keep = move |m: &MessageData| passes_filters(m, &needle, sender.as_deref(), muted.as_ref());
+    match history_store::search(keep, STORED_SEARCH_LIMIT).await {
+        Ok(hits) => Msg::StoredHits(query, hits),
+        Err(e) => {
+            log::error!("couldn't search stored history: {:?}", e);
+            Msg::StoredHits(query, vec![])
+        }
+    }
+}
+
+/// Loads the stored messages from a few before `hit` up to `until`, the oldest one
+/// loaded, so there's no gap between them and what's already shown.
+async fn load_context(hit: MessageData, until: Option<u64>) -> Msg {
+    let before = history_store::load_page::<MessageData>(Some(hit.time), STORED_CONTEXT).await;
+    let from = match before {
+        Ok(before) => before.first().map_or(hit.time, |m| m.time),
+        Err(_) => hit.time,
+    };
+    match history_store::load_between(from, until).await {
+        Ok(page) => Msg::StoredContext(hit.id, page),
+        Err(e) => {
+            log::error!("couldn't load stored history: {:?}", e);
+            Msg::StoredContext(hit.id.clone(), vec![hit])
+        }
+    }
+}
+
+async fn load_stored(before: Option<u64>) -> Msg {
+    match history_store::load_page(before, STORED_PAGE_SIZE).await {
+        Ok(page) => Msg::StoredPage(page),
+        Err(e) => {
+            log::error!("couldn't load stored history: {:?}", e);
+            Msg::StoredPage(vec![])
+        }
+    }
+}
+
+/// The classes of a bubble in the comfortable layout: ours on the primary colour, others'
+/// on white. An image gets a tighter frame, and is held to the same size whichever side
+/// it's on.
+fn bubble_class(own: bool, image: bool) -> &'static str {
+    match (own, image) {
+        (true, false) => {
+            "max-w-md bg-primary-light text-white rounded-2xl py-2 px-4 message-bubble"
+        }
+        (false, false) => "max-w-md bg-white rounded-2xl py-2 px-4 shadow-sm message-bubble",
+        (true, true) => {
+            "max-w-sm bg-primary-light text-white rounded-2xl p-2 message-bubble image-bubble"
+        }
+        (false, true) => "max-w-sm bg-white rounded-2xl p-2 shadow-sm message-bubble image-bubble",
+    }
+}
+
+/// Placeholder rows for the user list until the first one arrives, laid out as its
+/// entries are: avatar, then the name with the Mute button at the far end.
+fn user_skeleton() -> Html {
+    ["w-24", "w-16", "w-20", "w-28"]
+        .iter()
+        .map(|&width| {
+            html! {
+                <div class="flex m-3 bg-white rounded-xl p-3 shadow-sm animate-pulse user-skeleton" aria-hidden="true">
+                    <div class="flex-none w-12 h-12 rounded-full bg-gray-200 self-center"></div>
+                    <div class="flex-grow flex items-center justify-between p-2 ms-2">
+                        <div class={classes!("h-3", "rounded", "bg-gray-200", width)}></div>
+                        <div class="h-2 w-8 rounded bg-gray-100"></div>
+                    </div>
+                </div>
+            }
+        })
+        .collect()
+}
+
+/// A shared file: what it is, how big, and a button to download it, which opens in a new
+/// tab.
+fn file_card(attachment: &Attachment, href: &str, compact: bool, i18n: I18n) -> Html {
+    html! {
+        <div class={classes!(
+            "flex", "items-center", "gap-3", "rounded-lg", "border", "border-gray-200", "bg-white",
+            "text-gray-800", "file-card", if compact { "px-2 py-1" } else { "p-3 min-w-[14rem]" },
+        )}>
+            <svg viewBox="0 0 24 24" xmlns="http://www.w3.org/2000/svg" class="flex-none w-8 h-8 fill-current text-gray-400" aria-hidden="true">
+                <path d="M14 2H6a2 2 0 0 0-2 2v16a2 2 0 0 0 2 2h12a2 2 0 0 0 2-2V8l-6-6zm-1 7V3.5L18.5 9H13z"></path>
+            </svg>
+            <div class="min-w-0 grow">
+                <div class="text-sm font-medium truncate" title={attachment.name.clone()}>{attachment.name.clone()}</div>
+                <div class="text-xs text-gray-500">{upload::human_size(attachment.size)}</div>
+            </div>
+            <a class="flex-none px-2 py-1 rounded text-xs font-semibold text-white bg-primary-dark hover:bg-primary"
+                href={href.to_string()} target="_blank" rel="noopener noreferrer"
+                aria-label={i18n.fill("download_named", &[&attachment.name])}>
+                {i18n.t("download")}
+            </a>
+        </div>
+    }
+}
+
+/// Placeholder messages for an empty conversation until the first history arrives, laid
+/// out as rows are in `density`: bubbles with a name line on others' and an avatar at the
+/// foot, or one line each when compact.
+fn message_skeleton(density: Density) -> Html {
+    let rows = [
+        ("w-48", false),
+        ("w-64", true),
+        ("w-40", false),
+        ("w-56", true),
+    ];
+    if density == Density::Compact {
+        return rows
+            .iter()
+            .map(|&(width, _)| {
+                html! {
+                    <div class="flex items-center gap-2 px-2 py-1 animate-pulse message-skeleton" aria-hidden="true">
+                        <div class="h-3 w-10 rounded bg-gray-100"></div>
+                        <div class="h-3 w-16 rounded bg-gray-200"></div>
+                        <div class={classes!("h-3", "rounded", "bg-gray-200", width)}></div>
+                    </div>
+                }
+            })
+            .collect();
+    }
+    rows.iter()
+        .map(|&(width, own)| {
+            let avatar = html! {
+                <div class={classes!("flex-none", "self-end", "w-10", "h-10", "rounded-full", "bg-gray-200", if own { "ms-3" } else { "me-3" })}></div>
+            };
+            html! {
+                <div class={classes!("flex", "animate-pulse", "message-skeleton", own.then_some("justify-end"))} aria-hidden="true">
+                    if !own {
+                        { avatar.clone() }
+                    }
+                    <div class={classes!(
+                        "flex", "flex-col", "gap-2", "rounded-2xl", "py-2", "px-4", width,
+                        if own { "bg-primary-light/30" } else { "bg-white shadow-sm" },
+                    )}>
+                        if !own {
+                            <div class="h-3 w-16 rounded bg-gray-200"></div>
+                        }
+                        <div class="h-4 rounded bg-gray-200"></div>
+                        <div class="self-end h-2 w-8 rounded bg-gray-100"></div>
+                    </div>
+                    if own {
+                        {avatar}
+                    }
+                </div>
+            }
+        })
+        .collect()
+}
+
+/// Types `text` over the message box's selection, leaving the cursor after it.
+fn insert_at_cursor(input: &HtmlTextAreaElement, text: &str) {
+    let end = input.value().encode_utf16().count() as u32;
+    let start = input.selection_start().ok().flatten().unwrap_or(end);
+    let stop = input.selection_end().ok().flatten().unwrap_or(start);
+    let _ = input.set_range_text_with_start_and_end(text, start, stop);
+    let caret = start + text.encode_utf16().count() as u32;
+    let _ = input.set_selection_range(caret, caret);
+    fit_input(input);
+    let _ = input.focus();
+}
+
+/// Turns a `:shortcode:` the user just finished typing, right before the cursor, into its
+/// emoji.
+fn expand_typed_shortcode(input: &HtmlTextAreaElement) {
+    let Some(caret) = input.selection_start().ok().flatten() else {
+        return;
+    };
+    let before: Vec<u16> = input.value().encode_utf16().take(caret as usize).collect();
+    let before = String::from_utf16_lossy(&before);
+    let Some((start, emoji)) = trailing_shortcode(&before) else {
+        return;
+    };
+    // The textarea counts in UTF-16 units.
+    let start = before[..start].encode_utf16().count() as u32;
+    let _ = input.set_range_text_with_start_and_end(emoji, start, caret);
+    let caret = start + emoji.encode_utf16().count() as u32;
+    let _ = input.set_selection_range(caret, caret);
+}
+
+/// Sizes the message box to its content, so multi-line messages are visible as typed.
+fn fit_input(input: &HtmlTextAreaElement) {
+    let style = input.style();
+    let _ = style.set_property("height", "auto");
+    let height = input.scroll_height().min(INPUT_MAX_HEIGHT_PX);
+    let _ = style.set_property("height", &format!("{}px", height));
+}
+
+/// Whether a key pressed with focus on `target` is being typed into something.
+fn is_text_entry(target: Option<EventTarget>) -> bool {
+    match target.and_then(|t| t.dyn_into::<HtmlElement>().ok()) {
+        Some(el) => {
+            el.is_content_editable()
+                || matches!(el.tag_name().as_str(), "INPUT" | "TEXTAREA" | "SELECT")
+        }
+        None => false,
+    }
+}
+
+/// "/" or Ctrl+K (Cmd+K on macOS) jumps to the message box.
+fn is_focus_shortcut(e: &KeyboardEvent) -> bool {
+    let key = e.key();
+    (key == "/" && !e.ctrl_key() && !e.meta_key() && !e.alt_key())
+        || (key.eq_ignore_ascii_case("k") && (e.ctrl_key() || e.meta_key()) && !e.alt_key())
+}
+
+/// Where a key pressed on a focused message row sends focus.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum RowNav {
+    /// The row at this position among the rendered rows.
+    Row(usize),
+    /// Into the focused row's toolbar.
+    Toolbar,
+    /// The message box.
+    Input,
+}
+
+/// The keys a focused row acts on. Everything else, Tab above all, is left to the browser.
+const ROW_KEYS: [&str; 6] = ["ArrowUp", "ArrowDown", "Home", "End", "Enter", "Escape"];
+
+/// Where `key` sends focus from the row at position `current` of `len` rendered rows, if
+/// anywhere. Arrows stop at either end rather than wrapping, so the list doesn't trap.
+fn row_nav(key: &str, current: usize, len: usize) -> Option<RowNav> {
+    let last = len.checked_sub(1)?;
+    match key {
+        "ArrowUp" => Some(RowNav::Row(current.min(last).saturating_sub(1))),
+        "ArrowDown" => Some(RowNav::Row((current + 1).min(last))),
+        "Home" => Some(RowNav::Row(0)),
+        "End" => Some(RowNav::Row(last)),
+        "Enter" => Some(RowNav::Toolbar),
+        "Escape" => Some(RowNav::Input),
+        _ => None,
+    }
+}
+
+/// End (or Ctrl+End) jumps to the newest message. Shift+End is left to extend selections.
+fn is_latest_shortcut(e: &KeyboardEvent) -> bool {
+    e.key() == "End" && !e.shift_key() && !e.alt_key()
+}
+
+fn render_inline(inline: Inline, query: &str) -> Html {
+    match inline {
+        Inline::Text(t) => highlight(&expand_shortcodes(t), query),
+        Inline::Code(c) => html! {
+            <code class="px-1 rounded bg-black/10 font-mono text-[0.85em]">{c}</code>
+        },
+        Inline::Spoiler(content) => html! {
+            <Spoiler>{ for content.into_iter().map(|inline| render_inline(inline, query)) }</Spoiler>
+        },
+    }
+}
+
+/// Renders a text message: fenced blocks as code, the rest as wrapped text with inline
+/// code, spoilers and emoji shortcodes. Everything ends up in text nodes, so markup in the
+/// message is shown rather than interpreted.
+pub(crate) fn render_body(text: &str, query: &str, i18n: I18n) -> Html {
+    render_blocks(classify_message(text), query, i18n)
+}
+
+fn render_blocks(blocks: Vec<Block>, query: &str, i18n: I18n) -> Html {
+    blocks
+        .into_iter()
+        .map(|block| match block {
+            Block::Code { lang, code } => html! {
+                <CodeBlock lang={lang.map(String::from)} code={code.to_string()} {i18n}/>
+            },
+            // Each paragraph takes the direction of its own text, whichever way the layout goes.
+            Block::Text(inlines) => html! {
+                <p class="text-sm whitespace-pre-wrap break-words" dir="auto">
+                    {
+                        for inlines.into_iter().map(|inline| render_inline(inline, query))
+                    }
+                </p>
+            },
+        })
+        .collect()
+}
+
+/// If `s` starts with `needle` (already lowercased), ignoring case, returns the length of
+/// the matching prefix of `s` in bytes.
+fn match_len_at(s: &str, needle: &str) -> Option<usize> {
+    let mut needle = needle.chars().peekable();
+    for (i, c) in s.char_indices() {
+        for lower in c.to_lowercase() {
+            if needle.next() != Some(lower) {
+                return None;
+            }
+        }
+        if needle.peek().is_none() {
+            return Some(i + c.len_utf8());
+        }
+    }
+    None
+}
+
+/// Renders `text` with every case-insensitive occurrence of `query` marked.
+fn highlight(text: &str, query: &str) -> Html {
+    let query = query.trim().to_lowercase();
+    if query.is_empty() {
+        return html! { {text} };
+    }
+
+    let mut parts = vec![];
+    let (mut plain, mut pos) = (0, 0);
+    while let Some(c) = text[pos..].chars().next() {
+        match match_len_at(&text[pos..], &query) {
+            Some(len) => {
+                if plain < pos {
+                    parts.push(html! { {&text[plain..pos]} });
+                }
+                parts.push(html! {
+                    <mark class="bg-yellow-200 text-gray-900 rounded-sm">{&text[pos..pos + len]}</mark>
+                });
+                pos += len;
+                plain = pos;
+            }
+            None => pos += c.len_utf8(),
+        }
+    }
+    if plain < text.len() {
+        parts.push(html! { {&text[plain..]} });
+    }
+    parts.into_iter().collect()
+}
+
+pub(crate) fn status_icon(status: DeliveryStatus) -> Html {
+    match status {
+        DeliveryStatus::Pending => html! {
+            <svg viewBox="0 0 24 24" class="w-3 h-3 fill-current" aria-label="pending">
+                <path d="M12 2a10 10 0 1 0 0 20 10 10 0 0 0 0-20zm1 10.4 3.3 3.3-1.4 1.4L11 13.2V6h2z"></path>
+            </svg>
+        },
+        DeliveryStatus::Delivered => html! {
+            <svg viewBox="0 0 24 24" class="w-3 h-3 fill-current" aria-label="delivered">
+                <path d="M9 16.2 4.8 12l-1.4 1.4L9 19 21 7l-1.4-1.4z"></path>
+            </svg>
+        },
+        DeliveryStatus::Failed => html! {
+            <svg viewBox="0 0 24 24" class="w-4 h-4 fill-red-500" aria-label="failed">
+                <path d="M12 2a10 10 0 1 0 0 20 10 10 0 0 0 0-20zm1 15h-2v-2h2zm0-4h-2V7h2z"></path>
+            </svg>
+        },
+    }
+}
+
+fn star_icon(starred: bool) -> Html {
+    let path = if starred {
+        "M12 17.27 18.18 21l-1.64-7.03L22 9.24l-7.19-.61L12 2 9.19 8.63 2 9.24l5.46 4.73L5.82 21z"
+    } else {
+        "M22 9.24l-7.19-.62L12 2 9.19 8.63 2 9.24l5.46 4.73L5.82 21 12 17.27 18.18 21l-1.63-7.03L22 9.24zM12 15.4l-3.76 2.27 1-4.28-3.32-2.88 4.38-.38L12 6.1l1.71 4.04 4.38.38-3.32 2.88 1 4.28L12 15.4z"
+    };
+    html! {
+        <svg viewBox="0 0 24 24" xmlns="http://www.w3.org/2000/svg" class="fill-current w-4 h-4">
+            <path d={path}></path>
+        </svg>
+    }
+}
+
+/// One room's conversation, as the `Store` keeps it and `Chat` and `ChatFn` show it. It
+/// holds no DOM handles or timers, so `apply_incoming` can be exercised without a
+/// browser.
+#[derive(Clone, Default)]
+pub(crate) struct ChatState {
+    pub(crate) users: Vec<UserProfile>,
+    /// Changed only through `push_message` and `retain_messages`, which keep `seen` in step.
+    pub(crate) messages: Vec<MessageData>,
+    /// Ids of everything in `messages`, so a repeat delivery is caught without a scan.
+    seen: HashSet<String>,
+    /// Who's typing, by room. Rooms nobody is typing in have no entry.
+    typing: HashMap<String, BTreeSet<String>>,
+    /// Ids of the pinned messages, oldest pin first. They may be older than what's loaded.
+    pub(crate) pinned: Vec<String>,
+}
+
+/// Where `message` goes in `messages`, which is kept sorted by time and then id, so
+/// messages stamped in the same millisecond still have one fixed order.
+fn insertion_point(messages: &[MessageData], message: &MessageData) -> usize {
+    messages.partition_point(|m| (m.time, &m.id) < (message.time, &message.id))
+}
+
+impl ChatState {
+    /// Adds a message in time order unless one with its id is already here. Returns whether
+    /// it was added.
+    pub(crate) fn push_message(&mut self, message: MessageData) -> bool {
+        if !self.seen.insert(message.id.clone()) {
+            return false;
+        }
+        let at = insertion_point(&self.messages, &message);
+        self.messages.insert(at, message);
+        true
+    }
+
+    /// Whether the message with this id is loaded.
+    pub(crate) fn has(&self, id: &str) -> bool {
+        self.seen.contains(id)
+    }
+
+    /// The room's owner: the server lists users in the order they joined, so whoever has
+    /// been here longest.
+    pub(crate) fn owner(&self) -> Option<&str> {
+        self.users.first().map(|u| u.name.as_str())
+    }
+
+    /// Who's typing in `room`, by name.
+    pub(crate) fn typers(&self, room: &str) -> Vec<&str> {
+        self.typing
+            .get(room)
+            .map(|names| names.iter().map(String::as_str).collect())
+            .unwrap_or_default()
+    }
+
+    /// Marks `who` as typing in `room` or not. Returns whether that changed anything.
+    pub(crate) fn set_typing(&mut self, room: &str, who: &str, typing: bool) -> bool {
+        if typing {
+            return self
+                .typing
+                .entry(room.to_string())
+                .or_default()
+                .insert(who.to_string());
+        }
+        let Some(names) = self.typing.get_mut(room) else {
+            return false;
+        };
+        let removed = names.remove(who);
+        if names.is_empty() {
+            self.typing.remove(room);
+        }
+        removed
+    }
+
+    pub(crate) fn retain_messages(&mut self, mut keep: impl FnMut(&MessageData) -> bool) {
+        let seen = &mut self.seen;
+        self.messages.retain(|m| {
+            let kept = keep(m);
+            if !kept {
+                seen.remove(&m.id);
+            }
+            kept
+        });
+    }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
-#[serde(rename_all = "lowercase")]
-pub enum MsgTypes {
+/// What `apply_incoming` changed, so the component knows what follow-up work to do.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Applied {
     Users,
-    Register,
     Message,
+    /// The server echoed the message with this id, settling our optimistic copy.
+    Delivered(String),
+    /// A history reply arrived, adding the messages with these ids. `has_more` is whether
+    /// the server has older ones still, and `None` if the reply was an error.
+    History {
+        added: Vec<String>,
+        has_more: Option<bool>,
+    },
+    /// The message with this id was deleted by its author.
+    Deleted(String),
+    /// The owner, named here, cleared the conversation for everyone.
+    Cleared(String),
+    /// Someone's reaction was added or taken back, on the message with this id if it's
+    /// loaded.
+    Reacted(Option<String>),
+    /// Someone read the loaded messages with these ids.
+    Read(Vec<String>),
+    /// Someone pinned or unpinned a message, or the server listed the pins.
+    Pins,
+    /// Someone started or stopped typing; a repeat of what we already knew still counts,
+    /// as it shows they're still at it.
+    Typing(TypingNotice),
+    /// The server refused our username, for this reason.
+    RegisterRejected(String),
+    /// An admin removed someone from the chat, possibly us.
+    Kicked(KickNotice),
+    /// The server refused something we sent. A message it names has failed.
+    ServerError(ServerError),
+    /// A message we already had arrived again, from a reconnect or overlapping history.
+    Duplicate,
 }
 
-#[derive(Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct WebSocketMessage {
-    message_type: MsgTypes,
-    data_array: Option<Vec<String>>,
-    data: Option<String>,
+/// Indices of the messages whose text or sender contains `query`, ignoring case, oldest
+/// first, limited to `sender`'s messages if one is given and leaving out anyone in
+/// `muted`. A blank query matches nothing.
+fn search_matches(
+    messages: &[MessageData],
+    query: &str,
+    sender: Option<&str>,
+    muted: Option<&HashSet<String>>,
+) -> Vec<usize> {
+    let query = query.trim().to_lowercase();
+    if query.is_empty() {
+        return vec![];
+    }
+    messages
+        .iter()
+        .enumerate()
+        .filter(|(_, m)| passes_filters(m, &query, sender, muted))
+        .map(|(i, _)| i)
+        .collect()
 }
 
-#[derive(Clone)]
-struct UserProfile {
-    name: String,
-    avatar: String,
+/// Whether `m` stays in view: it's from `sender`, if one is picked, not from anyone in
+/// `muted`, and contains `query` (already lowercased) in its text or sender, unless
+/// `query` is empty.
+fn passes_filters(
+    m: &MessageData,
+    query: &str,
+    sender: Option<&str>,
+    muted: Option<&HashSet<String>>,
+) -> bool {
+    sender.is_none_or(|s| m.from == s)
+        && muted.is_none_or(|muted| !muted.contains(&m.from))
+        && (query.is_empty()
+            || m.message.to_lowercase().contains(query)
+            || m.from.to_lowercase().contains(query))
+}
+
+/// Merges a page of history into the conversation, dropping anything already there (e.g.
+/// after a reconnect, or when the local store and the server overlap). Returns the ids of
+/// the messages that were added.
+pub(crate) fn merge_history(state: &mut ChatState, page: Vec<MessageData>) -> Vec<String> {
+    let mut ids = vec![];
+    for message in page {
+        let id = message.id.clone();
+        if state.push_message(message) {
+            ids.push(id);
+        }
+    }
+    ids
+}
+
+/// Sends the `outbox` through `send`, oldest first, taking off each message that goes.
+/// It stops at the first that doesn't, so the server still sees them in the order they
+/// were typed: one the socket's queue was too full for stays first in line, and one it
+/// was closed for is taken off. Ids no longer in `messages`, deleted while queued, are
+/// dropped. Returns the ids sent, in order, and what stopped it, with the id it
+/// stopped at.
+fn drain_outbox(
+    outbox: &mut VecDeque<String>,
+    messages: &[MessageData],
+    mut send: impl FnMut(&WebSocketMessage) -> Result<(), SendError>,
+) -> (Vec<String>, Option<(String, SendError)>) {
+    let mut sent = vec![];
+    while let Some(id) = outbox.front().cloned() {
+        let Some(m) = messages.iter().find(|m| m.id == id) else {
+            outbox.pop_front();
+            continue;
+        };
+        let message = WebSocketMessage {
+            message_type: MsgTypes::Message,
+            data: Some(m.message.clone()),
+            data_array: None,
+            id: Some(id.clone()),
+            reply_to: m.reply_to.clone(),
+            attachment: m.attachment.clone(),
+        };
+        match send(&message) {
+            Ok(()) => {
+                outbox.pop_front();
+                sent.push(id);
+            }
+            Err(e) => {
+                if e == SendError::Closed {
+                    outbox.pop_front();
+                }
+                return (sent, Some((id, e)));
+            }
+        }
+    }
+    (sent, None)
+}
+
+/// When each user last sent a message, as far as the loaded conversation shows.
+fn last_message_times(messages: &[MessageData]) -> HashMap<&str, u64> {
+    let mut times = HashMap::new();
+    for m in messages {
+        let time = times.entry(m.from.as_str()).or_insert(m.time);
+        *time = (*time).max(m.time);
+    }
+    times
+}
+
+/// The sidebar's user list in display order, with `pinned` (ourselves) first if given.
+fn sort_users<'a>(
+    users: &'a [UserProfile],
+    messages: &[MessageData],
+    order: UserSort,
+    pinned: Option<&str>,
+) -> Vec<&'a UserProfile> {
+    let mut sorted: Vec<&UserProfile> = users.iter().collect();
+    match order {
+        UserSort::Name => sorted.sort_by_cached_key(|u| u.name.to_lowercase()),
+        UserSort::Recent => {
+            let times = last_message_times(messages);
+            sorted.sort_by_key(|u| std::cmp::Reverse(times.get(u.name.as_str()).copied()));
+        }
+    }
+    if let Some(pinned) = pinned {
+        sorted.sort_by_key(|u| u.name != pinned);
+    }
+    sorted
+}
+
+/// Drops a message from the conversation, and from the pins.
+pub(crate) fn remove_message(state: &mut ChatState, id: &str) {
+    state.retain_messages(|m| m.id != id);
+    state.pinned.retain(|p| p != id);
+}
+
+/// Empties the conversation apart from our own undelivered messages, which are still on
+/// their way.
+pub(crate) fn clear_delivered(state: &mut ChatState) {
+    state.retain_messages(|m| m.status != DeliveryStatus::Delivered);
+}
+
+/// Pins or unpins the message with `id`, keeping at most `PIN_LIMIT` pins. Returns whether
+/// anything changed; pinning what's already pinned, or past the limit, doesn't.
+fn set_pinned(pinned: &mut Vec<String>, id: &str, pin: bool) -> bool {
+    let at = pinned.iter().position(|p| p == id);
+    match (pin, at) {
+        (true, None) if pinned.len() < PIN_LIMIT => {
+            pinned.push(id.to_string());
+            true
+        }
+        (false, Some(i)) => {
+            pinned.remove(i);
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Where the "New messages" divider goes: the index of the first message after both
+/// `last_read` and our own latest message, since we've seen whatever we replied to.
+/// `None` when there's nothing newer, or neither is loaded.
+fn unread_boundary(messages: &[MessageData], last_read: &str, me: &str) -> Option<usize> {
+    let read = messages
+        .iter()
+        .rposition(|m| m.id == last_read || m.from == me)?;
+    (read + 1 < messages.len()).then_some(read + 1)
+}
+
+/// Whether `m` is past the retention `cutoff`. Our own undelivered messages never are,
+/// since they're still on their way.
+pub(crate) fn is_expired(m: &MessageData, cutoff: u64) -> bool {
+    m.status == DeliveryStatus::Delivered && m.time < cutoff
+}
+
+/// Adds `user` to those who reacted with `emoji`, or takes them off if they already had.
+fn toggle_reaction(reactions: &mut BTreeMap<String, Vec<String>>, user: &str, emoji: &str) {
+    let users = reactions.entry(emoji.to_string()).or_default();
+    match users.iter().position(|u| u == user) {
+        Some(i) => {
+            users.remove(i);
+        }
+        None => users.push(user.to_string()),
+    }
+    if users.is_empty() {
+        reactions.remove(emoji);
+    }
+}
+
+/// The first line of `text`, cut to `REPLY_SNIPPET_CHARS` characters, for quoting a
+/// message in one line.
+fn snippet(text: &str) -> String {
+    let line = text.trim().lines().next().unwrap_or_default();
+    if line.chars().count() <= REPLY_SNIPPET_CHARS && !text.trim().contains('\n') {
+        return line.to_string();
+    }
+    let cut: String = line.chars().take(REPLY_SNIPPET_CHARS).collect();
+    format!("{}…", cut.trim_end())
+}
+
+/// Quotes a message for the message box, Markdown style, with the sender on the first line.
+fn quote(from: &str, text: &str) -> String {
+    let mut lines = text.trim().lines();
+    let mut quoted = format!("> {}: {}\n", from, lines.next().unwrap_or_default());
+    for line in lines {
+        quoted.push_str(&format!("> {}\n", line));
+    }
+    quoted
+}
+
+/// `ms` since the epoch as a local time of day, like `09:41` or `9:41 AM`. Every time we
+/// hold is by the server's clock, including our own unconfirmed messages, so everyone
+/// sees the same.
+fn clock_time(ms: u64, clock: Clock) -> String {
+    let date = js_sys::Date::new(&JsValue::from_f64(ms as f64));
+    clock.format(date.get_hours(), date.get_minutes())
+}
+
+/// Whether what's being dragged, or dropped, in this event includes files.
+fn dragged_files(e: &Event) -> bool {
+    e.dyn_ref::<DragEvent>()
+        .and_then(DragEvent::data_transfer)
+        .is_some_and(|data| data.types().includes(&"Files".into(), 0))
+}
+
+/// The files dropped in this event.
+fn dropped_files(e: &DragEvent) -> Vec<File> {
+    let Some(files) = e.data_transfer().and_then(|data| data.files()) else {
+        return vec![];
+    };
+    (0..files.length()).filter_map(|i| files.get(i)).collect()
+}
+
+/// Whether the user has asked the system for less motion.
+fn reduced_motion() -> bool {
+    gloo_utils::window()
+        .match_media("(prefers-reduced-motion: reduce)")
+        .ok()
+        .flatten()
+        .is_some_and(|query| query.matches())
+}
+
+/// The id of the message a page hash like `#msg-abc` points at.
+fn permalink_id(hash: &str) -> Option<String> {
+    let id = hash.strip_prefix(PERMALINK_PREFIX)?;
+    let id = js_sys::decode_uri_component(id).ok().map(String::from)?;
+    (!id.is_empty()).then_some(id)
+}
+
+/// A link to this page that opens at the message with this id.
+fn permalink(id: &str) -> String {
+    let location = gloo_utils::window().location();
+    format!(
+        "{}{}{}{}",
+        location.origin().unwrap_or_default(),
+        location.pathname().unwrap_or_default(),
+        PERMALINK_PREFIX,
+        String::from(js_sys::encode_uri_component(id))
+    )
+}
+
+async fn find_stored(id: String) -> Msg {
+    let hit = history_store::get::<MessageData>(&id)
+        .await
+        .map_err(|e| log::error!("couldn't look in stored history: {:?}", e))
+        .ok()
+        .flatten();
+    Msg::PermalinkStored(id, hit)
+}
+
+/// `ms` since the epoch as a local date and time, like `2024-03-09 09:41`.
+fn date_time(ms: u64, clock: Clock) -> String {
+    let date = js_sys::Date::new(&JsValue::from_f64(ms as f64));
+    format!(
+        "{}-{:02}-{:02} {}",
+        date.get_full_year(),
+        date.get_month() + 1,
+        date.get_date(),
+        clock.format(date.get_hours(), date.get_minutes())
+    )
+}
+
+/// How long ago a recent message was.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Ago {
+    /// Under a minute, or apparently in the future.
+    JustNow,
+    Minutes(u64),
+    Hours(u64),
+}
+
+/// How long before `now` the time `ts` was, both in ms since the epoch, rounded down.
+/// `None` from `RELATIVE_LIMIT_MS` on, where the clock time says more.
+fn relative_time(ts: u64, now: u64) -> Option<Ago> {
+    const MINUTE: u64 = 60 * 1000;
+    const HOUR: u64 = 60 * MINUTE;
+    match now.saturating_sub(ts) {
+        age if age >= RELATIVE_LIMIT_MS => None,
+        age if age >= HOUR => Some(Ago::Hours(age / HOUR)),
+        age if age >= MINUTE => Some(Ago::Minutes(age / MINUTE)),
+        _ => Some(Ago::JustNow),
+    }
+}
+
+/// What the typing indicator says for these typers, if anything.
+fn typing_label(names: &[&str], i18n: I18n) -> Option<String> {
+    match names {
+        [] => None,
+        [one] => Some(i18n.fill("typing_one", &[one])),
+        [one, two] => Some(i18n.fill("typing_two", &[one, two])),
+        [one, two, three] => Some(i18n.fill("typing_three", &[one, two, three])),
+        _ => Some(i18n.fill("typing_many", &[&names.len()])),
+    }
+}
+
+/// The part of handling a server event that only touches `state`: no DOM, storage or
+/// socket, so it runs anywhere. Together with `BusEvent::from_frame` it takes a raw
+/// frame all the way to the resulting users and messages; the caller acts on what
+/// comes back.
+pub(crate) fn apply_incoming(state: &mut ChatState, event: BusEvent) -> Applied {
+    match event {
+        BusEvent::UsersUpdated {
+            users,
+            statuses,
+            admins,
+            activity,
+        } => {
+            state.users = users
+                .iter()
+                .map(|u| UserProfile {
+                    name: u.into(),
+                    status: statuses.get(u).copied().unwrap_or_default(),
+                    admin: admins.contains(u),
+                    activity: activity.get(u).copied(),
+                })
+                .collect();
+            // Anyone who left stopped typing with them.
+            let present: HashSet<&str> = users.iter().map(String::as_str).collect();
+            state.typing.retain(|_, names| {
+                names.retain(|n| present.contains(n.as_str()));
+                !names.is_empty()
+            });
+            Applied::Users
+        }
+        BusEvent::MessageReceived(message_data) => {
+            let id = message_data.id.clone();
+            if !state.seen.contains(&id) {
+                state.push_message(message_data);
+                return Applied::Message;
+            }
+            // An echo of our own message settles the optimistic copy instead of adding a
+            // second bubble; anything already delivered is a repeat and is left alone.
+            match state.messages.iter().position(|m| m.id == id) {
+                Some(i) if state.messages[i].status != DeliveryStatus::Delivered => {
+                    // The server's timestamp replaces ours, which can move the message.
+                    let mut settled = state.messages.remove(i);
+                    settled.status = DeliveryStatus::Delivered;
+                    settled.time = message_data.time;
+                    let at = insertion_point(&state.messages, &settled);
+                    state.messages.insert(at, settled);
+                    Applied::Delivered(id)
+                }
+                _ => Applied::Duplicate,
+            }
+        }
+        BusEvent::HistoryReceived(page) => {
+            let page = match page {
+                Ok(page) => page,
+                Err(e) => {
+                    log::error!("{}", e);
+                    return Applied::History {
+                        added: vec![],
+                        has_more: None,
+                    };
+                }
+            };
+            Applied::History {
+                added: merge_history(state, page.messages),
+                has_more: Some(page.has_more),
+            }
+        }
+        BusEvent::MessageDeleted(id) => {
+            remove_message(state, &id);
+            Applied::Deleted(id)
+        }
+        BusEvent::RoomCleared(by) => {
+            clear_delivered(state);
+            // The server dropped its pins along with its history.
+            state.pinned.clear();
+            Applied::Cleared(by)
+        }
+        BusEvent::ReactionToggled(reaction) => {
+            match state.messages.iter_mut().find(|m| m.id == reaction.id) {
+                Some(m) => {
+                    toggle_reaction(&mut m.reactions, &reaction.from, &reaction.emoji);
+                    Applied::Reacted(Some(reaction.id))
+                }
+                None => Applied::Reacted(None),
+            }
+        }
+        BusEvent::MessagesRead(receipt) => {
+            let mut read = vec![];
+            for m in state.messages.iter_mut() {
+                if receipt.ids.contains(&m.id)
+                    && m.from != receipt.from
+                    && !m.read_by.contains(&receipt.from)
+                {
+                    m.read_by.push(receipt.from.clone());
+                    read.push(m.id.clone());
+                }
+            }
+            Applied::Read(read)
+        }
+        BusEvent::TypingChanged(notice) => {
+            state.set_typing(&notice.room, &notice.from, notice.typing);
+            Applied::Typing(notice)
+        }
+        BusEvent::PinToggled(notice) => {
+            set_pinned(&mut state.pinned, &notice.id, notice.pinned);
+            Applied::Pins
+        }
+        BusEvent::PinsListed(ids) => {
+            // Through `set_pinned`, so repeats and anything past the limit are dropped.
+            state.pinned.clear();
+            for id in ids {
+                set_pinned(&mut state.pinned, &id, true);
+            }
+            Applied::Pins
+        }
+        BusEvent::Kicked(notice) => Applied::Kicked(notice),
+        BusEvent::RegisterRejected(reason) => Applied::RegisterRejected(reason),
+        BusEvent::ServerError(error) => {
+            // It won't be echoed, so it's no use waiting for that.
+            if let Some(m) = error
+                .id
+                .as_ref()
+                .and_then(|id| state.messages.iter_mut().find(|m| m.id == *id))
+            {
+                if m.status == DeliveryStatus::Pending {
+                    m.status = DeliveryStatus::Failed;
+                }
+            }
+            Applied::ServerError(error)
+        }
+    }
+}
+
+pub struct Chat {
+    username: String,
+    /// Our copy of the `Store`'s state, with our room filled in. Every change to the
+    /// conversation is applied here first, so it can be read back straight away, and
+    /// then dispatched to the `Store`, whose copy replaces this one once it has caught up.
+    app: AppState,
+    /// Changes applied to `app` in this update, for the `Store`.
+    dispatching: Vec<StoreAction>,
+    /// Whether any of `dispatching` changed our room.
+    room_changed: bool,
+    /// How many dispatches the `Store` has still to send our room back for. Its copy
+    /// is behind ours until then.
+    ahead: usize,
+    /// Ids of pending messages not yet handed to the socket, in the order they were typed.
+    outbox: VecDeque<String>,
+    /// Ids of delivered messages not yet written to the local store.
+    unsaved: Vec<String>,
+    history_loading: bool,
+    /// False once the server says there's nothing older, so we stop asking.
+    has_more_history: bool,
+    /// False once the local store has run out of older messages, so paging moves on to
+    /// the server.
+    stored_has_more: bool,
+    chat_input: NodeRef,
+    messages_container: NodeRef,
+    /// The room being viewed: its messages and typers are the ones shown.
+    room: String,
+    /// The connection's state, and the frames on presence and this room.
+    _connection: Subscription,
+    /// Frames that have arrived since the last render, applied together on the next
+    /// frame so a burst of them is rendered once.
+    incoming: Coalescer<BusEvent>,
+    incoming_flush: Option<NextFrame>,
+    /// Follows our room in the `Store`, and takes it our changes. The outbox, paging and
+    /// local storage bookkeeping only `Chat` does stays here.
+    store: Box<dyn Bridge<Store>>,
+    wss: WebsocketService,
+    /// Server time as of the last tick, which every relative message time is measured
+    /// from, so one render shows them all as of the same moment.
+    now: u64,
+    conn_state: ConnState,
+    /// Smoothed ping round-trip time of the current connection, once measured.
+    rtt: Option<u32>,
+    outbox_overflowed: bool,
+    /// Running echo timers for sent messages, keyed by message id.
+    echo_timeouts: HashMap<String, Timeout>,
+    /// Set while the outbox waits for room in the socket's queue.
+    outbox_retry: Option<Timeout>,
+    /// Scroll height from before a page of history was prepended, used to keep the view
+    /// from jumping once it renders.
+    scroll_anchor: Option<i32>,
+    /// Our own presence, as last reported to the server.
+    presence: Presence,
+    /// Updated by the document listeners on any input, without a re-render.
+    last_activity: Rc<Cell<f64>>,
+    /// Shared with the listeners so they only wake the component when coming back from away.
+    away: Rc<Cell<bool>>,
+    _activity_listeners: Vec<EventListener>,
+    /// The keyboard shortcuts for the message box and the newest message; removed when the
+    /// chat unmounts.
+    _shortcuts: EventListener,
+    /// Follows the page's hash, for permalinks opened in this tab.
+    _hash_listener: EventListener,
+    /// A message a permalink points at that isn't loaded, while we look for it.
+    seek: Option<String>,
+    /// Set once the server's been asked for the history around `seek`.
+    seek_asked: bool,
+    /// The message last jumped to, highlighted until the timer clears it.
+    jump_highlight: Option<(String, Timeout)>,
+    /// Messages added since the list was last still, which animate in.
+    entering: HashSet<String>,
+    /// Clears `entering` once the last of them is in.
+    entered: Option<Timeout>,
+    /// Long messages the reader has asked to see the whole of.
+    expanded: HashSet<String>,
+    /// Deleted messages still on screen while they fade out.
+    leaving: HashMap<String, Timeout>,
+    /// Set when a message is added while the list is at the bottom, so `rendered` keeps
+    /// it there.
+    follow_latest: bool,
+    _idle_check: Interval,
+    // One for the whole list, however many messages it holds.
+    _time_tick: Interval,
+    _retention_sweep: Interval,
+    show_gif_picker: bool,
+    /// Clears the highlight on the online count once it has played.
+    count_flash: Option<Timeout>,
+    /// Set once the first user list has arrived; until then the sidebar shows placeholders.
+    initialized: bool,
+    /// Set once the server's first page of history has arrived; until then an empty
+    /// conversation shows placeholders.
+    history_loaded: bool,
+    /// What we've sent, for Up and Down in the message box to bring back.
+    input_history: InputHistory,
+    /// Saves the draft once typing pauses.
+    draft_save: Option<Timeout>,
+    /// Pasted or dropped files uploading, or that failed to, shown at the end of the
+    /// conversation.
+    uploads: Vec<Upload>,
+    /// How far into the chat area a drag of files has gone, in nested elements; the drop
+    /// overlay is up while it's more than 0.
+    drag_depth: u32,
+    /// Files dropped anywhere but the chat area would be opened by the browser in place of
+    /// the app.
+    _drop_guards: [EventListener; 2],
+    /// Where passing notices go, when there's a `ToastHost` above.
+    toasts: Option<ToastContext>,
+    /// Set when the connection drops, so opening again can say it's back.
+    connection_lost: bool,
+    /// Set from when the connection drops until a fresh user list comes in, while the
+    /// sidebar may show people who've since left.
+    users_stale: bool,
+    search_open: bool,
+    search_query: String,
+    /// Position of the current hit within `search_matches`.
+    search_index: usize,
+    search_input: NodeRef,
+    /// Set when the current hit changes, so `rendered` scrolls it into view.
+    scroll_to_match: bool,
+    /// Set when the search bar opens, so `rendered` can focus it once it's in the DOM.
+    focus_search: bool,
+    /// Matches in the local store for the search query that aren't loaded, newest first.
+    stored_hits: Vec<MessageData>,
+    /// Waits for the query to settle before searching the local store.
+    stored_search: Option<Timeout>,
+    /// The user whose messages alone are shown, picked by clicking their name.
+    sender_filter: Option<String>,
+    menu: Option<MenuTarget>,
+    /// The id of the message the next one sent replies to, shown above the message box.
+    replying_to: Option<String>,
+    /// Ids of the messages starred on this device.
+    starred: HashSet<String>,
+    show_saved: bool,
+    /// Names whose messages and typing aren't shown, saved on this device.
+    muted: HashSet<String>,
+    /// Whether muted users' messages are shown for now anyway, from the hidden notice.
+    show_muted: bool,
+    /// Whether the pinned bar lists every pin rather than just the newest.
+    pins_open: bool,
+    /// Set by `JumpTo`, so `rendered` scrolls to the message once the list is unfiltered.
+    jump_to: Option<String>,
+    user_sort: UserSort,
+    /// Keeps ourselves at the top of the user list whatever the sort.
+    pin_self: bool,
+    retention: Retention,
+    density: Density,
+    /// The user sidebar's width in CSS pixels, kept within `sidebar::MIN_WIDTH` and
+    /// `sidebar::MAX_WIDTH`.
+    sidebar_width: i32,
+    /// Set while the sidebar's edge is being dragged.
+    resizing: bool,
+    i18n: I18n,
+    /// What the open emoji picker is picking for, if it's open.
+    emoji_picker: Option<PickerMode>,
+    /// Where the export menu is open, if it is.
+    export_menu: Option<(i32, i32)>,
+    /// Where the menu of ways to clear the conversation is open, if it is.
+    clear_menu: Option<(i32, i32)>,
+    /// What the confirm dialog is asking about, while it's open.
+    confirming: Option<Confirm>,
+    settings: Settings,
+    _settings_listener: ContextHandle<SettingsContext>,
+    settings_open: bool,
+    /// Who last cleared the conversation for everyone, said at the top of the list.
+    cleared_by: Option<String>,
+    /// Set once the retention sweep has cleared something, so the list can say so.
+    expired_cleared: bool,
+    receipts: ReceiptQueue,
+    /// The newest message read on this device, as saved.
+    last_read: Option<String>,
+    /// Where `last_read` was when we were last away: the divider goes after it. `None` once
+    /// it's been scrolled past, or when nothing came in meanwhile.
+    unread_after: Option<String>,
+    /// `None` where the browser has no IntersectionObserver; nothing is marked read then.
+    visible_messages: Option<VisibleMessages>,
+    _focus_listeners: Vec<EventListener>,
+    /// The window's `online` and `offline` events, passed on to `wss`.
+    _network_listeners: [EventListener; 2],
+    /// Clears each remote typer, by room and name, unless they're heard from again first.
+    typing_expiry: HashMap<(String, String), Timeout>,
+    /// When we last told the room we're typing; `None` once we've said we stopped.
+    typing_sent: Option<f64>,
+    /// How many of the newest messages are rendered; anything older waits for a scroll.
+    window: usize,
+    /// Set while a page of older messages asked for by scrolling up is on its way, so it
+    /// is rendered when it lands rather than left outside the window.
+    paging_back: bool,
+    /// Set when the next render adds or brings back rows that aren't new messages (a page
+    /// of history, a wider window, a changed filter), so the message log doesn't read
+    /// them out as if they'd just arrived.
+    quiet_log: bool,
+    /// The message whose row is the list's one tab stop, once focus has been in the list.
+    focused_message: Option<String>,
+    #[cfg(feature = "bench")]
+    bench: crate::bench::Timer,
+}
+
+impl Chat {
+    /// The conversation in the room being viewed.
+    fn state(&self) -> &ChatState {
+        self.app
+            .get(&self.room)
+            .expect("the room is opened when the component is created")
+    }
+
+    /// Applies `action` to our copy of the state, returning the slices it changed, and
+    /// queues it for the `Store`.
+    fn dispatch(&mut self, action: StoreAction) -> Vec<Slice> {
+        let changed = self.app.apply(action.clone());
+        self.dispatched(action, &changed);
+        changed
+    }
+
+    /// Applies a server frame as `dispatch` does, returning what it did.
+    fn dispatch_incoming(&mut self, event: BusEvent) -> Applied {
+        let (applied, changed) = self.app.apply_incoming(event.clone());
+        self.dispatched(StoreAction::Incoming(event), &changed);
+        applied
+    }
+
+    fn dispatched(&mut self, action: StoreAction, changed: &[Slice]) {
+        self.room_changed |= changed.contains(&Slice::Room(self.room.clone()));
+        self.dispatching.push(action);
+    }
+
+    /// Sends the `Store` everything applied in this update, as one change.
+    fn flush_dispatches(&mut self) {
+        let mut actions = std::mem::take(&mut self.dispatching);
+        let action = match actions.len() {
+            0 => return,
+            1 => actions.remove(0),
+            _ => StoreAction::Batch(actions),
+        };
+        // The `Store` sends the room back once for the lot, if it changed.
+        if std::mem::take(&mut self.room_changed) {
+            self.ahead += 1;
+        }
+        self.store.send(StoreRequest::Dispatch(action));
+    }
+
+    /// Index of the oldest message inside the render window.
+    fn window_start(&self) -> usize {
+        self.state().messages.len().saturating_sub(self.window)
+    }
+
+    /// The search text and sender the message list is narrowed to, if any.
+    fn row_filter(&self) -> (String, Option<&str>) {
+        let needle = if self.search_open {
+            self.search_query.trim().to_lowercase()
+        } else {
+            String::new()
+        };
+        (needle, self.sender_filter.as_deref())
+    }
+
+    /// Who to leave out of the message list: the muted, unless they're being shown.
+    fn muted_filter(&self) -> Option<&HashSet<String>> {
+        (!self.show_muted).then_some(&self.muted)
+    }
+
+    /// How many loaded messages muting leaves out, whether or not they're being shown.
+    fn muted_count(&self) -> usize {
+        let (needle, sender) = self.row_filter();
+        self.state()
+            .messages
+            .iter()
+            .filter(|m| self.muted.contains(&m.from) && passes_filters(m, &needle, sender, None))
+            .count()
+    }
+
+    /// The indices of the messages rendered as rows, oldest first.
+    fn rendered_rows(&self) -> Vec<usize> {
+        let (needle, sender) = self.row_filter();
+        (self.window_start()..self.state().messages.len())
+            .filter(|&i| {
+                passes_filters(
+                    &self.state().messages[i],
+                    &needle,
+                    sender,
+                    self.muted_filter(),
+                )
+            })
+            .collect()
+    }
+
+    /// Which of `rows` is the list's tab stop: the focused message's, or else the newest.
+    fn active_row(&self, rows: &[usize]) -> Option<usize> {
+        let focused = self.focused_message.as_deref().and_then(|id| {
+            rows.iter()
+                .copied()
+                .find(|&i| self.state().messages[i].id == id)
+        });
+        focused.or_else(|| rows.last().copied())
+    }
+
+    /// Focuses the first element matching `selector` inside the row of the message at
+    /// `index`, or the row itself when `selector` is empty.
+    fn focus_row(&self, index: usize, selector: &str) {
+        let target = self.messages_container.cast::<Element>().and_then(|c| {
+            c.query_selector(format!("[data-msg-index='{}'] {}", index, selector).trim_end())
+                .ok()?
+        });
+        if let Some(el) = target.and_then(|el| el.dyn_into::<HtmlElement>().ok()) {
+            let _ = el.focus();
+        }
+    }
+
+    /// Whether any message that would be shown sits before the render window.
+    fn has_earlier(&self) -> bool {
+        let (needle, sender) = self.row_filter();
+        self.state().messages[..self.window_start()]
+            .iter()
+            .any(|m| passes_filters(m, &needle, sender, self.muted_filter()))
+    }
+
+    /// Widens the render window to take in the message at `index`.
+    fn reveal(&mut self, index: usize) {
+        self.window = self.window.max(self.state().messages.len() - index);
+        self.quiet_log = true;
+    }
+
+    /// Widens the render window to take in the current search hit.
+    fn reveal_match(&mut self) {
+        let matches = search_matches(
+            &self.state().messages,
+            &self.search_query,
+            self.sender_filter.as_deref(),
+            self.muted_filter(),
+        );
+        if let Some(&i) = matches.get(self.search_index) {
+            self.reveal(i);
+        }
+    }
+
+    /// Everyone else typing in the room being viewed.
+    fn typers(&self) -> Vec<&str> {
+        let mut typers = self.state().typers(&self.room);
+        typers.retain(|&name| name != self.username && !self.muted.contains(name));
+        typers
+    }
+
+    /// Whether the sidebar shows placeholders: until the first user list, while it's on
+    /// its way. Once we've stopped trying, or are offline, the banner says so instead.
+    fn users_pending(&self) -> bool {
+        !self.initialized && matches!(self.conn_state, ConnState::Connecting | ConnState::Open)
+    }
+
+    /// Whether the conversation shows placeholders: while there's nothing to show yet, and
+    /// we're connecting or history's on its way. Messages from the local store, or the
+    /// server's first page even if it's empty, end them.
+    fn messages_pending(&self) -> bool {
+        !self.history_loaded
+            && self.state().messages.is_empty()
+            && (self.conn_state == ConnState::Connecting || self.history_loading)
+    }
+
+    fn online_count(&self) -> usize {
+        self.state()
+            .users
+            .iter()
+            .filter(|u| u.status == Presence::Active)
+            .count()
+    }
+
+    fn send(&self, message: &WebSocketMessage) -> bool {
+        self.try_send(message).is_ok()
+    }
+
+    fn try_send(&self, message: &WebSocketMessage) -> Result<(), SendError> {
+        self.wss
+            .send(serde_json::to_string(message).unwrap())
+            .inspect_err(|e| log::debug!("error sending to channel: {:?}", e))
+    }
+
+    /// Draws the user's attention to someone else's new message, as their settings ask.
+    fn announce(&self, message: &MessageData) {
+        if message.from == self.username || self.muted.contains(&message.from) {
+            return;
+        }
+        if self.settings.sound {
+            notify::chime();
+        }
+        if self.settings.notifications && gloo_utils::document().hidden() {
+            notify::show(&message.from, &snippet(&message.message));
+        }
+    }
+
+    /// Queues receipts for the messages on screen and sends them, as long as someone's
+    /// actually looking: a background tab or an unfocused window doesn't count. Also moves
+    /// the last read message along, and the divider with it. Returns whether the divider
+    /// went.
+    fn mark_visible_read(&mut self) -> bool {
+        let document = gloo_utils::document();
+        if document.hidden() {
+            // Away: whatever comes in from now on is new.
+            if self.unread_after.is_none() {
+                self.unread_after = self.last_read.clone();
+            }
+            return false;
+        }
+        if !document.has_focus().unwrap_or(false) {
+            return false;
+        }
+        let visible = match &self.visible_messages {
+            Some(visible) => visible.ids(),
+            None => return false,
+        };
+        let divider_gone = self.advance_last_read(&visible);
+        for id in visible {
+            // Read on another visit, or from another tab: the server already knows.
+            let already_read = self
+                .state()
+                .messages
+                .iter()
+                .any(|m| m.id == id && m.read_by.contains(&self.username));
+            if !already_read {
+                self.receipts.mark(&id);
+            }
+        }
+        self.flush_receipts();
+        divider_gone
+    }
+
+    /// Saves the newest of the `visible` messages as read, if it's newer than the last,
+    /// and drops the divider once every row on screen is below it, or nothing's new after
+    /// all. Returns whether the divider went.
+    fn advance_last_read(&mut self, visible: &[String]) -> bool {
+        let messages = &self.state().messages;
+        let index_of = |id: &str| messages.iter().position(|m| m.id == id);
+        let rows: Vec<usize> = visible.iter().filter_map(|id| index_of(id)).collect();
+        let saved = self.last_read.as_deref().and_then(index_of);
+        let read = rows
+            .iter()
+            .max()
+            .filter(|&&newest| saved.is_none_or(|saved| newest > saved))
+            .map(|&newest| messages[newest].id.clone());
+
+        let after = match &self.unread_after {
+            Some(after) => after,
+            None => return false,
+        };
+        let gone = match unread_boundary(messages, after, &self.username) {
+            Some(boundary) => !rows.is_empty() && rows.iter().all(|&row| row > boundary),
+            // Nothing new, as long as what it's measured from has loaded; history may not
+            // have yet.
+            None => messages
+                .iter()
+                .any(|m| m.id == *after || m.from == self.username),
+        };
+        if let Some(id) = read {
+            last_read::save(&id);
+            self.last_read = Some(id);
+        }
+        if gone {
+            self.unread_after = None;
+        }
+        gone
+    }
+
+    /// The strip along the sidebar's inner edge that resizes it: dragged with the pointer,
+    /// or with the arrow keys once focused.
+    fn view_resize_handle(&self, ctx: &Context<Self>) -> Html {
+        const KEY_STEP_PX: i32 = 16;
+        let rtl = self.i18n.rtl();
+        // The sidebar starts at the window's left edge, or its right one in RTL.
+        let width_at = move |x: i32| {
+            if rtl {
+                let window = gloo_utils::window().inner_width().ok();
+                window.and_then(|w| w.as_f64()).unwrap_or_default() as i32 - x
+            } else {
+                x
+            }
+        };
+        let onpointerdown = ctx.link().callback(|e: PointerEvent| {
+            e.prevent_default();
+            // Moves keep coming here even once the pointer's off the strip.
+            if let Some(handle) = e.target_dyn_into::<Element>() {
+                let _ = handle.set_pointer_capture(e.pointer_id());
+            }
+            Msg::StartResize
+        });
+        let onpointermove = {
+            let resizing = self.resizing;
+            ctx.link().batch_callback(move |e: PointerEvent| {
+                resizing.then(|| Msg::ResizeSidebar(width_at(e.client_x())))
+            })
+        };
+        let onpointerup = ctx.link().callback(|_: PointerEvent| Msg::EndResize);
+        let onkeydown = {
+            let width = self.sidebar_width;
+            ctx.link().batch_callback(move |e: KeyboardEvent| {
+                // Right widens, whichever side the sidebar is on.
+                let step = match e.key().as_str() {
+                    "ArrowRight" => KEY_STEP_PX,
+                    "ArrowLeft" => -KEY_STEP_PX,
+                    _ => return None,
+                };
+                e.prevent_default();
+                Some(Msg::ResizeSidebar(width + if rtl { -step } else { step }))
+            })
+        };
+
+        html! {
+            <div role="separator" aria-orientation="vertical" aria-label={self.i18n.t("resize_users")} tabindex="0"
+                aria-valuenow={self.sidebar_width.to_string()}
+                aria-valuemin={sidebar::MIN_WIDTH.to_string()} aria-valuemax={sidebar::MAX_WIDTH.to_string()}
+                class={classes!(
+                    "flex-none", "w-1", "h-screen", "cursor-col-resize", "touch-none",
+                    "hover:bg-primary-light", "focus:bg-primary-light", "outline-none", "resize-handle",
+                    self.resizing.then_some("bg-primary-light"),
+                )}
+                {onpointerdown} {onpointermove} {onpointerup}
+                onpointercancel={ctx.link().callback(|_: PointerEvent| Msg::EndResize)}
+                {onkeydown}>
+            </div>
+        }
+    }
+
+    /// Does what the user just confirmed.
+    fn perform(&mut self, ctx: &Context<Self>, action: Confirm) {
+        match action {
+            Confirm::ClearHistory => {
+                self.dispatch(StoreAction::Cleared(self.room.clone()));
+                self.cleared();
+                self.cleared_by = None;
+                clear_store();
+                // Nothing left to be scrolled to, or to be new.
+                self.window = RENDER_WINDOW;
+                self.paging_back = false;
+                self.focused_message = None;
+                self.jump_to = None;
+                self.replying_to = None;
+                self.unread_after = None;
+            }
+            Confirm::ClearRoom => {
+                let message = WebSocketMessage {
+                    message_type: MsgTypes::ClearRoom,
+                    data: None,
+                    data_array: None,
+                    id: None,
+                    reply_to: None,
+                    attachment: None,
+                };
+                // Our own copy goes when the server's confirmation comes back.
+                if !self.send(&message) {
+                    log::warn!("couldn't send room clear");
+                }
+            }
+            Confirm::Delete(id) => {
+                // A message still in the outbox never reached the server, so there's
+                // nothing to tell it.
+                let sent = !self.outbox.contains(&id);
+                self.outbox.retain(|o| *o != id);
+                self.save_outbox();
+                if sent {
+                    let message = WebSocketMessage {
+                        message_type: MsgTypes::Delete,
+                        data: Some(id.clone()),
+                        data_array: None,
+                        id: None,
+                        reply_to: None,
+                        attachment: None,
+                    };
+                    if !self.send(&message) {
+                        log::warn!("couldn't send delete for {}", id);
+                    }
+                }
+                if !self.fade_out(ctx, &id) {
+                    self.dispatch(StoreAction::Deleted(self.room.clone(), id.clone()));
+                    self.deleted(id);
+                }
+            }
+            Confirm::Kick(nick) => {
+                let message = WebSocketMessage {
+                    message_type: MsgTypes::Kick,
+                    data: Some(nick),
+                    data_array: None,
+                    id: None,
+                    reply_to: None,
+                    attachment: None,
+                };
+                if !self.send(&message) {
+                    log::warn!("couldn't send kick");
+                }
+            }
+        }
+    }
+
+    /// Sends every queued receipt in one frame. If it can't go now they stay queued for
+    /// the next flush, which happens at the latest when the socket reopens.
+    fn flush_receipts(&mut self) {
+        if self.conn_state != ConnState::Open {
+            return;
+        }
+        let ids = self.receipts.take();
+        if ids.is_empty() {
+            return;
+        }
+        let receipt = ReadReceipt {
+            ids,
+            from: self.username.clone(),
+        };
+        let message = WebSocketMessage {
+            message_type: MsgTypes::Read,
+            data: Some(serde_json::to_string(&receipt).unwrap()),
+            data_array: None,
+            id: None,
+            reply_to: None,
+            attachment: None,
+        };
+        if !self.send(&message) {
+            self.receipts.restore(receipt.ids);
+        }
+    }
+
+    /// Asks the server to toggle our reaction. It's shown once the server broadcasts it
+    /// back, so everyone sees the same counts.
+    fn send_reaction(&self, id: String, emoji: String) {
+        let reaction = Reaction {
+            id,
+            from: self.username.clone(),
+            emoji,
+        };
+        let message = WebSocketMessage {
+            message_type: MsgTypes::Reaction,
+            data: Some(serde_json::to_string(&reaction).unwrap()),
+            data_array: None,
+            id: None,
+            reply_to: None,
+            attachment: None,
+        };
+        if !self.send(&message) {
+            log::warn!("couldn't send reaction to {}", reaction.id);
+        }
+    }
+
+    /// The server answers by telling everyone, us included, so `pinned` changes then.
+    fn send_pin(&self, id: String, pinned: bool) {
+        let notice = PinNotice {
+            id,
+            pinned,
+            from: self.username.clone(),
+        };
+        let message = WebSocketMessage {
+            message_type: MsgTypes::Pin,
+            data: Some(serde_json::to_string(&notice).unwrap()),
+            data_array: None,
+            id: None,
+            reply_to: None,
+            attachment: None,
+        };
+        if !self.send(&message) {
+            log::warn!("couldn't send pin for {}", notice.id);
+        }
+    }
+
+    /// Puts the room's draft back in the message box, unless something's typed there.
+    fn restore_draft(&self) {
+        let Some(input) = self.chat_input.cast::<HtmlTextAreaElement>() else {
+            return;
+        };
+        if let Some(draft) = drafts::load(&self.username, &self.room) {
+            if input.value().is_empty() {
+                input.set_value(&draft);
+                fit_input(&input);
+            }
+        }
+    }
+
+    /// Forgets the room's draft, its text having been sent or dealt with.
+    fn discard_draft(&mut self) {
+        self.draft_save = None;
+        drafts::clear(&self.username, &self.room);
+    }
+
+    /// Tells the room whether we're typing. Saying we are is repeated at most every
+    /// `TYPING_REFRESH_MS`, and saying we aren't only follows having said we are.
+    fn announce_typing(&mut self, typing: bool) {
+        if self.conn_state != ConnState::Open {
+            return;
+        }
+        let now = js_sys::Date::now();
+        let due = match (typing, self.typing_sent) {
+            (true, Some(at)) => now - at >= TYPING_REFRESH_MS as f64,
+            (true, None) => true,
+            (false, sent) => sent.is_some(),
+        };
+        if !due {
+            return;
+        }
+        let notice = TypingNotice {
+            room: self.room.clone(),
+            from: self.username.clone(),
+            typing,
+        };
+        let message = WebSocketMessage {
+            message_type: MsgTypes::Typing,
+            data: Some(serde_json::to_string(&notice).unwrap()),
+            data_array: None,
+            id: None,
+            reply_to: None,
+            attachment: None,
+        };
+        if self.send(&message) {
+            self.typing_sent = typing.then_some(now);
+        }
+    }
+
+    /// Starts or cancels the timer that clears a remote typer.
+    fn track_typer(&mut self, ctx: &Context<Self>, notice: TypingNotice) {
+        let key = (notice.room, notice.from);
+        if !notice.typing {
+            self.typing_expiry.remove(&key);
+            return;
+        }
+        let link = ctx.link().clone();
+        let (room, from) = key.clone();
+        self.typing_expiry.insert(
+            key,
+            Timeout::new(TYPING_TIMEOUT_MS, move || {
+                link.send_message(Msg::TypingExpired(room, from))
+            }),
+        );
+    }
+
+    fn register(&self) {
+        let message = WebSocketMessage {
+            message_type: MsgTypes::Register,
+            data: Some(self.username.clone()),
+            data_array: None,
+            id: None,
+            reply_to: None,
+            attachment: None,
+        };
+
+        if self.send(&message) {
+            log::debug!("message sent successfully");
+        }
+        // The server assumes everyone who registers is active.
+        if self.presence != Presence::Active {
+            self.send_presence();
+        }
+    }
+
+    /// Asks the server for who's connected now. Registering makes it tell everyone anyway,
+    /// but not if it turned us away, or if the broadcast was lost in the reconnect.
+    fn request_users(&self) {
+        let message = WebSocketMessage {
+            message_type: MsgTypes::Users,
+            data: None,
+            data_array: None,
+            id: None,
+            reply_to: None,
+            attachment: None,
+        };
+        self.send(&message);
+    }
+
+    fn send_presence(&self) {
+        let message = WebSocketMessage {
+            message_type: MsgTypes::Status,
+            data: Some(self.presence.as_str().into()),
+            data_array: None,
+            id: None,
+            reply_to: None,
+            attachment: None,
+        };
+        self.send(&message);
+    }
+
+    fn set_presence(&mut self, presence: Presence) -> bool {
+        self.away.set(presence == Presence::Away);
+        if self.presence == presence {
+            return false;
+        }
+        self.presence = presence;
+        if self.conn_state == ConnState::Open {
+            self.send_presence();
+        }
+        true
+    }
+
+    /// Pages in the messages just before the oldest one we have, from the local store
+    /// while it has any and from the server after that.
+    fn request_history(&mut self, ctx: &Context<Self>) {
+        if self.history_loading {
+            return;
+        }
+
+        let before = self
+            .state()
+            .messages
+            .iter()
+            .filter(|m| m.status == DeliveryStatus::Delivered)
+            .map(|m| m.time)
+            .min();
+        if self.stored_has_more {
+            self.history_loading = true;
+            ctx.link().send_future(load_stored(before));
+            return;
+        }
+
+        if !self.has_more_history || self.conn_state != ConnState::Open {
+            return;
+        }
+        let message = WebSocketMessage {
+            message_type: MsgTypes::History,
+            data: before.map(|t| t.to_string()),
+            data_array: None,
+            id: None,
+            reply_to: None,
+            attachment: None,
+        };
+        self.history_loading = self.send(&message);
+    }
+
+    /// Asks the server for the history around the message a permalink points at. If
+    /// we're not connected, it's asked once we are.
+    fn request_around(&mut self) {
+        let message = WebSocketMessage {
+            message_type: MsgTypes::History,
+            data: None,
+            data_array: None,
+            id: self.seek.clone(),
+            reply_to: None,
+            attachment: None,
+        };
+        if self.seek.is_some() {
+            self.seek_asked = true;
+            if self.conn_state == ConnState::Open && self.send(&message) {
+                self.history_loading = true;
+            }
+        }
+    }
+
+    /// Goes to the message a permalink points at, if it's loaded now, and stops looking.
+    fn seek_loaded(&mut self, ctx: &Context<Self>) -> bool {
+        match self.seek.take() {
+            Some(id) if self.state().has(&id) => {
+                self.seek_asked = false;
+                self.jump(ctx, id);
+                true
+            }
+            seek => {
+                self.seek = seek;
+                false
+            }
+        }
+    }
+
+    /// Scrolls to the message with this id once it's rendered, and highlights it for a
+    /// moment.
+    fn jump(&mut self, ctx: &Context<Self>, id: String) {
+        // Filters could be hiding it; drop them so it's there to scroll to.
+        self.show_saved = false;
+        self.pins_open = false;
+        self.sender_filter = None;
+        self.search_open = false;
+        self.search_query.clear();
+        self.stored_hits.clear();
+        if let Some(i) = self.state().messages.iter().position(|m| m.id == id) {
+            self.reveal(i);
+        }
+        let link = ctx.link().clone();
+        let done = Timeout::new(JUMP_HIGHLIGHT_MS, move || {
+            link.send_message(Msg::JumpHighlightDone)
+        });
+        self.jump_highlight = Some((id.clone(), done));
+        self.jump_to = Some(id);
+    }
+
+    /// Asks the server for its newest page, to catch up on whatever was said while we
+    /// were away. Overlap with what we already have is dropped when it arrives.
+    fn request_latest(&mut self) {
+        let message = WebSocketMessage {
+            message_type: MsgTypes::History,
+            data: None,
+            data_array: None,
+            id: None,
+            reply_to: None,
+            attachment: None,
+        };
+        if self.send(&message) {
+            self.history_loading = true;
+        }
+    }
+
+    /// When a message was sent, as shown in its bubble: how long ago while it's recent,
+    /// its clock time after that.
+    fn message_time(&self, ts: u64) -> String {
+        match relative_time(ts, self.now) {
+            Some(Ago::JustNow) => self.i18n.t("just_now").to_string(),
+            Some(Ago::Minutes(m)) => format!("{}{}", m, self.i18n.t("minutes_short")),
+            Some(Ago::Hours(h)) => format!("{}{}", h, self.i18n.t("hours_short")),
+            None => clock_time(ts, self.settings.clock),
+        }
+    }
+
+    /// A text message's body, cut short if it's long and the reader hasn't asked for all of
+    /// it. While searching it's shown whole, so no hit is hidden.
+    fn view_body(&self, ctx: &Context<Self>, m: &MessageData, query: &str) -> Html {
+        let blocks = classify_message(&m.message);
+        let folded = if query.trim().is_empty() {
+            fold(&blocks, ctx.props().fold)
+        } else {
+            None
+        };
+        let Some(folded) = folded else {
+            return render_blocks(blocks, query, self.i18n);
+        };
+        let expanded = self.expanded.contains(&m.id);
+        let onclick = {
+            let id = m.id.clone();
+            ctx.link()
+                .callback(move |_| Msg::ToggleExpanded(id.clone()))
+        };
+        html! {
+            <>
+                { render_blocks(if expanded { blocks } else { folded }, query, self.i18n) }
+                <button class="text-xs font-medium underline opacity-80 hover:opacity-100 fold-toggle"
+                    aria-expanded={expanded.to_string()} {onclick}>
+                    { self.i18n.t(if expanded { "show_less" } else { "show_more" }) }
+                </button>
+            </>
+        }
+    }
+
+    /// What the sidebar's card for `u` says: who they are, whether they're here, since
+    /// when, and when they last did anything, with a button to mention them.
+    fn view_user_card(&self, ctx: &Context<Self>, u: &UserProfile) -> Html {
+        let me = u.name == self.username;
+        let role = if me {
+            Some(self.i18n.t("role_you"))
+        } else if u.admin {
+            Some(self.i18n.t("role_admin"))
+        } else {
+            None
+        };
+        let (dot, presence) = match u.status {
+            Presence::Active => ("bg-green-500", self.i18n.t("online")),
+            Presence::Away => ("bg-amber-400", self.i18n.t("away")),
+        };
+        html! {
+            <div class="flex flex-col gap-1 user-card">
+                <div class="flex items-center gap-2">
+                    <Avatar name={u.name.clone()} size={AvatarSize::Small} decorative=true/>
+                    <span class={classes!("font-semibold", "truncate", user_color(&u.name))}>{u.name.clone()}</span>
+                    if let Some(role) = role {
+                        <span class="ms-auto px-2 py-0.5 rounded-full text-xs bg-gray-100 text-gray-700">{role}</span>
+                    }
+                </div>
+                <div class="flex items-center gap-1 text-gray-700">
+                    <span class={classes!("w-2", "h-2", "rounded-full", dot)}></span>
+                    {presence}
+                </div>
+                if let Some(activity) = u.activity {
+                    <div class="text-xs text-gray-500">{self.i18n.fill("online_since", &[&clock_time(activity.since, self.settings.clock)])}</div>
+                    <div class="text-xs text-gray-500">{self.i18n.fill("last_active", &[&self.message_time(activity.last_active)])}</div>
+                }
+                if !me {
+                    <button class="mt-1 self-start px-2 py-1 rounded bg-primary-dark text-white text-xs hover:bg-primary mention-button"
+                        onclick={ctx.link().callback({
+                            let name = u.name.clone();
+                            move |_| Msg::Mention(name.clone())
+                        })}>
+                        {self.i18n.t("mention")}
+                    </button>
+                }
+            </div>
+        }
+    }
+
+    /// The parent of a reply, quoted in one line, which scrolls to it when clicked. A parent
+    /// that's been deleted or isn't loaded can't be shown, so it just says so.
+    fn view_reply_quote(&self, ctx: &Context<Self>, parent: &str, class: &'static str) -> Html {
+        let Some(p) = self.state().messages.iter().find(|m| m.id == parent) else {
+            return html! {
+                <span class={classes!(class, "italic", "reply-quote")}>{self.i18n.t("original_unavailable")}</span>
+            };
+        };
+        let id = p.id.clone();
+        html! {
+            <button class={classes!(class, "w-full", "text-start", "truncate", "hover:underline", "reply-quote")}
+                title={self.i18n.t("show_original")}
+                onclick={ctx.link().callback(move |_| Msg::JumpTo(id.clone()))}>
+                <span class="font-semibold">{format!("{}: ", p.from)}</span>
+                <span dir="auto">{snippet(&p.message)}</span>
+            </button>
+        }
+    }
+
+    /// The row of the message at `i`, laid out for the chosen density. The `active` row
+    /// is the list's tab stop.
+    fn view_message(
+        &self,
+        ctx: &Context<Self>,
+        i: usize,
+        sender: Option<&UserProfile>,
+        search_query: &str,
+        is_match: bool,
+        active: bool,
+    ) -> Html {
+        let m = &self.state().messages[i];
+        let compact = self.density == Density::Compact;
+        let is_current_user = m.from == self.username;
+        let is_starred = self.starred.contains(&m.id);
+        let open_menu = {
+            let (id, from, text) = (m.id.clone(), m.from.clone(), m.message.clone());
+            ctx.link().batch_callback(move |e: MouseEvent| {
+                // Links keep the browser's own menu, for opening or copying them.
+                let on_link = e
+                    .target_dyn_into::<Element>()
+                    .and_then(|el| el.closest("a").ok().flatten())
+                    .is_some();
+                if e.type_() == "contextmenu" && on_link {
+                    return None;
+                }
+                e.prevent_default();
+                Some(Msg::OpenMenu(MenuTarget {
+                    x: e.client_x(),
+                    y: e.client_y(),
+                    id: id.clone(),
+                    from: from.clone(),
+                    text: text.clone(),
+                }))
+            })
+        };
+        let body = m.message.trim();
+        let image = image_url(body, &ctx.props().image_hosts);
+        // An image we won't inline is still offered as a link.
+        let image_link = if image.is_none() && looks_like_image(body) {
+            safe_url(body)
+        } else {
+            None
+        };
+        // Only a link that's safe to follow makes a card; otherwise the text says it all.
+        let file = m
+            .attachment
+            .as_ref()
+            .and_then(|a| Some((a, safe_url(&a.url)?)));
+        // Compact rows stay one line where they can, so they go without link previews.
+        let preview_url = if compact
+            || image.is_some()
+            || image_link.is_some()
+            || file.is_some()
+            || ctx.props().unfurl_endpoint.is_empty()
+        {
+            None
+        } else {
+            first_url(&m.message).and_then(safe_url)
+        };
+
+        // Only the active row's toolbar is in the tab order, so tabbing through the list
+        // doesn't stop at every message's hidden buttons.
+        let tab = (!active).then_some("-1");
+        let toolbar = html! {
+            <MessageToolbar text={m.message.clone()} tabbable={active} i18n={self.i18n}>
+                <button class={classes!("p-1", "rounded", "hover:bg-gray-100", if is_starred { "text-yellow-500" } else { "hover:text-gray-800" })}
+                    tabindex={tab} title={self.i18n.t(if is_starred { "unstar" } else { "star" })}
+                    onclick={{ let id = m.id.clone(); ctx.link().callback(move |_| Msg::ToggleStar(id.clone())) }}>
+                    {star_icon(is_starred)}
+                </button>
+                if m.status == DeliveryStatus::Delivered {
+                    <button class="p-1 rounded hover:bg-gray-100 hover:text-gray-800 leading-none" title={self.i18n.t("reply")} tabindex={tab}
+                        onclick={{ let id = m.id.clone(); ctx.link().callback(move |_| Msg::ReplyTo(id.clone())) }}>
+                        {"↩"}
+                    </button>
+                    <button class="p-1 rounded hover:bg-gray-100 hover:text-gray-800 leading-none" title={self.i18n.t("add_reaction")} tabindex={tab}
+                        onclick={{ let id = m.id.clone(); ctx.link().callback(move |_| Msg::ToggleEmojiPicker(PickerMode::React(id.clone()))) }}>
+                        {"☺"}
+                    </button>
+                }
+                <button class="p-1 rounded hover:bg-gray-100 hover:text-gray-800 leading-none"
+                    tabindex={tab} title={self.i18n.t("more_actions")} onclick={open_menu.clone()}>
+                    {"⋯"}
+                </button>
+            </MessageToolbar>
+        };
+        let sender_link = |class: &'static str| {
+            html! {
+                <button class={classes!(class, user_color(&m.from))}
+                    title={self.i18n.fill("show_only", &[&m.from])}
+                    onclick={ctx.link().callback({
+                        let name = m.from.clone();
+                        move |_| Msg::FilterSender(Some(name.clone()))
+                    })}>
+                    {highlight(&m.from, search_query)}
+                </button>
+            }
+        };
+        let content = html! {
+            if let Some((attachment, href)) = file {
+                { file_card(attachment, href, compact, self.i18n) }
+            } else if let Some(src) = image {
+                <img class={classes!("max-w-full", "object-contain", "message-image", if compact { "rounded max-h-40" } else { "rounded-xl max-h-80" })}
+                     src={src.to_string()} alt={if is_current_user { self.i18n.t("image_you_sent").to_string() } else { self.i18n.fill("image_from", &[&m.from]) }}
+                     loading="lazy"/>
+            } else if let Some(href) = image_link {
+                <a class="text-sm underline break-all" href={href.to_string()}
+                   target="_blank" rel="noopener noreferrer">{href}</a>
+            } else {
+                { self.view_body(ctx, m, search_query) }
+            }
+        };
+        let status = match m.status {
+            DeliveryStatus::Pending if self.outbox.contains(&m.id) => html! {
+                <>{self.i18n.t("queued")}{status_icon(m.status)}</>
+            },
+            DeliveryStatus::Pending => html! {
+                <>{self.i18n.t("sending")}{status_icon(m.status)}</>
+            },
+            DeliveryStatus::Failed => {
+                let id = m.id.clone();
+                html! {
+                    <button class="flex items-center gap-1 font-medium"
+                            title={self.i18n.t("retry_sending")}
+                            onclick={ctx.link().callback(move |_| Msg::Retry(id.clone()))}>
+                        {self.i18n.t("failed_retry")}{status_icon(m.status)}
+                    </button>
+                }
+            }
+            DeliveryStatus::Delivered if is_current_user && !m.read_by.is_empty() => html! {
+                <span title={self.i18n.fill("seen_by", &[&m.read_by.join(", ")])}>
+                    {self.i18n.t("seen")}
+                    {status_icon(m.status)}
+                </span>
+            },
+            DeliveryStatus::Delivered => html! {
+                <>
+                    // Compact rows lead with the clock time instead.
+                    if !compact {
+                        <span title={clock_time(m.time, self.settings.clock)}>{self.message_time(m.time)}</span>
+                    }
+                    if is_current_user {
+                        {status_icon(m.status)}
+                    }
+                </>
+            },
+        };
+        let reactions = if m.reactions.is_empty() {
+            html! {}
+        } else {
+            html! {
+                <div class={classes!(
+                    "flex", "flex-wrap", "gap-1", "reactions",
+                    if compact { "inline-flex ms-2 align-middle" } else { "mt-1" },
+                    (is_current_user && !compact).then_some("justify-end"),
+                )}>
+                    {
+                        m.reactions.iter().map(|(emoji, users)| {
+                            let mine = users.contains(&self.username);
+                            let react = {
+                                let (id, emoji) = (m.id.clone(), emoji.clone());
+                                ctx.link().callback(move |_| Msg::React(id.clone(), emoji.clone()))
+                            };
+                            html! {
+                                <button title={users.join(", ")}
+                                    onclick={react}
+                                    class={classes!("flex", "items-center", "gap-1", "rounded-full", "text-xs", "border", "reaction-chip",
+                                        if compact { "px-1.5" } else { "px-2 py-0.5" },
+                                        if mine { "bg-blue-50 border-blue-300 text-blue-700" } else { "bg-white border-gray-200 text-gray-600 hover:bg-gray-50" })}>
+                                    <span>{emoji.clone()}</span>
+                                    <span>{users.len()}</span>
+                                </button>
+                            }
+                        }).collect::<Html>()
+                    }
+                </div>
+            }
+        };
+        let row_id = (!is_current_user).then(|| m.id.clone());
+        let jumped = self
+            .jump_highlight
+            .as_ref()
+            .is_some_and(|(id, _)| *id == m.id)
+            .then_some("jump-highlight");
+        let motion = if self.leaving.contains_key(&m.id) {
+            Some("message-leave")
+        } else {
+            self.entering.contains(&m.id).then_some("message-enter")
+        };
+
+        if compact {
+            return html! {
+                <div key={m.id.clone()} data-msg-index={i.to_string()} data-msg-id={row_id}
+                    tabindex={if active { "0" } else { "-1" }}
+                    class={classes!(
+                        "flex", "items-baseline", "gap-2", "px-2", "py-0.5", "rounded", "text-sm",
+                        "hover:bg-gray-50", "focus:outline-none", "focus-visible:bg-primary/10", "message-row", "compact-row",
+                        (m.status == DeliveryStatus::Pending).then_some("opacity-60"),
+                        (m.status == DeliveryStatus::Failed).then_some("ring-2 ring-red-400"),
+                        is_match.then_some("ring-2 ring-yellow-400"),
+                        jumped,
+                        motion,
+                    )}>
+                    <span class="flex-none w-10 text-xs text-gray-400 tabular-nums message-time">
+                        {clock_time(m.time, self.settings.clock)}
+                    </span>
+                    if is_current_user {
+                        <span class={classes!("flex-none", "font-semibold", user_color(&m.from))}>{highlight(&m.from, search_query)}</span>
+                    } else {
+                        { sender_link("flex-none font-semibold hover:underline sender-filter-link") }
+                    }
+                    <div class="relative min-w-0 grow break-words message-bubble" oncontextmenu={open_menu}>
+                        {toolbar}
+                        if is_starred {
+                            <span class="text-yellow-400 float-end" title={self.i18n.t("starred")}>{star_icon(true)}</span>
+                        }
+                        if let Some(parent) = &m.reply_to {
+                            { self.view_reply_quote(ctx, parent, "block text-xs") }
+                        }
+                        {content}
+                        {reactions}
+                    </div>
+                    if is_current_user {
+                        <div class="flex-none flex items-center gap-1 text-xs text-gray-400">{status}</div>
+                    }
+                </div>
+            };
+        }
+
+        let avatar = html! {
+            <Avatar name={m.from.clone()} status={AvatarStatus::from(sender.map(|u| u.status))} i18n={self.i18n}
+                class={classes!("self-end", if is_current_user { "ms-3" } else { "me-3" })}/>
+        };
+        html! {
+            <div key={m.id.clone()} data-msg-index={i.to_string()} data-msg-id={row_id}
+                tabindex={if active { "0" } else { "-1" }}
+                class={classes!(
+                    "flex", "rounded-2xl", "focus:outline-none", "focus-visible:bg-primary/10", "message-row",
+                    is_current_user.then_some("justify-end"),
+                    motion,
+                )}>
+                if !is_current_user {
+                    { avatar.clone() }
+                }
+
+                <div class="flex flex-col">
+                <div class={classes!(
+                    "relative",
+                    bubble_class(is_current_user, image.is_some()),
+                    (m.status == DeliveryStatus::Pending).then_some("opacity-60"),
+                    (m.status == DeliveryStatus::Failed).then_some("ring-2 ring-red-400"),
+                    is_match.then_some("ring-2 ring-yellow-400"),
+                    jumped,
+                )} oncontextmenu={open_menu}>
+                    {toolbar}
+                    if is_current_user {
+                        // Our bubbles don't show a name, but read out they need one.
+                        <span class="sr-only">{self.i18n.t("you_said")}</span>
+                    } else {
+                        { sender_link("block font-medium text-sm mb-1 hover:underline sender-filter-link") }
+                    }
+                    if let Some(parent) = &m.reply_to {
+                        { self.view_reply_quote(ctx, parent, if is_current_user {
+                            "block mb-1 ps-2 border-s-2 border-white/60 text-xs text-white/80"
+                        } else {
+                            "block mb-1 ps-2 border-s-2 border-gray-300 text-xs text-gray-500"
+                        }) }
+                    }
+
+                    {content}
+
+                    <div class="flex items-center justify-end gap-1 text-xs mt-1 message-time">
+                        if is_starred {
+                            <span class="text-yellow-400 me-auto" title={self.i18n.t("starred")}>{star_icon(true)}</span>
+                        }
+                        {status}
+                    </div>
+                </div>
+                {reactions}
+                if let Some(url) = preview_url {
+                    <LinkPreviewCard url={url.to_string()} endpoint={ctx.props().unfurl_endpoint.clone()}
+                        image_hosts={ctx.props().image_hosts.clone()}/>
+                }
+                </div>
+
+                if is_current_user {
+                    {avatar}
+                }
+            </div>
+        }
+    }
+
+    fn view_search_bar(&self, ctx: &Context<Self>, matches: &[usize]) -> Html {
+        let oninput = ctx.link().callback(|e: InputEvent| {
+            Msg::SearchInput(e.target_unchecked_into::<HtmlInputElement>().value())
+        });
+        // Enter walks back to older hits, Shift+Enter forward; Escape closes the bar.
+        let onkeydown = ctx
+            .link()
+            .batch_callback(|e: KeyboardEvent| match e.key().as_str() {
+                "Enter" => Some(Msg::SearchStep(if e.shift_key() { 1 } else { -1 })),
+                "Escape" => Some(Msg::ToggleSearch),
+                _ => None,
+            });
+        let count = if self.search_query.trim().is_empty() {
+            String::new()
+        } else if matches.is_empty() {
+            self.i18n.t("no_matches").to_string()
+        } else {
+            let current = self.search_index.min(matches.len() - 1);
+            self.i18n
+                .fill("match_count", &[&(current + 1), &matches.len()])
+        };
+
+        html! {
+            <div class="w-full px-6 py-2 bg-white border-b border-gray-200 flex items-center gap-2 search-bar">
+                <input ref={self.search_input.clone()} type="search" placeholder={self.i18n.t("search_placeholder")}
+                    aria-label={self.i18n.t("search_placeholder")}
+                    value={self.search_query.clone()} {oninput} {onkeydown}
+                    class="grow py-1.5 px-3 bg-gray-100 rounded-full outline-none text-sm"/>
+                <span class="text-xs text-gray-500 whitespace-nowrap">{count}</span>
+                <button class="p-1 text-gray-500 hover:text-gray-800 disabled:opacity-30" title={self.i18n.t("previous_match")}
+                    disabled={matches.is_empty()} onclick={ctx.link().callback(|_| Msg::SearchStep(-1))}>
+                    <svg viewBox="0 0 24 24" class="fill-current w-4 h-4"><path d="M7.41 15.41 12 10.83l4.59 4.58L18 14l-6-6-6 6z"></path></svg>
+                </button>
+                <button class="p-1 text-gray-500 hover:text-gray-800 disabled:opacity-30" title={self.i18n.t("next_match")}
+                    disabled={matches.is_empty()} onclick={ctx.link().callback(|_| Msg::SearchStep(1))}>
+                    <svg viewBox="0 0 24 24" class="fill-current w-4 h-4"><path d="M7.41 8.59 12 13.17l4.59-4.58L18 10l-6 6-6-6z"></path></svg>
+                </button>
+                <button class="p-1 text-gray-500 hover:text-gray-800" title={self.i18n.t("close_search")}
+                    onclick={ctx.link().callback(|_| Msg::ToggleSearch)}>
+                    <svg viewBox="0 0 24 24" class="fill-current w-4 h-4"><path d="M19 6.41 17.59 5 12 10.59 6.41 5 5 6.41 10.59 12 5 17.59 6.41 19 12 13.41 17.59 19 19 17.59 13.41 12z"></path></svg>
+                </button>
+            </div>
+        }
+    }
+
+    /// Matches for the search from the local store that aren't loaded yet, set apart from
+    /// the ones in the conversation. Clicking one loads it with what came around it.
+    fn view_stored_hits(&self, ctx: &Context<Self>) -> Html {
+        if self.stored_hits.is_empty() {
+            return html! {};
+        }
+        html! {
+            <div class="w-full max-h-48 overflow-y-auto bg-gray-50 border-b border-gray-200 stored-hits">
+                <div class="px-6 pt-2 pb-1 text-xs font-semibold text-gray-500 uppercase">
+                    {self.i18n.fill("older_here", &[&self.stored_hits.len()])}
+                </div>
+                {
+                    self.stored_hits.iter().map(|m| {
+                        let open = {
+                            let hit = m.clone();
+                            ctx.link().callback(move |_| Msg::OpenStoredHit(hit.clone()))
+                        };
+                        html! {
+                            <button key={m.id.clone()} onclick={open}
+                                class="w-full flex items-baseline gap-2 px-6 py-1.5 text-start text-sm hover:bg-gray-100">
+                                <span class="flex-none text-xs text-gray-500">{date_time(m.time, self.settings.clock)}</span>
+                                <span class="flex-none font-medium">{highlight(&m.from, &self.search_query)}</span>
+                                <span class="truncate" dir="auto">{highlight(&snippet(&m.message), &self.search_query)}</span>
+                            </button>
+                        }
+                    }).collect::<Html>()
+                }
+            </div>
+        }
+    }
+
+    /// A pasted or dropped file's place in the conversation until it's sent: how far its upload
+    /// has got, or why it failed.
+    fn view_upload(&self, ctx: &Context<Self>, upload: &Upload) -> Html {
+        let percent = (upload.progress * 100.0).round() as u32;
+        html! {
+            <div key={format!("upload-{}", upload.id)} class="flex justify-end upload-row">
+                <div class="max-w-md rounded-2xl py-2 px-4 bg-white shadow-sm text-sm message-bubble">
+                    if let Some(error) = &upload.error {
+                        <div class="text-red-600" role="alert">{error.clone()}</div>
+                        <div class="flex justify-end mt-1">
+                            <button class="text-xs text-gray-500 hover:text-gray-800 underline"
+                                onclick={ctx.link().callback({
+                                    let id = upload.id.clone();
+                                    move |_| Msg::DismissUpload(id.clone())
+                                })}>
+                                {self.i18n.t("dismiss")}
+                            </button>
+                        </div>
+                    } else {
+                        <div class="text-gray-600 truncate">{self.i18n.fill("uploading", &[&upload.name])}</div>
+                        <div class="w-48 h-1.5 mt-2 bg-gray-200 rounded-full overflow-hidden" role="progressbar"
+                            aria-label={self.i18n.fill("uploading", &[&upload.name])}
+                            aria-valuemin="0" aria-valuemax="100" aria-valuenow={percent.to_string()}>
+                            <div class="h-full bg-primary transition-all" style={format!("width:{}%", percent)}></div>
+                        </div>
+                    }
+                </div>
+            </div>
+        }
+    }
+
+    /// Starts a deleted message fading out, to be removed by `FadedOut` when it's gone.
+    /// Returns `false`, for it to go at once, if it isn't on screen or motion is
+    /// reduced.
+    fn fade_out(&mut self, ctx: &Context<Self>, id: &str) -> bool {
+        if self.leaving.contains_key(id) {
+            return true;
+        }
+        let shown = self
+            .rendered_rows()
+            .into_iter()
+            .any(|i| self.state().messages[i].id == id);
+        if !shown || reduced_motion() {
+            return false;
+        }
+        let link = ctx.link().clone();
+        let gone = id.to_string();
+        let done = Timeout::new(MESSAGE_LEAVE_MS, move || {
+            link.send_message(Msg::FadedOut(gone))
+        });
+        self.leaving.insert(id.to_string(), done);
+        true
+    }
+
+    /// Applies every buffered frame, oldest first, in one update. Returns whether any of
+    /// them changed what's shown.
+    fn flush_incoming(&mut self, ctx: &Context<Self>) -> bool {
+        self.incoming_flush = None;
+        let events = self.incoming.drain();
+        #[cfg(feature = "bench")]
+        if let Some(first) = events.first() {
+            self.bench.start(first);
+        }
+        let mut changed = false;
+        for event in events {
+            changed |= self.apply_event(ctx, event);
+        }
+        changed
+    }
+
+    /// Applies one frame from the server. Returns whether it changed what's shown.
+    fn apply_event(&mut self, ctx: &Context<Self>, event: BusEvent) -> bool {
+        if let BusEvent::MessageDeleted(id) = &event {
+            if self.fade_out(ctx, id) {
+                return true;
+            }
+        }
+        let online_before = self.online_count();
+        let incoming = match &event {
+            BusEvent::MessageReceived(m) => Some(m.clone()),
+            _ => None,
+        };
+        let around = match &event {
+            BusEvent::HistoryReceived(Ok(page)) => page.around.clone(),
+            _ => None,
+        };
+        let applied = self.dispatch_incoming(event);
+        match &applied {
+            Applied::Message => self.unsaved.extend(incoming.iter().map(|m| m.id.clone())),
+            Applied::Delivered(id) => {
+                self.outbox.retain(|o| o != id);
+                self.unsaved.push(id.clone());
+            }
+            Applied::History { added, has_more } => {
+                self.history_loading = false;
+                if let Some(has_more) = has_more {
+                    self.has_more_history = *has_more;
+                }
+                self.unsaved.extend(added.iter().cloned());
+            }
+            Applied::Reacted(Some(id)) => self.unsaved.push(id.clone()),
+            Applied::Read(ids) => self.unsaved.extend(ids.iter().cloned()),
+            Applied::Cleared(_) => self.cleared(),
+            _ => {}
+        }
+        self.save_unsaved();
+        match applied {
+            Applied::Users => {
+                self.initialized = true;
+                self.users_stale = false;
+                // The server rebroadcasts the list periodically; only flash on a change.
+                if self.online_count() != online_before {
+                    let link = ctx.link().clone();
+                    self.count_flash = Some(Timeout::new(COUNT_FLASH_MS, move || {
+                        link.send_message(Msg::CountFlashed)
+                    }));
+                }
+                true
+            }
+            Applied::Message => {
+                // Keeps the oldest rendered row where it is.
+                self.window += 1;
+                if let Some(message) = incoming {
+                    self.enter(ctx, &message.id, false);
+                    self.announce(&message);
+                }
+                true
+            }
+            Applied::Delivered(id) => {
+                self.echo_timeouts.remove(&id);
+                self.save_outbox();
+                true
+            }
+            Applied::History { added, .. } => {
+                let added = added.len();
+                self.history_loaded = true;
+                self.quiet_log = true;
+                if std::mem::take(&mut self.paging_back) {
+                    self.window += added;
+                }
+                // History from before the cutoff is cleared as soon as it lands.
+                let swept = added > 0 && self.sweep_expired();
+                if self.seek_loaded(ctx) {
+                    return true;
+                }
+                if around.is_some() && around == self.seek {
+                    log::warn!(
+                        "the linked message {:?} is no longer in the history",
+                        around
+                    );
+                    self.seek = None;
+                }
+                if added > 0 && !swept {
+                    self.anchor_scroll();
+                }
+                true
+            }
+            Applied::Cleared(by) => {
+                log::info!("{} cleared the conversation for everyone", by);
+                clear_store();
+                self.cleared_by = Some(by);
+                self.save_outbox();
+                true
+            }
+            Applied::Deleted(id) => {
+                self.deleted(id);
+                true
+            }
+            Applied::Reacted(_) => true,
+            Applied::Pins => true,
+            Applied::Read(_) => true,
+            Applied::Typing(notice) => {
+                let shown = notice.room == self.room;
+                self.track_typer(ctx, notice);
+                shown
+            }
+            Applied::Duplicate => false,
+            Applied::Kicked(notice) if notice.nick == self.username => {
+                // Off to the login screen, with no reconnecting behind our back.
+                self.wss.close();
+                if let Some((user, _)) = ctx.link().context::<User>(Callback::noop()) {
+                    *user.register_error.borrow_mut() =
+                        Some(self.i18n.fill("kicked_by", &[&notice.by]));
+                }
+                if let Some(history) = ctx.link().history() {
+                    history.push(Route::Login);
+                }
+                false
+            }
+            Applied::Kicked(notice) => {
+                log::info!("{} removed {} from the chat", notice.by, notice.nick);
+                false
+            }
+            Applied::ServerError(error) => {
+                log::warn!("server error: {:?}", error);
+                if let Some(id) = &error.id {
+                    self.echo_timeouts.remove(id);
+                }
+                let text = match error.text_id() {
+                    Some(id) => self.i18n.t(id),
+                    None => error.reason_text().unwrap_or_default(),
+                };
+                self.toast(Toast::error(text));
+                true
+            }
+            Applied::RegisterRejected(reason) => {
+                // Back to the login screen, which says why.
+                if let Some((user, _)) = ctx.link().context::<User>(Callback::noop()) {
+                    *user.register_error.borrow_mut() = Some(reason);
+                }
+                if let Some(history) = ctx.link().history() {
+                    history.push(Route::Login);
+                }
+                false
+            }
+        }
+    }
+
+    /// Stops paging back into a history that's just been cleared, and saving any of it.
+    fn cleared(&mut self) {
+        self.unsaved.clear();
+        self.stored_has_more = false;
+        self.has_more_history = false;
+    }
+
+    fn toast(&self, toast: Toast) {
+        if let Some(toasts) = &self.toasts {
+            toasts.push.emit(toast);
+        }
+    }
+
+    /// Tidies up after the message with this id has left the conversation, which leaves
+    /// nothing of it to send or save.
+    fn deleted(&mut self, id: String) {
+        self.outbox.retain(|o| *o != id);
+        self.unsaved.retain(|u| *u != id);
+        if self.replying_to.as_ref() == Some(&id) {
+            self.replying_to = None;
+        }
+        self.forget_message(id);
+        self.save_outbox();
+    }
+
+    /// Has a message that was just added animate in, and keeps the list at the bottom
+    /// if it was there, or if the message is one we sent.
+    fn enter(&mut self, ctx: &Context<Self>, id: &str, ours: bool) {
+        let at_bottom = self.messages_container.cast::<Element>().is_some_and(|c| {
+            c.scroll_height() - c.scroll_top() - c.client_height() <= FOLLOW_THRESHOLD_PX
+        });
+        self.follow_latest |= ours || at_bottom;
+        self.entering.insert(id.to_string());
+        let link = ctx.link().clone();
+        self.entered = Some(Timeout::new(MESSAGE_ENTER_MS, move || {
+            link.send_message(Msg::Entered)
+        }));
+    }
+
+    /// Cleans up after a message has been deleted: its timers, its star and its stored copy.
+    fn forget_message(&mut self, id: String) {
+        self.echo_timeouts.remove(&id);
+        if self.starred.remove(&id) {
+            stars::save(&self.starred);
+        }
+        unstore(id);
+    }
+
+    /// Clears messages older than the retention setting allows, from the conversation and
+    /// the local store. Returns whether any loaded messages went.
+    fn sweep_expired(&mut self) -> bool {
+        let Some(cutoff) = self.retention.cutoff(self.wss.server_now() as u64) else {
+            return false;
+        };
+        spawn_local(async move {
+            if let Err(e) = history_store::remove_older_than(cutoff).await {
+                log::error!("couldn't clear expired history: {:?}", e);
+            }
+        });
+        let expired: Vec<String> = self
+            .state()
+            .messages
+            .iter()
+            .filter(|m| is_expired(m, cutoff))
+            .map(|m| m.id.clone())
+            .collect();
+        if expired.is_empty() {
+            return false;
+        }
+        self.dispatch(StoreAction::Expired(self.room.clone(), cutoff));
+        self.unsaved.retain(|u| !expired.contains(u));
+        // Anything older than what was just cleared would be cleared too.
+        self.has_more_history = false;
+        self.stored_has_more = false;
+        let before = self.starred.len();
+        self.starred.retain(|id| !expired.contains(id));
+        if self.starred.len() != before {
+            stars::save(&self.starred);
+        }
+        self.expired_cleared = true;
+        true
+    }
+
+    /// Starred messages that are loaded, oldest first, with a button to jump to each.
+    fn view_saved(&self, ctx: &Context<Self>) -> Html {
+        let saved: Vec<&MessageData> = self
+            .state()
+            .messages
+            .iter()
+            .filter(|m| self.starred.contains(&m.id))
+            .collect();
+        // Stars on messages older than what's loaded are kept, just not listed yet.
+        let unloaded = self.starred.len() - saved.len();
+
+        html! {
+            <div class="absolute end-4 top-16 w-80 max-h-96 overflow-y-auto bg-white rounded-xl shadow-lg border border-gray-200 z-10 saved-list">
+                <div class="px-4 py-2 text-sm font-semibold border-b border-gray-200">{self.i18n.t("saved_messages")}</div>
+                if saved.is_empty() && unloaded == 0 {
+                    <p class="px-4 py-3 text-sm text-gray-500">{self.i18n.t("saved_empty")}</p>
+                }
+                {
+                    saved.into_iter().map(|m| {
+                        let id = m.id.clone();
+                        let jump = {
+                            let id = id.clone();
+                            ctx.link().callback(move |_| Msg::JumpTo(id.clone()))
+                        };
+                        let unstar = ctx.link().callback(move |_| Msg::ToggleStar(id.clone()));
+                        html! {
+                            <div class="px-4 py-2 border-b border-gray-100 last:border-b-0">
+                                <div class="flex items-center justify-between text-xs text-gray-500">
+                                    <span class="font-medium text-gray-700">{m.from.clone()}</span>
+                                    <div class="flex items-center gap-2">
+                                        <button class="hover:text-primary" onclick={jump}>{self.i18n.t("jump")}</button>
+                                        <button class="text-yellow-500 hover:text-gray-400" title={self.i18n.t("unstar")} onclick={unstar}>
+                                            {star_icon(true)}
+                                        </button>
+                                    </div>
+                                </div>
+                                <p class="text-sm text-gray-700 truncate">{m.message.clone()}</p>
+                            </div>
+                        }
+                    }).collect::<Html>()
+                }
+                if unloaded > 0 {
+                    <p class="px-4 py-2 text-xs text-gray-400">
+                        {self.i18n.fill("more_in_history", &[&unloaded])}
+                    </p>
+                }
+            </div>
+        }
+    }
+
+    fn menu_actions(&self, ctx: &Context<Self>, target: &MenuTarget) -> Vec<MenuAction> {
+        let mut actions = vec![
+            MenuAction {
+                label: self.i18n.t("reply"),
+                danger: false,
+                onselect: {
+                    let id = target.id.clone();
+                    ctx.link().callback(move |_| Msg::ReplyTo(id.clone()))
+                },
+            },
+            MenuAction {
+                label: self.i18n.t("quote"),
+                danger: false,
+                onselect: {
+                    let quoted = quote(&target.from, &target.text);
+                    ctx.link().callback(move |_| Msg::Quote(quoted.clone()))
+                },
+            },
+            MenuAction {
+                label: self.i18n.t("react"),
+                danger: false,
+                onselect: {
+                    let mode = PickerMode::React(target.id.clone());
+                    ctx.link()
+                        .callback(move |_| Msg::ToggleEmojiPicker(mode.clone()))
+                },
+            },
+            MenuAction {
+                label: self.i18n.t("copy_text"),
+                danger: false,
+                onselect: {
+                    let (text, toasts, i18n) =
+                        (target.text.clone(), self.toasts.clone(), self.i18n);
+                    Callback::from(move |_| {
+                        let (text, toasts) = (text.clone(), toasts.clone());
+                        spawn_local(async move {
+                            let toast = match copy_text(&text).await {
+                                Ok(()) => Toast::success(i18n.t("copied_message")),
+                                Err(e) => {
+                                    log::warn!("couldn't copy message: {:?}", e);
+                                    Toast::error(i18n.t("copy_message_failed"))
+                                }
+                            };
+                            if let Some(toasts) = toasts {
+                                toasts.push.emit(toast);
+                            }
+                        });
+                    })
+                },
+            },
+            MenuAction {
+                label: self.i18n.t("copy_link"),
+                danger: false,
+                onselect: {
+                    let id = target.id.clone();
+                    ctx.link().callback(move |_| Msg::CopyLink(id.clone()))
+                },
+            },
+        ];
+        let pinned = self.state().pinned.contains(&target.id);
+        // Pinning is left out once the bar is full; unpinning something makes room.
+        if pinned || self.state().pinned.len() < PIN_LIMIT {
+            let id = target.id.clone();
+            actions.push(MenuAction {
+                label: self.i18n.t(if pinned { "unpin" } else { "pin" }),
+                danger: false,
+                onselect: ctx
+                    .link()
+                    .callback(move |_| Msg::SetPinned(id.clone(), !pinned)),
+            });
+        }
+        if target.from == self.username {
+            let id = target.id.clone();
+            actions.push(MenuAction {
+                label: self.i18n.t("delete"),
+                danger: true,
+                onselect: ctx.link().callback(move |_| Msg::DeleteMessage(id.clone())),
+            });
+        }
+        actions
+    }
+
+    /// Shown instead of the chat when the browser can't open a WebSocket, as nothing in it
+    /// would work.
+    fn view_unsupported(&self) -> Html {
+        html! {
+            <div class="flex w-screen h-screen items-center justify-center chat-bg" lang={self.i18n.lang()} dir={self.i18n.dir()}>
+                <div class="max-w-md m-6 p-6 bg-white rounded-xl shadow-sm text-center unsupported-notice" role="alert">
+                    <div class="text-4xl mb-3" aria-hidden="true">{"🔌"}</div>
+                    <h1 class="text-lg font-semibold mb-2">{self.i18n.t("unsupported_title")}</h1>
+                    <p class="text-sm text-gray-600">{self.i18n.t("unsupported_body")}</p>
+                </div>
+            </div>
+        }
+    }
+
+    /// The bar of pinned messages under the header: the newest pin, or every pin once
+    /// expanded, each of which jumps to the message.
+    fn view_pins(&self, ctx: &Context<Self>) -> Html {
+        if self.state().pinned.is_empty() {
+            return html! {};
+        }
+        let pins: Vec<&MessageData> = self
+            .state()
+            .pinned
+            .iter()
+            .rev()
+            .filter_map(|id| self.state().messages.iter().find(|m| &m.id == id))
+            .collect();
+        // Pins on messages older than what's loaded are counted, just not listed yet.
+        let unloaded = self.state().pinned.len() - pins.len();
+        let shown = if self.pins_open { pins.len() } else { 1 };
+        // Collapsed, the note stands in for the newest pin when none are loaded.
+        let note = unloaded > 0 && (self.pins_open || pins.is_empty());
+
+        html! {
+            <div class="w-full px-6 py-2 bg-white border-b border-gray-200 text-sm pinned-bar">
+                <div class="flex items-center gap-2">
+                    <span class="font-semibold text-gray-700">
+                        {self.i18n.fill("pinned_count", &[&self.state().pinned.len(), &PIN_LIMIT])}
+                    </span>
+                    <button class="ms-auto text-xs text-gray-500 hover:text-primary" aria-expanded={self.pins_open.to_string()}
+                        onclick={ctx.link().callback(|_| Msg::TogglePins)}>
+                        {self.i18n.t(if self.pins_open { "show_less" } else { "show_all" })}
+                    </button>
+                </div>
+                {
+                    pins.into_iter().take(shown).map(|m| {
+                        let id = m.id.clone();
+                        let jump = {
+                            let id = id.clone();
+                            ctx.link().callback(move |_| Msg::JumpTo(id.clone()))
+                        };
+                        let unpin = ctx.link().callback(move |_| Msg::SetPinned(id.clone(), false));
+                        html! {
+                            <div key={m.id.clone()} class="flex items-center gap-2 mt-1">
+                                <button class="min-w-0 grow flex items-center gap-2 text-start hover:text-primary" title={self.i18n.t("jump_to_message")} onclick={jump}>
+                                    <span class={classes!("flex-none", "font-medium", user_color(&m.from))}>{m.from.clone()}</span>
+                                    <span class="truncate text-gray-700" dir="auto">{m.message.clone()}</span>
+                                </button>
+                                <button class="flex-none text-xs text-gray-400 hover:text-red-500" title={self.i18n.t("unpin")} onclick={unpin}>{"✕"}</button>
+                            </div>
+                        }
+                    }).collect::<Html>()
+                }
+                if note {
+                    <p class="mt-1 text-xs text-gray-400">
+                        {self.i18n.fill("more_in_history", &[&unloaded])}
+                    </p>
+                }
+            </div>
+        }
+    }
+
+    /// The ways to clear the conversation: on this device, or for everyone if we're the
+    /// owner.
+    fn clear_actions(&self, ctx: &Context<Self>) -> Vec<MenuAction> {
+        let mut actions = vec![MenuAction {
+            label: self.i18n.t("clear_here"),
+            danger: false,
+            onselect: ctx.link().callback(|_| Msg::ClearHistory),
+        }];
+        if self.state().owner() == Some(self.username.as_str()) {
+            actions.push(MenuAction {
+                label: self.i18n.t("clear_everyone"),
+                danger: true,
+                onselect: ctx.link().callback(|_| Msg::ClearRoom),
+            });
+        }
+        actions
+    }
+
+    /// Hands newly delivered messages to the local store.
+    fn save_unsaved(&mut self) {
+        if self.unsaved.is_empty() {
+            return;
+        }
+        let ids: HashSet<String> = self.unsaved.drain(..).collect();
+        let batch: Vec<&MessageData> = self
+            .state()
+            .messages
+            .iter()
+            .filter(|m| ids.contains(&m.id))
+            .collect();
+        history_store::persist(&batch);
+    }
+
+    /// Writes the outbox to localStorage, so it's still there if the page reloads before
+    /// the connection comes back.
+    fn save_outbox(&self) {
+        let queued: Vec<&MessageData> = self
+            .outbox
+            .iter()
+            .filter_map(|id| self.state().messages.iter().find(|m| m.id == *id))
+            .collect();
+        outbox::save(&queued);
+    }
+
+    /// Remembers the current scroll height before older messages are added, so
+    /// `rendered` can keep the view where it was.
+    fn anchor_scroll(&mut self) {
+        self.scroll_anchor = self
+            .messages_container
+            .cast::<Element>()
+            .map(|c| c.scroll_height());
+        self.quiet_log = true;
+    }
+
+    /// Adds one of our own messages to the conversation and queues it for the server.
+    /// Returns `false`, leaving the body with the caller, if the outbox is full.
+    fn queue_message(
+        &mut self,
+        ctx: &Context<Self>,
+        body: String,
+        attachment: Option<Box<Attachment>>,
+    ) -> bool {
+        if self.outbox.len() >= OUTBOX_CAPACITY {
+            log::warn!("outbox full, not queueing message");
+            self.outbox_overflowed = true;
+            return false;
+        }
+        self.outbox_overflowed = false;
+        let id = new_id();
+        let message = MessageData {
+            id: id.clone(),
+            from: self.username.clone(),
+            message: body,
+            // Until the echo brings the server's own stamp.
+            time: self.wss.server_now() as u64,
+            reactions: BTreeMap::new(),
+            read_by: vec![],
+            reply_to: self.replying_to.take(),
+            attachment,
+            status: DeliveryStatus::Pending,
+        };
+        self.dispatch(StoreAction::Sent(self.room.clone(), message));
+        self.window += 1;
+        self.enter(ctx, &id, true);
+        self.outbox.push_back(id);
+        self.flush_outbox(ctx);
+        true
+    }
+
+    /// Sends every queued message, as `drain_outbox` does. The one that failed is marked
+    /// as such, for the user to retry, unless the socket's queue was only full: then the
+    /// outbox tries again shortly. The rest wait for the next flush.
+    fn flush_outbox(&mut self, ctx: &Context<Self>) {
+        if self.conn_state != ConnState::Open {
+            return;
+        }
+
+        let mut outbox = std::mem::take(&mut self.outbox);
+        let (sent, stopped) =
+            drain_outbox(&mut outbox, &self.state().messages, |m| self.try_send(m));
+        self.outbox = outbox;
+        for id in sent {
+            let link = ctx.link().clone();
+            let timeout_id = id.clone();
+            self.echo_timeouts.insert(
+                id,
+                Timeout::new(ECHO_TIMEOUT_MS, move || {
+                    link.send_message(Msg::EchoTimeout(timeout_id))
+                }),
+            );
+        }
+        match stopped {
+            // It stays first in line, and goes once the socket's caught up.
+            Some((_, SendError::Full)) => {
+                let link = ctx.link().clone();
+                self.outbox_retry = Some(Timeout::new(OUTBOX_RETRY_MS, move || {
+                    link.send_message(Msg::FlushOutbox)
+                }));
+            }
+            Some((id, SendError::Closed)) => {
+                self.dispatch(StoreAction::Failed(self.room.clone(), id));
+            }
+            None => {}
+        }
+        self.save_outbox();
+    }
+    /// Does what `update` is asked to. Anything it dispatches is sent on by `update`
+    /// afterwards, as one change.
+    fn handle(&mut self, ctx: &Context<Self>, msg: Msg) -> bool {
+        match msg {
+            Msg::HandleMsg(event) => {
+                if self.incoming.push(event) {
+                    let link = ctx.link().clone();
+                    self.incoming_flush =
+                        Some(next_frame(move || link.send_message(Msg::FlushIncoming)));
+                }
+                false
+            }
+            Msg::Stored(update) => {
+                // Any change of ours is in our copy already. The `Store`'s catches up one
+                // dispatch at a time, and is the same as ours from the last one on. One
+                // we weren't ahead for is someone else's, and is news.
+                let news = self.ahead == 0;
+                self.ahead = self.ahead.saturating_sub(1);
+                if self.ahead == 0 {
+                    self.app.merge(update);
+                }
+                news
+            }
+            Msg::FlushIncoming => self.flush_incoming(ctx),
+            Msg::FlushOutbox => {
+                self.outbox_retry = None;
+                self.flush_outbox(ctx);
+                true
+            }
+            Msg::SubmitMessage => {
+                let input = self.chat_input.cast::<HtmlTextAreaElement>();
+                if let Some(input) = input {
+                    if input.value().trim().is_empty() {
+                        return false;
+                    }
+                    match input.value().trim() {
+                        "/clear" | "/clear everyone" => {
+                            let everyone = input.value().trim() == "/clear everyone";
+                            input.set_value("");
+                            fit_input(&input);
+                            self.discard_draft();
+                            return self.handle(
+                                ctx,
+                                if everyone {
+                                    Msg::ClearRoom
+                                } else {
+                                    Msg::ClearHistory
+                                },
+                            );
+                        }
+                        _ => {}
+                    }
+                    // On overflow the text stays in the input so nothing is lost while
+                    // we're backed up. Anything not expanded while typing, such as pasted
+                    // codes, is sent as emoji too; rendering expands them anyway for older
+                    // messages.
+                    if !self.queue_message(ctx, expand_shortcodes(&input.value()), None) {
+                        return true;
+                    }
+                    self.input_history.record(&input.value());
+                    input.set_value("");
+                    fit_input(&input);
+                    self.discard_draft();
+                    self.announce_typing(false);
+                };
+                self.last_activity.set(js_sys::Date::now());
+                self.set_presence(Presence::Active);
+                true
+            }
+            Msg::ConnState(state) => {
+                // What arrived before the change is dealt with before it.
+                self.flush_incoming(ctx);
+                self.conn_state = state;
+                if state != ConnState::Open {
+                    self.rtt = None;
+                    self.users_stale = !self.state().users.is_empty();
+                }
+                match state {
+                    ConnState::Open => {
+                        if std::mem::take(&mut self.connection_lost) {
+                            self.toast(Toast::success(self.i18n.t("reconnected")));
+                        }
+                        // The server forgets us on disconnect, so register on every open.
+                        self.register();
+                        self.request_users();
+                        self.flush_outbox(ctx);
+                        self.request_latest();
+                        if self.seek_asked {
+                            self.seek_asked = false;
+                            self.request_around();
+                        }
+                        self.flush_receipts();
+                    }
+                    ConnState::Closed => {
+                        self.connection_lost = true;
+                        // A reply can't arrive on a dead socket; allow asking again.
+                        self.history_loading = false;
+                        // The server forgot we were typing along with us.
+                        self.typing_sent = None;
+                        // Anything not echoed yet may never have reached the server, so
+                        // queue it again ahead of what was typed while disconnected.
+                        let in_flight: Vec<String> = self
+                            .state()
+                            .messages
+                            .iter()
+                            .map(|m| m.id.clone())
+                            .filter(|id| self.echo_timeouts.contains_key(id))
+                            .collect();
+                        self.echo_timeouts.clear();
+                        for id in in_flight.into_iter().rev() {
+                            self.outbox.push_front(id);
+                        }
+                        self.save_outbox();
+                    }
+                    // Already treated as closed when the last attempt failed.
+                    ConnState::Connecting | ConnState::Stopped | ConnState::Offline => {}
+                    // `view` shows only a notice from now on.
+                    ConnState::Unsupported => {}
+                }
+                true
+            }
+            Msg::Typed(has_text) => {
+                // Edited, what's there is the draft now. The key handler has to hear that
+                // recalling's over.
+                let was_recalling = self.input_history.recalling();
+                self.input_history.stop();
+                self.announce_typing(has_text);
+                let link = ctx.link().clone();
+                self.draft_save = Some(Timeout::new(DRAFT_SAVE_MS, move || {
+                    link.send_message(Msg::SaveDraft)
+                }));
+                was_recalling
+            }
+            Msg::SaveDraft => {
+                self.draft_save = None;
+                if let Some(input) = self.chat_input.cast::<HtmlTextAreaElement>() {
+                    drafts::save(&self.username, &self.room, &input.value());
+                }
+                false
+            }
+            Msg::Recall(older) => {
+                let Some(input) = self.chat_input.cast::<HtmlTextAreaElement>() else {
+                    return false;
+                };
+                let was_recalling = self.input_history.recalling();
+                let text = if older {
+                    self.input_history.older(&input.value()).map(str::to_string)
+                } else {
+                    self.input_history.newer()
+                };
+                if let Some(text) = text {
+                    input.set_value(&text);
+                    let end = text.encode_utf16().count() as u32;
+                    let _ = input.set_selection_range(end, end);
+                    fit_input(&input);
+                    self.announce_typing(!text.trim().is_empty());
+                }
+                was_recalling != self.input_history.recalling()
+            }
+            Msg::TypingExpired(room, from) => {
+                self.typing_expiry.remove(&(room.clone(), from.clone()));
+                let changed = self.dispatch(StoreAction::StoppedTyping(room.clone(), from));
+                !changed.is_empty() && room == self.room
+            }
+            Msg::Reconnect => {
+                self.wss.reconnect();
+                false
+            }
+            Msg::NetworkChanged(online) => {
+                self.wss.set_online(online);
+                false
+            }
+            Msg::EchoTimeout(id) => {
+                self.echo_timeouts.remove(&id);
+                // Settled after all, or gone.
+                if self
+                    .dispatch(StoreAction::Failed(self.room.clone(), id.clone()))
+                    .is_empty()
+                {
+                    return false;
+                }
+                let retry = ctx.link().callback(move |_| Msg::Retry(id.clone()));
+                self.toast(
+                    Toast::error(self.i18n.t("send_failed"))
+                        .with_action(self.i18n.t("retry"), retry),
+                );
+                true
+            }
+            Msg::Retry(id) => {
+                let retried = StoreAction::Retried(self.room.clone(), id.clone());
+                if !self.dispatch(retried).is_empty() {
+                    self.outbox.push_back(id);
+                    self.flush_outbox(ctx);
+                }
+                true
+            }
+            Msg::MessagesScrolled => {
+                let at_top = match self.messages_container.cast::<Element>() {
+                    Some(c) => c.scroll_top() <= HISTORY_SCROLL_THRESHOLD,
+                    None => false,
+                };
+                if !at_top || self.history_loading {
+                    return false;
+                }
+                // What's already loaded gets rendered before anything older is fetched.
+                if self.has_earlier() {
+                    self.window += RENDER_WINDOW;
+                    self.anchor_scroll();
+                    return true;
+                }
+                self.request_history(ctx);
+                self.paging_back = self.history_loading;
+                self.history_loading
+            }
+            Msg::RowFocused(index) => {
+                let id = self.state().messages.get(index).map(|m| m.id.clone());
+                if id.is_none() || id == self.focused_message {
+                    return false;
+                }
+                self.focused_message = id;
+                true
+            }
+            Msg::RowKey(index, key) => {
+                let rows = self.rendered_rows();
+                let Some(current) = rows.iter().position(|&i| i == index) else {
+                    return false;
+                };
+                match row_nav(&key, current, rows.len()) {
+                    Some(RowNav::Row(position)) => self.focus_row(rows[position], ""),
+                    Some(RowNav::Toolbar) => self.focus_row(index, ".message-toolbar button"),
+                    Some(RowNav::Input) => ctx.link().send_message(Msg::FocusInput),
+                    None => {}
+                }
+                false
+            }
+            Msg::FocusInput => {
+                if let Some(input) = self.chat_input.cast::<HtmlTextAreaElement>() {
+                    let _ = input.focus();
+                }
+                false
+            }
+            Msg::ShowEarlier => {
+                self.window += RENDER_WINDOW;
+                self.anchor_scroll();
+                true
+            }
+            Msg::Tick => {
+                // Nobody's looking; showing the tab again ticks.
+                if gloo_utils::document().hidden() {
+                    return false;
+                }
+                self.now = self.wss.server_now() as u64;
+                // Only cozy rows show relative times.
+                self.density == Density::Cozy
+            }
+            Msg::Rtt(rtt) => {
+                log::debug!("ws round trip: {}ms", rtt);
+                self.rtt = Some(rtt);
+                true
+            }
+            Msg::Activity => self.set_presence(Presence::Active),
+            Msg::CheckIdle => {
+                let idle_for = js_sys::Date::now() - self.last_activity.get();
+                if idle_for >= self.settings.idle_threshold_ms() as f64 {
+                    self.set_presence(Presence::Away)
+                } else {
+                    false
+                }
+            }
+            Msg::CountFlashed => {
+                self.count_flash = None;
+                true
+            }
+            Msg::StoredPage(page) => {
+                self.history_loading = false;
+                if page.len() < STORED_PAGE_SIZE {
+                    self.stored_has_more = false;
+                }
+                let before = self.state().messages.len();
+                self.dispatch(StoreAction::Loaded(self.room.clone(), page));
+                let added = self.state().messages.len() - before;
+                if std::mem::take(&mut self.paging_back) {
+                    self.window += added;
+                }
+                if added == 0 {
+                    return false;
+                }
+                if !self.sweep_expired() {
+                    self.anchor_scroll();
+                }
+                true
+            }
+            Msg::ClearHistory => {
+                self.clear_menu = None;
+                self.confirming = Some(Confirm::ClearHistory);
+                true
+            }
+            Msg::ClearRoom => {
+                self.clear_menu = None;
+                let owner = self.state().owner();
+                if owner != Some(self.username.as_str()) {
+                    let owner = owner.unwrap_or(self.i18n.t("first_joiner"));
+                    self.toast(Toast::info(self.i18n.fill("owner_only", &[&owner])));
+                    return true;
+                }
+                self.confirming = Some(Confirm::ClearRoom);
+                true
+            }
+            Msg::OpenClearMenu(x, y) => {
+                self.clear_menu = Some((x, y));
+                true
+            }
+            Msg::CloseClearMenu => self.clear_menu.take().is_some(),
+            Msg::Confirmed => match self.confirming.take() {
+                Some(action) => {
+                    self.perform(ctx, action);
+                    true
+                }
+                None => false,
+            },
+            Msg::CancelConfirm => self.confirming.take().is_some(),
+            Msg::ToggleSearch => {
+                self.search_open = !self.search_open;
+                self.search_query.clear();
+                self.stored_hits.clear();
+                self.stored_search = None;
+                self.quiet_log = true;
+                self.search_index = 0;
+                self.focus_search = self.search_open;
+                true
+            }
+            Msg::SearchInput(query) => {
+                self.search_query = query;
+                self.quiet_log = true;
+                // Start from the newest hit, since that's usually the one being looked for.
+                let matches = search_matches(
+                    &self.state().messages,
+                    &self.search_query,
+                    self.sender_filter.as_deref(),
+                    self.muted_filter(),
+                );
+                self.search_index = matches.len().saturating_sub(1);
+                self.scroll_to_match = !matches.is_empty();
+                self.reveal_match();
+                // What's in the store waits for a pause in typing; the old hits are for
+                // the old query.
+                self.stored_hits.clear();
+                self.stored_search = (!self.search_query.trim().is_empty()).then(|| {
+                    let (link, query) = (ctx.link().clone(), self.search_query.clone());
+                    Timeout::new(STORED_SEARCH_DEBOUNCE_MS, move || {
+                        link.send_message(Msg::SearchStored(query))
+                    })
+                });
+                true
+            }
+            Msg::SearchStored(query) => {
+                self.stored_search = None;
+                if self.search_open && query == self.search_query {
+                    let sender = self.sender_filter.clone();
+                    let muted = self.muted_filter().cloned();
+                    ctx.link().send_future(search_stored(query, sender, muted));
+                }
+                false
+            }
+            Msg::StoredHits(query, mut hits) => {
+                // Typed on since, or closed.
+                if !self.search_open || query != self.search_query {
+                    return false;
+                }
+                hits.retain(|m| !self.state().has(&m.id));
+                self.stored_hits = hits;
+                true
+            }
+            Msg::OpenStoredHit(hit) => {
+                let until = self.state().messages.first().map(|m| m.time);
+                ctx.link().send_future(load_context(hit, until));
+                false
+            }
+            Msg::StoredContext(id, page) => {
+                self.dispatch(StoreAction::Loaded(self.room.clone(), page));
+                self.stored_hits.clear();
+                if self.seek.as_ref() == Some(&id) {
+                    self.seek = None;
+                }
+                self.jump(ctx, id);
+                true
+            }
+            Msg::SearchStep(delta) => {
+                let count = search_matches(
+                    &self.state().messages,
+                    &self.search_query,
+                    self.sender_filter.as_deref(),
+                    self.muted_filter(),
+                )
+                .len();
+                if count == 0 {
+                    return false;
+                }
+                let current = self.search_index.min(count - 1) as isize;
+                self.search_index = (current + delta).rem_euclid(count as isize) as usize;
+                self.scroll_to_match = true;
+                self.reveal_match();
+                true
+            }
+            Msg::FilterSender(sender) => {
+                if self.sender_filter == sender {
+                    return false;
+                }
+                self.sender_filter = sender;
+                self.quiet_log = true;
+                // The hits have changed under the search bar; go back to the newest one.
+                let matches = search_matches(
+                    &self.state().messages,
+                    &self.search_query,
+                    self.sender_filter.as_deref(),
+                    self.muted_filter(),
+                );
+                self.search_index = matches.len().saturating_sub(1);
+                self.scroll_to_match = !matches.is_empty();
+                self.reveal_match();
+                true
+            }
+            Msg::OpenMenu(target) => {
+                self.menu = Some(target);
+                true
+            }
+            Msg::CloseMenu => self.menu.take().is_some(),
+            Msg::Quote(quoted) => {
+                if let Some(input) = self.chat_input.cast::<HtmlTextAreaElement>() {
+                    input.set_value(&format!("{}{}", quoted, input.value()));
+                    fit_input(&input);
+                    let _ = input.focus();
+                }
+                false
+            }
+            Msg::Mention(name) => {
+                if let Some(input) = self.chat_input.cast::<HtmlTextAreaElement>() {
+                    insert_at_cursor(&input, &format!("@{} ", name));
+                }
+                false
+            }
+            Msg::ReplyTo(id) => {
+                self.replying_to = Some(id);
+                if let Some(input) = self.chat_input.cast::<HtmlTextAreaElement>() {
+                    let _ = input.focus();
+                }
+                true
+            }
+            Msg::CancelReply => self.replying_to.take().is_some(),
+            Msg::DeleteMessage(id) => {
+                self.confirming = Some(Confirm::Delete(id));
+                true
+            }
+            Msg::ToggleExpanded(id) => {
+                if !self.expanded.remove(&id) {
+                    self.expanded.insert(id);
+                }
+                true
+            }
+            Msg::ToggleStar(id) => {
+                stars::toggle(&mut self.starred, &id);
+                stars::save(&self.starred);
+                true
+            }
+            Msg::ToggleSaved => {
+                self.show_saved = !self.show_saved;
+                true
+            }
+            Msg::Kick(nick) => {
+                self.confirming = Some(Confirm::Kick(nick));
+                true
+            }
+            Msg::SetMuted(name, muted) => {
+                let changed = if muted {
+                    self.muted.insert(name)
+                } else {
+                    self.muted.remove(&name)
+                };
+                if changed {
+                    mutes::save(&self.muted);
+                    self.quiet_log = true;
+                }
+                changed
+            }
+            Msg::ToggleShowMuted => {
+                self.show_muted = !self.show_muted;
+                self.quiet_log = true;
+                true
+            }
+            Msg::SetPinned(id, pinned) => {
+                self.send_pin(id, pinned);
+                false
+            }
+            Msg::TogglePins => {
+                self.pins_open = !self.pins_open;
+                true
+            }
+            Msg::JumpTo(id) => {
+                self.jump(ctx, id);
+                true
+            }
+            Msg::JumpHighlightDone => self.jump_highlight.take().is_some(),
+            Msg::Entered => {
+                self.entered = None;
+                !std::mem::take(&mut self.entering).is_empty()
+            }
+            Msg::FadedOut(id) => {
+                self.leaving.remove(&id);
+                self.dispatch(StoreAction::Deleted(self.room.clone(), id.clone()));
+                self.deleted(id);
+                true
+            }
+            Msg::SeekPermalink(id) => {
+                self.seek = Some(id.clone());
+                self.seek_asked = false;
+                if self.seek_loaded(ctx) {
+                    return true;
+                }
+                ctx.link().send_future(find_stored(id));
+                false
+            }
+            Msg::PermalinkStored(id, hit) => {
+                if self.seek.as_ref() != Some(&id) || self.seek_loaded(ctx) {
+                    return true;
+                }
+                match hit {
+                    Some(hit) => {
+                        let until = self.state().messages.first().map(|m| m.time);
+                        ctx.link().send_future(load_context(hit, until));
+                    }
+                    // Not on this device; the server may still have it, once we're connected.
+                    None => self.request_around(),
+                }
+                false
+            }
+            Msg::CopyLink(id) => {
+                let (link, toasts, i18n) = (permalink(&id), self.toasts.clone(), self.i18n);
+                spawn_local(async move {
+                    let toast = match copy_text(&link).await {
+                        Ok(()) => Toast::success(i18n.t("copied_link")),
+                        Err(e) => {
+                            log::warn!("couldn't copy link: {:?}", e);
+                            Toast::error(i18n.t("copy_link_failed"))
+                        }
+                    };
+                    if let Some(toasts) = toasts {
+                        toasts.push.emit(toast);
+                    }
+                });
+                false
+            }
+            Msg::SortUsers(order) => {
+                let changed = self.user_sort != order;
+                self.user_sort = order;
+                changed
+            }
+            Msg::TogglePinSelf => {
+                self.pin_self = !self.pin_self;
+                true
+            }
+            Msg::SetDensity(density) => {
+                self.density = density;
+                // Every row is rebuilt in the other layout.
+                self.quiet_log = true;
+                density::save(density);
+                true
+            }
+            Msg::StartResize => {
+                self.resizing = true;
+                true
+            }
+            Msg::ResizeSidebar(width) => {
+                let width = sidebar::clamp(width);
+                let changed = width != self.sidebar_width;
+                self.sidebar_width = width;
+                // Dragging saves once, at the end; the keyboard has no end to wait for.
+                if changed && !self.resizing {
+                    sidebar::save(width);
+                }
+                changed
+            }
+            Msg::EndResize => {
+                if !std::mem::replace(&mut self.resizing, false) {
+                    return false;
+                }
+                sidebar::save(self.sidebar_width);
+                true
+            }
+            Msg::SetRetention(retention) => {
+                self.retention = retention;
+                retention::save(retention);
+                self.sweep_expired();
+                true
+            }
+            Msg::SweepExpired => self.sweep_expired(),
+            Msg::ToggleSettings => {
+                self.settings_open = !self.settings_open;
+                true
+            }
+            Msg::SettingsChanged(next) => {
+                self.settings = next.settings;
+                true
+            }
+            Msg::ToggleGifPicker => {
+                self.show_gif_picker = !self.show_gif_picker;
+                self.emoji_picker = None;
+                true
+            }
+            Msg::ToggleEmojiPicker(mode) => {
+                self.emoji_picker = match self.emoji_picker.take() {
+                    Some(open) if open == mode => None,
+                    _ => Some(mode),
+                };
+                self.show_gif_picker = false;
+                self.menu = None;
+                true
+            }
+            Msg::CloseEmojiPicker => self.emoji_picker.take().is_some(),
+            Msg::PickEmoji(emoji) => {
+                match self.emoji_picker.take() {
+                    Some(PickerMode::Insert) => {
+                        if let Some(input) = self.chat_input.cast::<HtmlTextAreaElement>() {
+                            insert_at_cursor(&input, &emoji);
+                        }
+                    }
+                    Some(PickerMode::React(id)) => self.send_reaction(id, emoji),
+                    None => {}
+                }
+                true
+            }
+            Msg::React(id, emoji) => {
+                self.send_reaction(id, emoji);
+                false
+            }
+            Msg::OpenExportMenu(x, y) => {
+                self.export_menu = Some((x, y));
+                true
+            }
+            Msg::CloseExportMenu => self.export_menu.take().is_some(),
+            Msg::Export(format) => {
+                if let Err(e) = export::download(&self.state().messages, format) {
+                    log::error!("couldn't export the conversation: {:?}", e);
+                }
+                false
+            }
+            Msg::MarkVisibleRead => self.mark_visible_read(),
+            Msg::ShareFiles(files) => {
+                self.drag_depth = 0;
+                let (files, too_large): (Vec<File>, Vec<File>) = files
+                    .into_iter()
+                    .partition(|f| f.size() <= upload::MAX_UPLOAD_BYTES);
+                if let Some(file) = too_large.first() {
+                    let also = match too_large.len() {
+                        1 => String::new(),
+                        n => self.i18n.fill("and_more", &[&(n - 1)]),
+                    };
+                    let reason = UploadError::TooLarge(file.size());
+                    let text = self.i18n.fill("file_too_large", &[&file.name(), &reason]);
+                    self.toast(Toast::error(text + &also));
+                }
+                for file in files {
+                    let id = new_id();
+                    self.uploads.push(Upload {
+                        id: id.clone(),
+                        name: file.name(),
+                        size: file.size(),
+                        image: file.type_().starts_with("image/"),
+                        progress: 0.0,
+                        error: None,
+                    });
+                    let endpoint = ctx.props().upload_endpoint.clone();
+                    let on_progress = {
+                        let id = id.clone();
+                        ctx.link()
+                            .callback(move |p| Msg::UploadProgress(id.clone(), p))
+                    };
+                    ctx.link().send_future(async move {
+                        let result = upload::upload(&endpoint, file, on_progress).await;
+                        Msg::Uploaded(id, result)
+                    });
+                }
+                true
+            }
+            Msg::UploadProgress(id, progress) => {
+                match self.uploads.iter_mut().find(|u| u.id == id) {
+                    Some(upload) => {
+                        upload.progress = progress;
+                        true
+                    }
+                    None => false,
+                }
+            }
+            Msg::Uploaded(id, result) => {
+                let at = match self.uploads.iter().position(|u| u.id == id) {
+                    Some(at) => at,
+                    None => return false,
+                };
+                match result {
+                    Ok(url) => {
+                        let upload = self.uploads.remove(at);
+                        if upload.image {
+                            self.queue_message(ctx, url, None);
+                        } else {
+                            let attachment = Attachment {
+                                name: upload.name,
+                                size: upload.size as u64,
+                                url,
+                            };
+                            let body = upload::file_message(&attachment);
+                            self.queue_message(ctx, body, Some(Box::new(attachment)));
+                        }
+                    }
+                    Err(e) => {
+                        let upload = &mut self.uploads[at];
+                        log::warn!("upload of {} failed: {}", upload.name, e);
+                        upload.error = Some(self.i18n.fill("upload_failed", &[&upload.name, &e]));
+                    }
+                }
+                true
+            }
+            Msg::DragFiles(entered) => {
+                let shown = self.drag_depth > 0;
+                // Entering a child fires before leaving its parent, so this counts rather
+                // than flips.
+                self.drag_depth = if entered {
+                    self.drag_depth + 1
+                } else {
+                    self.drag_depth.saturating_sub(1)
+                };
+                shown != (self.drag_depth > 0)
+            }
+            Msg::DismissUpload(id) => {
+                let before = self.uploads.len();
+                self.uploads.retain(|u| u.id != id);
+                self.uploads.len() != before
+            }
+            Msg::SendGif(url) => {
+                self.show_gif_picker = false;
+                self.queue_message(ctx, url, None);
+                self.last_activity.set(js_sys::Date::now());
+                self.set_presence(Presence::Active);
+                true
+            }
+        }
+    }
 }
 
-pub struct Chat {
-    users: Vec<UserProfile>,
-    chat_input: NodeRef,
-    _producer: Box<dyn Bridge<EventBus>>,
-    wss: WebsocketService,
-    messages: Vec<MessageData>,
-}
 impl Component for Chat {
     type Message = Msg;
-    type Properties = ();
+    type Properties = ChatProps;
 
     fn create(ctx: &Context<Self>) -> Self {
         let (user, _) = ctx
             .link()
             .context::<User>(Callback::noop())
             .expect("context to be set");
-        let wss = WebsocketService::new();
+        // The connection's state comes back through `_connection`, as `Msg::ConnState`.
+        let wss = WebsocketService::with_config(
+            Callback::noop(),
+            ctx.link().callback(Msg::Rtt),
+            ServiceConfig {
+                queue_capacity: ctx.props().queue_capacity,
+                ..ServiceConfig::default()
+            },
+        );
+        if !gloo_utils::window().navigator().on_line() {
+            wss.set_online(false);
+        }
+        let network_listeners = [("online", true), ("offline", false)].map(|(kind, online)| {
+            let on_change = ctx.link().callback(Msg::NetworkChanged);
+            EventListener::new(&gloo_utils::window(), kind, move |_| on_change.emit(online))
+        });
         let username = user.username.borrow().clone();
+        let (settings, settings_listener) = ctx
+            .link()
+            .context::<SettingsContext>(ctx.link().callback(Msg::SettingsChanged))
+            .expect("settings context to be set");
 
-        let message = WebSocketMessage {
-            message_type: MsgTypes::Register,
-            data: Some(username.to_string()),
-            data_array: None,
+        let last_activity = Rc::new(Cell::new(js_sys::Date::now()));
+        let away = Rc::new(Cell::new(false));
+        let document = gloo_utils::document();
+        let activity_listeners = ACTIVITY_EVENTS
+            .iter()
+            .map(|event| {
+                let last_activity = last_activity.clone();
+                let away = away.clone();
+                let link = ctx.link().clone();
+                EventListener::new(&document, *event, move |_| {
+                    last_activity.set(js_sys::Date::now());
+                    if away.get() {
+                        link.send_message(Msg::Activity);
+                    }
+                })
+            })
+            .collect();
+        let chat_input = NodeRef::default();
+        let messages_container = NodeRef::default();
+        let shortcuts = {
+            let (chat_input, messages_container) = (chat_input.clone(), messages_container.clone());
+            EventListener::new(&document, "keydown", move |e| {
+                let e = e.unchecked_ref::<KeyboardEvent>();
+                // Already typing somewhere: leave the key alone so it's entered as usual.
+                if is_text_entry(e.target()) {
+                    return;
+                }
+                if is_focus_shortcut(e) {
+                    if let Some(input) = chat_input.cast::<HtmlTextAreaElement>() {
+                        e.prevent_default();
+                        let _ = input.focus();
+                    }
+                } else if is_latest_shortcut(e) {
+                    // Straight there, not smoothly: this is for getting out of deep history.
+                    if let Some(container) = messages_container.cast::<Element>() {
+                        e.prevent_default();
+                        container.set_scroll_top(container.scroll_height());
+                    }
+                }
+            })
         };
-
-        if let Ok(_) = wss
-            .tx
-            .clone()
-            .try_send(serde_json::to_string(&message).unwrap())
+        let hash_listener = {
+            let link = ctx.link().clone();
+            EventListener::new(&gloo_utils::window(), "hashchange", move |_| {
+                let hash = gloo_utils::window().location().hash().unwrap_or_default();
+                if let Some(id) = permalink_id(&hash) {
+                    link.send_message(Msg::SeekPermalink(id));
+                }
+            })
+        };
+        if let Some(id) = permalink_id(&gloo_utils::window().location().hash().unwrap_or_default())
         {
-            log::debug!("message sent successfully");
+            ctx.link().send_message(Msg::SeekPermalink(id));
         }
+        let idle_check = {
+            let link = ctx.link().clone();
+            Interval::new(IDLE_CHECK_MS, move || link.send_message(Msg::CheckIdle))
+        };
+        let time_tick = {
+            let link = ctx.link().clone();
+            Interval::new(TIME_TICK_MS, move || link.send_message(Msg::Tick))
+        };
+        let focus_listeners = {
+            let window = gloo_utils::window();
+            let on_focus = ctx.link().callback(|_| Msg::MarkVisibleRead);
+            let on_visibility = on_focus.clone();
+            // Relative times stop ticking while hidden, so they're caught up on the way back.
+            let on_shown = ctx.link().callback(|_| Msg::Tick);
+            vec![
+                EventListener::new(&window, "focus", move |_| on_focus.emit(())),
+                EventListener::new(&document, "visibilitychange", move |_| {
+                    on_visibility.emit(());
+                    if !gloo_utils::document().hidden() {
+                        on_shown.emit(());
+                    }
+                }),
+            ]
+        };
+        let visible_messages = VisibleMessages::new(ctx.link().callback(|_| Msg::MarkVisibleRead))
+            .map_err(|e| log::warn!("read receipts unavailable: {:?}", e))
+            .ok();
+        let retention_sweep = {
+            let link = ctx.link().clone();
+            Interval::new(RETENTION_SWEEP_MS, move || {
+                link.send_message(Msg::SweepExpired)
+            })
+        };
+
+        // Show what we saved last time right away, before the server has a say.
+        ctx.link().send_future(load_stored(None));
+        // Messages still queued when the page went away go out once we're connected. Only
+        // our own, in case someone else signs in on this device.
+        let queued: Vec<MessageData> = outbox::load()
+            .into_iter()
+            .filter(|m| m.from == username)
+            .collect();
+        let room = DEFAULT_ROOM.to_string();
+        let outbox = queued.iter().map(|m| m.id.clone()).collect();
+        // Whatever the `Store` had for the room from before is loaded again along with the
+        // rest, so we both start from the same.
+        let opened = StoreAction::Opened(room.clone(), queued);
+        let mut app = AppState::default();
+        app.apply(opened.clone());
+        let mut store = Store::bridge(ctx.link().callback(Msg::Stored));
+        store.send(StoreRequest::Dispatch(opened));
+        store.send(StoreRequest::Follow(Slice::Room(room.clone())));
 
         Self {
-            users: vec![],
-            messages: vec![],
-            chat_input: NodeRef::default(),
+            username,
+            app,
+            dispatching: vec![],
+            room_changed: false,
+            // The room as it's opened, which `Follow` sends back.
+            ahead: 1,
+            outbox,
+            unsaved: vec![],
+            history_loading: true,
+            has_more_history: true,
+            stored_has_more: true,
+            chat_input,
+            messages_container,
+            now: wss.server_now() as u64,
             wss,
-            _producer: EventBus::bridge(ctx.link().callback(Msg::HandleMsg)),
+            conn_state: ConnState::Connecting,
+            rtt: None,
+            outbox_overflowed: false,
+            echo_timeouts: HashMap::new(),
+            outbox_retry: None,
+            scroll_anchor: None,
+            presence: Presence::Active,
+            last_activity,
+            away,
+            _activity_listeners: activity_listeners,
+            _shortcuts: shortcuts,
+            _hash_listener: hash_listener,
+            seek: None,
+            seek_asked: false,
+            jump_highlight: None,
+            entering: HashSet::new(),
+            entered: None,
+            expanded: HashSet::new(),
+            leaving: HashMap::new(),
+            follow_latest: false,
+            _idle_check: idle_check,
+            _time_tick: time_tick,
+            _retention_sweep: retention_sweep,
+            receipts: ReceiptQueue::default(),
+            last_read: last_read::load(),
+            unread_after: last_read::load(),
+            visible_messages,
+            _focus_listeners: focus_listeners,
+            _network_listeners: network_listeners,
+            show_gif_picker: false,
+            count_flash: None,
+            initialized: false,
+            history_loaded: false,
+            users_stale: false,
+            input_history: InputHistory::default(),
+            draft_save: None,
+            uploads: vec![],
+            drag_depth: 0,
+            _drop_guards: ["dragover", "drop"].map(|kind| {
+                EventListener::new_with_options(
+                    &gloo_utils::window(),
+                    kind,
+                    EventListenerOptions::enable_prevent_default(),
+                    |e| {
+                        if dragged_files(e) {
+                            e.prevent_default();
+                        }
+                    },
+                )
+            }),
+            connection_lost: false,
+            toasts: ctx
+                .link()
+                .context::<ToastContext>(Callback::noop())
+                .map(|(toasts, _)| toasts),
+            search_open: false,
+            search_query: String::new(),
+            search_index: 0,
+            search_input: NodeRef::default(),
+            scroll_to_match: false,
+            focus_search: false,
+            stored_hits: vec![],
+            stored_search: None,
+            sender_filter: None,
+            menu: None,
+            replying_to: None,
+            starred: stars::load(),
+            muted: mutes::load(),
+            show_muted: false,
+            show_saved: false,
+            pins_open: false,
+            jump_to: None,
+            user_sort: UserSort::default(),
+            pin_self: true,
+            retention: retention::load(),
+            density: density::load(),
+            sidebar_width: sidebar::load(),
+            resizing: false,
+            i18n: I18n::pick(ctx.props().lang.as_deref()),
+            expired_cleared: false,
+            emoji_picker: None,
+            export_menu: None,
+            clear_menu: None,
+            confirming: None,
+            settings: settings.settings,
+            _settings_listener: settings_listener,
+            settings_open: false,
+            cleared_by: None,
+            _connection: WebsocketService::subscribe(
+                &[Topic::Presence, Topic::Room(room.clone())],
+                ctx.link().callback(|event| match event {
+                    WsEvent::State(state) => Msg::ConnState(state),
+                    WsEvent::Frame(event) => Msg::HandleMsg(event),
+                }),
+            ),
+            store,
+            incoming: Coalescer::default(),
+            incoming_flush: None,
+            room,
+            typing_expiry: HashMap::new(),
+            typing_sent: None,
+            window: RENDER_WINDOW,
+            paging_back: false,
+            quiet_log: false,
+            focused_message: None,
+            #[cfg(feature = "bench")]
+            bench: crate::bench::Timer::default(),
         }
     }
 
-    fn update(&mut self, _ctx: &Context<Self>, msg: Self::Message) -> bool {
-        match msg {
-            Msg::HandleMsg(s) => {
-                let msg: WebSocketMessage = serde_json::from_str(&s).unwrap();
-                match msg.message_type {
-                    MsgTypes::Users => {
-                        let users_from_message = msg.data_array.unwrap_or_default();
-                        self.users = users_from_message
-                            .iter()
-                            .map(|u| UserProfile {
-                                name: u.into(),
-                                avatar: format!(
-                                    "https://avatars.dicebear.com/api/adventurer-neutral/{}.svg",
-                                    u
-                                )
-                                .into(),
-                            })
-                            .collect();
-                        return true;
-                    }
-                    MsgTypes::Message => {
-                        let message_data: MessageData =
-                            serde_json::from_str(&msg.data.unwrap()).unwrap();
-                        self.messages.push(message_data);
-                        return true;
-                    }
-                    _ => {
-                        return false;
-                    }
-                }
+    fn changed(&mut self, ctx: &Context<Self>) -> bool {
+        self.i18n = I18n::pick(ctx.props().lang.as_deref());
+        true
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        let render = self.handle(ctx, msg);
+        self.flush_dispatches();
+        render
+    }
+
+    fn rendered(&mut self, _ctx: &Context<Self>, first_render: bool) {
+        self.quiet_log = false;
+        if first_render {
+            self.restore_draft();
+        }
+        #[cfg(feature = "bench")]
+        self.bench.finish(
+            &self.messages_container,
+            self.state().messages.len(),
+            self.state().messages.len() - self.window_start(),
+        );
+        if let (Some(visible), Some(container)) = (
+            &self.visible_messages,
+            self.messages_container.cast::<Element>(),
+        ) {
+            visible.watch_all(&container);
+        }
+        if std::mem::take(&mut self.focus_search) {
+            if let Some(input) = self.search_input.cast::<HtmlInputElement>() {
+                let _ = input.focus();
             }
-            Msg::SubmitMessage => {
-                let input = self.chat_input.cast::<HtmlInputElement>();
-                if let Some(input) = input {
-                    let message = WebSocketMessage {
-                        message_type: MsgTypes::Message,
-                        data: Some(input.value()),
-                        data_array: None,
-                    };
-                    if let Err(e) = self
-                        .wss
-                        .tx
-                        .clone()
-                        .try_send(serde_json::to_string(&message).unwrap())
-                    {
-                        log::debug!("error sending to channel: {:?}", e);
-                    }
-                    input.set_value("");
+        }
+        if std::mem::take(&mut self.scroll_to_match) {
+            let matches = search_matches(
+                &self.state().messages,
+                &self.search_query,
+                self.sender_filter.as_deref(),
+                self.muted_filter(),
+            );
+            let target = matches.get(self.search_index).and_then(|i| {
+                self.messages_container
+                    .cast::<Element>()?
+                    .query_selector(&format!("[data-msg-index='{}']", i))
+                    .ok()?
+            });
+            if let Some(target) = target {
+                let mut options = ScrollIntoViewOptions::new();
+                options
+                    .block(ScrollLogicalPosition::Center)
+                    .behavior(ScrollBehavior::Smooth);
+                target.scroll_into_view_with_scroll_into_view_options(&options);
+            }
+        }
+
+        // Straight to the bottom rather than smoothly, so the scroll is done before the new
+        // row's entrance, which doesn't change its height, has got going.
+        if std::mem::take(&mut self.follow_latest) {
+            if let Some(container) = self.messages_container.cast::<Element>() {
+                container.set_scroll_top(container.scroll_height());
+            }
+        }
+
+        if let Some(id) = self.jump_to.take() {
+            let target = self
+                .state()
+                .messages
+                .iter()
+                .position(|m| m.id == id)
+                .and_then(|i| {
+                    self.messages_container
+                        .cast::<Element>()?
+                        .query_selector(&format!("[data-msg-index='{}']", i))
+                        .ok()?
+                });
+            if let Some(target) = target {
+                let mut options = ScrollIntoViewOptions::new();
+                options
+                    .block(ScrollLogicalPosition::Center)
+                    .behavior(ScrollBehavior::Smooth);
+                target.scroll_into_view_with_scroll_into_view_options(&options);
+            }
+        }
+
+        if let Some(old_height) = self.scroll_anchor.take() {
+            if let Some(container) = self.messages_container.cast::<Element>() {
+                // On the first page there's nothing to preserve; start at the newest message.
+                let offset = if old_height <= container.client_height() {
+                    container.scroll_height()
+                } else {
+                    container.scroll_height() - old_height + container.scroll_top()
                 };
-                false
+                container.set_scroll_top(offset);
             }
         }
-    }    fn view(&self, ctx: &Context<Self>) -> Html {
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        if self.conn_state == ConnState::Unsupported {
+            return self.view_unsupported();
+        }
+        let users_pending = self.users_pending();
+        let messages_pending = self.messages_pending();
         let submit = ctx.link().callback(|_| Msg::SubmitMessage);
+        // Enter sends; Shift+Enter (or Enter while an IME is composing) adds a line.
+        let replying = self.replying_to.is_some();
+        let recalling = self.input_history.recalling();
+        let onkeydown = ctx.link().batch_callback(move |e: KeyboardEvent| {
+            let plain = !(e.shift_key() || e.alt_key() || e.ctrl_key() || e.meta_key());
+            if e.key() == "Enter" && !e.shift_key() && !e.is_composing() {
+                e.prevent_default();
+                Some(Msg::SubmitMessage)
+            } else if e.key() == "Escape" && replying {
+                Some(Msg::CancelReply)
+            } else if (e.key() == "ArrowUp" || e.key() == "ArrowDown") && plain && !e.is_composing()
+            {
+                // Recalling starts only from the very start of the box, so moving between
+                // lines is left alone; once started, the arrows page through sends until
+                // the text is edited.
+                let older = e.key() == "ArrowUp";
+                let input: HtmlTextAreaElement = e.target_unchecked_into();
+                let at_start =
+                    input.selection_start() == Ok(Some(0)) && input.selection_end() == Ok(Some(0));
+                if recalling || (older && at_start) {
+                    e.prevent_default();
+                    Some(Msg::Recall(older))
+                } else {
+                    None
+                }
+            } else {
+                None
+            }
+        });
+        let oninput = ctx.link().callback(|e: InputEvent| {
+            let input: HtmlTextAreaElement = e.target_unchecked_into();
+            // Only on a typed colon, so pasting or deleting back to one doesn't expand.
+            if e.data().as_deref() == Some(":") && !e.is_composing() {
+                expand_typed_shortcode(&input);
+            }
+            fit_input(&input);
+            Msg::Typed(!input.value().trim().is_empty())
+        });
+        // Pasted images are uploaded rather than left to the browser; anything else pastes
+        // as usual.
+        let uploads_on = !ctx.props().upload_endpoint.is_empty();
+        let onpaste = ctx.link().batch_callback(move |e: Event| {
+            // `ClipboardEvent` is still behind web-sys's unstable APIs.
+            let data = js_sys::Reflect::get(&e, &"clipboardData".into()).ok()?;
+            let files = data.dyn_into::<DataTransfer>().ok()?.files()?;
+            let images: Vec<File> = (0..files.length())
+                .filter_map(|i| files.get(i))
+                .filter(|f| f.type_().starts_with("image/"))
+                .collect();
+            if !uploads_on || images.is_empty() {
+                return None;
+            }
+            e.prevent_default();
+            Some(Msg::ShareFiles(images))
+        });
+        // Dropped files are uploaded; drags of anything else are left to the browser.
+        let ondragenter = ctx.link().batch_callback(move |e: DragEvent| {
+            (uploads_on && dragged_files(&e)).then_some(Msg::DragFiles(true))
+        });
+        let ondragleave = ctx.link().batch_callback(move |e: DragEvent| {
+            (uploads_on && dragged_files(&e)).then_some(Msg::DragFiles(false))
+        });
+        let ondragover = Callback::from(move |e: DragEvent| {
+            if uploads_on && dragged_files(&e) {
+                e.prevent_default();
+                if let Some(data) = e.data_transfer() {
+                    data.set_drop_effect("copy");
+                }
+            }
+        });
+        let ondrop = ctx.link().batch_callback(move |e: DragEvent| {
+            if !uploads_on || !dragged_files(&e) {
+                return None;
+            }
+            e.prevent_default();
+            Some(Msg::ShareFiles(dropped_files(&e)))
+        });
+        let toggle_gif_picker = ctx.link().callback(|_| Msg::ToggleGifPicker);
+        let send_gif = ctx.link().callback(Msg::SendGif);
+        let open_clear_menu = ctx
+            .link()
+            .callback(|e: MouseEvent| Msg::OpenClearMenu(e.client_x(), e.client_y()));
+        let toggle_search = ctx.link().callback(|_| Msg::ToggleSearch);
 
+        let search_query = if self.search_open {
+            self.search_query.as_str()
+        } else {
+            ""
+        };
+        let sender_filter = self.sender_filter.as_deref();
+        let muted_count = self.muted_count();
+        let me = self.state().users.iter().find(|u| u.name == self.username);
+        let matches = search_matches(
+            &self.state().messages,
+            search_query,
+            sender_filter,
+            self.muted_filter(),
+        );
+        let current_match = matches
+            .get(self.search_index.min(matches.len().saturating_sub(1)))
+            .copied();
+        let rows = self.rendered_rows();
+        let active_row = self.active_row(&rows);
+        // Before the first shown row that's new, in case the first new one is filtered out.
+        let divider_row = self
+            .unread_after
+            .as_deref()
+            .and_then(|after| unread_boundary(&self.state().messages, after, &self.username))
+            .and_then(|boundary| rows.iter().copied().find(|&i| i >= boundary));
+        let onfocusin = ctx.link().batch_callback(|e: FocusEvent| {
+            let row = e
+                .target_dyn_into::<Element>()?
+                .closest("[data-msg-index]")
+                .ok()??;
+            Some(Msg::RowFocused(
+                row.get_attribute("data-msg-index")?.parse().ok()?,
+            ))
+        });
+        // Escape leaves the list from anywhere in it; the other keys only act on a row
+        // itself, so they keep working as usual on the buttons and links inside one.
+        let onrowkey = ctx.link().batch_callback(|e: KeyboardEvent| {
+            if e.key() == "Escape" {
+                return Some(Msg::FocusInput);
+            }
+            let index = e
+                .target_dyn_into::<Element>()?
+                .get_attribute("data-msg-index")?
+                .parse()
+                .ok()?;
+            let modified = e.ctrl_key() || e.meta_key() || e.alt_key() || e.shift_key();
+            if modified || !ROW_KEYS.contains(&e.key().as_str()) {
+                return None;
+            }
+            e.prevent_default();
+            Some(Msg::RowKey(index, e.key()))
+        });
+        let profiles: HashMap<&str, &UserProfile> = self
+            .state()
+            .users
+            .iter()
+            .map(|u| (u.name.as_str(), u))
+            .collect();
+
+        // Logical classes (`ms-`, `end-`, ...) and flex rows follow `dir`, so an RTL
+        // language mirrors the whole layout: the sidebar, our bubbles and the send button
+        // go to the left.
         html! {
-            <div class="flex w-screen chat-bg">
+            <div class={classes!("flex", "w-screen", "chat-bg", self.resizing.then_some("select-none cursor-col-resize"))}
+                lang={self.i18n.lang()} dir={self.i18n.dir()}>
                 // User sidebar
-                <div class="flex-none w-64 h-screen user-list overflow-hidden">
+                <div class="flex-none h-screen user-list overflow-hidden" role="complementary" aria-label={self.i18n.t("users")}
+                    style={format!("width:{}px", self.sidebar_width)}>
                     <div class="text-xl p-4 font-semibold border-b border-gray-200 flex items-center">
-                        <span class="mr-2">{"👥"}</span>
-                        <span>{"Users"}</span>
+                        <span class="me-2">{"👥"}</span>
+                        <span>{format!("{} ", self.i18n.t("users"))}</span>
+                        <span class={classes!("online-count", self.count_flash.is_some().then_some("count-flash"))}>
+                            {format!("({})", self.online_count())}
+                        </span>
+                        if self.users_stale {
+                            <span class="ms-auto text-xs font-normal text-gray-500 animate-pulse" role="status">
+                                {self.i18n.t("users_stale")}
+                            </span>
+                        }
+                    </div>
+                    <div class="flex items-center gap-1 px-4 py-2 text-xs text-gray-500 border-b border-gray-200 user-sort">
+                        {
+                            [(UserSort::Name, "A–Z"), (UserSort::Recent, self.i18n.t("sort_recent"))].into_iter().map(|(order, label)| html! {
+                                <button onclick={ctx.link().callback(move |_| Msg::SortUsers(order))}
+                                    class={classes!("px-2", "py-0.5", "rounded-full",
+                                        if self.user_sort == order { "bg-primary-dark text-white" } else { "hover:bg-gray-100" })}>
+                                    {label}
+                                </button>
+                            }).collect::<Html>()
+                        }
+                        <label class="ms-auto flex items-center gap-1 cursor-pointer">
+                            <input type="checkbox" checked={self.pin_self}
+                                onchange={ctx.link().callback(|_| Msg::TogglePinSelf)}/>
+                            {self.i18n.t("me_first")}
+                        </label>
                     </div>
-                    <div class="overflow-y-auto h-full">
+                    <div class={classes!("overflow-y-auto", "h-full", (!users_pending).then_some("fade-in"), self.users_stale.then_some("opacity-60"))}
+                        role="list" aria-busy={(users_pending || self.users_stale).to_string()}>
+                        if users_pending {
+                            { user_skeleton() }
+                        }
                         {
-                            self.users.clone().iter().map(|u| {
+                            sort_users(
+                                &self.state().users,
+                                &self.state().messages,
+                                self.user_sort,
+                                self.pin_self.then_some(self.username.as_str()),
+                            ).into_iter().map(|u| {
+                                let muted = self.muted.contains(&u.name);
+                                let kick = can_kick(me, u).then(|| ctx.link().callback({
+                                    let name = u.name.clone();
+                                    move |_| Msg::Kick(name.clone())
+                                }));
+                                let toggle_mute = ctx.link().callback({
+                                    let name = u.name.clone();
+                                    move |_| Msg::SetMuted(name.clone(), !muted)
+                                });
                                 html!{
-                                    <div class="flex m-3 bg-white rounded-xl p-3 shadow-sm user-item">
-                                        <div>
-                                            <img class="w-12 h-12 rounded-full user-avatar" src={u.avatar.clone()} alt="avatar"/>
-                                        </div>
-                                        <div class="flex-grow p-2 ml-2">
-                                            <div class="flex text-sm font-medium justify-between">
-                                                <div>{u.name.clone()}</div>
-                                            </div>
-                                            <div class="text-xs text-gray-400 mt-1">
-                                                {"Online"}
+                                    <HoverCard key={u.name.clone()} role="listitem" card={self.view_user_card(ctx, u)}>
+                                        <div class={classes!(
+                                            "flex", "m-3", "bg-white", "rounded-xl", "p-3", "shadow-sm", "user-item",
+                                            (u.status == Presence::Away || muted).then_some("opacity-50"),
+                                        )}>
+                                            <Avatar name={u.name.clone()} size={AvatarSize::Large}
+                                                status={AvatarStatus::Present(u.status)} i18n={self.i18n} class="self-center"/>
+                                            <div class="flex-grow p-2 ms-2">
+                                                <div class="flex text-sm font-medium justify-between">
+                                                    <button class={classes!("hover:underline", "sender-filter-link", user_color(&u.name))} title={self.i18n.fill("show_only", &[&u.name])}
+                                                        onclick={ctx.link().callback({
+                                                            let name = u.name.clone();
+                                                            move |_| Msg::FilterSender(Some(name.clone()))
+                                                        })}>
+                                                        {u.name.clone()}
+                                                    </button>
+                                                    if u.name != self.username {
+                                                        <button class="text-xs text-gray-400 hover:text-gray-700 mute-button" onclick={toggle_mute}
+                                                            title={self.i18n.fill(if muted { "unmute_title" } else { "mute_title" }, &[&u.name])}>
+                                                            {self.i18n.t(if muted { "unmute" } else { "mute" })}
+                                                        </button>
+                                                    }
+                                                    if let Some(onclick) = kick {
+                                                        <button class="ms-2 text-xs text-red-400 hover:text-red-600 kick-button" {onclick}
+                                                            title={self.i18n.fill("kick_title", &[&u.name])}>
+                                                            {self.i18n.t("kick")}
+                                                        </button>
+                                                    }
+                                                </div>
                                             </div>
                                         </div>
-                                    </div>
+                                    </HoverCard>
                                 }
                             }).collect::<Html>()
                         }
                     </div>
                 </div>
-                
+
                 // Main chat area
-                <div class="grow h-screen flex flex-col">
+                { self.view_resize_handle(ctx) }
+                <div class="grow h-screen flex flex-col relative"
+                    {ondragenter} {ondragover} {ondragleave} {ondrop}>
+                    if self.drag_depth > 0 {
+                        <div class="absolute inset-2 z-20 flex items-center justify-center rounded-xl border-4 border-dashed border-primary bg-white/80 pointer-events-none drop-overlay">
+                            <div class="text-lg font-semibold text-primary-dark">{self.i18n.t("drop_files")}</div>
+                        </div>
+                    }
                     // Chat header
                     <div class="w-full h-16 chat-header flex items-center px-6 border-b border-gray-200">
                         <div class="text-xl font-semibold">{"💬 YewChat"}</div>
-                        <div class="ml-3 text-sm text-gray-500">{"Let's chat!"}</div>
+                        <div class="ms-3 text-sm text-gray-500">{self.i18n.t("tagline")}</div>
+                        <div class="ms-auto flex items-center gap-3">
+                            <ConnectionStatus i18n={self.i18n}/>
+                            if let Some(rtt) = self.rtt {
+                                <div class={classes!(
+                                    "px-2", "py-0.5", "rounded-full", "text-xs", "font-medium",
+                                    if rtt <= RTT_GOOD_MS {
+                                        "bg-green-100 text-green-700"
+                                    } else if rtt <= RTT_FAIR_MS {
+                                        "bg-yellow-100 text-yellow-700"
+                                    } else {
+                                        "bg-red-100 text-red-700"
+                                    },
+                                )} title={self.i18n.t("round_trip")}>
+                                    {format!("{}ms", rtt)}
+                                </div>
+                            }
+                            <button onclick={ctx.link().callback(|_| Msg::ToggleSaved)} title={self.i18n.t("saved_messages")}
+                                class={classes!("hover:text-primary", "saved-button", if self.show_saved { "text-primary" } else { "text-gray-400" })}>
+                                {star_icon(self.show_saved)}
+                            </button>
+                            <button onclick={toggle_search} title={self.i18n.t("search_placeholder")}
+                                class={classes!("hover:text-primary", "search-button", if self.search_open { "text-primary" } else { "text-gray-400" })}>
+                                <svg viewBox="0 0 24 24" xmlns="http://www.w3.org/2000/svg" class="fill-current w-5 h-5">
+                                    <path d="M15.5 14h-.79l-.28-.27A6.47 6.47 0 0 0 16 9.5 6.5 6.5 0 1 0 9.5 16c1.61 0 3.09-.59 4.23-1.57l.27.28v.79l5 4.99L20.49 19l-4.99-5zm-6 0C7.01 14 5 11.99 5 9.5S7.01 5 9.5 5 14 7.01 14 9.5 11.99 14 9.5 14z"></path>
+                                </svg>
+                            </button>
+                            <select title={self.i18n.t("density")} class="text-xs text-gray-500 bg-transparent density-select"
+                                onchange={ctx.link().callback(|e: Event| {
+                                    let index = e.target_unchecked_into::<HtmlSelectElement>().selected_index();
+                                    Msg::SetDensity(Density::ALL.get(index as usize).copied().unwrap_or_default())
+                                })}>
+                                {
+                                    Density::ALL.into_iter().map(|d| html! {
+                                        <option selected={d == self.density}>{d.label(self.i18n)}</option>
+                                    }).collect::<Html>()
+                                }
+                            </select>
+                            <select title={self.i18n.t("retention")} class="text-xs text-gray-500 bg-transparent retention-select"
+                                onchange={ctx.link().callback(|e: Event| {
+                                    let index = e.target_unchecked_into::<HtmlSelectElement>().selected_index();
+                                    Msg::SetRetention(Retention::ALL.get(index as usize).copied().unwrap_or_default())
+                                })}>
+                                {
+                                    Retention::ALL.into_iter().map(|r| html! {
+                                        <option selected={r == self.retention}>{r.label(self.i18n)}</option>
+                                    }).collect::<Html>()
+                                }
+                            </select>
+                            <button onclick={ctx.link().callback(|_| Msg::ToggleSettings)} title={self.i18n.t("settings")}
+                                class="text-gray-400 hover:text-primary settings-button">
+                                <svg viewBox="0 0 24 24" xmlns="http://www.w3.org/2000/svg" class="fill-current w-5 h-5">
+                                    <path d="M19.14 12.94c.04-.3.06-.61.06-.94 0-.32-.02-.64-.07-.94l2.03-1.58a.49.49 0 0 0 .12-.61l-1.92-3.32a.49.49 0 0 0-.59-.22l-2.39.96c-.5-.38-1.03-.7-1.62-.94l-.36-2.54a.48.48 0 0 0-.48-.41h-3.84c-.24 0-.43.17-.47.41l-.36 2.54c-.59.24-1.13.57-1.62.94l-2.39-.96a.48.48 0 0 0-.59.22L2.74 8.87c-.12.21-.08.47.12.61l2.03 1.58c-.05.3-.09.63-.09.94s.02.64.07.94l-2.03 1.58a.49.49 0 0 0-.12.61l1.92 3.32c.12.22.37.29.59.22l2.39-.96c.5.38 1.03.7 1.62.94l.36 2.54c.05.24.24.41.48.41h3.84c.24 0 .44-.17.47-.41l.36-2.54c.59-.24 1.13-.56 1.62-.94l2.39.96c.22.08.47 0 .59-.22l1.92-3.32c.12-.22.07-.47-.12-.61l-2.01-1.58zM12 15.6c-1.98 0-3.6-1.62-3.6-3.6s1.62-3.6 3.6-3.6 3.6 1.62 3.6 3.6-1.62 3.6-3.6 3.6z"></path>
+                                </svg>
+                            </button>
+                            <button title={self.i18n.t("export")} class="text-gray-400 hover:text-primary export-button"
+                                onclick={ctx.link().callback(|e: MouseEvent| Msg::OpenExportMenu(e.client_x(), e.client_y()))}>
+                                <svg viewBox="0 0 24 24" xmlns="http://www.w3.org/2000/svg" class="fill-current w-5 h-5">
+                                    <path d="M19 9h-4V3H9v6H5l7 7 7-7zM5 18v2h14v-2H5z"></path>
+                                </svg>
+                            </button>
+                            <button onclick={open_clear_menu} title={self.i18n.t("clear_conversation")}
+                                class="text-gray-400 hover:text-red-500 clear-history-button">
+                                <svg viewBox="0 0 24 24" xmlns="http://www.w3.org/2000/svg" class="fill-current w-5 h-5">
+                                    <path d="M6 19c0 1.1.9 2 2 2h8c1.1 0 2-.9 2-2V7H6v12zM19 4h-3.5l-1-1h-5l-1 1H5v2h14V4z"></path>
+                                </svg>
+                            </button>
+                        </div>
                     </div>
-                    
-                    // Messages container
-                    <div class="w-full grow overflow-auto p-6 space-y-6">
+
+                    { self.view_pins(ctx) }
+                    if self.show_saved {
+                        { self.view_saved(ctx) }
+                    }
+                    if self.search_open {
+                        { self.view_search_bar(ctx, &matches) }
+                        { self.view_stored_hits(ctx) }
+                    }
+                    if let Some(sender) = sender_filter {
+                        <div class="w-full px-6 py-2 bg-white border-b border-gray-200 flex items-center">
+                            <span class="inline-flex items-center gap-2 px-3 py-1 rounded-full text-sm bg-gray-100 text-gray-700 sender-filter-chip">
+                                {self.i18n.fill("filtering_by", &[&sender])}
+                                <button class="text-gray-500 hover:text-gray-800" title={self.i18n.t("show_everyone")}
+                                    onclick={ctx.link().callback(|_| Msg::FilterSender(None))}>
+                                    {"✕"}
+                                </button>
+                            </span>
+                        </div>
+                    }
+
+                    // Connection banner
+                    {
+                        match self.conn_state {
+                            // `view` never gets this far without a socket.
+                            ConnState::Open | ConnState::Unsupported => html! {},
+                            ConnState::Connecting => html! {
+                                <div class="w-full px-6 py-2 text-sm bg-amber-50 text-amber-700 border-b border-amber-200">
+                                    {self.i18n.t("connecting")}
+                                </div>
+                            },
+                            ConnState::Closed => html! {
+                                <div class="w-full px-6 py-2 text-sm bg-red-50 text-red-700 border-b border-red-200">
+                                    {self.i18n.fill("retrying", &[&self.wss.attempts(), &MAX_RECONNECT_ATTEMPTS])}
+                                </div>
+                            },
+                            ConnState::Offline => html! {
+                                <div class="w-full px-6 py-2 text-sm bg-gray-100 text-gray-700 border-b border-gray-200" role="status">
+                                    {self.i18n.t("you_offline")}
+                                </div>
+                            },
+                            ConnState::Stopped => html! {
+                                <div class="w-full px-6 py-2 text-sm bg-red-50 text-red-700 border-b border-red-200 flex items-center">
+                                    {self.i18n.t("connection_lost")}
+                                    <button class="ms-auto font-semibold underline" onclick={ctx.link().callback(|_| Msg::Reconnect)}>
+                                        {self.i18n.t("reconnect")}
+                                    </button>
+                                </div>
+                            },
+                        }
+                    }
+                    if muted_count > 0 {
+                        <div class="w-full px-6 py-1.5 bg-gray-50 border-b border-gray-200 flex items-center text-xs text-gray-500 muted-notice">
+                            {self.i18n.fill(
+                                match (self.show_muted, muted_count) {
+                                    (true, 1) => "muted_shown_one",
+                                    (true, _) => "muted_shown",
+                                    (false, 1) => "muted_hidden_one",
+                                    (false, _) => "muted_hidden",
+                                },
+                                &[&muted_count],
+                            )}
+                            <button class="ms-2 font-semibold hover:text-gray-800" aria-expanded={self.show_muted.to_string()}
+                                onclick={ctx.link().callback(|_| Msg::ToggleShowMuted)}>
+                                {self.i18n.t(if self.show_muted { "hide_them" } else { "show_them" })}
+                            </button>
+                        </div>
+                    }
+                    if self.outbox_overflowed {
+                        <div class="w-full px-6 py-2 text-sm bg-red-50 text-red-700 border-b border-red-200">
+                            {self.i18n.fill("outbox_full", &[&OUTBOX_CAPACITY])}
+                        </div>
+                    }
+
+                    // Messages container. As a log, screen readers read out rows as they're
+                    // added, but not rows changing or going; see `quiet_log` for the rest.
+                    <div role="log" aria-label={self.i18n.t("messages")} aria-relevant="additions" aria-busy={messages_pending.to_string()}
+                         aria-live={if self.quiet_log { "off" } else { "polite" }}
+                         class={classes!(
+                            "w-full", "grow", "overflow-auto",
+                            if self.density == Density::Compact { "p-3 space-y-0.5" } else { "p-6 space-y-6" },
+                            (!messages_pending).then_some("fade-in"),
+                         )}
+                         ref={self.messages_container.clone()}
+                         onscroll={ctx.link().callback(|_| Msg::MessagesScrolled)}
+                         {onfocusin} onkeydown={onrowkey}>
+                        if messages_pending {
+                            { message_skeleton(self.density) }
+                        } else if self.history_loading {
+                            <div class="flex justify-center">
+                                <div class="w-5 h-5 border-2 border-primary border-t-transparent rounded-full animate-spin"></div>
+                            </div>
+                        } else if let Some(by) = &self.cleared_by {
+                            <div class="text-center text-xs text-gray-400 cleared-marker">
+                                {self.i18n.fill("cleared_by", &[by])}
+                            </div>
+                        } else if self.expired_cleared && self.retention != Retention::Forever {
+                            <div class="text-center text-xs text-gray-400 retention-marker">
+                                {self.i18n.fill("expired", &[&self.retention.label(self.i18n)])}
+                            </div>
+                        }
+                        if self.has_earlier() {
+                            <div class="flex justify-center">
+                                <button class="text-xs text-gray-500 hover:text-gray-800 underline show-earlier"
+                                    onclick={ctx.link().callback(|_| Msg::ShowEarlier)}>
+                                    {self.i18n.t("show_earlier")}
+                                </button>
+                            </div>
+                        }
                         {
-                            self.messages.iter().map(|m| {
-                                let user = self.users.iter().find(|u| u.name == m.from).unwrap_or_else(|| {
-                                    // Fallback for when user is not found
-                                    &self.users[0]
-                                });
-                                
-                                let is_current_user = false; // Replace with actual check when user context is available
-                                
-                                html!{
-                                    <div class={if is_current_user { 
-                                        "flex justify-end" 
-                                    } else { 
-                                        "flex" 
-                                    }}>
-                                        if !is_current_user {
-                                            <img class="w-10 h-10 rounded-full user-avatar self-end mr-3" 
-                                                 src={user.avatar.clone()} alt="avatar"/>
-                                        }
-                                        
-                                        <div class={if is_current_user {
-                                            "max-w-md bg-primary-light text-white rounded-2xl py-2 px-4 message-bubble"
-                                        } else {
-                                            "max-w-md bg-white rounded-2xl py-2 px-4 shadow-sm message-bubble"
-                                        }}>
-                                            if !is_current_user {
-                                                <div class="font-medium text-sm mb-1">{m.from.clone()}</div>
-                                            }
-                                            
-                                            if m.message.ends_with(".gif") {
-                                                <img class="rounded-lg w-full" src={m.message.clone()}/>
-                                            } else {
-                                                <p class="text-sm">{m.message.clone()}</p>
-                                            }
-                                            
-                                            <div class="text-xs text-right mt-1 message-time">
-                                                {"Just now"}
-                                            </div>
-                                        </div>
-                                        
-                                        if is_current_user {
-                                            <img class="w-10 h-10 rounded-full user-avatar self-end ml-3" 
-                                                 src={user.avatar.clone()} alt="avatar"/>
-                                        }
+                            rows.iter().flat_map(|&i| {
+                                let divider = (divider_row == Some(i)).then(|| html! {
+                                    <div key="unread-divider" role="separator" aria-label={self.i18n.t("new_messages")}
+                                        class="flex items-center gap-3 text-xs font-semibold text-red-600 unread-divider">
+                                        <div class="grow border-t border-red-300"></div>
+                                        {self.i18n.t("new_messages")}
+                                        <div class="grow border-t border-red-300"></div>
                                     </div>
-                                }
+                                });
+                                divider.into_iter().chain(std::iter::once(self.view_message(
+                                    ctx,
+                                    i,
+                                    profiles.get(self.state().messages[i].from.as_str()).copied(),
+                                    search_query,
+                                    current_match == Some(i),
+                                    active_row == Some(i),
+                                )))
                             }).collect::<Html>()
                         }
+                        { for self.uploads.iter().map(|u| self.view_upload(ctx, u)) }
+                    </div>
+
+                    if let Some((x, y)) = self.export_menu {
+                        <ContextMenu {x} {y}
+                            actions={vec![
+                                MenuAction {
+                                    label: self.i18n.t("export_text"),
+                                    danger: false,
+                                    onselect: ctx.link().callback(|_| Msg::Export(ExportFormat::Text)),
+                                },
+                                MenuAction {
+                                    label: self.i18n.t("export_json"),
+                                    danger: false,
+                                    onselect: ctx.link().callback(|_| Msg::Export(ExportFormat::Json)),
+                                },
+                            ]}
+                            onclose={ctx.link().callback(|_| Msg::CloseExportMenu)}/>
+                    }
+                    if let Some((x, y)) = self.clear_menu {
+                        <ContextMenu {x} {y} actions={self.clear_actions(ctx)}
+                            onclose={ctx.link().callback(|_| Msg::CloseClearMenu)}/>
+                    }
+                    if let Some(target) = &self.menu {
+                        <ContextMenu x={target.x} y={target.y} actions={self.menu_actions(ctx, target)}
+                            onclose={ctx.link().callback(|_| Msg::CloseMenu)}/>
+                    }
+                    if let Some(action) = &self.confirming {
+                        <ConfirmModal message={action.question(self.i18n)} confirm_label={action.label(self.i18n)}
+                            i18n={self.i18n} onconfirm={ctx.link().callback(|_| Msg::Confirmed)}
+                            oncancel={ctx.link().callback(|_| Msg::CancelConfirm)}/>
+                    }
+                    if self.settings_open {
+                        <SettingsModal onclose={ctx.link().callback(|_| Msg::ToggleSettings)} i18n={self.i18n}/>
+                    }
+
+                    if self.show_gif_picker {
+                        <Overlay onclose={ctx.link().callback(|_| Msg::ToggleGifPicker)}>
+                            <GifPicker provider={ctx.props().gif_provider} onselect={send_gif} i18n={self.i18n}/>
+                        </Overlay>
+                    }
+                    if let Some(mode) = self.emoji_picker.clone() {
+                        <EmojiPicker {mode} onselect={ctx.link().callback(Msg::PickEmoji)} i18n={self.i18n}
+                            onclose={ctx.link().callback(|_| Msg::CloseEmojiPicker)}/>
+                    }
+
+                    <div class="h-5 px-6 text-xs italic text-gray-500 typing-indicator" aria-live="polite">
+                        { typing_label(&self.typers(), self.i18n).unwrap_or_default() }
                     </div>
-                    
+
+                    if let Some(parent) = &self.replying_to {
+                        <div class="w-full px-6 py-2 bg-gray-50 border-t border-gray-200 flex items-center gap-2 text-sm reply-preview">
+                            <span class="min-w-0 grow truncate text-gray-600">
+                                {
+                                    match self.state().messages.iter().find(|m| &m.id == parent) {
+                                        Some(p) => html! {
+                                            <>
+                                                {self.i18n.t("replying_to")}
+                                                <span class={classes!("font-semibold", user_color(&p.from))}>{p.from.clone()}</span>
+                                                {": "}
+                                                <span dir="auto">{snippet(&p.message)}</span>
+                                            </>
+                                        },
+                                        None => html! { {self.i18n.t("replying_unloaded")} },
+                                    }
+                                }
+                            </span>
+                            <button class="flex-none text-gray-500 hover:text-gray-800" title={self.i18n.t("cancel_reply")}
+                                onclick={ctx.link().callback(|_| Msg::CancelReply)}>
+                                {"✕"}
+                            </button>
+                        </div>
+                    }
+
                     // Message input
-                    <div class="w-full px-4 py-3 bg-white border-t border-gray-200 flex items-center">
-                        <input 
-                            ref={self.chat_input.clone()} 
-                            type="text" 
-                            placeholder="Type a message..." 
-                            class="block w-full py-3 px-4 bg-gray-50 rounded-full outline-none message-input" 
-                            name="message" 
-                            required=true 
+                    <div class="w-full px-4 py-3 bg-white border-t border-gray-200 flex items-end">
+                        <textarea
+                            ref={self.chat_input.clone()}
+                            rows="1"
+                            placeholder={self.i18n.t("message_placeholder")}
+                            aria-label={self.i18n.t("message_input")}
+                            title={self.i18n.t("input_hint")}
+                            class="block w-full py-3 px-4 bg-gray-50 rounded-3xl outline-none resize-none message-input"
+                            name="message"
+                            required=true
+                            {onkeydown}
+                            {oninput}
+                            {onpaste}
                         />
-                        <button 
-                            onclick={submit} 
-                            class="p-3 ml-3 bg-primary-dark hover:bg-primary-dark w-12 h-12 rounded-full flex justify-center items-center text-white send-button"
+                        <button
+                            onclick={ctx.link().callback(|_| Msg::ToggleEmojiPicker(PickerMode::Insert))}
+                            title={self.i18n.t("insert_emoji")} aria-label={self.i18n.t("insert_emoji")}
+                            class={classes!("ms-3", "w-12", "h-12", "rounded-full", "text-xl", "emoji-button",
+                                if self.emoji_picker == Some(PickerMode::Insert) { "bg-primary-dark text-white" } else { "bg-gray-100 text-gray-600 hover:bg-gray-200" })}
+                        >
+                            {"☺"}
+                        </button>
+                        <button
+                            onclick={toggle_gif_picker}
+                            aria-label={self.i18n.t("send_gif")}
+                            class={classes!("ms-3", "px-3", "h-12", "rounded-full", "text-sm", "font-semibold", "gif-button",
+                                if self.show_gif_picker { "bg-primary-dark text-white" } else { "bg-gray-100 text-gray-600 hover:bg-gray-200" })}
+                        >
+                            {"GIF"}
+                        </button>
+                        <button
+                            onclick={submit}
+                            aria-label={self.i18n.t("send_message")}
+                            class="p-3 ms-3 bg-primary-dark hover:bg-primary-dark w-12 h-12 rounded-full flex justify-center items-center text-white send-button"
                         >
-                            <svg viewBox="0 0 24 24" xmlns="http://www.w3.org/2000/svg" class="fill-white w-5 h-5">
+                            <svg viewBox="0 0 24 24" xmlns="http://www.w3.org/2000/svg" class="fill-white w-5 h-5 rtl:-scale-x-100">
                                 <path d="M0 0h24v24H0z" fill="none"></path><path d="M2.01 21L23 12 2.01 3 2 10l15 2-15 2z"></path>
                             </svg>
                         </button>
@@ -251,3 +5005,691 @@ impl Component for Chat {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::HistoryPage;
+    use crate::services::event_bus::FrameError;
+    use serde_json::json;
+
+    /// Takes a raw frame the way `Chat` does: decoded, then applied if it decoded.
+    fn feed(state: &mut ChatState, frame: &str) -> Result<Applied, FrameError> {
+        BusEvent::from_frame(frame).map(|event| apply_incoming(state, event))
+    }
+
+    fn message_frame(id: &str, from: &str, time: u64) -> String {
+        let message = json!({ "id": id, "from": from, "message": "hi", "time": time });
+        json!({ "messageType": "message", "data": message.to_string() }).to_string()
+    }
+
+    fn users_frame(users: &[&str]) -> String {
+        let roster = json!({ "statuses": { "bob": "away" }, "admins": ["ann"] });
+        json!({ "messageType": "users", "dataArray": users, "data": roster.to_string() })
+            .to_string()
+    }
+
+    fn message(id: &str, from: &str, time: u64) -> MessageData {
+        MessageData {
+            id: id.into(),
+            from: from.into(),
+            message: format!("{} says hi", from),
+            time,
+            reactions: BTreeMap::new(),
+            read_by: vec![],
+            reply_to: None,
+            attachment: None,
+            status: DeliveryStatus::Delivered,
+        }
+    }
+
+    /// A conversation holding `messages`.
+    fn loaded(messages: impl IntoIterator<Item = MessageData>) -> ChatState {
+        let mut state = ChatState::default();
+        merge_history(&mut state, messages.into_iter().collect());
+        state
+    }
+
+    fn ids(state: &ChatState) -> Vec<&str> {
+        state.messages.iter().map(|m| m.id.as_str()).collect()
+    }
+
+    #[test]
+    fn a_users_frame_lists_who_is_here() {
+        let mut state = ChatState::default();
+        assert_eq!(
+            feed(&mut state, &users_frame(&["ann", "bob"])),
+            Ok(Applied::Users)
+        );
+        let users: Vec<_> = state
+            .users
+            .iter()
+            .map(|u| (u.name.as_str(), u.status, u.admin))
+            .collect();
+        assert_eq!(
+            users,
+            [
+                ("ann", Presence::Active, true),
+                ("bob", Presence::Away, false)
+            ]
+        );
+        assert_eq!(state.owner(), Some("ann"));
+    }
+
+    #[test]
+    fn whoever_leaves_stops_typing() {
+        let mut state = ChatState::default();
+        let typing = json!({ "room": DEFAULT_ROOM, "from": "bob", "typing": true });
+        let frame = json!({ "messageType": "typing", "data": typing.to_string() });
+        feed(&mut state, &frame.to_string()).unwrap();
+        assert_eq!(state.typers(DEFAULT_ROOM), ["bob"]);
+        feed(&mut state, &users_frame(&["ann"])).unwrap();
+        assert!(state.typers(DEFAULT_ROOM).is_empty());
+    }
+
+    #[test]
+    fn message_frames_are_kept_in_time_order_once_each() {
+        let mut state = ChatState::default();
+        for (id, time) in [("b", 20), ("a", 10), ("c", 30)] {
+            assert_eq!(
+                feed(&mut state, &message_frame(id, "bob", time)),
+                Ok(Applied::Message)
+            );
+        }
+        assert_eq!(ids(&state), ["a", "b", "c"]);
+        assert_eq!(
+            feed(&mut state, &message_frame("b", "bob", 20)),
+            Ok(Applied::Duplicate)
+        );
+        assert_eq!(ids(&state), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn the_echo_of_our_message_settles_it() {
+        let mut state = ChatState::default();
+        feed(&mut state, &message_frame("a", "bob", 20)).unwrap();
+        state.push_message(MessageData {
+            status: DeliveryStatus::Pending,
+            ..message("ours", "ann", 10)
+        });
+        assert_eq!(ids(&state), ["ours", "a"]);
+        assert_eq!(
+            feed(&mut state, &message_frame("ours", "ann", 30)),
+            Ok(Applied::Delivered("ours".into()))
+        );
+        // Moved to where the server's time puts it.
+        assert_eq!(ids(&state), ["a", "ours"]);
+        assert_eq!(state.messages[1].status, DeliveryStatus::Delivered);
+    }
+
+    #[test]
+    fn malformed_frames_change_nothing() {
+        let mut state = ChatState::default();
+        feed(&mut state, &users_frame(&["ann", "bob"])).unwrap();
+        feed(&mut state, &message_frame("a", "bob", 10)).unwrap();
+        let message = message_frame("b", "bob", 20);
+        let no_sender = json!({ "id": "b", "from": " ", "message": "hi" });
+        for frame in [
+            &message[..message.len() / 2],
+            r#"{"messageType":"message"}"#,
+            r#"{"messageType":"message","data":{"from":"bob","message":"hi"}}"#,
+            &json!({ "messageType": "message", "data": no_sender.to_string() }).to_string(),
+            r#"{"messageType":"users","data":"{}"}"#,
+            r#"{"messageType":"delete","data":" "}"#,
+        ] {
+            assert!(feed(&mut state, frame).is_err(), "{:?}", frame);
+            assert_eq!(ids(&state), ["a"], "{:?}", frame);
+            assert_eq!(state.users.len(), 2, "{:?}", frame);
+        }
+    }
+
+    #[test]
+    fn frames_of_unknown_types_change_nothing() {
+        let mut state = ChatState::default();
+        feed(&mut state, &message_frame("a", "bob", 10)).unwrap();
+        assert!(matches!(
+            feed(&mut state, r#"{"messageType":"bogus","data":"a"}"#),
+            Err(FrameError::Malformed(_))
+        ));
+        // Types only clients send.
+        assert_eq!(
+            feed(&mut state, r#"{"messageType":"register","data":"eve"}"#),
+            Err(FrameError::Unexpected(MsgTypes::Register))
+        );
+        assert_eq!(
+            feed(&mut state, r#"{"messageType":"status","data":"away"}"#),
+            Err(FrameError::Unexpected(MsgTypes::Status))
+        );
+        assert_eq!(ids(&state), ["a"]);
+        assert!(state.users.is_empty());
+    }
+
+    struct Drained {
+        /// The ids the socket was handed, whether or not it took them.
+        handed: Vec<String>,
+        sent: Vec<String>,
+        stopped: Option<(String, SendError)>,
+        left: Vec<String>,
+    }
+
+    /// Runs `drain_outbox` over `queued` with a socket that gives each send the next of
+    /// `results`, and `Ok` once they run out.
+    fn drain(
+        queued: &[&str],
+        messages: &[MessageData],
+        results: &[Result<(), SendError>],
+    ) -> Drained {
+        let mut outbox: VecDeque<String> = queued.iter().map(|id| id.to_string()).collect();
+        let (mut handed, mut results) = (vec![], results.iter());
+        let (sent, stopped) = drain_outbox(&mut outbox, messages, |frame| {
+            handed.push(frame.id.clone().unwrap());
+            results.next().copied().unwrap_or(Ok(()))
+        });
+        Drained {
+            handed,
+            sent,
+            stopped,
+            left: outbox.into(),
+        }
+    }
+
+    #[test]
+    fn the_outbox_goes_in_the_order_it_was_typed() {
+        // Typed in an order their times don't follow, as when the clock was corrected.
+        let messages = [
+            message("a", "ann", 30),
+            message("b", "ann", 10),
+            message("c", "ann", 20),
+        ];
+        let d = drain(&["a", "b", "c"], &messages, &[]);
+        assert_eq!(d.handed, ["a", "b", "c"]);
+        assert_eq!(d.sent, ["a", "b", "c"]);
+        assert_eq!(d.stopped, None);
+        assert!(d.left.is_empty());
+    }
+
+    #[test]
+    fn a_full_socket_keeps_the_rest_in_line() {
+        let messages = [
+            message("a", "ann", 10),
+            message("b", "ann", 20),
+            message("c", "ann", 30),
+        ];
+        let d = drain(&["a", "b", "c"], &messages, &[Ok(()), Err(SendError::Full)]);
+        assert_eq!(d.handed, ["a", "b"]);
+        assert_eq!(d.sent, ["a"]);
+        assert_eq!(d.stopped, Some(("b".into(), SendError::Full)));
+        assert_eq!(d.left, ["b", "c"]);
+    }
+
+    #[test]
+    fn a_closed_socket_takes_off_the_one_it_stopped_at() {
+        let messages = [
+            message("a", "ann", 10),
+            message("b", "ann", 20),
+            message("c", "ann", 30),
+        ];
+        let d = drain(
+            &["a", "b", "c"],
+            &messages,
+            &[Ok(()), Err(SendError::Closed)],
+        );
+        assert_eq!(d.handed, ["a", "b"]);
+        assert_eq!(d.sent, ["a"]);
+        assert_eq!(d.stopped, Some(("b".into(), SendError::Closed)));
+        assert_eq!(d.left, ["c"]);
+    }
+
+    #[test]
+    fn messages_deleted_while_queued_are_dropped() {
+        let messages = [message("a", "ann", 10), message("c", "ann", 30)];
+        let d = drain(&["a", "gone", "c"], &messages, &[]);
+        assert_eq!(d.handed, ["a", "c"]);
+        assert_eq!(d.stopped, None);
+        assert!(d.left.is_empty());
+    }
+
+    #[test]
+    fn a_history_page_adds_what_was_missing() {
+        let mut state = loaded([message("b", "bob", 20)]);
+        let page = HistoryPage {
+            messages: vec![
+                message("a", "ann", 10),
+                message("b", "bob", 20),
+                message("c", "ann", 30),
+            ],
+            has_more: true,
+            around: None,
+        };
+        assert_eq!(
+            apply_incoming(&mut state, BusEvent::HistoryReceived(Ok(page))),
+            Applied::History {
+                added: vec!["a".into(), "c".into()],
+                has_more: Some(true),
+            }
+        );
+        assert_eq!(ids(&state), ["a", "b", "c"]);
+
+        let broken = Err(FrameError::Empty("history page"));
+        assert_eq!(
+            apply_incoming(&mut state, BusEvent::HistoryReceived(broken)),
+            Applied::History {
+                added: vec![],
+                has_more: None,
+            }
+        );
+        assert_eq!(ids(&state), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn a_deleted_message_goes_with_its_pin() {
+        let mut state = loaded([message("a", "ann", 10), message("b", "bob", 20)]);
+        let pin = PinNotice {
+            id: "a".into(),
+            pinned: true,
+            from: "bob".into(),
+        };
+        assert_eq!(
+            apply_incoming(&mut state, BusEvent::PinToggled(pin)),
+            Applied::Pins
+        );
+        assert_eq!(state.pinned, ["a"]);
+        assert_eq!(
+            apply_incoming(&mut state, BusEvent::MessageDeleted("a".into())),
+            Applied::Deleted("a".into())
+        );
+        assert_eq!(ids(&state), ["b"]);
+        assert!(!state.has("a"));
+        assert!(state.pinned.is_empty());
+    }
+
+    #[test]
+    fn a_room_clear_keeps_only_our_undelivered_messages() {
+        let pending = MessageData {
+            status: DeliveryStatus::Pending,
+            ..message("ours", "ann", 30)
+        };
+        let mut state = loaded([message("a", "bob", 10), pending]);
+        apply_incoming(&mut state, BusEvent::PinsListed(vec!["a".into()]));
+        assert_eq!(
+            apply_incoming(&mut state, BusEvent::RoomCleared("ann".into())),
+            Applied::Cleared("ann".into())
+        );
+        assert_eq!(ids(&state), ["ours"]);
+        assert!(state.pinned.is_empty());
+    }
+
+    #[test]
+    fn a_reaction_toggles() {
+        let mut state = loaded([message("a", "ann", 10)]);
+        let reaction = |id: &str| {
+            BusEvent::ReactionToggled(Reaction {
+                id: id.into(),
+                from: "bob".into(),
+                emoji: "👍".into(),
+            })
+        };
+        assert_eq!(
+            apply_incoming(&mut state, reaction("a")),
+            Applied::Reacted(Some("a".into()))
+        );
+        assert_eq!(state.messages[0].reactions["👍"], ["bob"]);
+        apply_incoming(&mut state, reaction("a"));
+        assert!(state.messages[0].reactions.is_empty());
+        assert_eq!(
+            apply_incoming(&mut state, reaction("missing")),
+            Applied::Reacted(None)
+        );
+    }
+
+    #[test]
+    fn a_read_receipt_marks_others_messages_once() {
+        let mut state = loaded([message("a", "ann", 10), message("b", "bob", 20)]);
+        let read = || {
+            BusEvent::MessagesRead(ReadReceipt {
+                ids: vec!["a".into(), "b".into(), "missing".into()],
+                from: "bob".into(),
+            })
+        };
+        // Bob wrote `b`, so reading it doesn't count.
+        assert_eq!(
+            apply_incoming(&mut state, read()),
+            Applied::Read(vec!["a".into()])
+        );
+        assert_eq!(apply_incoming(&mut state, read()), Applied::Read(vec![]));
+        assert_eq!(state.messages[0].read_by, ["bob"]);
+        assert!(state.messages[1].read_by.is_empty());
+    }
+
+    #[test]
+    fn typing_notices_start_and_stop() {
+        let mut state = ChatState::default();
+        let notice = |typing| TypingNotice {
+            room: DEFAULT_ROOM.into(),
+            from: "bob".into(),
+            typing,
+        };
+        assert_eq!(
+            apply_incoming(&mut state, BusEvent::TypingChanged(notice(true))),
+            Applied::Typing(notice(true))
+        );
+        assert_eq!(state.typers(DEFAULT_ROOM), ["bob"]);
+        // A repeat still counts, as they're still at it.
+        assert_eq!(
+            apply_incoming(&mut state, BusEvent::TypingChanged(notice(true))),
+            Applied::Typing(notice(true))
+        );
+        apply_incoming(&mut state, BusEvent::TypingChanged(notice(false)));
+        assert!(state.typers(DEFAULT_ROOM).is_empty());
+        assert!(state.typers("elsewhere").is_empty());
+    }
+
+    #[test]
+    fn a_pin_list_replaces_the_pins() {
+        let mut state = ChatState::default();
+        apply_incoming(&mut state, BusEvent::PinsListed(vec!["old".into()]));
+        let listed = vec!["a".into(), "b".into(), "a".into()];
+        assert_eq!(
+            apply_incoming(&mut state, BusEvent::PinsListed(listed)),
+            Applied::Pins
+        );
+        assert_eq!(state.pinned, ["a", "b"]);
+    }
+
+    #[test]
+    fn kicks_and_rejections_are_passed_back_untouched() {
+        let mut state = loaded([message("a", "ann", 10)]);
+        let notice = KickNotice {
+            nick: "bob".into(),
+            by: "ann".into(),
+        };
+        assert_eq!(
+            apply_incoming(&mut state, BusEvent::Kicked(notice.clone())),
+            Applied::Kicked(notice)
+        );
+        assert_eq!(
+            apply_incoming(&mut state, BusEvent::RegisterRejected("taken".into())),
+            Applied::RegisterRejected("taken".into())
+        );
+        assert_eq!(ids(&state), ["a"]);
+    }
+
+    #[test]
+    fn a_server_error_fails_the_message_it_names() {
+        let pending = |id: &str| MessageData {
+            status: DeliveryStatus::Pending,
+            ..message(id, "ann", 10)
+        };
+        let mut state = loaded([pending("a"), pending("b")]);
+        let error = |id: Option<&str>| ServerError {
+            code: "rate_limited".into(),
+            reason: None,
+            id: id.map(str::to_string),
+        };
+        assert_eq!(
+            apply_incoming(&mut state, BusEvent::ServerError(error(Some("a")))),
+            Applied::ServerError(error(Some("a")))
+        );
+        apply_incoming(&mut state, BusEvent::ServerError(error(None)));
+        let statuses: Vec<_> = state.messages.iter().map(|m| m.status).collect();
+        assert_eq!(statuses, [DeliveryStatus::Failed, DeliveryStatus::Pending]);
+    }
+
+    #[test]
+    fn a_sender_filter_composes_with_the_search() {
+        let messages = [
+            MessageData {
+                message: "Lunch?".into(),
+                ..message("a", "ann", 10)
+            },
+            MessageData {
+                message: "lunch at noon".into(),
+                ..message("b", "bob", 20)
+            },
+            MessageData {
+                message: "see you".into(),
+                ..message("c", "bob", 30)
+            },
+        ];
+        let passing = |query: &str, sender: Option<&str>| -> Vec<&str> {
+            messages
+                .iter()
+                .filter(|m| passes_filters(m, query, sender, None))
+                .map(|m| m.id.as_str())
+                .collect()
+        };
+        assert_eq!(passing("", None), ["a", "b", "c"]);
+        assert_eq!(passing("", Some("bob")), ["b", "c"]);
+        assert_eq!(passing("lunch", None), ["a", "b"]);
+        assert_eq!(passing("lunch", Some("bob")), ["b"]);
+        assert!(passing("lunch", Some("cat")).is_empty());
+        // The sender's name is searched too.
+        assert_eq!(passing("ann", None), ["a"]);
+
+        // Searching lowercases the query itself, and a blank one finds nothing.
+        assert_eq!(search_matches(&messages, " LUNCH ", Some("bob"), None), [1]);
+        assert!(search_matches(&messages, "  ", None, None).is_empty());
+    }
+
+    #[test]
+    fn a_message_fed_twice_is_one_bubble() {
+        let mut state = ChatState::default();
+        let received = || BusEvent::MessageReceived(message("a", "bob", 10));
+        assert_eq!(apply_incoming(&mut state, received()), Applied::Message);
+        assert_eq!(apply_incoming(&mut state, received()), Applied::Duplicate);
+        assert!(!state.push_message(message("a", "bob", 10)));
+        // A page that overlaps what's here only adds what's new.
+        let page = vec![message("a", "bob", 10), message("b", "bob", 20)];
+        assert_eq!(merge_history(&mut state, page), ["b"]);
+        assert_eq!(ids(&state), ["a", "b"]);
+    }
+
+    #[test]
+    fn a_reaction_toggles_per_user_in_the_order_they_reacted() {
+        let mut reactions = BTreeMap::new();
+        toggle_reaction(&mut reactions, "bob", "👍");
+        toggle_reaction(&mut reactions, "ann", "👍");
+        toggle_reaction(&mut reactions, "bob", "🎉");
+        assert_eq!(reactions["👍"], ["bob", "ann"]);
+        assert_eq!(reactions["🎉"], ["bob"]);
+
+        toggle_reaction(&mut reactions, "bob", "👍");
+        assert_eq!(reactions["👍"], ["ann"]);
+        // An emoji nobody's reacting with any more goes.
+        toggle_reaction(&mut reactions, "bob", "🎉");
+        assert!(!reactions.contains_key("🎉"));
+    }
+
+    #[test]
+    fn messages_are_inserted_by_time_then_id() {
+        let mut state = loaded([
+            message("a", "ann", 10),
+            message("c", "ann", 30),
+            message("e", "ann", 50),
+        ]);
+        state.push_message(message("d", "bob", 40));
+        state.push_message(message("b", "bob", 20));
+        assert_eq!(ids(&state), ["a", "b", "c", "d", "e"]);
+
+        // The same millisecond goes by id, whichever arrives first.
+        for id in ["y", "x", "z"] {
+            state.push_message(message(id, "bob", 30));
+        }
+        assert_eq!(ids(&state), ["a", "b", "c", "x", "y", "z", "d", "e"]);
+        assert_eq!(
+            insertion_point(&state.messages, &message("w", "bob", 30)),
+            3
+        );
+        assert_eq!(
+            insertion_point(&state.messages, &message("f", "bob", 60)),
+            8
+        );
+        assert_eq!(insertion_point(&state.messages, &message("f", "bob", 0)), 0);
+    }
+
+    #[test]
+    fn relative_times_round_down() {
+        const MINUTE: u64 = 60 * 1000;
+        let now = 1_000 * RELATIVE_LIMIT_MS;
+        assert_eq!(relative_time(now, now), Some(Ago::JustNow));
+        assert_eq!(relative_time(now - MINUTE + 1, now), Some(Ago::JustNow));
+        // A sender's clock a little ahead of ours.
+        assert_eq!(relative_time(now + MINUTE, now), Some(Ago::JustNow));
+        assert_eq!(relative_time(now - MINUTE, now), Some(Ago::Minutes(1)));
+        assert_eq!(
+            relative_time(now - 60 * MINUTE + 1, now),
+            Some(Ago::Minutes(59))
+        );
+        assert_eq!(relative_time(now - 60 * MINUTE, now), Some(Ago::Hours(1)));
+        assert_eq!(
+            relative_time(now - RELATIVE_LIMIT_MS + 1, now),
+            Some(Ago::Hours(5))
+        );
+        assert_eq!(relative_time(now - RELATIVE_LIMIT_MS, now), None);
+    }
+
+    #[test]
+    fn pins_are_added_removed_and_capped() {
+        let mut pinned = Vec::new();
+        assert!(set_pinned(&mut pinned, "a", true));
+        assert!(!set_pinned(&mut pinned, "a", true));
+        assert!(!set_pinned(&mut pinned, "b", false));
+        assert_eq!(pinned, ["a"]);
+
+        for i in 1..PIN_LIMIT {
+            assert!(set_pinned(&mut pinned, &i.to_string(), true));
+        }
+        assert_eq!(pinned.len(), PIN_LIMIT);
+        assert!(!set_pinned(&mut pinned, "over", true));
+        assert_eq!(pinned.len(), PIN_LIMIT);
+
+        // Unpinning makes room again, and the rest keep their order.
+        assert!(set_pinned(&mut pinned, "a", false));
+        assert!(set_pinned(&mut pinned, "over", true));
+        assert_eq!(pinned.first().map(String::as_str), Some("1"));
+        assert_eq!(pinned.last().map(String::as_str), Some("over"));
+    }
+
+    #[test]
+    fn muting_hides_past_and_future_messages_until_unmuted() {
+        let visible = |state: &ChatState, muted: &HashSet<String>| -> Vec<String> {
+            state
+                .messages
+                .iter()
+                .filter(|m| passes_filters(m, "", None, Some(muted)))
+                .map(|m| m.id.clone())
+                .collect()
+        };
+        let mut state = loaded([message("1", "ann", 1), message("2", "bob", 2)]);
+        let mut muted = HashSet::from(["bob".to_string()]);
+        assert_eq!(visible(&state, &muted), ["1"]);
+
+        state.push_message(message("3", "bob", 3));
+        state.push_message(message("4", "ann", 4));
+        assert_eq!(visible(&state, &muted), ["1", "4"]);
+        assert!(search_matches(&state.messages, "bob", None, Some(&muted)).is_empty());
+
+        // Nothing was dropped, so unmuting brings it all back.
+        muted.remove("bob");
+        assert_eq!(visible(&state, &muted), ["1", "2", "3", "4"]);
+        assert_eq!(
+            search_matches(&state.messages, "bob", None, Some(&muted)),
+            [1, 2]
+        );
+    }
+
+    #[test]
+    fn the_unread_divider_follows_the_last_read_and_our_own_messages() {
+        let messages = [
+            message("1", "ann", 1),
+            message("2", "bob", 2),
+            message("3", "me", 3),
+            message("4", "ann", 4),
+            message("5", "bob", 5),
+        ];
+        assert_eq!(unread_boundary(&messages, "1", "carol"), Some(1));
+        // Our reply counts as having read what came before it.
+        assert_eq!(unread_boundary(&messages, "1", "me"), Some(3));
+        assert_eq!(unread_boundary(&messages, "4", "me"), Some(4));
+        assert_eq!(unread_boundary(&messages, "5", "me"), None);
+        // Last read scrolled out of what's loaded, and nothing of ours either.
+        assert_eq!(unread_boundary(&messages, "0", "carol"), None);
+        assert_eq!(unread_boundary(&messages, "0", "me"), Some(3));
+        assert_eq!(unread_boundary(&[], "1", "me"), None);
+    }
+
+    #[test]
+    fn the_typing_indicator_names_up_to_three() {
+        let (en, id) = (I18n::default(), I18n::new("id"));
+        assert_eq!(typing_label(&[], en), None);
+        assert_eq!(typing_label(&["ann"], en).unwrap(), "ann is typing…");
+        assert_eq!(
+            typing_label(&["ann", "bob", "cy"], en).unwrap(),
+            "ann, bob and cy are typing…"
+        );
+        assert_eq!(
+            typing_label(&["a", "b", "c", "d"], id).unwrap(),
+            "4 orang sedang mengetik…"
+        );
+    }
+
+    #[test]
+    fn only_admins_get_kick_buttons_and_not_on_admins() {
+        let profile = |name: &str, admin: bool| UserProfile {
+            name: name.into(),
+            status: Presence::Active,
+            admin,
+            activity: None,
+        };
+        let (ann, root) = (profile("ann", true), profile("root", true));
+        let (bob, cy) = (profile("bob", false), profile("cy", false));
+        assert!(can_kick(Some(&ann), &bob));
+        assert!(!can_kick(Some(&ann), &ann), "admins can't kick themselves");
+        assert!(!can_kick(Some(&ann), &root), "or another admin");
+        assert!(!can_kick(Some(&cy), &bob), "nor can anyone else");
+        assert!(!can_kick(None, &bob), "or anyone not on the list yet");
+    }
+
+    #[test]
+    fn row_keys_move_focus_without_wrapping() {
+        use RowNav::*;
+        assert_eq!(row_nav("ArrowUp", 2, 5), Some(Row(1)));
+        assert_eq!(row_nav("ArrowUp", 0, 5), Some(Row(0)));
+        assert_eq!(row_nav("ArrowDown", 2, 5), Some(Row(3)));
+        assert_eq!(row_nav("ArrowDown", 4, 5), Some(Row(4)));
+        // A row that's gone since it was focused counts as the last.
+        assert_eq!(row_nav("ArrowUp", 9, 5), Some(Row(3)));
+        assert_eq!(row_nav("ArrowDown", 9, 5), Some(Row(4)));
+        assert_eq!(row_nav("Home", 3, 5), Some(Row(0)));
+        assert_eq!(row_nav("End", 1, 5), Some(Row(4)));
+        assert_eq!(row_nav("Enter", 1, 5), Some(Toolbar));
+        assert_eq!(row_nav("Escape", 1, 5), Some(Input));
+        for key in ["Tab", "a", " ", "PageDown"] {
+            assert_eq!(row_nav(key, 1, 5), None, "{:?}", key);
+        }
+        for key in ROW_KEYS {
+            assert_eq!(row_nav(key, 0, 0), None, "{:?} with no rows", key);
+        }
+    }
+
+    #[test]
+    fn bubbles_are_coloured_by_sender_and_framed_by_content() {
+        let classes = |own, image| -> Vec<&str> { bubble_class(own, image).split(' ').collect() };
+        for own in [true, false] {
+            for image in [true, false] {
+                let c = classes(own, image);
+                let case = format!("own {}, image {}", own, image);
+                assert!(c.contains(&"message-bubble"), "{}", case);
+                assert_eq!(c.contains(&"bg-primary-light"), own, "{}", case);
+                assert_eq!(c.contains(&"text-white"), own, "{}", case);
+                assert_eq!(c.contains(&"bg-white"), !own, "{}", case);
+                assert_eq!(c.contains(&"shadow-sm"), !own, "{}", case);
+                assert_eq!(c.contains(&"image-bubble"), image, "{}", case);
+                assert_eq!(c.contains(&"max-w-sm"), image, "{}", case);
+                assert_eq!(c.contains(&"max-w-md"), !image, "{}", case);
+                assert_eq!(c.contains(&"p-2"), image, "{}", case);
+                assert_eq!(c.contains(&"px-4"), !image, "{}", case);
+            }
+        }
+    }
+}
@@ -0,0 +1,249 @@
+//! `ChatFn`: the chat written as a function component. It covers the core of `Chat` —
+//! registering once connected, applying incoming frames, and sending on submit — with
+//! the conversation kept in the shared `Store` and changed only by dispatching to it, and
+//! the socket left to `use_websocket`. `Chat` stays the full-featured version.
+
+use std::collections::{BTreeMap, HashMap};
+
+use gloo_timers::callback::Timeout;
+use web_sys::HtmlTextAreaElement;
+use yew::functional::*;
+use yew::prelude::*;
+use yew_router::prelude::{use_history, History};
+
+use crate::avatar::user_color;
+use crate::components::avatar::Avatar;
+use crate::components::chat::{render_body, status_icon, ECHO_TIMEOUT_MS};
+use crate::hooks::{use_store, use_toasts, use_websocket};
+use crate::i18n::I18n;
+use crate::ids::new_id;
+use crate::protocol::{DeliveryStatus, MessageData, MsgTypes, WebSocketMessage};
+use crate::services::event_bus::{BusEvent, DEFAULT_ROOM};
+use crate::services::websocket::{ConnState, SendError, WS_URL};
+use crate::store::{Slice, StoreAction};
+use crate::toasts::Toast;
+use crate::{Route, User};
+
+fn frame(message_type: MsgTypes, data: Option<String>, id: Option<String>) -> WebSocketMessage {
+    WebSocketMessage {
+        message_type,
+        data,
+        data_array: None,
+        id,
+        reply_to: None,
+        attachment: None,
+    }
+}
+
+#[function_component(ChatFn)]
+pub fn chat_fn() -> Html {
+    let user = use_context::<User>().expect("No context found.");
+    let username = user.username.borrow().clone();
+    let store = use_store(vec![
+        Slice::Room(DEFAULT_ROOM.to_string()),
+        Slice::Users,
+        Slice::Connection,
+    ]);
+    let state = store.state.room(DEFAULT_ROOM);
+    let users = store.state.users();
+    let conn = store.state.conn();
+    let input = use_node_ref();
+
+    let history = use_history();
+
+    // Held so none fire after the component is gone.
+    let echo_timeouts = use_mut_ref(HashMap::<String, Timeout>::new);
+    let ws = {
+        let (store, user) = (store.clone(), user.clone());
+        let echo_timeouts = echo_timeouts.clone();
+        use_websocket(
+            WS_URL,
+            Callback::from(move |e| match e {
+                // Back to the login screen, which says why.
+                BusEvent::RegisterRejected(reason) => {
+                    *user.register_error.borrow_mut() = Some(reason);
+                    if let Some(history) = &history {
+                        history.push(Route::Login);
+                    }
+                }
+                BusEvent::Kicked(notice) if notice.nick == *user.username.borrow() => {
+                    *user.register_error.borrow_mut() =
+                        Some(I18n::pick(None).fill("kicked_by", &[&notice.by]));
+                    if let Some(history) = &history {
+                        history.push(Route::Login);
+                    }
+                }
+                e => {
+                    // An echo settles the message, so its timeout has nothing left to do.
+                    if let BusEvent::MessageReceived(m) = &e {
+                        echo_timeouts.borrow_mut().remove(&m.id);
+                    }
+                    store.dispatch(StoreAction::Incoming(e))
+                }
+            }),
+        )
+    };
+
+    {
+        // The server forgets us on disconnect, so register on every open.
+        let (socket, username, store) = (ws.clone(), username.clone(), store.clone());
+        use_effect_with_deps(
+            move |conn_state| {
+                store.dispatch(StoreAction::Connection(*conn_state));
+                if *conn_state == ConnState::Open {
+                    let _ = socket.send(&frame(MsgTypes::Register, Some(username), None));
+                    let _ = socket.send(&frame(MsgTypes::History, None, None));
+                }
+                || ()
+            },
+            ws.state,
+        );
+    }
+
+    // Sends the message with this id and body, and gives the server until the echo
+    // timeout to confirm it.
+    let toasts = use_toasts();
+    let deliver = {
+        let (ws, store) = (ws.clone(), store.clone());
+        Callback::from(move |(id, body): (String, String)| {
+            if let Err(e) = ws.send(&frame(MsgTypes::Message, Some(body), Some(id.clone()))) {
+                store.dispatch(StoreAction::Failed(DEFAULT_ROOM.to_string(), id));
+                toasts.push.emit(Toast::error(match e {
+                    SendError::Full => "Too much is waiting to be sent. Try again in a moment.",
+                    SendError::Closed => "Your message couldn't be sent.",
+                }));
+                return;
+            }
+            let (store, timeout_id) = (store.clone(), id.clone());
+            echo_timeouts.borrow_mut().insert(
+                id,
+                Timeout::new(ECHO_TIMEOUT_MS, move || {
+                    store.dispatch(StoreAction::Failed(DEFAULT_ROOM.to_string(), timeout_id))
+                }),
+            );
+        })
+    };
+
+    let submit = {
+        let (store, input, username) = (store.clone(), input.clone(), username.clone());
+        let deliver = deliver.clone();
+        Callback::from(move |_| {
+            let input = match input.cast::<HtmlTextAreaElement>() {
+                Some(input) if !input.value().trim().is_empty() => input,
+                _ => return,
+            };
+            let id = new_id();
+            let message = MessageData {
+                id: id.clone(),
+                from: username.clone(),
+                message: input.value(),
+                time: js_sys::Date::now() as u64,
+                reactions: BTreeMap::new(),
+                read_by: vec![],
+                reply_to: None,
+                attachment: None,
+                status: DeliveryStatus::Pending,
+            };
+            store.dispatch(StoreAction::Sent(DEFAULT_ROOM.to_string(), message));
+            deliver.emit((id, input.value()));
+            input.set_value("");
+        })
+    };
+    let onkeydown = {
+        let submit = submit.clone();
+        Callback::from(move |e: KeyboardEvent| {
+            if e.key() == "Enter" && !e.shift_key() && !e.is_composing() {
+                e.prevent_default();
+                submit.emit(());
+            }
+        })
+    };
+    let i18n = I18n::pick(None);
+
+    html! {
+        <div class="flex w-screen chat-bg">
+            <div class="flex-none w-64 h-screen user-list overflow-y-auto">
+                <div class="text-xl p-4 font-semibold border-b border-gray-200">
+                    {format!("👥 Users ({})", users.len())}
+                </div>
+                {
+                    users.iter().map(|u| html! {
+                        <div key={u.name.clone()} class="flex items-center m-3 bg-white rounded-xl p-3 shadow-sm user-item">
+                            <Avatar name={u.name.clone()}/>
+                            <span class={classes!("ml-3", "text-sm", "font-medium", user_color(&u.name))}>{u.name.clone()}</span>
+                        </div>
+                    }).collect::<Html>()
+                }
+            </div>
+            <div class="grow h-screen flex flex-col">
+                <div class="w-full h-16 chat-header flex items-center px-6 border-b border-gray-200">
+                    <div class="text-xl font-semibold">{"💬 YewChat"}</div>
+                    if conn == ConnState::Unsupported {
+                        <div class="ml-auto text-sm text-red-700">{"This browser doesn't support WebSockets"}</div>
+                    } else if conn == ConnState::Offline {
+                        <div class="ml-auto text-sm text-gray-500">{"You are offline"}</div>
+                    } else if conn == ConnState::Stopped {
+                        <div class="ml-auto text-sm text-red-700">
+                            {"Connection lost — "}
+                            <button class="font-semibold underline" onclick={let ws = ws.clone(); Callback::from(move |_| ws.reconnect())}>
+                                {"Reconnect"}
+                            </button>
+                        </div>
+                    } else if conn != ConnState::Open {
+                        <div class="ml-auto text-sm text-gray-500">{"Connecting…"}</div>
+                    }
+                </div>
+                <div class="w-full grow overflow-auto p-6 space-y-4 messages-container">
+                    {
+                        state.messages.iter().map(|m| {
+                            let mine = m.from == username;
+                            let retry = {
+                                let (store, deliver) = (store.clone(), deliver.clone());
+                                let (id, body) = (m.id.clone(), m.message.clone());
+                                Callback::from(move |_| {
+                                    store.dispatch(StoreAction::Retried(DEFAULT_ROOM.to_string(), id.clone()));
+                                    deliver.emit((id.clone(), body.clone()));
+                                })
+                            };
+                            html! {
+                                <div key={m.id.clone()} class={classes!("flex", "message-row", mine.then_some("justify-end"))}>
+                                    <div class={classes!(
+                                        "max-w-md", "rounded-2xl", "py-2", "px-4", "message-bubble",
+                                        if mine { "bg-primary-light text-white" } else { "bg-white shadow-sm" },
+                                        (m.status != DeliveryStatus::Delivered).then_some("opacity-60"),
+                                    )}>
+                                        if !mine {
+                                            <div class={classes!("font-medium", "text-sm", "mb-1", user_color(&m.from))}>{m.from.clone()}</div>
+                                        }
+                                        { render_body(&m.message, "", i18n) }
+                                        if mine {
+                                            <div class="flex justify-end text-xs mt-1 message-time">
+                                                if m.status == DeliveryStatus::Failed {
+                                                    <button class="flex items-center gap-1 font-medium" title="Retry sending" onclick={retry}>
+                                                        {"Failed · Retry"}{status_icon(m.status)}
+                                                    </button>
+                                                } else {
+                                                    {status_icon(m.status)}
+                                                }
+                                            </div>
+                                        }
+                                    </div>
+                                </div>
+                            }
+                        }).collect::<Html>()
+                    }
+                </div>
+                <div class="w-full px-4 py-3 bg-white border-t border-gray-200 flex items-end">
+                    <textarea ref={input} rows="1" placeholder="Type a message..." name="message" {onkeydown}
+                        class="block w-full py-3 px-4 bg-gray-50 rounded-3xl outline-none resize-none message-input"/>
+                    <button onclick={submit.reform(|_: MouseEvent| ())}
+                        class="p-3 ml-3 bg-primary-dark w-12 h-12 rounded-full flex justify-center items-center text-white send-button">
+                        <svg viewBox="0 0 24 24" xmlns="http://www.w3.org/2000/svg" class="fill-white w-5 h-5">
+                            <path d="M0 0h24v24H0z" fill="none"></path><path d="M2.01 21L23 12 2.01 3 2 10l15 2-15 2z"></path>
+                        </svg>
+                    </button>
+                </div>
+            </div>
+        </div>
+    }
+}
@@ -0,0 +1,86 @@
+use gloo_timers::future::TimeoutFuture;
+use wasm_bindgen_futures::spawn_local;
+use yew::functional::*;
+use yew::prelude::*;
+
+use crate::i18n::I18n;
+use crate::services::clipboard::copy_text;
+use crate::syntax::highlight;
+
+// How long the copy button says "Copied" after a successful copy.
+const COPIED_FEEDBACK_MS: u32 = 1500;
+// Longer blocks show this many lines until they're expanded.
+const FOLDED_LINES: usize = 15;
+
+#[derive(Properties, PartialEq)]
+pub struct CodeBlockProps {
+    pub code: String,
+    #[prop_or_default]
+    pub lang: Option<String>,
+    #[prop_or_default]
+    pub i18n: I18n,
+}
+
+/// A fenced code block from a message: monospaced, scrollable, coloured if we know its
+/// language, with a copy button. A long one shows its first lines, with a button for the
+/// rest. Copying takes all of it.
+#[function_component(CodeBlock)]
+pub fn code_block(props: &CodeBlockProps) -> Html {
+    let i18n = props.i18n;
+    let copied = use_state(|| false);
+    let expanded = use_state(|| false);
+    let lines = props.code.lines().count();
+    let folded = lines > FOLDED_LINES && !*expanded;
+    let shown = if folded {
+        props
+            .code
+            .lines()
+            .take(FOLDED_LINES)
+            .collect::<Vec<_>>()
+            .join("\n")
+    } else {
+        props.code.clone()
+    };
+    let toggle = {
+        let expanded = expanded.clone();
+        Callback::from(move |_| expanded.set(!*expanded))
+    };
+
+    let onclick = {
+        let copied = copied.clone();
+        let code = props.code.clone();
+        Callback::from(move |_| {
+            let copied = copied.clone();
+            let code = code.clone();
+            spawn_local(async move {
+                match copy_text(&code).await {
+                    Ok(()) => {
+                        copied.set(true);
+                        TimeoutFuture::new(COPIED_FEEDBACK_MS).await;
+                        copied.set(false);
+                    }
+                    Err(e) => log::warn!("couldn't copy code: {:?}", e),
+                }
+            });
+        })
+    };
+
+    html! {
+        // Code reads left to right even in a right-to-left layout.
+        <div class="group my-1 rounded-lg bg-gray-900 text-gray-100 text-left code-block" dir="ltr">
+            <div class="flex items-center justify-between px-3 pt-1 text-xs text-gray-400">
+                <span class="font-mono">{props.lang.clone().unwrap_or_default()}</span>
+                <button class="opacity-0 group-hover:opacity-100 focus:opacity-100 hover:text-white" {onclick}>
+                    { i18n.t(if *copied { "copied" } else { "copy" }) }
+                </button>
+            </div>
+            <pre class="overflow-x-auto px-3 pb-2 text-xs font-mono"><code>{ highlight(&shown, props.lang.as_deref()) }</code></pre>
+            if lines > FOLDED_LINES {
+                <button class="w-full px-3 pb-1 text-xs text-start text-gray-400 hover:text-white fold-toggle"
+                    aria-expanded={(!folded).to_string()} onclick={toggle}>
+                    { if folded { i18n.fill("show_all_lines", &[&lines]) } else { i18n.t("show_less").to_string() } }
+                </button>
+            }
+        </div>
+    }
+}
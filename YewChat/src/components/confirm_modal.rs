@@ -0,0 +1,77 @@
+use web_sys::HtmlElement;
+use yew::functional::*;
+use yew::prelude::*;
+
+use crate::components::overlay::Overlay;
+use crate::i18n::I18n;
+
+#[derive(Properties, PartialEq)]
+pub struct ConfirmModalProps {
+    /// What's about to happen, and what it can't undo.
+    pub message: String,
+    /// The button that goes ahead, like "Delete".
+    pub confirm_label: &'static str,
+    /// Draws the confirm button in red, for actions that destroy something.
+    #[prop_or(true)]
+    pub danger: bool,
+    pub onconfirm: Callback<()>,
+    /// Called on Cancel, Esc, or a click outside the dialog.
+    pub oncancel: Callback<()>,
+    #[prop_or_default]
+    pub i18n: I18n,
+}
+
+/// Asks before doing something that can't be taken back. The owner decides when it's
+/// shown and what happens on each answer; the dialog only reports which one it got.
+/// Cancel has focus to begin with, so Enter alone never confirms.
+#[function_component(ConfirmModal)]
+pub fn confirm_modal(props: &ConfirmModalProps) -> Html {
+    let cancel_button = use_node_ref();
+
+    {
+        let cancel_button = cancel_button.clone();
+        use_effect_with_deps(
+            move |_| {
+                if let Some(button) = cancel_button.cast::<HtmlElement>() {
+                    let _ = button.focus();
+                }
+                || ()
+            },
+            (),
+        );
+    }
+
+    let onbackdrop = {
+        let oncancel = props.oncancel.clone();
+        Callback::from(move |e: MouseEvent| {
+            if e.target() == e.current_target() {
+                oncancel.emit(());
+            }
+        })
+    };
+
+    html! {
+        <Overlay onclose={props.oncancel.clone()} modal=true>
+            <div class="fixed inset-0 z-30 flex items-center justify-center bg-black bg-opacity-40 confirm-backdrop"
+                onclick={onbackdrop}>
+                <div role="alertdialog" aria-modal="true" aria-describedby="confirm-message"
+                    class="w-80 max-w-full m-4 p-5 bg-white rounded-xl shadow-xl confirm-modal">
+                    <p id="confirm-message" class="text-sm text-gray-700">{props.message.clone()}</p>
+                    <div class="flex justify-end gap-2 mt-5">
+                        <button ref={cancel_button} class="px-3 py-1.5 rounded-lg text-sm text-gray-700 hover:bg-gray-100"
+                            onclick={props.oncancel.reform(|_| ())}>
+                            {props.i18n.t("cancel")}
+                        </button>
+                        <button onclick={props.onconfirm.reform(|_| ())}
+                            class={classes!(
+                                "px-3", "py-1.5", "rounded-lg", "text-sm", "font-semibold", "text-white",
+                                if props.danger { "bg-red-600 hover:bg-red-700" } else { "bg-primary-dark" },
+                            )}>
+                            {props.confirm_label}
+                        </button>
+                    </div>
+                </div>
+            </div>
+        </Overlay>
+    }
+}
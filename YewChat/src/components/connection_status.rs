@@ -0,0 +1,32 @@
+use yew::functional::*;
+use yew::prelude::*;
+
+use crate::hooks::use_connection_state;
+use crate::i18n::I18n;
+use crate::services::websocket::ConnState;
+
+#[derive(Properties, PartialEq)]
+pub struct ConnectionStatusProps {
+    /// For the word.
+    #[prop_or_default]
+    pub i18n: I18n,
+}
+
+/// A dot and a word for how the connection is doing, following it from wherever it's
+/// shown.
+#[function_component(ConnectionStatus)]
+pub fn connection_status(props: &ConnectionStatusProps) -> Html {
+    let (label, dot) = match use_connection_state() {
+        ConnState::Open => ("online", "bg-green-500"),
+        ConnState::Connecting => ("connecting", "bg-yellow-400 animate-pulse"),
+        ConnState::Closed | ConnState::Stopped | ConnState::Offline => ("offline", "bg-red-500"),
+        ConnState::Unsupported => ("unavailable", "bg-gray-400"),
+    };
+    let label = props.i18n.t(label);
+    html! {
+        <span class="flex items-center gap-1.5 text-xs text-gray-500 connection-status" role="status">
+            <span class={classes!("w-2", "h-2", "rounded-full", dot)} aria-hidden="true"></span>
+            {label}
+        </span>
+    }
+}
@@ -0,0 +1,70 @@
+use yew::prelude::*;
+
+use crate::components::overlay::Overlay;
+
+// Used to keep the menu inside the viewport when opened near its right or bottom edge.
+const MENU_WIDTH_PX: i32 = 176;
+const ITEM_HEIGHT_PX: i32 = 32;
+
+#[derive(Clone, PartialEq)]
+pub struct MenuAction {
+    pub label: &'static str,
+    /// Drawn in red, for actions that destroy something.
+    pub danger: bool,
+    pub onselect: Callback<()>,
+}
+
+#[derive(Properties, PartialEq)]
+pub struct ContextMenuProps {
+    /// Where to open, in viewport coordinates; usually the cursor.
+    pub x: i32,
+    pub y: i32,
+    pub actions: Vec<MenuAction>,
+    /// Called after an action is picked, or when the user clicks elsewhere or presses Esc.
+    pub onclose: Callback<()>,
+}
+
+/// A floating menu of actions. The owner decides when it's shown and which actions it
+/// offers; the menu only reports when it should go away.
+#[function_component(ContextMenu)]
+pub fn context_menu(props: &ContextMenuProps) -> Html {
+    let window = gloo_utils::window();
+    let viewport = |size: Result<wasm_bindgen::JsValue, _>| {
+        size.ok().and_then(|s| s.as_f64()).unwrap_or(f64::MAX) as i32
+    };
+    let height = props.actions.len() as i32 * ITEM_HEIGHT_PX + 8;
+    let left = props
+        .x
+        .min(viewport(window.inner_width()) - MENU_WIDTH_PX)
+        .max(0);
+    let top = props.y.min(viewport(window.inner_height()) - height).max(0);
+
+    html! {
+        <Overlay onclose={props.onclose.clone()} close_on_outside_click=true>
+            <div role="menu" style={format!("left:{}px;top:{}px;width:{}px", left, top, MENU_WIDTH_PX)}
+                class="fixed z-20 py-1 bg-white rounded-lg shadow-lg border border-gray-200 text-sm context-menu">
+                {
+                    props.actions.iter().map(|action| {
+                        let onclick = {
+                            let onselect = action.onselect.clone();
+                            let onclose = props.onclose.clone();
+                            Callback::from(move |_| {
+                                onselect.emit(());
+                                onclose.emit(());
+                            })
+                        };
+                        html! {
+                            <button role="menuitem" {onclick}
+                                class={classes!(
+                                    "block", "w-full", "text-start", "px-3", "py-1.5", "hover:bg-gray-100",
+                                    if action.danger { "text-red-600" } else { "text-gray-700" },
+                                )}>
+                                {action.label}
+                            </button>
+                        }
+                    }).collect::<Html>()
+                }
+            </div>
+        </Overlay>
+    }
+}
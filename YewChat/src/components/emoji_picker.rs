@@ -0,0 +1,99 @@
+use std::collections::HashSet;
+
+use web_sys::HtmlInputElement;
+use yew::functional::*;
+use yew::prelude::*;
+
+use crate::components::overlay::Overlay;
+use crate::emoji::shortcodes;
+use crate::i18n::I18n;
+
+/// What a picked emoji is for. The picker only uses it for its heading; the owner routes
+/// the pick.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PickerMode {
+    /// Typed into the message box at the cursor.
+    Insert,
+    /// Sent as a reaction to the message with this id.
+    React(String),
+}
+
+#[derive(Properties, PartialEq)]
+pub struct EmojiPickerProps {
+    pub mode: PickerMode,
+    pub onselect: Callback<String>,
+    /// Called when the user presses Esc.
+    pub onclose: Callback<()>,
+    #[prop_or_default]
+    pub i18n: I18n,
+}
+
+/// The emoji whose shortcode contains `query`, each once, under its first shortcode.
+fn matching(query: &str) -> Vec<(&'static str, &'static str)> {
+    let query = query.trim().trim_matches(':').to_lowercase();
+    let mut seen = HashSet::new();
+    shortcodes()
+        .iter()
+        .filter(|(code, _)| code.contains(query.as_str()))
+        .filter(|(_, emoji)| seen.insert(*emoji))
+        .copied()
+        .collect()
+}
+
+#[function_component(EmojiPicker)]
+pub fn emoji_picker(props: &EmojiPickerProps) -> Html {
+    let query = use_state(String::new);
+    let search = use_node_ref();
+
+    {
+        let search = search.clone();
+        use_effect_with_deps(
+            move |_| {
+                if let Some(input) = search.cast::<HtmlInputElement>() {
+                    let _ = input.focus();
+                }
+                || ()
+            },
+            (),
+        );
+    }
+
+    let oninput = {
+        let query = query.clone();
+        Callback::from(move |e: InputEvent| {
+            query.set(e.target_unchecked_into::<HtmlInputElement>().value())
+        })
+    };
+    let heading = props.i18n.t(match props.mode {
+        PickerMode::Insert => "insert_emoji",
+        PickerMode::React(_) => "add_reaction",
+    });
+    let found = matching(&query);
+
+    html! {
+        <Overlay onclose={props.onclose.clone()}>
+            <div class="absolute bottom-16 end-4 w-80 bg-white rounded-xl shadow-lg border border-gray-200 flex flex-col z-10 emoji-picker">
+                <div class="px-3 pt-2 text-xs font-semibold text-gray-500">{heading}</div>
+                <input ref={search} type="text" placeholder={props.i18n.t("emoji_search")} value={(*query).clone()} {oninput}
+                    class="m-2 py-1.5 px-3 bg-gray-100 rounded-full outline-none focus:text-gray-700"/>
+                <div class="max-h-60 overflow-y-auto grid grid-cols-8 gap-1 p-2">
+                    if found.is_empty() {
+                        <p class="col-span-8 text-sm text-gray-500 p-2">{props.i18n.t("emoji_none")}</p>
+                    }
+                    {
+                        found.into_iter().map(|(code, emoji)| {
+                            let onselect = props.onselect.clone();
+                            let onclick = Callback::from(move |_| onselect.emit(emoji.to_string()));
+                            html! {
+                                <button key={code} title={format!(":{}:", code)} {onclick}
+                                    class="text-xl leading-none p-1 rounded hover:bg-gray-100 focus:outline-none focus:ring-2 focus:ring-blue-400">
+                                    {emoji}
+                                </button>
+                            }
+                        }).collect::<Html>()
+                    }
+                </div>
+            </div>
+        </Overlay>
+    }
+}
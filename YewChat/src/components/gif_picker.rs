@@ -0,0 +1,215 @@
+use gloo_timers::callback::Timeout;
+use web_sys::{Element, HtmlInputElement};
+use yew::prelude::*;
+
+use crate::i18n::I18n;
+use crate::services::gif::{search, Gif, GifPage, GifProvider};
+
+const SEARCH_DEBOUNCE_MS: u32 = 350;
+// Scrolling within this many pixels of the bottom of the grid loads the next page.
+const LOAD_MORE_THRESHOLD: i32 = 80;
+
+pub enum Msg {
+    Input(String),
+    Search,
+    LoadMore,
+    Page(u32, Result<GifPage, String>),
+    Select(String),
+}
+
+#[derive(Properties, PartialEq)]
+pub struct GifPickerProps {
+    #[prop_or_default]
+    pub provider: GifProvider,
+    /// Overrides the provider's build-time key (see `GifProvider`).
+    #[prop_or_default]
+    pub api_key: Option<String>,
+    pub onselect: Callback<String>,
+    #[prop_or_default]
+    pub i18n: I18n,
+}
+
+impl GifPickerProps {
+    fn api_key(&self) -> Option<String> {
+        self.api_key
+            .clone()
+            .or_else(|| self.provider.default_api_key().map(String::from))
+    }
+}
+
+enum Results {
+    Idle,
+    Loading,
+    Loaded,
+    Failed,
+}
+
+pub struct GifPicker {
+    query: String,
+    results: Results,
+    gifs: Vec<Gif>,
+    /// Cursor for the next page of the current search, if there is one.
+    next: Option<String>,
+    loading_more: bool,
+    debounce: Option<Timeout>,
+    // Bumped on every search so a slow response can't overwrite a newer one.
+    generation: u32,
+}
+
+impl GifPicker {
+    fn fetch(&self, ctx: &Context<Self>, cursor: Option<String>) {
+        let api_key = match ctx.props().api_key() {
+            Some(key) => key,
+            None => return,
+        };
+        let provider = ctx.props().provider;
+        let query = self.query.trim().to_string();
+        let generation = self.generation;
+        ctx.link().send_future(async move {
+            let page = search(provider, &api_key, &query, cursor.as_deref()).await;
+            Msg::Page(generation, page)
+        });
+    }
+}
+
+impl Component for GifPicker {
+    type Message = Msg;
+    type Properties = GifPickerProps;
+
+    fn create(_ctx: &Context<Self>) -> Self {
+        Self {
+            query: String::new(),
+            results: Results::Idle,
+            gifs: vec![],
+            next: None,
+            loading_more: false,
+            debounce: None,
+            generation: 0,
+        }
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            Msg::Input(query) => {
+                self.query = query;
+                let link = ctx.link().clone();
+                self.debounce = Some(Timeout::new(SEARCH_DEBOUNCE_MS, move || {
+                    link.send_message(Msg::Search)
+                }));
+                false
+            }
+            Msg::Search => {
+                self.debounce = None;
+                self.generation += 1;
+                self.gifs.clear();
+                self.next = None;
+                self.loading_more = false;
+                if self.query.trim().is_empty() || ctx.props().api_key().is_none() {
+                    self.results = Results::Idle;
+                    return true;
+                }
+                self.results = Results::Loading;
+                self.fetch(ctx, None);
+                true
+            }
+            Msg::LoadMore => {
+                if self.loading_more {
+                    return false;
+                }
+                match self.next.clone() {
+                    Some(cursor) => {
+                        self.loading_more = true;
+                        self.fetch(ctx, Some(cursor));
+                        true
+                    }
+                    None => false,
+                }
+            }
+            Msg::Page(generation, result) => {
+                if generation != self.generation {
+                    return false;
+                }
+                self.loading_more = false;
+                match result {
+                    Ok(page) => {
+                        self.gifs.extend(page.gifs);
+                        self.next = page.next;
+                        self.results = Results::Loaded;
+                    }
+                    Err(e) => {
+                        log::error!("gif search: {}", e);
+                        // A failed later page keeps what's already shown; scrolling retries.
+                        if self.gifs.is_empty() {
+                            self.results = Results::Failed;
+                        }
+                    }
+                }
+                true
+            }
+            Msg::Select(url) => {
+                ctx.props().onselect.emit(url);
+                false
+            }
+        }
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let oninput = ctx.link().callback(|e: InputEvent| {
+            Msg::Input(e.target_unchecked_into::<HtmlInputElement>().value())
+        });
+        let onscroll = ctx.link().batch_callback(|e: Event| {
+            let grid = e.target_unchecked_into::<Element>();
+            let from_bottom = grid.scroll_height() - grid.scroll_top() - grid.client_height();
+            (from_bottom <= LOAD_MORE_THRESHOLD).then_some(Msg::LoadMore)
+        });
+
+        let i18n = ctx.props().i18n;
+        let body = if ctx.props().api_key().is_none() {
+            html! { <p class="text-sm text-gray-500 p-4">{i18n.t("gif_unconfigured")}</p> }
+        } else {
+            match &self.results {
+                Results::Idle => {
+                    html! { <p class="text-sm text-gray-500 p-4">{i18n.t("gif_prompt")}</p> }
+                }
+                Results::Loading => {
+                    html! { <p class="text-sm text-gray-500 p-4">{i18n.t("gif_searching")}</p> }
+                }
+                Results::Failed => html! {
+                    <p class="text-sm text-red-500 p-4">{i18n.t("gif_failed")}</p>
+                },
+                Results::Loaded if self.gifs.is_empty() => html! {
+                    <p class="text-sm text-gray-500 p-4">{i18n.fill("gif_none", &[&self.query.trim()])}</p>
+                },
+                Results::Loaded => html! {
+                    <>
+                        <div class="grid grid-cols-3 gap-1 p-1">
+                            {
+                                self.gifs.iter().map(|g| {
+                                    let url = g.url.clone();
+                                    let onclick = ctx.link().callback(move |_| Msg::Select(url.clone()));
+                                    html! {
+                                        <button key={g.id.clone()} class="block focus:outline-none focus:ring-2 focus:ring-blue-400 rounded" {onclick}>
+                                            <img class="w-full h-24 object-cover rounded" src={g.preview_url.clone()} alt={g.title.clone()} loading="lazy"/>
+                                        </button>
+                                    }
+                                }).collect::<Html>()
+                            }
+                        </div>
+                        if self.loading_more {
+                            <p class="text-xs text-center text-gray-400 py-2">{i18n.t("gif_loading_more")}</p>
+                        }
+                    </>
+                },
+            }
+        };
+
+        html! {
+            <div class="absolute bottom-16 end-4 w-80 bg-white rounded-xl shadow-lg border border-gray-200 flex flex-col z-10">
+                <input type="text" placeholder={i18n.t("gif_search")} value={self.query.clone()} {oninput}
+                    class="m-2 py-1.5 px-3 bg-gray-100 rounded-full outline-none focus:text-gray-700"/>
+                <div class="max-h-72 overflow-y-auto" {onscroll}>{body}</div>
+                <div class="text-end text-xs text-gray-400 px-3 pb-1">{ctx.props().provider.attribution()}</div>
+            </div>
+        }
+    }
+}
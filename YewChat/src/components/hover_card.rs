@@ -0,0 +1,115 @@
+use gloo_timers::callback::Timeout;
+use wasm_bindgen::JsCast;
+use web_sys::{Element, Node};
+use yew::prelude::*;
+
+// How long the pointer rests on the trigger before the card opens, so it doesn't flash
+// up for everything the pointer passes over on its way elsewhere.
+const HOVER_DELAY_MS: u32 = 400;
+// Used to keep a card opened near the foot of the viewport inside it.
+const CARD_HEIGHT_PX: f64 = 180.0;
+
+#[derive(Properties, PartialEq)]
+pub struct HoverCardProps {
+    /// What's hovered or focused to open the card.
+    pub children: Children,
+    pub card: Html,
+    /// For the box around the children, such as `listitem` when they're an entry in a list.
+    #[prop_or_default]
+    pub role: Option<&'static str>,
+}
+
+/// Where the card goes: level with the trigger's top, just past its far edge, which is
+/// its left one in a right-to-left page.
+fn place(trigger: &Element) -> String {
+    let rect = trigger.get_bounding_client_rect();
+    let viewport = gloo_utils::document().document_element();
+    let (width, height) = viewport
+        .map(|v| (v.client_width() as f64, v.client_height() as f64))
+        .unwrap_or((f64::MAX, f64::MAX));
+    let top = rect.top().min(height - CARD_HEIGHT_PX).max(0.0);
+    if trigger.closest("[dir=rtl]").ok().flatten().is_some() {
+        format!("right:{}px;top:{}px", width - rect.left(), top)
+    } else {
+        format!("left:{}px;top:{}px", rect.right(), top)
+    }
+}
+
+/// A card of more about its children, shown beside them while the pointer rests on them
+/// or focus is within them, and gone when it leaves or on Esc. The card stays inside the
+/// same box as the children, so moving the pointer or focus into it keeps it open.
+#[function_component(HoverCard)]
+pub fn hover_card(props: &HoverCardProps) -> Html {
+    let trigger = use_node_ref();
+    // Where the card is drawn, while it's open.
+    let placed = use_state(|| None::<String>);
+    let pending = use_mut_ref(|| None::<Timeout>);
+
+    let open = {
+        let trigger = trigger.clone();
+        let placed = placed.clone();
+        let pending = pending.clone();
+        Callback::from(move |_: ()| {
+            pending.borrow_mut().take();
+            if let Some(trigger) = trigger.cast::<Element>() {
+                placed.set(Some(place(&trigger)));
+            }
+        })
+    };
+    let close = {
+        let placed = placed.clone();
+        let pending = pending.clone();
+        Callback::from(move |_: ()| {
+            pending.borrow_mut().take();
+            if placed.is_some() {
+                placed.set(None);
+            }
+        })
+    };
+
+    let onmouseenter = {
+        let open = open.clone();
+        Callback::from(move |_: MouseEvent| {
+            let open = open.clone();
+            *pending.borrow_mut() = Some(Timeout::new(HOVER_DELAY_MS, move || open.emit(())));
+        })
+    };
+    let onmouseleave = close.reform(|_: MouseEvent| ());
+    let onfocusin = open.reform(|_: FocusEvent| ());
+    let onfocusout = {
+        let trigger = trigger.clone();
+        let close = close.clone();
+        Callback::from(move |e: FocusEvent| {
+            let to = e.related_target().and_then(|t| t.dyn_into::<Node>().ok());
+            let inside = trigger
+                .cast::<Node>()
+                .is_some_and(|trigger| to.is_some_and(|to| trigger.contains(Some(&to))));
+            if !inside {
+                close.emit(());
+            }
+        })
+    };
+    let onkeydown = {
+        let open = placed.is_some();
+        Callback::from(move |e: KeyboardEvent| {
+            if open && e.key() == "Escape" {
+                // Only the card goes, not whatever else Esc would close.
+                e.stop_propagation();
+                close.emit(());
+            }
+        })
+    };
+
+    html! {
+        <div ref={trigger} role={props.role} {onmouseenter} {onmouseleave} {onfocusin} {onfocusout} {onkeydown}>
+            { for props.children.iter() }
+            if let Some(style) = (*placed).clone() {
+                <div {style} class="fixed z-30 ps-2 hover-card">
+                    <div class="w-64 p-3 bg-white rounded-xl shadow-lg border border-gray-200 text-sm">
+                        { props.card.clone() }
+                    </div>
+                </div>
+            }
+        </div>
+    }
+}
@@ -0,0 +1,58 @@
+use std::rc::Rc;
+
+use wasm_bindgen_futures::spawn_local;
+use yew::functional::*;
+use yew::prelude::*;
+
+use crate::sanitize::image_url;
+use crate::services::link_preview::{fetch_preview, LinkPreview};
+
+#[derive(Properties, PartialEq)]
+pub struct LinkPreviewProps {
+    pub url: String,
+    /// The unfurl proxy to ask, as in `services::link_preview::DEFAULT_UNFURL_ENDPOINT`.
+    pub endpoint: String,
+    /// Where the card's image may come from, as in `ChatProps::image_hosts`.
+    pub image_hosts: Vec<String>,
+}
+
+/// OpenGraph card for a shared link. Renders nothing until (and unless) the metadata
+/// fetch succeeds.
+#[function_component(LinkPreviewCard)]
+pub fn link_preview_card(props: &LinkPreviewProps) -> Html {
+    let preview = use_state(|| None::<Rc<LinkPreview>>);
+
+    {
+        let preview = preview.clone();
+        use_effect_with_deps(
+            move |(url, endpoint): &(String, String)| {
+                let (url, endpoint) = (url.clone(), endpoint.clone());
+                // What's shown is for the new link or nothing, never the old link's card.
+                preview.set(None);
+                spawn_local(async move {
+                    preview.set(fetch_preview(&endpoint, &url).await);
+                });
+                || ()
+            },
+            (props.url.clone(), props.endpoint.clone()),
+        );
+    }
+
+    match &*preview {
+        None => html! {},
+        Some(p) => html! {
+            <a href={props.url.clone()} target="_blank" rel="noopener noreferrer"
+               class="flex max-w-md mt-2 bg-white rounded-xl shadow-sm overflow-hidden border border-gray-200 link-preview">
+                if let Some(image) = p.image.as_deref().and_then(|i| image_url(i, &props.image_hosts)) {
+                    <img class="w-24 h-24 object-cover flex-none" src={image.to_string()} alt=""/>
+                }
+                <div class="p-3 min-w-0">
+                    <div class="text-sm font-medium truncate">{p.title.clone()}</div>
+                    if let Some(description) = &p.description {
+                        <div class="text-xs text-gray-500 mt-1 line-clamp-2">{description.clone()}</div>
+                    }
+                </div>
+            </a>
+        },
+    }
+}
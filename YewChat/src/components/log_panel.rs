@@ -0,0 +1,159 @@
+use gloo_events::EventListener;
+use gloo_timers::callback::Interval;
+use log::{Level, LevelFilter};
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::HtmlSelectElement;
+use yew::functional::*;
+use yew::prelude::*;
+
+use crate::logger::{self, Line};
+
+// How often the open panel checks for new lines.
+const POLL_MS: u32 = 500;
+
+const LEVELS: [LevelFilter; 6] = [
+    LevelFilter::Off,
+    LevelFilter::Error,
+    LevelFilter::Warn,
+    LevelFilter::Info,
+    LevelFilter::Debug,
+    LevelFilter::Trace,
+];
+
+/// Ctrl+Shift+L (Cmd+Shift+L on macOS) opens and closes the panel.
+fn is_toggle(e: &KeyboardEvent) -> bool {
+    e.key().eq_ignore_ascii_case("l") && (e.ctrl_key() || e.meta_key()) && e.shift_key()
+}
+
+fn level_class(level: Level) -> &'static str {
+    match level {
+        Level::Error => "text-red-400",
+        Level::Warn => "text-yellow-300",
+        Level::Info => "text-sky-300",
+        Level::Debug | Level::Trace => "text-gray-400",
+    }
+}
+
+/// `ms` since the epoch as a local time with milliseconds, like `09:41:07.250`.
+fn log_time(ms: f64) -> String {
+    let date = js_sys::Date::new(&JsValue::from_f64(ms));
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        date.get_hours(),
+        date.get_minutes(),
+        date.get_seconds(),
+        date.get_milliseconds()
+    )
+}
+
+fn view_line(line: &Line) -> Html {
+    html! {
+        <div class="whitespace-pre-wrap break-all">
+            <span class="text-gray-500">{log_time(line.time)}</span>
+            {" "}
+            <span class={level_class(line.level)}>{format!("{:<5}", line.level)}</span>
+            {" "}
+            <span class="text-gray-500">{&line.target}</span>
+            {" "}
+            {&line.message}
+        </div>
+    }
+}
+
+/// A developer panel over the app: the recent log and the level it's recorded at, which
+/// can be changed without a rebuild. Hidden until Ctrl+Shift+L.
+#[function_component(LogPanel)]
+pub fn log_panel() -> Html {
+    let open = use_state(|| false);
+    // The log generation last drawn; updating it redraws the panel.
+    let generation = use_state_eq(logger::generation);
+    let level = use_state(logger::level);
+
+    {
+        let handle = open.clone();
+        use_effect_with_deps(
+            move |&is_open: &bool| {
+                let toggle = EventListener::new(&gloo_utils::document(), "keydown", move |e| {
+                    let e = e.unchecked_ref::<KeyboardEvent>();
+                    if is_toggle(e) {
+                        e.prevent_default();
+                        handle.set(!is_open);
+                    }
+                });
+                move || drop(toggle)
+            },
+            // Re-registered with each change, so it toggles from the current state.
+            *open,
+        );
+    }
+    {
+        let generation = generation.clone();
+        use_effect_with_deps(
+            move |open: &bool| {
+                let poll = open
+                    .then(|| Interval::new(POLL_MS, move || generation.set(logger::generation())));
+                move || drop(poll)
+            },
+            *open,
+        );
+    }
+
+    if !*open {
+        return html! {};
+    }
+
+    let onchange = {
+        let level = level.clone();
+        Callback::from(move |e: Event| {
+            let index = e
+                .target_unchecked_into::<HtmlSelectElement>()
+                .selected_index();
+            let picked = LEVELS
+                .get(index as usize)
+                .copied()
+                .unwrap_or(LevelFilter::Debug);
+            logger::set_level(picked);
+            level.set(picked);
+        })
+    };
+    let clear = {
+        let generation = generation.clone();
+        Callback::from(move |_| {
+            logger::clear();
+            generation.set(logger::generation());
+        })
+    };
+    let close = {
+        let open = open.clone();
+        Callback::from(move |_| open.set(false))
+    };
+    let lines = logger::lines();
+
+    html! {
+        <div class="fixed bottom-4 end-4 z-50 w-[36rem] max-w-[calc(100vw-2rem)] h-80 flex flex-col bg-gray-900 text-gray-100 rounded-xl shadow-2xl text-xs log-panel"
+            role="dialog" aria-label="Log">
+            <div class="flex items-center gap-2 px-3 py-2 border-b border-gray-700">
+                <span class="font-semibold">{"Log"}</span>
+                <span class="text-gray-500" title={format!("The newest {} are kept", logger::CAPACITY)}>
+                    {format!("{} lines", lines.len())}
+                </span>
+                <label class="ms-auto flex items-center gap-1">
+                    {"Level"}
+                    <select class="bg-gray-800 rounded px-1 py-0.5" {onchange}>
+                        {
+                            LEVELS.iter().map(|&l| html! {
+                                <option selected={l == *level}>{l.to_string()}</option>
+                            }).collect::<Html>()
+                        }
+                    </select>
+                </label>
+                <button class="px-2 py-0.5 rounded hover:bg-gray-700" onclick={clear}>{"Clear"}</button>
+                <button class="px-2 py-0.5 rounded hover:bg-gray-700" title="Close (Ctrl+Shift+L)" onclick={close}>{"✕"}</button>
+            </div>
+            // Newest at the bottom, kept in view by reversing the scroll direction.
+            <div class="grow overflow-y-auto flex flex-col-reverse px-3 py-2 font-mono">
+                <div>{ for lines.iter().map(view_line) }</div>
+            </div>
+        </div>
+    }
+}
@@ -3,6 +3,7 @@ use yew::functional::*;
 use yew::prelude::*;
 use yew_router::prelude::*;
 
+use crate::username::validate;
 use crate::Route;
 use crate::User;
 
@@ -10,6 +11,12 @@ use crate::User;
 pub fn login() -> Html {
     let username = use_state(|| String::new());
     let user = use_context::<User>().expect("No context found.");
+    let history = use_history();
+    // Starts out as the server's reason if it just sent us back here.
+    let error = {
+        let user = user.clone();
+        use_state(move || user.register_error.borrow_mut().take())
+    };
 
     let oninput = {
         let current_username = username.clone();
@@ -20,19 +27,32 @@ pub fn login() -> Html {
         })
     };
 
-    let onclick = {
-        let username = username.clone();
-        let user = user.clone();
-        Callback::from(move |_| *user.username.borrow_mut() = (*username).clone())
+    let onsubmit = {
+        let (username, user, error) = (username.clone(), user.clone(), error.clone());
+        Callback::from(move |e: FocusEvent| {
+            e.prevent_default();
+            match validate(&username) {
+                Ok(name) => {
+                    *user.username.borrow_mut() = name.to_string();
+                    if let Some(history) = &history {
+                        history.push(Route::Chat);
+                    }
+                }
+                Err(e) => error.set(Some(e.to_string())),
+            }
+        })
     };
 
     html! {
        <div class="bg-gray-800 flex w-screen">
             <div class="container mx-auto flex flex-col justify-center items-center">
-                <form class="m-4 flex">
+                <form class="m-4 flex" {onsubmit}>
                     <input {oninput} class="rounded-l-lg p-4 border-t mr-0 border-b border-l text-gray-800 border-gray-200 bg-white" placeholder="Username" />
-                    <Link<Route> to={Route::Chat}> <button {onclick} disabled={username.len()<1} class="px-8 rounded-r-lg bg-violet-600	  text-white font-bold p-4 uppercase border-violet-600 border-t border-b border-r" >{"Go Chatting!"}</button></Link<Route>>
+                    <button type="submit" disabled={username.len()<1} class="px-8 rounded-r-lg bg-violet-600	  text-white font-bold p-4 uppercase border-violet-600 border-t border-b border-r" >{"Go Chatting!"}</button>
                 </form>
+                if let Some(error) = &*error {
+                    <p class="text-red-400 text-sm" role="alert">{error}</p>
+                }
             </div>
         </div>
     }
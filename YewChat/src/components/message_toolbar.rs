@@ -0,0 +1,70 @@
+use gloo_timers::future::TimeoutFuture;
+use wasm_bindgen_futures::spawn_local;
+use yew::functional::*;
+use yew::prelude::*;
+
+use crate::i18n::I18n;
+use crate::services::clipboard::copy_text;
+
+// How long the "Copied" tooltip stays up after a successful copy.
+const COPIED_FEEDBACK_MS: u32 = 1500;
+
+#[derive(Properties, PartialEq)]
+pub struct MessageToolbarProps {
+    /// The message as it was sent, before any rendering, which is what gets copied.
+    pub text: String,
+    /// Further actions, shown before the copy button.
+    #[prop_or_default]
+    pub children: Children,
+    /// Whether the copy button is in the tab order. The caller sets its own buttons' to
+    /// match.
+    #[prop_or(true)]
+    pub tabbable: bool,
+    #[prop_or_default]
+    pub i18n: I18n,
+}
+
+/// The row of actions that appears over a message bubble on hover. It's shown by the
+/// `.message-row:hover .message-toolbar` rule, so it must sit inside a `message-row`.
+#[function_component(MessageToolbar)]
+pub fn message_toolbar(props: &MessageToolbarProps) -> Html {
+    let copied = use_state(|| false);
+
+    let copy = {
+        let copied = copied.clone();
+        let text = props.text.clone();
+        Callback::from(move |_| {
+            let copied = copied.clone();
+            let text = text.clone();
+            spawn_local(async move {
+                match copy_text(&text).await {
+                    Ok(()) => {
+                        copied.set(true);
+                        TimeoutFuture::new(COPIED_FEEDBACK_MS).await;
+                        copied.set(false);
+                    }
+                    Err(e) => log::warn!("couldn't copy message: {:?}", e),
+                }
+            });
+        })
+    };
+
+    html! {
+        <div class="absolute -top-3 end-2 flex items-center gap-1 px-1 py-0.5 bg-white text-gray-500 rounded-lg shadow border border-gray-200 message-toolbar">
+            { for props.children.iter() }
+            <div class="relative">
+                <button class="p-1 rounded hover:bg-gray-100 hover:text-gray-800" title={props.i18n.t("copy_text")} onclick={copy}
+                    tabindex={(!props.tabbable).then_some("-1")}>
+                    <svg viewBox="0 0 24 24" xmlns="http://www.w3.org/2000/svg" class="fill-current w-4 h-4">
+                        <path d="M16 1H4c-1.1 0-2 .9-2 2v14h2V3h12V1zm3 4H8c-1.1 0-2 .9-2 2v14c0 1.1.9 2 2 2h11c1.1 0 2-.9 2-2V7c0-1.1-.9-2-2-2zm0 16H8V7h11v14z"></path>
+                    </svg>
+                </button>
+                if *copied {
+                    <span class="absolute bottom-full end-0 mb-1 px-2 py-0.5 rounded bg-gray-800 text-white text-xs whitespace-nowrap" role="status">
+                        {props.i18n.t("copied")}
+                    </span>
+                }
+            </div>
+        </div>
+    }
+}
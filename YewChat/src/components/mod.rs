@@ -1,2 +1,18 @@
+pub mod avatar;
 pub mod chat;
+pub mod chat_fn;
+pub mod code_block;
+pub mod confirm_modal;
+pub mod connection_status;
+pub mod context_menu;
+pub mod emoji_picker;
+pub mod gif_picker;
+pub mod hover_card;
+pub mod link_preview;
+pub mod log_panel;
 pub mod login;
+pub mod message_toolbar;
+pub mod overlay;
+pub mod settings_modal;
+pub mod spoiler;
+pub mod toast_host;
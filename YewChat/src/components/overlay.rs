@@ -0,0 +1,184 @@
+use std::cell::{Cell, RefCell};
+
+use gloo_events::{EventListener, EventListenerOptions, EventListenerPhase};
+use wasm_bindgen::JsCast;
+use web_sys::{Element, HtmlElement, Node};
+use yew::functional::*;
+use yew::prelude::*;
+
+// What Tab can land on, for keeping it inside a modal.
+const FOCUSABLE: &str = "a[href], button:not([disabled]), input:not([disabled]), \
+    textarea:not([disabled]), select:not([disabled]), [tabindex]:not([tabindex=\"-1\"])";
+
+thread_local! {
+    // The overlays open now by id, the topmost last.
+    static OPEN: RefCell<Vec<usize>> = const { RefCell::new(Vec::new()) };
+    static NEXT_ID: Cell<usize> = const { Cell::new(0) };
+}
+
+fn is_topmost(id: usize) -> bool {
+    OPEN.with(|open| open.borrow().last() == Some(&id))
+}
+
+fn focusables(container: &Element) -> Vec<HtmlElement> {
+    let found = match container.query_selector_all(FOCUSABLE) {
+        Ok(found) => found,
+        Err(_) => return Vec::new(),
+    };
+    (0..found.length())
+        .filter_map(|i| found.get(i)?.dyn_into::<HtmlElement>().ok())
+        .collect()
+}
+
+/// Wraps Tab and Shift+Tab around at the ends of `container`, and brings focus back into
+/// it from wherever it strayed.
+fn trap_tab(container: &Element, e: &KeyboardEvent) {
+    let items = focusables(container);
+    let (first, last) = match (items.first(), items.last()) {
+        (Some(first), Some(last)) => (first, last),
+        _ => {
+            e.prevent_default();
+            return;
+        }
+    };
+    let active = gloo_utils::document().active_element();
+    let inside = active.as_ref().is_some_and(|a| container.contains(Some(a)));
+    let is = |item: &HtmlElement| active.as_ref() == Some(item.unchecked_ref::<Element>());
+    let to = if e.shift_key() {
+        (!inside || is(first)).then_some(last)
+    } else {
+        (!inside || is(last)).then_some(first)
+    };
+    if let Some(to) = to {
+        e.prevent_default();
+        let _ = to.focus();
+    }
+}
+
+#[derive(Properties, PartialEq)]
+pub struct OverlayProps {
+    pub children: Children,
+    /// Called on Esc, and on a click outside if `close_on_outside_click` is set, as long
+    /// as this is the topmost overlay.
+    pub onclose: Callback<()>,
+    /// Keeps Tab inside, and moves focus in on opening if the children haven't.
+    #[prop_or(false)]
+    pub modal: bool,
+    #[prop_or(false)]
+    pub close_on_outside_click: bool,
+}
+
+/// What every popup, menu and dialog shares: Esc closes the topmost one and only it, a
+/// modal keeps focus to itself, and focus goes back to what was focused before on
+/// close. The owner still decides when it's shown. Adds no box of its own, so the
+/// children are laid out as if it weren't there.
+#[function_component(Overlay)]
+pub fn overlay(props: &OverlayProps) -> Html {
+    let container = use_node_ref();
+    let id = *use_state(|| {
+        NEXT_ID.with(|next| {
+            let id = next.get();
+            next.set(id + 1);
+            id
+        })
+    });
+    // Taken on the first render, before anything inside has had the chance to take focus.
+    let trigger = (*use_state(|| {
+        gloo_utils::document()
+            .active_element()
+            .and_then(|e| e.dyn_into::<HtmlElement>().ok())
+    }))
+    .clone();
+
+    {
+        let container = container.clone();
+        let onclose = props.onclose.clone();
+        let (modal, outside) = (props.modal, props.close_on_outside_click);
+        use_effect_with_deps(
+            move |_| {
+                OPEN.with(|open| open.borrow_mut().push(id));
+                let document = gloo_utils::document();
+
+                if let Some(element) = container.cast::<Element>().filter(|_| modal) {
+                    let active = document.active_element();
+                    if !active.is_some_and(|a| element.contains(Some(&a))) {
+                        if let Some(first) = focusables(&element).first() {
+                            let _ = first.focus();
+                        }
+                    }
+                }
+
+                // Capturing, so Esc is ours before anything under the overlay sees it.
+                let capture = EventListenerOptions {
+                    phase: EventListenerPhase::Capture,
+                    passive: false,
+                };
+                let keys = {
+                    let (container, onclose) = (container.clone(), onclose.clone());
+                    EventListener::new_with_options(&document, "keydown", capture, move |e| {
+                        if !is_topmost(id) {
+                            return;
+                        }
+                        let e = e.unchecked_ref::<KeyboardEvent>();
+                        match e.key().as_str() {
+                            "Escape" => {
+                                e.prevent_default();
+                                e.stop_propagation();
+                                onclose.emit(());
+                            }
+                            "Tab" if modal => {
+                                if let Some(element) = container.cast::<Element>() {
+                                    trap_tab(&element, e);
+                                }
+                            }
+                            _ => {}
+                        }
+                    })
+                };
+                let outside_click = {
+                    let container = container.clone();
+                    outside.then(|| {
+                        EventListener::new(&document, "mousedown", move |e| {
+                            let target = e.target().and_then(|t| t.dyn_into::<Node>().ok());
+                            let inside = container
+                                .cast::<Node>()
+                                .is_some_and(|c| c.contains(target.as_ref()));
+                            if is_topmost(id) && !inside {
+                                onclose.emit(());
+                            }
+                        })
+                    })
+                };
+
+                move || {
+                    drop((keys, outside_click));
+                    OPEN.with(|open| open.borrow_mut().retain(|&open| open != id));
+                    // Back to the trigger, unless focus has since moved on somewhere else
+                    // outside, as on a click away.
+                    let document = gloo_utils::document();
+                    let lost = match document.active_element() {
+                        Some(active) => {
+                            document
+                                .body()
+                                .is_some_and(|body| body.unchecked_ref::<Element>() == &active)
+                                || container
+                                    .cast::<Node>()
+                                    .is_some_and(|c| c.contains(Some(&active)))
+                        }
+                        None => true,
+                    };
+                    if let Some(trigger) = trigger.filter(|t| lost && t.is_connected()) {
+                        let _ = trigger.focus();
+                    }
+                }
+            },
+            (),
+        );
+    }
+
+    html! {
+        <div ref={container} class="contents">
+            { for props.children.iter() }
+        </div>
+    }
+}
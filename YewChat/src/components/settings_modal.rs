@@ -0,0 +1,164 @@
+use wasm_bindgen_futures::spawn_local;
+use web_sys::{HtmlInputElement, HtmlSelectElement};
+use yew::functional::*;
+use yew::prelude::*;
+
+use crate::components::overlay::Overlay;
+use crate::hooks::use_settings;
+use crate::i18n::I18n;
+use crate::services::notify;
+use crate::settings::{
+    Clock, Settings, SettingsContext, Theme, MAX_IDLE_MINUTES, MIN_IDLE_MINUTES,
+};
+
+// Applies `change` to a copy of the current settings and makes that the settings.
+fn edit(ctx: &SettingsContext, change: impl FnOnce(&mut Settings)) {
+    let mut next = ctx.settings.clone();
+    change(&mut next);
+    ctx.update.emit(next);
+}
+
+#[derive(Properties, PartialEq)]
+pub struct SettingsModalProps {
+    /// Called on Done, Esc, or a click outside the dialog.
+    pub onclose: Callback<()>,
+    #[prop_or_default]
+    pub i18n: I18n,
+}
+
+/// Edits the user's settings. Every change takes effect, and is saved, as it's made, so
+/// there's nothing to apply or cancel.
+#[function_component(SettingsModal)]
+pub fn settings_modal(props: &SettingsModalProps) -> Html {
+    let ctx = use_settings();
+    let settings = &ctx.settings;
+    // Set when the browser turned down notifications just now.
+    let blocked = use_state(|| false);
+
+    let onbackdrop = {
+        let onclose = props.onclose.clone();
+        Callback::from(move |e: MouseEvent| {
+            if e.target() == e.current_target() {
+                onclose.emit(());
+            }
+        })
+    };
+    let ontheme = {
+        let ctx = ctx.clone();
+        Callback::from(move |e: Event| {
+            let index = e
+                .target_unchecked_into::<HtmlSelectElement>()
+                .selected_index();
+            let theme = Theme::ALL.get(index as usize).copied().unwrap_or_default();
+            edit(&ctx, |s| s.theme = theme);
+        })
+    };
+    let onclock = {
+        let ctx = ctx.clone();
+        Callback::from(move |e: Event| {
+            let index = e
+                .target_unchecked_into::<HtmlSelectElement>()
+                .selected_index();
+            let clock = Clock::ALL.get(index as usize).copied().unwrap_or_default();
+            edit(&ctx, |s| s.clock = clock);
+        })
+    };
+    let onsound = {
+        let ctx = ctx.clone();
+        Callback::from(move |e: Event| {
+            let on = e.target_unchecked_into::<HtmlInputElement>().checked();
+            // A sample of what it'll sound like; it also lets the page play sound at all.
+            if on {
+                notify::chime();
+            }
+            edit(&ctx, |s| s.sound = on);
+        })
+    };
+    let onnotifications = {
+        let (ctx, blocked) = (ctx.clone(), blocked.clone());
+        Callback::from(move |e: Event| {
+            if !e.target_unchecked_into::<HtmlInputElement>().checked() {
+                edit(&ctx, |s| s.notifications = false);
+                return;
+            }
+            // Only on once the browser agrees to show them.
+            let (ctx, blocked) = (ctx.clone(), blocked.clone());
+            spawn_local(async move {
+                let granted = notify::request_permission().await;
+                blocked.set(!granted);
+                edit(&ctx, |s| s.notifications = granted);
+            });
+        })
+    };
+    let onidle = {
+        let ctx = ctx.clone();
+        Callback::from(move |e: Event| {
+            if let Ok(minutes) = e
+                .target_unchecked_into::<HtmlInputElement>()
+                .value()
+                .trim()
+                .parse::<u32>()
+            {
+                let minutes = minutes.clamp(MIN_IDLE_MINUTES, MAX_IDLE_MINUTES);
+                edit(&ctx, |s| s.idle_minutes = minutes);
+            }
+        })
+    };
+
+    let i18n = props.i18n;
+    let row = "flex items-center justify-between gap-4 py-2";
+    html! {
+        <Overlay onclose={props.onclose.clone()} modal=true>
+            <div class="fixed inset-0 z-30 flex items-center justify-center bg-black bg-opacity-40 settings-backdrop"
+                onclick={onbackdrop}>
+                <div role="dialog" aria-modal="true" aria-labelledby="settings-title"
+                    class="w-96 max-w-full m-4 p-5 bg-white rounded-xl shadow-xl settings-modal">
+                    <h2 id="settings-title" class="text-lg font-semibold mb-3">{i18n.t("settings")}</h2>
+                    <label class={row}>
+                        <span class="text-sm text-gray-700">{i18n.t("theme")}</span>
+                        <select class="text-sm bg-transparent" onchange={ontheme}>
+                            {
+                                Theme::ALL.into_iter().map(|t| html! {
+                                    <option selected={t == settings.theme}>{t.label(i18n)}</option>
+                                }).collect::<Html>()
+                            }
+                        </select>
+                    </label>
+                    <label class={row}>
+                        <span class="text-sm text-gray-700">{i18n.t("time_format")}</span>
+                        <select class="text-sm bg-transparent" onchange={onclock}>
+                            {
+                                Clock::ALL.into_iter().map(|c| html! {
+                                    <option selected={c == settings.clock}>{c.label(i18n)}</option>
+                                }).collect::<Html>()
+                            }
+                        </select>
+                    </label>
+                    <label class={row}>
+                        <span class="text-sm text-gray-700">{i18n.t("sound_setting")}</span>
+                        <input type="checkbox" checked={settings.sound} onchange={onsound}/>
+                    </label>
+                    <label class={row}>
+                        <span class="text-sm text-gray-700">{i18n.t("notifications_setting")}</span>
+                        <input type="checkbox" checked={settings.notifications} onchange={onnotifications}/>
+                    </label>
+                    if *blocked {
+                        <p class="text-xs text-red-600">{i18n.t("notifications_blocked")}</p>
+                    }
+                    <label class={row}>
+                        <span class="text-sm text-gray-700">{i18n.t("away_after")}</span>
+                        <input type="number" class="w-16 px-2 py-1 text-sm border border-gray-200 rounded"
+                            min={MIN_IDLE_MINUTES.to_string()} max={MAX_IDLE_MINUTES.to_string()}
+                            value={settings.idle_minutes.to_string()} onchange={onidle}/>
+                    </label>
+                    <div class="flex justify-end mt-4">
+                        <button class="px-3 py-1.5 rounded-lg text-sm font-semibold text-white bg-primary-dark"
+                            onclick={props.onclose.reform(|_| ())}>
+                            {i18n.t("done")}
+                        </button>
+                    </div>
+                </div>
+            </div>
+        </Overlay>
+    }
+}
@@ -0,0 +1,48 @@
+use yew::functional::*;
+use yew::prelude::*;
+
+#[derive(Properties, PartialEq)]
+pub struct SpoilerProps {
+    pub children: Children,
+}
+
+/// Part of a message hidden behind a blur until it's clicked, and hidden again on the
+/// next click. While hidden it can't be selected and screen readers skip it.
+#[function_component(Spoiler)]
+pub fn spoiler(props: &SpoilerProps) -> Html {
+    let revealed = use_state(|| false);
+
+    let toggle = {
+        let revealed = revealed.clone();
+        Callback::from(move |_| revealed.set(!*revealed))
+    };
+    let onkeydown = {
+        let toggle = toggle.clone();
+        Callback::from(move |e: KeyboardEvent| {
+            if e.key() == "Enter" || e.key() == " " {
+                e.prevent_default();
+                toggle.emit(());
+            }
+        })
+    };
+
+    let (class, label) = if *revealed {
+        (
+            "rounded px-0.5 bg-black/10 cursor-pointer spoiler",
+            "Spoiler, click to hide",
+        )
+    } else {
+        (
+            "rounded px-0.5 bg-gray-400/60 cursor-pointer select-none blur-sm spoiler",
+            "Spoiler, click to reveal",
+        )
+    };
+
+    html! {
+        <span {class} role="button" tabindex="0" title={label} aria-label={(!*revealed).then_some(label)}
+            aria-pressed={revealed.to_string()}
+            onclick={toggle.reform(|_: MouseEvent| ())} {onkeydown}>
+            <span aria-hidden={(!*revealed).then_some("true")}>{ for props.children.iter() }</span>
+        </span>
+    }
+}
@@ -0,0 +1,120 @@
+use gloo_timers::callback::Timeout;
+use yew::prelude::*;
+
+use crate::toasts::{Severity, Toast, ToastContext, Toasts};
+
+pub enum Msg {
+    Push(Toast),
+    Dismiss(u32),
+    /// Pressed the button on the toast with this id.
+    Act(u32),
+    Expire,
+}
+
+#[derive(Properties, PartialEq)]
+pub struct ToastHostProps {
+    pub children: Children,
+}
+
+/// Shows the toasts pushed through the `ToastContext` it gives its children, stacked at
+/// the foot of the page, each until it's dismissed or its time is up. Screen readers
+/// hear each as it comes up; errors interrupt.
+pub struct ToastHost {
+    toasts: Toasts,
+    context: ToastContext,
+    // Set for whenever the next toast goes.
+    expiry: Option<Timeout>,
+}
+
+impl ToastHost {
+    /// Takes down what's had its time and waits for the next to go.
+    fn expire(&mut self, ctx: &Context<Self>) -> bool {
+        let now = js_sys::Date::now();
+        let expired = self.toasts.expire(now);
+        self.expiry = self.toasts.next_expiry().map(|at| {
+            let link = ctx.link().clone();
+            Timeout::new((at - now).max(0.0).ceil() as u32, move || {
+                link.send_message(Msg::Expire)
+            })
+        });
+        expired
+    }
+}
+
+impl Component for ToastHost {
+    type Message = Msg;
+    type Properties = ToastHostProps;
+
+    fn create(ctx: &Context<Self>) -> Self {
+        Self {
+            toasts: Toasts::default(),
+            context: ToastContext {
+                push: ctx.link().callback(Msg::Push),
+            },
+            expiry: None,
+        }
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            Msg::Push(toast) => {
+                self.toasts.push(toast, js_sys::Date::now());
+                self.expire(ctx);
+                true
+            }
+            Msg::Dismiss(id) => self.toasts.dismiss(id),
+            Msg::Act(id) => {
+                let action = self
+                    .toasts
+                    .shown()
+                    .iter()
+                    .find(|s| s.id == id)
+                    .and_then(|s| s.toast.action.clone());
+                if let Some(action) = action {
+                    action.onclick.emit(());
+                }
+                self.toasts.dismiss(id)
+            }
+            Msg::Expire => self.expire(ctx),
+        }
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let link = ctx.link();
+        html! {
+            <ContextProvider<ToastContext> context={self.context.clone()}>
+                { for ctx.props().children.iter() }
+                <div class="fixed bottom-24 inset-x-0 z-40 flex flex-col items-center gap-2 pointer-events-none toast-host"
+                    aria-live="polite">
+                    {
+                        self.toasts.shown().iter().map(|shown| {
+                            let id = shown.id;
+                            let toast = &shown.toast;
+                            let (role, tone) = match toast.severity {
+                                Severity::Info => ("status", "bg-gray-800"),
+                                Severity::Success => ("status", "bg-green-700"),
+                                Severity::Error => ("alert", "bg-red-700"),
+                            };
+                            html! {
+                                <div key={id} {role}
+                                    class={classes!("flex", "items-center", "gap-3", "px-4", "py-2", "rounded-lg", "shadow-lg",
+                                        "text-sm", "text-white", "pointer-events-auto", "toast", tone)}>
+                                    <span>{toast.text.clone()}</span>
+                                    if let Some(action) = &toast.action {
+                                        <button class="font-semibold underline" onclick={link.callback(move |_| Msg::Act(id))}>
+                                            {action.label.clone()}
+                                        </button>
+                                    }
+                                    <button class="opacity-75 hover:opacity-100" aria-label="Dismiss"
+                                        onclick={link.callback(move |_| Msg::Dismiss(id))}>
+                                        {"✕"}
+                                    </button>
+                                </div>
+                            }
+                        }).collect::<Html>()
+                    }
+                </div>
+            </ContextProvider<ToastContext>>
+        }
+    }
+}
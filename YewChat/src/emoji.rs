@@ -0,0 +1,322 @@
+//! GitHub-style `:shortcode:` emoji.
+
+// Sorted by code so lookups can binary search.
+const SHORTCODES: [(&str, &str); 161] = [
+    ("+1", "👍"),
+    ("-1", "👎"),
+    ("100", "💯"),
+    ("airplane", "✈️"),
+    ("alarm_clock", "⏰"),
+    ("alien", "👽"),
+    ("angry", "😠"),
+    ("apple", "🍎"),
+    ("astonished", "😲"),
+    ("balloon", "🎈"),
+    ("beer", "🍺"),
+    ("beers", "🍻"),
+    ("bell", "🔔"),
+    ("black_heart", "🖤"),
+    ("blue_heart", "💙"),
+    ("blush", "😊"),
+    ("books", "📚"),
+    ("boom", "💥"),
+    ("broken_heart", "💔"),
+    ("bug", "🐛"),
+    ("bulb", "💡"),
+    ("cake", "🍰"),
+    ("calendar", "📅"),
+    ("car", "🚗"),
+    ("cat", "🐱"),
+    ("chart_with_upwards_trend", "📈"),
+    ("check", "✔️"),
+    ("clap", "👏"),
+    ("cloud", "☁️"),
+    ("clown_face", "🤡"),
+    ("coffee", "☕"),
+    ("computer", "💻"),
+    ("confetti_ball", "🎊"),
+    ("confused", "😕"),
+    ("cookie", "🍪"),
+    ("cowboy_hat_face", "🤠"),
+    ("crab", "🦀"),
+    ("crossed_fingers", "🤞"),
+    ("crown", "👑"),
+    ("cry", "😢"),
+    ("dizzy_face", "😵"),
+    ("dog", "🐶"),
+    ("doughnut", "🍩"),
+    ("earth_africa", "🌍"),
+    ("email", "📧"),
+    ("exclamation", "❗"),
+    ("exploding_head", "🤯"),
+    ("expressionless", "😑"),
+    ("eyes", "👀"),
+    ("facepalm", "🤦"),
+    ("fire", "🔥"),
+    ("flushed", "😳"),
+    ("fox_face", "🦊"),
+    ("gear", "⚙️"),
+    ("gem", "💎"),
+    ("ghost", "👻"),
+    ("gift", "🎁"),
+    ("globe_with_meridians", "🌐"),
+    ("green_heart", "💚"),
+    ("grimacing", "😬"),
+    ("grin", "😁"),
+    ("grinning", "😀"),
+    ("hamburger", "🍔"),
+    ("hammer", "🔨"),
+    ("handshake", "🤝"),
+    ("hear_no_evil", "🙉"),
+    ("heart", "❤️"),
+    ("heart_eyes", "😍"),
+    ("hourglass", "⌛"),
+    ("house", "🏠"),
+    ("innocent", "😇"),
+    ("joy", "😂"),
+    ("key", "🔑"),
+    ("keyboard", "⌨️"),
+    ("kissing_heart", "😘"),
+    ("laughing", "😆"),
+    ("link", "🔗"),
+    ("lock", "🔒"),
+    ("mask", "😷"),
+    ("memo", "📝"),
+    ("money_with_wings", "💸"),
+    ("moon", "🌙"),
+    ("muscle", "💪"),
+    ("musical_note", "🎵"),
+    ("nerd_face", "🤓"),
+    ("neutral_face", "😐"),
+    ("no_mouth", "😶"),
+    ("ok_hand", "👌"),
+    ("open_mouth", "😮"),
+    ("orange_heart", "🧡"),
+    ("partying_face", "🥳"),
+    ("penguin", "🐧"),
+    ("pensive", "😔"),
+    ("pizza", "🍕"),
+    ("pleading_face", "🥺"),
+    ("point_down", "👇"),
+    ("point_left", "👈"),
+    ("point_right", "👉"),
+    ("point_up", "☝️"),
+    ("poop", "💩"),
+    ("pray", "🙏"),
+    ("purple_heart", "💜"),
+    ("pushpin", "📌"),
+    ("question", "❓"),
+    ("rage", "😡"),
+    ("rainbow", "🌈"),
+    ("raised_hands", "🙌"),
+    ("relieved", "😌"),
+    ("robot", "🤖"),
+    ("rocket", "🚀"),
+    ("rofl", "🤣"),
+    ("roll_eyes", "🙄"),
+    ("scream", "😱"),
+    ("see_no_evil", "🙈"),
+    ("ship", "🚢"),
+    ("shrug", "🤷"),
+    ("skull", "💀"),
+    ("sleeping", "😴"),
+    ("sleepy", "😪"),
+    ("slightly_smiling_face", "🙂"),
+    ("smile", "😄"),
+    ("smiley", "😃"),
+    ("smirk", "😏"),
+    ("snake", "🐍"),
+    ("snowflake", "❄️"),
+    ("sob", "😭"),
+    ("sparkles", "✨"),
+    ("sparkling_heart", "💖"),
+    ("speak_no_evil", "🙊"),
+    ("speech_balloon", "💬"),
+    ("star", "⭐"),
+    ("stuck_out_tongue", "😛"),
+    ("stuck_out_tongue_winking_eye", "😜"),
+    ("sun_with_face", "🌞"),
+    ("sunglasses", "😎"),
+    ("sunny", "☀️"),
+    ("sweat_smile", "😅"),
+    ("taco", "🌮"),
+    ("tada", "🎉"),
+    ("thinking", "🤔"),
+    ("thumbsdown", "👎"),
+    ("thumbsup", "👍"),
+    ("trophy", "🏆"),
+    ("two_hearts", "💕"),
+    ("unamused", "😒"),
+    ("unicorn", "🦄"),
+    ("upside_down_face", "🙃"),
+    ("v", "✌️"),
+    ("video_game", "🎮"),
+    ("warning", "⚠️"),
+    ("wave", "👋"),
+    ("wavy_dash", "〰️"),
+    ("white_check_mark", "✅"),
+    ("wink", "😉"),
+    ("worried", "😟"),
+    ("wrench", "🔧"),
+    ("x", "❌"),
+    ("yellow_heart", "💛"),
+    ("yum", "😋"),
+    ("zap", "⚡"),
+    ("zzz", "💤"),
+];
+
+/// Every known `(shortcode, emoji)` pair, sorted by shortcode.
+pub fn shortcodes() -> &'static [(&'static str, &'static str)] {
+    &SHORTCODES
+}
+
+/// Looks up the glyph for a shortcode, given without its colons.
+pub fn emoji_for(code: &str) -> Option<&'static str> {
+    SHORTCODES
+        .binary_search_by(|(c, _)| (*c).cmp(code))
+        .ok()
+        .map(|i| SHORTCODES[i].1)
+}
+
+fn is_code_char(c: char) -> bool {
+    c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '_' | '+' | '-')
+}
+
+/// Replaces known `:shortcode:`s in `text` with their emoji, leaving unknown ones as
+/// typed. Text inside backtick code spans is left alone.
+pub fn expand_shortcodes(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(i) = rest.find([':', '`']) {
+        out.push_str(&rest[..i]);
+        rest = &rest[i..];
+
+        if rest.starts_with('`') {
+            // A code span closes on a run of backticks as long as the one that opened it;
+            // an unclosed run is just text.
+            let fence = rest.len() - rest.trim_start_matches('`').len();
+            let end = rest[fence..]
+                .match_indices(&rest[..fence])
+                .map(|(j, _)| fence + j)
+                .find(|&j| !rest[j + fence..].starts_with('`'));
+            let span = end.map_or(fence, |j| j + fence);
+            out.push_str(&rest[..span]);
+            rest = &rest[span..];
+            continue;
+        }
+
+        let len = rest[1..]
+            .find(|c: char| !is_code_char(c))
+            .unwrap_or(rest.len() - 1);
+        let code = &rest[1..1 + len];
+        match emoji_for(code) {
+            Some(emoji) if !code.is_empty() && rest[1 + len..].starts_with(':') => {
+                out.push_str(emoji);
+                rest = &rest[len + 2..];
+            }
+            // Not a known code; the closing colon may still open the next one.
+            _ => {
+                out.push_str(&rest[..1 + len]);
+                rest = &rest[1 + len..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// For expanding as the user types: if `before`, the text up to the cursor, ends in a
+/// known `:shortcode:`, returns where that code starts and its emoji. A code inside an
+/// open backtick span is left alone, as `expand_shortcodes` would.
+pub fn trailing_shortcode(before: &str) -> Option<(usize, &'static str)> {
+    let body = before.strip_suffix(':')?;
+    let start = body.rfind(':')?;
+    let code = &body[start + 1..];
+    if code.is_empty() || !code.chars().all(is_code_char) {
+        return None;
+    }
+    if before[..start].matches('`').count() % 2 == 1 {
+        return None;
+    }
+    Some((start, emoji_for(code)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_table_is_sorted_for_lookups() {
+        assert!(SHORTCODES.windows(2).all(|w| w[0].0 < w[1].0));
+        assert_eq!(emoji_for("+1"), Some("👍"));
+        assert_eq!(emoji_for("wave"), Some("👋"));
+        assert_eq!(emoji_for("nope"), None);
+    }
+
+    #[test]
+    fn known_codes_become_emoji() {
+        for (text, expanded) in [
+            (":smile:", "😄"),
+            ("hi :wave: there", "hi 👋 there"),
+            ("done:tada:", "done🎉"),
+            (":+1: ok", "👍 ok"),
+        ] {
+            assert_eq!(expand_shortcodes(text), expanded, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn unknown_codes_are_left_as_typed() {
+        for text in [
+            ":nope:",
+            "a:b",
+            "ratio 3:4:5",
+            "::",
+            ": smile :",
+            ":Smile:",
+            ":smile",
+            "smile:",
+        ] {
+            assert_eq!(expand_shortcodes(text), text, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn adjacent_codes_all_expand() {
+        assert_eq!(expand_shortcodes(":smile::wave:"), "😄👋");
+        assert_eq!(expand_shortcodes(":tada::tada::tada:"), "🎉🎉🎉");
+        // The colon closing an unknown code can open a known one.
+        assert_eq!(expand_shortcodes(":nope:smile:"), ":nope😄");
+    }
+
+    #[test]
+    fn code_spans_are_left_alone() {
+        for (text, expanded) in [
+            ("`:smile:`", "`:smile:`"),
+            ("`:smile:` :smile:", "`:smile:` 😄"),
+            ("``a ` :wave: ``:wave:", "``a ` :wave: ``👋"),
+            // Never closed, so just a backtick.
+            ("` :wave:", "` 👋"),
+        ] {
+            assert_eq!(expand_shortcodes(text), expanded, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn a_code_is_expanded_once_it_is_closed() {
+        assert_eq!(trailing_shortcode("hi :wave:"), Some((3, "👋")));
+        assert_eq!(trailing_shortcode(":+1:"), Some((0, "👍")));
+        for before in [
+            "hi :wave",
+            ":nope:",
+            "::",
+            "a :Wave:",
+            "`:wave:",
+            "hi : wave:",
+        ] {
+            assert_eq!(trailing_shortcode(before), None, "{:?}", before);
+        }
+        assert_eq!(trailing_shortcode("`x` :wave:"), Some((4, "👋")));
+    }
+}
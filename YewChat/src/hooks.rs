@@ -0,0 +1,276 @@
+//! Hooks shared by the function components.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use gloo_events::EventListener;
+use yew::functional::*;
+use yew::Callback;
+use yew_agent::{Bridge, Bridged};
+
+use crate::protocol::WebSocketMessage;
+use crate::services::event_bus::BusEvent;
+use crate::services::websocket::{ConnState, SendError, ServiceConfig, WebsocketService, WsEvent};
+use crate::settings::SettingsContext;
+use crate::store::{AppState, Slice, Store, StoreAction, StoreRequest, StoreUpdate};
+use crate::toasts::ToastContext;
+
+/// What `use_websocket` gives its component each render.
+#[derive(Clone)]
+pub struct WsHandle {
+    /// The connection's state as of this render.
+    pub state: ConnState,
+    // Whichever connection is current, so a handle stays valid across a new URL.
+    service: Rc<RefCell<Option<WebsocketService>>>,
+}
+
+impl WsHandle {
+    /// Queues a frame for the socket, or says why it couldn't. Frames sent while the
+    /// socket is reconnecting wait until it's back.
+    pub fn send(&self, message: &WebSocketMessage) -> Result<(), SendError> {
+        let frame = serde_json::to_string(message).unwrap();
+        let sent = match self.service.borrow().as_ref() {
+            Some(service) => service.send(frame),
+            None => Err(SendError::Closed),
+        };
+        if let Err(e) = sent {
+            log::debug!("couldn't queue frame: {:?}", e);
+        }
+        sent
+    }
+
+    /// See `WebsocketService::reconnect`.
+    pub fn reconnect(&self) {
+        if let Some(service) = self.service.borrow().as_ref() {
+            service.reconnect();
+        }
+    }
+}
+
+/// Decodes raw frames for `on_message`, logging and dropping the ones it can't read.
+fn decoder(on_message: Callback<BusEvent>) -> Callback<String> {
+    Callback::from(move |frame: String| match BusEvent::from_frame(&frame) {
+        Ok(event) => on_message.emit(event),
+        Err(e) => log::error!("ws: dropping frame: {}", e),
+    })
+}
+
+/// Makes `service` the connection behind every `WsHandle` on `current`, and returns what
+/// drops it again, which closes it.
+fn install(
+    current: &Rc<RefCell<Option<WebsocketService>>>,
+    service: WebsocketService,
+) -> impl FnOnce() {
+    *current.borrow_mut() = Some(service);
+    let current = current.clone();
+    move || drop(current.borrow_mut().take())
+}
+
+/// Keeps a connection to `url` open while the calling component is mounted, passing
+/// every decoded frame to `on_message`, and following the browser online and offline.
+/// Changing `url` closes the old socket and opens a new one; `on_message` is the one
+/// given when the current socket was opened. The socket and its tasks go away with the
+/// component.
+pub fn use_websocket(url: &str, on_message: Callback<BusEvent>) -> WsHandle {
+    let state = use_state_eq(|| ConnState::Connecting);
+    let service = use_mut_ref(|| None);
+
+    {
+        let (state, current) = (state.clone(), service.clone());
+        use_effect_with_deps(
+            move |url: &String| {
+                let service = WebsocketService::connect(
+                    url,
+                    Callback::from(move |s| state.set(s)),
+                    Callback::noop(),
+                    decoder(on_message),
+                    ServiceConfig::default(),
+                );
+                if !gloo_utils::window().navigator().on_line() {
+                    service.set_online(false);
+                }
+                let close = install(&current, service);
+                let listeners = [("online", true), ("offline", false)].map(|(kind, online)| {
+                    let current = current.clone();
+                    EventListener::new(&gloo_utils::window(), kind, move |_| {
+                        if let Some(service) = current.borrow().as_ref() {
+                            service.set_online(online);
+                        }
+                    })
+                });
+                move || {
+                    drop(listeners);
+                    close()
+                }
+            },
+            url.to_string(),
+        );
+    }
+
+    WsHandle {
+        state: *state,
+        service,
+    }
+}
+
+/// The state of the connection `Chat` keeps open, for components that show it without
+/// owning it; the calling component renders again whenever it changes.
+pub fn use_connection_state() -> ConnState {
+    let state = use_state_eq(|| ConnState::Connecting);
+    {
+        let state = state.clone();
+        use_mut_ref(move || {
+            WebsocketService::subscribe(
+                &[],
+                Callback::from(move |event| {
+                    if let WsEvent::State(s) = event {
+                        state.set(s);
+                    }
+                }),
+            )
+        });
+    }
+    *state
+}
+
+/// What `use_store` gives its component each render.
+#[derive(Clone)]
+pub struct StoreHandle {
+    /// The followed slices as of this render. The rest are left empty.
+    pub state: Rc<AppState>,
+    bridge: Rc<RefCell<Box<dyn Bridge<Store>>>>,
+}
+
+impl StoreHandle {
+    pub fn dispatch(&self, action: StoreAction) {
+        self.bridge
+            .borrow_mut()
+            .send(StoreRequest::Dispatch(action));
+    }
+}
+
+// A component's copy of what it follows in the store.
+#[derive(Default)]
+struct Mirror(Rc<AppState>);
+
+impl Reducible for Mirror {
+    type Action = StoreUpdate;
+
+    fn reduce(self: Rc<Self>, update: StoreUpdate) -> Rc<Self> {
+        let mut state = (*self.0).clone();
+        state.merge(update);
+        Rc::new(Mirror(Rc::new(state)))
+    }
+}
+
+/// Follows `slices` of the shared `Store` while the calling component is mounted,
+/// rendering again whenever one changes. The slices are the ones given on the first
+/// render.
+pub fn use_store(slices: Vec<Slice>) -> StoreHandle {
+    let mirror = use_reducer(Mirror::default);
+    let bridge = {
+        let mirror = mirror.clone();
+        use_mut_ref(move || {
+            let mut bridge = Store::bridge(Callback::from(move |u| mirror.dispatch(u)));
+            for slice in slices {
+                bridge.send(StoreRequest::Follow(slice));
+            }
+            bridge
+        })
+    };
+    StoreHandle {
+        state: mirror.0.clone(),
+        bridge,
+    }
+}
+
+/// The user's settings, and how to change them; the calling component renders again
+/// whenever they change.
+pub fn use_settings() -> SettingsContext {
+    use_context::<SettingsContext>().expect("No settings context found.")
+}
+
+/// How to put up a toast, from under a `ToastHost`.
+pub fn use_toasts() -> ToastContext {
+    use_context::<ToastContext>().expect("No toast context found.")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    use futures::executor::LocalPool;
+    use futures::task::LocalSpawnExt;
+
+    use crate::protocol::MsgTypes;
+    use crate::transport::{MockTransport, Transport};
+
+    /// A service on one mock connection, running on `pool`, and a flag for when its
+    /// connection task has ended.
+    fn service(pool: &LocalPool) -> (WebsocketService, MockTransport, Rc<Cell<bool>>) {
+        let transport = MockTransport::default();
+        let connect = {
+            let transport = transport.clone();
+            move || Ok(Box::new(transport.clone()) as Box<dyn Transport>)
+        };
+        let (service, task) = WebsocketService::unspawned(connect, Callback::noop());
+        let ended = Rc::new(Cell::new(false));
+        let flag = ended.clone();
+        pool.spawner()
+            .spawn_local(async move {
+                task.await;
+                flag.set(true);
+            })
+            .unwrap();
+        (service, transport, ended)
+    }
+
+    fn message(text: &str) -> WebSocketMessage {
+        WebSocketMessage {
+            message_type: MsgTypes::Message,
+            data: Some(text.to_string()),
+            data_array: None,
+            id: None,
+            reply_to: None,
+            attachment: None,
+        }
+    }
+
+    #[test]
+    fn a_new_url_closes_the_old_connection_and_unmounting_closes_the_last() {
+        let mut pool = LocalPool::new();
+        let current = Rc::new(RefCell::new(None));
+        let handle = WsHandle {
+            state: ConnState::Connecting,
+            service: current.clone(),
+        };
+
+        let (first, old, old_ended) = service(&pool);
+        let close_first = install(&current, first);
+        pool.run_until_stalled();
+        old.open();
+        handle.send(&message("one")).unwrap();
+        pool.run_until_stalled();
+        assert_eq!(old.sent().iter().filter(|f| f.contains("one")).count(), 1);
+
+        // What the effect does when the URL changes: clean up, then connect again.
+        close_first();
+        pool.run_until_stalled();
+        assert!(old_ended.get());
+        let (second, new, new_ended) = service(&pool);
+        let close_second = install(&current, second);
+        pool.run_until_stalled();
+        new.open();
+        handle.send(&message("two")).unwrap();
+        pool.run_until_stalled();
+        assert!(new.sent().iter().any(|f| f.contains("two")));
+        assert!(!old.sent().iter().any(|f| f.contains("two")));
+
+        // And on unmount.
+        close_second();
+        pool.run_until_stalled();
+        assert!(new_ended.get());
+        assert_eq!(handle.send(&message("late")), Err(SendError::Closed));
+    }
+}
@@ -0,0 +1,724 @@
+//! UI text by language. Each language is a catalog mapping message ids to its text;
+//! adding a language means adding a catalog to `CATALOGS`. English has every id, and
+//! anything another catalog lacks falls back to it. Languages in `RTL` are laid out
+//! right to left.
+
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::fmt::{self, Write};
+
+/// Message ids paired with their text.
+pub type Catalog = &'static [(&'static str, &'static str)];
+
+const EN: Catalog = &[
+    ("users", "Users"),
+    ("online", "Online"),
+    ("away", "Away"),
+    ("offline", "Offline"),
+    ("tagline", "Let's chat!"),
+    ("message_placeholder", "Type a message..."),
+    ("search_placeholder", "Search messages"),
+    ("no_matches", "No matches"),
+    ("connecting", "Connecting…"),
+    ("reconnect", "Reconnect"),
+    ("you_offline", "You are offline. Queued messages will be sent once you're back."),
+    ("unsupported_title", "This browser can't connect to the chat"),
+    (
+        "unsupported_body",
+        "YewChat needs WebSockets, which this browser doesn't support or has turned off. Try an up-to-date Firefox, Chrome, Edge or Safari.",
+    ),
+    ("show_earlier", "Show earlier messages"),
+    ("queued", "Queued"),
+    ("sending", "Sending…"),
+    ("failed_retry", "Failed · Retry"),
+    ("seen", "Seen"),
+    ("just_now", "Just now"),
+    ("minutes_short", "m"),
+    ("hours_short", "h"),
+    ("sort_recent", "Recent"),
+    ("me_first", "Me first"),
+    ("users_stale", "Updating…"),
+    ("star", "Star"),
+    ("unstar", "Unstar"),
+    ("confirm_clear_history", "Clear the conversation on this device? The messages saved here are deleted; everyone else keeps theirs."),
+    ("confirm_clear_room", "Clear the conversation for everyone? The server's history and pins are deleted for all users, and this can't be undone."),
+    ("confirm_delete", "Delete this message for everyone?"),
+    ("confirm_kick", "Remove {0} from the chat?"),
+    ("clear", "Clear"),
+    ("delete", "Delete"),
+    ("remove", "Remove"),
+    ("owner_only", "Only the room's owner ({0}) can clear the conversation for everyone."),
+    ("first_joiner", "whoever joined first"),
+    ("download", "Download"),
+    ("download_named", "Download {0}"),
+    ("resize_users", "Resize user list"),
+    ("show_more", "Show more"),
+    ("show_less", "Show less"),
+    ("show_all", "Show all"),
+    ("role_you", "You"),
+    ("role_admin", "Admin"),
+    ("online_since", "Online since {0}"),
+    ("last_active", "Last active: {0}"),
+    ("mention", "Message"),
+    ("original_unavailable", "Original message unavailable"),
+    ("show_original", "Show the original message"),
+    ("reply", "Reply"),
+    ("add_reaction", "Add reaction"),
+    ("more_actions", "More actions"),
+    ("show_only", "Show only {0}'s messages"),
+    ("image_you_sent", "Image you sent"),
+    ("image_from", "Image from {0}"),
+    ("retry_sending", "Retry sending"),
+    ("seen_by", "Seen by {0}"),
+    ("starred", "Starred"),
+    ("you_said", "You: "),
+    ("match_count", "{0} of {1}"),
+    ("previous_match", "Previous match"),
+    ("next_match", "Next match"),
+    ("close_search", "Close search"),
+    ("older_here", "Older on this device ({0})"),
+    ("dismiss", "Dismiss"),
+    ("uploading", "Uploading {0}…"),
+    ("upload_failed", "Couldn't send {0}: {1}"),
+    ("file_too_large", "{0} is {1}"),
+    ("and_more", " (and {0} more)"),
+    ("saved_messages", "Saved messages"),
+    ("saved_empty", "Star a message to keep it here."),
+    ("jump", "Jump"),
+    ("jump_to_message", "Jump to message"),
+    ("more_in_history", "{0} more in older history; scroll up to load them."),
+    ("quote", "Quote"),
+    ("react", "React"),
+    ("copy_text", "Copy text"),
+    ("copied_message", "Copied the message"),
+    ("copy_message_failed", "Couldn't copy the message"),
+    ("copy_link", "Copy link"),
+    ("copied_link", "Copied the link"),
+    ("copy_link_failed", "Couldn't copy the link"),
+    ("pin", "Pin"),
+    ("unpin", "Unpin"),
+    ("pinned_count", "📌 Pinned ({0}/{1})"),
+    ("clear_here", "Clear on this device"),
+    ("clear_everyone", "Clear for everyone (owner)"),
+    ("reconnected", "Reconnected"),
+    ("send_failed", "Your message couldn't be sent."),
+    ("retry", "Retry"),
+    ("mute", "Mute"),
+    ("unmute", "Unmute"),
+    ("mute_title", "Hide {0}'s messages on this device"),
+    ("unmute_title", "Show {0}'s messages again"),
+    ("kick", "Kick"),
+    ("kick_title", "Remove {0} from the chat"),
+    ("drop_files", "Drop files to share them"),
+    ("round_trip", "Round-trip time to the server"),
+    ("density", "Message density"),
+    ("retention", "Clear messages automatically after"),
+    ("settings", "Settings"),
+    ("export", "Export conversation"),
+    ("export_text", "Export as text"),
+    ("export_json", "Export as JSON"),
+    ("clear_conversation", "Clear conversation"),
+    ("filtering_by", "Filtering by {0}"),
+    ("show_everyone", "Show everyone's messages"),
+    ("retrying", "Connection lost. Retrying ({0} of {1}) — queued messages will be sent once it's back."),
+    ("connection_lost", "Connection lost — queued messages will be sent once it's back."),
+    ("muted_shown_one", "1 message from muted users shown"),
+    ("muted_shown", "{0} messages from muted users shown"),
+    ("muted_hidden_one", "1 message from muted users hidden"),
+    ("muted_hidden", "{0} messages from muted users hidden"),
+    ("hide_them", "Hide them"),
+    ("show_them", "Show them"),
+    ("outbox_full", "Too many messages waiting to be sent (max {0}). Try again once the connection recovers."),
+    ("messages", "Messages"),
+    ("cleared_by", "{0} cleared the conversation for everyone"),
+    ("expired", "Messages older than {0} have been cleared"),
+    ("new_messages", "New messages"),
+    ("replying_to", "Replying to "),
+    ("replying_unloaded", "Replying to a message that's no longer loaded"),
+    ("cancel_reply", "Cancel reply (Esc)"),
+    ("message_input", "Message"),
+    ("input_hint", "Press / or Ctrl+K to jump here"),
+    ("insert_emoji", "Insert emoji"),
+    ("send_gif", "Send a GIF"),
+    ("send_message", "Send message"),
+    ("typing_one", "{0} is typing…"),
+    ("typing_two", "{0} and {1} are typing…"),
+    ("typing_three", "{0}, {1} and {2} are typing…"),
+    ("typing_many", "{0} people are typing…"),
+    ("unavailable", "Unavailable"),
+    ("density_cozy", "Cozy"),
+    ("density_compact", "Compact"),
+    ("retention_forever", "Keep everything"),
+    ("retention_hour", "1 hour"),
+    ("retention_day", "1 day"),
+    ("retention_week", "1 week"),
+    ("gif_search", "Search GIFs"),
+    ("gif_unconfigured", "GIF search isn't configured."),
+    ("gif_prompt", "Search for a GIF"),
+    ("gif_searching", "Searching…"),
+    ("gif_failed", "Couldn't load GIFs. Try again."),
+    ("gif_none", "No GIFs found for \"{0}\""),
+    ("gif_loading_more", "Loading more…"),
+    ("emoji_search", "Search emoji"),
+    ("emoji_none", "No matching emoji"),
+    ("copied", "Copied"),
+    ("error_rate_limited", "You're sending messages too quickly. Wait a moment and try again."),
+    ("error_banned_word", "That message wasn't sent: it has a word that isn't allowed here."),
+    ("error_rejected", "The server turned that message down."),
+    ("error_generic", "The server couldn't do that."),
+    ("kicked_by", "{0} removed you from the chat."),
+    ("cancel", "Cancel"),
+    ("theme", "Theme"),
+    ("theme_system", "Same as system"),
+    ("theme_light", "Light"),
+    ("theme_dark", "Dark"),
+    ("time_format", "Time format"),
+    ("clock_24h", "24-hour"),
+    ("clock_12h", "12-hour"),
+    ("sound_setting", "Sound for new messages"),
+    ("notifications_setting", "Desktop notifications while away"),
+    ("notifications_blocked", "Your browser is blocking notifications from this page."),
+    ("away_after", "Show me as away after (minutes)"),
+    ("done", "Done"),
+    ("copy", "Copy"),
+    ("show_all_lines", "Show all {0} lines"),
+];
+
+const ID: Catalog = &[
+    ("users", "Pengguna"),
+    ("online", "Daring"),
+    ("away", "Tidak di tempat"),
+    ("offline", "Luring"),
+    ("tagline", "Ayo ngobrol!"),
+    ("message_placeholder", "Ketik pesan..."),
+    ("search_placeholder", "Cari pesan"),
+    ("no_matches", "Tidak ada hasil"),
+    ("connecting", "Menghubungkan…"),
+    ("reconnect", "Sambung ulang"),
+    ("you_offline", "Anda sedang luring. Pesan dalam antrean akan dikirim setelah Anda kembali."),
+    ("unsupported_title", "Peramban ini tidak dapat terhubung ke obrolan"),
+    (
+        "unsupported_body",
+        "YewChat membutuhkan WebSocket, yang tidak didukung atau dimatikan di peramban ini. Coba Firefox, Chrome, Edge, atau Safari versi terbaru.",
+    ),
+    ("show_earlier", "Tampilkan pesan sebelumnya"),
+    ("queued", "Dalam antrean"),
+    ("sending", "Mengirim…"),
+    ("failed_retry", "Gagal · Coba lagi"),
+    ("seen", "Dilihat"),
+    ("just_now", "Baru saja"),
+    ("minutes_short", "m"),
+    ("hours_short", "j"),
+    ("sort_recent", "Terbaru"),
+    ("me_first", "Saya di atas"),
+    ("users_stale", "Memperbarui…"),
+    ("star", "Bintangi"),
+    ("unstar", "Hapus bintang"),
+    ("confirm_clear_history", "Hapus percakapan di perangkat ini? Pesan yang tersimpan di sini dihapus; pengguna lain tetap menyimpan miliknya."),
+    ("confirm_clear_room", "Hapus percakapan untuk semua orang? Riwayat dan sematan di server dihapus untuk semua pengguna, dan ini tidak dapat dibatalkan."),
+    ("confirm_delete", "Hapus pesan ini untuk semua orang?"),
+    ("confirm_kick", "Keluarkan {0} dari obrolan?"),
+    ("clear", "Hapus"),
+    ("delete", "Hapus"),
+    ("remove", "Keluarkan"),
+    ("owner_only", "Hanya pemilik ruang ({0}) yang dapat menghapus percakapan untuk semua orang."),
+    ("first_joiner", "siapa pun yang bergabung pertama"),
+    ("download", "Unduh"),
+    ("download_named", "Unduh {0}"),
+    ("resize_users", "Ubah ukuran daftar pengguna"),
+    ("show_more", "Tampilkan lebih banyak"),
+    ("show_less", "Tampilkan lebih sedikit"),
+    ("show_all", "Tampilkan semua"),
+    ("role_you", "Anda"),
+    ("role_admin", "Admin"),
+    ("online_since", "Daring sejak {0}"),
+    ("last_active", "Terakhir aktif: {0}"),
+    ("mention", "Kirim pesan"),
+    ("original_unavailable", "Pesan asli tidak tersedia"),
+    ("show_original", "Tampilkan pesan asli"),
+    ("reply", "Balas"),
+    ("add_reaction", "Tambah reaksi"),
+    ("more_actions", "Tindakan lain"),
+    ("show_only", "Tampilkan hanya pesan {0}"),
+    ("image_you_sent", "Gambar yang Anda kirim"),
+    ("image_from", "Gambar dari {0}"),
+    ("retry_sending", "Coba kirim lagi"),
+    ("seen_by", "Dilihat oleh {0}"),
+    ("starred", "Berbintang"),
+    ("you_said", "Anda: "),
+    ("match_count", "{0} dari {1}"),
+    ("previous_match", "Hasil sebelumnya"),
+    ("next_match", "Hasil berikutnya"),
+    ("close_search", "Tutup pencarian"),
+    ("older_here", "Lebih lama di perangkat ini ({0})"),
+    ("dismiss", "Tutup"),
+    ("uploading", "Mengunggah {0}…"),
+    ("upload_failed", "Tidak dapat mengirim {0}: {1}"),
+    ("file_too_large", "{0}: {1}"),
+    ("and_more", " (dan {0} lainnya)"),
+    ("saved_messages", "Pesan tersimpan"),
+    ("saved_empty", "Beri bintang pada pesan untuk menyimpannya di sini."),
+    ("jump", "Lompat"),
+    ("jump_to_message", "Lompat ke pesan"),
+    ("more_in_history", "{0} lagi di riwayat lama; gulir ke atas untuk memuatnya."),
+    ("quote", "Kutip"),
+    ("react", "Beri reaksi"),
+    ("copy_text", "Salin teks"),
+    ("copied_message", "Pesan disalin"),
+    ("copy_message_failed", "Tidak dapat menyalin pesan"),
+    ("copy_link", "Salin tautan"),
+    ("copied_link", "Tautan disalin"),
+    ("copy_link_failed", "Tidak dapat menyalin tautan"),
+    ("pin", "Sematkan"),
+    ("unpin", "Lepas sematan"),
+    ("pinned_count", "📌 Disematkan ({0}/{1})"),
+    ("clear_here", "Hapus di perangkat ini"),
+    ("clear_everyone", "Hapus untuk semua orang (pemilik)"),
+    ("reconnected", "Tersambung kembali"),
+    ("send_failed", "Pesan Anda tidak dapat dikirim."),
+    ("retry", "Coba lagi"),
+    ("mute", "Bisukan"),
+    ("unmute", "Batal bisukan"),
+    ("mute_title", "Sembunyikan pesan {0} di perangkat ini"),
+    ("unmute_title", "Tampilkan lagi pesan {0}"),
+    ("kick", "Keluarkan"),
+    ("kick_title", "Keluarkan {0} dari obrolan"),
+    ("drop_files", "Lepaskan berkas untuk membagikannya"),
+    ("round_trip", "Waktu pulang-pergi ke server"),
+    ("density", "Kerapatan pesan"),
+    ("retention", "Hapus pesan otomatis setelah"),
+    ("settings", "Pengaturan"),
+    ("export", "Ekspor percakapan"),
+    ("export_text", "Ekspor sebagai teks"),
+    ("export_json", "Ekspor sebagai JSON"),
+    ("clear_conversation", "Hapus percakapan"),
+    ("filtering_by", "Disaring menurut {0}"),
+    ("show_everyone", "Tampilkan pesan semua orang"),
+    ("retrying", "Koneksi terputus. Mencoba lagi ({0} dari {1}) — pesan dalam antrean akan dikirim setelah tersambung kembali."),
+    ("connection_lost", "Koneksi terputus — pesan dalam antrean akan dikirim setelah tersambung kembali."),
+    ("muted_shown_one", "1 pesan dari pengguna yang dibisukan ditampilkan"),
+    ("muted_shown", "{0} pesan dari pengguna yang dibisukan ditampilkan"),
+    ("muted_hidden_one", "1 pesan dari pengguna yang dibisukan disembunyikan"),
+    ("muted_hidden", "{0} pesan dari pengguna yang dibisukan disembunyikan"),
+    ("hide_them", "Sembunyikan"),
+    ("show_them", "Tampilkan"),
+    ("outbox_full", "Terlalu banyak pesan menunggu dikirim (maks. {0}). Coba lagi setelah koneksi pulih."),
+    ("messages", "Pesan"),
+    ("cleared_by", "{0} menghapus percakapan untuk semua orang"),
+    ("expired", "Pesan yang lebih lama dari {0} telah dihapus"),
+    ("new_messages", "Pesan baru"),
+    ("replying_to", "Membalas "),
+    ("replying_unloaded", "Membalas pesan yang sudah tidak dimuat"),
+    ("cancel_reply", "Batalkan balasan (Esc)"),
+    ("message_input", "Pesan"),
+    ("input_hint", "Tekan / atau Ctrl+K untuk ke sini"),
+    ("insert_emoji", "Sisipkan emoji"),
+    ("send_gif", "Kirim GIF"),
+    ("send_message", "Kirim pesan"),
+    ("typing_one", "{0} sedang mengetik…"),
+    ("typing_two", "{0} dan {1} sedang mengetik…"),
+    ("typing_three", "{0}, {1}, dan {2} sedang mengetik…"),
+    ("typing_many", "{0} orang sedang mengetik…"),
+    ("unavailable", "Tidak tersedia"),
+    ("density_cozy", "Lega"),
+    ("density_compact", "Ringkas"),
+    ("retention_forever", "Simpan semuanya"),
+    ("retention_hour", "1 jam"),
+    ("retention_day", "1 hari"),
+    ("retention_week", "1 minggu"),
+    ("gif_search", "Cari GIF"),
+    ("gif_unconfigured", "Pencarian GIF belum diatur."),
+    ("gif_prompt", "Cari sebuah GIF"),
+    ("gif_searching", "Mencari…"),
+    ("gif_failed", "Tidak dapat memuat GIF. Coba lagi."),
+    ("gif_none", "Tidak ada GIF untuk \"{0}\""),
+    ("gif_loading_more", "Memuat lagi…"),
+    ("emoji_search", "Cari emoji"),
+    ("emoji_none", "Tidak ada emoji yang cocok"),
+    ("copied", "Disalin"),
+    ("error_rate_limited", "Anda mengirim pesan terlalu cepat. Tunggu sebentar lalu coba lagi."),
+    ("error_banned_word", "Pesan itu tidak terkirim: ada kata yang tidak diizinkan di sini."),
+    ("error_rejected", "Server menolak pesan itu."),
+    ("error_generic", "Server tidak dapat melakukannya."),
+    ("kicked_by", "{0} mengeluarkan Anda dari obrolan."),
+    ("cancel", "Batal"),
+    ("theme", "Tema"),
+    ("theme_system", "Sama dengan sistem"),
+    ("theme_light", "Terang"),
+    ("theme_dark", "Gelap"),
+    ("time_format", "Format waktu"),
+    ("clock_24h", "24 jam"),
+    ("clock_12h", "12 jam"),
+    ("sound_setting", "Suara untuk pesan baru"),
+    ("notifications_setting", "Notifikasi desktop saat tidak di tempat"),
+    ("notifications_blocked", "Peramban Anda memblokir notifikasi dari halaman ini."),
+    ("away_after", "Tampilkan saya tidak di tempat setelah (menit)"),
+    ("done", "Selesai"),
+    ("copy", "Salin"),
+    ("show_all_lines", "Tampilkan semua {0} baris"),
+];
+
+const AR: Catalog = &[
+    ("users", "المستخدمون"),
+    ("online", "متصل"),
+    ("away", "بعيد"),
+    ("offline", "غير متصل"),
+    ("tagline", "هيا نتحدث!"),
+    ("message_placeholder", "اكتب رسالة..."),
+    ("search_placeholder", "ابحث في الرسائل"),
+    ("no_matches", "لا توجد نتائج"),
+    ("connecting", "جارٍ الاتصال…"),
+    ("reconnect", "إعادة الاتصال"),
+    ("you_offline", "أنت غير متصل بالإنترنت. ستُرسل الرسائل المنتظرة عند عودتك."),
+    ("unsupported_title", "لا يمكن لهذا المتصفح الاتصال بالدردشة"),
+    (
+        "unsupported_body",
+        "يحتاج YewChat إلى WebSocket، وهو غير مدعوم أو معطّل في هذا المتصفح. جرّب إصدارًا حديثًا من Firefox أو Chrome أو Edge أو Safari.",
+    ),
+    ("show_earlier", "عرض الرسائل السابقة"),
+    ("queued", "في الانتظار"),
+    ("sending", "جارٍ الإرسال…"),
+    ("failed_retry", "فشل · أعد المحاولة"),
+    ("seen", "شوهدت"),
+    ("just_now", "الآن"),
+    ("minutes_short", " د"),
+    ("hours_short", " س"),
+    ("sort_recent", "الأحدث"),
+    ("me_first", "أنا أولاً"),
+    ("users_stale", "جارٍ التحديث…"),
+    ("star", "تمييز بنجمة"),
+    ("unstar", "إزالة النجمة"),
+    ("confirm_clear_history", "مسح المحادثة على هذا الجهاز؟ تُحذف الرسائل المحفوظة هنا، ويحتفظ الآخرون برسائلهم."),
+    ("confirm_clear_room", "مسح المحادثة للجميع؟ يُحذف سجل الخادم والرسائل المثبتة لدى جميع المستخدمين، ولا يمكن التراجع عن ذلك."),
+    ("confirm_delete", "حذف هذه الرسالة للجميع؟"),
+    ("confirm_kick", "إزالة {0} من الدردشة؟"),
+    ("clear", "مسح"),
+    ("delete", "حذف"),
+    ("remove", "إزالة"),
+    ("owner_only", "لا يمكن إلا لمالك الغرفة ({0}) مسح المحادثة للجميع."),
+    ("first_joiner", "أول من انضم"),
+    ("download", "تنزيل"),
+    ("download_named", "تنزيل {0}"),
+    ("resize_users", "تغيير حجم قائمة المستخدمين"),
+    ("show_more", "عرض المزيد"),
+    ("show_less", "عرض أقل"),
+    ("show_all", "عرض الكل"),
+    ("role_you", "أنت"),
+    ("role_admin", "مشرف"),
+    ("online_since", "متصل منذ {0}"),
+    ("last_active", "آخر نشاط: {0}"),
+    ("mention", "مراسلة"),
+    ("original_unavailable", "الرسالة الأصلية غير متاحة"),
+    ("show_original", "عرض الرسالة الأصلية"),
+    ("reply", "رد"),
+    ("add_reaction", "إضافة تفاعل"),
+    ("more_actions", "إجراءات أخرى"),
+    ("show_only", "عرض رسائل {0} فقط"),
+    ("image_you_sent", "صورة أرسلتها"),
+    ("image_from", "صورة من {0}"),
+    ("retry_sending", "إعادة محاولة الإرسال"),
+    ("seen_by", "شاهدها {0}"),
+    ("starred", "مميزة بنجمة"),
+    ("you_said", "أنت: "),
+    ("match_count", "{0} من {1}"),
+    ("previous_match", "النتيجة السابقة"),
+    ("next_match", "النتيجة التالية"),
+    ("close_search", "إغلاق البحث"),
+    ("older_here", "أقدم على هذا الجهاز ({0})"),
+    ("dismiss", "تجاهل"),
+    ("uploading", "جارٍ رفع {0}…"),
+    ("upload_failed", "تعذّر إرسال {0}: {1}"),
+    ("file_too_large", "{0}: {1}"),
+    ("and_more", " (و{0} أخرى)"),
+    ("saved_messages", "الرسائل المحفوظة"),
+    ("saved_empty", "ميّز رسالة بنجمة للاحتفاظ بها هنا."),
+    ("jump", "انتقال"),
+    ("jump_to_message", "الانتقال إلى الرسالة"),
+    ("more_in_history", "{0} أخرى في السجل الأقدم؛ مرّر لأعلى لتحميلها."),
+    ("quote", "اقتباس"),
+    ("react", "تفاعل"),
+    ("copy_text", "نسخ النص"),
+    ("copied_message", "تم نسخ الرسالة"),
+    ("copy_message_failed", "تعذّر نسخ الرسالة"),
+    ("copy_link", "نسخ الرابط"),
+    ("copied_link", "تم نسخ الرابط"),
+    ("copy_link_failed", "تعذّر نسخ الرابط"),
+    ("pin", "تثبيت"),
+    ("unpin", "إلغاء التثبيت"),
+    ("pinned_count", "📌 المثبتة ({0}/{1})"),
+    ("clear_here", "مسح على هذا الجهاز"),
+    ("clear_everyone", "مسح للجميع (المالك)"),
+    ("reconnected", "تمت إعادة الاتصال"),
+    ("send_failed", "تعذّر إرسال رسالتك."),
+    ("retry", "إعادة المحاولة"),
+    ("mute", "كتم"),
+    ("unmute", "إلغاء الكتم"),
+    ("mute_title", "إخفاء رسائل {0} على هذا الجهاز"),
+    ("unmute_title", "إظهار رسائل {0} مجددًا"),
+    ("kick", "طرد"),
+    ("kick_title", "إزالة {0} من الدردشة"),
+    ("drop_files", "أفلت الملفات لمشاركتها"),
+    ("round_trip", "زمن الذهاب والإياب إلى الخادم"),
+    ("density", "كثافة الرسائل"),
+    ("retention", "مسح الرسائل تلقائيًا بعد"),
+    ("settings", "الإعدادات"),
+    ("export", "تصدير المحادثة"),
+    ("export_text", "تصدير كنص"),
+    ("export_json", "تصدير بصيغة JSON"),
+    ("clear_conversation", "مسح المحادثة"),
+    ("filtering_by", "التصفية حسب {0}"),
+    ("show_everyone", "عرض رسائل الجميع"),
+    ("retrying", "انقطع الاتصال. إعادة المحاولة ({0} من {1}) — ستُرسل الرسائل المنتظرة عند عودته."),
+    ("connection_lost", "انقطع الاتصال — ستُرسل الرسائل المنتظرة عند عودته."),
+    ("muted_shown_one", "رسالة واحدة من المستخدمين المكتومين ظاهرة"),
+    ("muted_shown", "{0} رسائل من المستخدمين المكتومين ظاهرة"),
+    ("muted_hidden_one", "رسالة واحدة من المستخدمين المكتومين مخفية"),
+    ("muted_hidden", "{0} رسائل من المستخدمين المكتومين مخفية"),
+    ("hide_them", "إخفاؤها"),
+    ("show_them", "إظهارها"),
+    ("outbox_full", "رسائل كثيرة جدًا بانتظار الإرسال (الحد {0}). حاول مجددًا عند استعادة الاتصال."),
+    ("messages", "الرسائل"),
+    ("cleared_by", "مسح {0} المحادثة للجميع"),
+    ("expired", "مُسحت الرسائل الأقدم من {0}"),
+    ("new_messages", "رسائل جديدة"),
+    ("replying_to", "رد على "),
+    ("replying_unloaded", "رد على رسالة لم تعد محمّلة"),
+    ("cancel_reply", "إلغاء الرد (Esc)"),
+    ("message_input", "رسالة"),
+    ("input_hint", "اضغط / أو Ctrl+K للانتقال إلى هنا"),
+    ("insert_emoji", "إدراج رمز تعبيري"),
+    ("send_gif", "إرسال صورة GIF"),
+    ("send_message", "إرسال الرسالة"),
+    ("typing_one", "{0} يكتب…"),
+    ("typing_two", "{0} و{1} يكتبان…"),
+    ("typing_three", "{0} و{1} و{2} يكتبون…"),
+    ("typing_many", "{0} أشخاص يكتبون…"),
+    ("unavailable", "غير متاح"),
+    ("density_cozy", "مريح"),
+    ("density_compact", "مضغوط"),
+    ("retention_forever", "الاحتفاظ بكل شيء"),
+    ("retention_hour", "ساعة واحدة"),
+    ("retention_day", "يوم واحد"),
+    ("retention_week", "أسبوع واحد"),
+    ("gif_search", "ابحث عن صور GIF"),
+    ("gif_unconfigured", "البحث عن صور GIF غير مُعدّ."),
+    ("gif_prompt", "ابحث عن صورة GIF"),
+    ("gif_searching", "جارٍ البحث…"),
+    ("gif_failed", "تعذّر تحميل صور GIF. حاول مجددًا."),
+    ("gif_none", "لم يُعثر على صور GIF لـ \"{0}\""),
+    ("gif_loading_more", "جارٍ تحميل المزيد…"),
+    ("emoji_search", "ابحث عن رمز تعبيري"),
+    ("emoji_none", "لا توجد رموز تعبيرية مطابقة"),
+    ("copied", "تم النسخ"),
+    ("error_rate_limited", "أنت ترسل الرسائل بسرعة كبيرة. انتظر قليلًا ثم حاول مجددًا."),
+    ("error_banned_word", "لم تُرسل تلك الرسالة: فيها كلمة غير مسموح بها هنا."),
+    ("error_rejected", "رفض الخادم تلك الرسالة."),
+    ("error_generic", "لم يتمكن الخادم من فعل ذلك."),
+    ("kicked_by", "أزالك {0} من الدردشة."),
+    ("cancel", "إلغاء"),
+    ("theme", "السمة"),
+    ("theme_system", "مثل النظام"),
+    ("theme_light", "فاتح"),
+    ("theme_dark", "داكن"),
+    ("time_format", "تنسيق الوقت"),
+    ("clock_24h", "24 ساعة"),
+    ("clock_12h", "12 ساعة"),
+    ("sound_setting", "صوت للرسائل الجديدة"),
+    ("notifications_setting", "إشعارات سطح المكتب أثناء الغياب"),
+    ("notifications_blocked", "متصفحك يحظر الإشعارات من هذه الصفحة."),
+    ("away_after", "أظهرني بعيدًا بعد (دقائق)"),
+    ("done", "تم"),
+    ("copy", "نسخ"),
+    ("show_all_lines", "عرض كل الأسطر ({0})"),
+];
+
+/// Every catalog, by the primary language subtag it's chosen for.
+const CATALOGS: &[(&str, Catalog)] = &[("en", EN), ("id", ID), ("ar", AR)];
+
+/// Languages written right to left, by primary subtag.
+const RTL: &[&str] = &["ar", "fa", "he", "ur"];
+
+thread_local! {
+    // Missing text is looked up on every render; say so once per language and id.
+    static WARNED: RefCell<HashSet<(&'static str, String)>> = RefCell::new(HashSet::new());
+}
+
+fn warn_once(lang: &'static str, id: &str, message: &str) {
+    WARNED.with(|warned| {
+        if warned.borrow_mut().insert((lang, id.to_string())) {
+            log::warn!("i18n: {}", message);
+        }
+    });
+}
+
+fn lookup(catalog: Catalog, id: &str) -> Option<&'static str> {
+    catalog
+        .iter()
+        .find(|(key, _)| *key == id)
+        .map(|(_, text)| *text)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct I18n {
+    lang: &'static str,
+    catalog: Catalog,
+}
+
+impl Default for I18n {
+    fn default() -> Self {
+        Self::from_catalog("en", EN)
+    }
+}
+
+impl I18n {
+    /// The catalog for `lang`, a tag like `id-ID` matched on its primary language
+    /// regardless of case; English when there's none for it.
+    pub fn new(lang: &str) -> Self {
+        let primary = lang.split(['-', '_']).next().unwrap_or_default();
+        CATALOGS
+            .iter()
+            .find(|(code, _)| code.eq_ignore_ascii_case(primary))
+            .map(|&(code, catalog)| Self::from_catalog(code, catalog))
+            .unwrap_or_default()
+    }
+
+    /// Uses `catalog` for `lang`, falling back to English as any other catalog does.
+    pub const fn from_catalog(lang: &'static str, catalog: Catalog) -> Self {
+        Self { lang, catalog }
+    }
+
+    /// The catalog for `lang` if it's given, or else for the browser's language.
+    pub fn pick(lang: Option<&str>) -> Self {
+        match lang.map(str::to_string).or_else(browser_lang) {
+            Some(lang) => Self::new(&lang),
+            None => Self::default(),
+        }
+    }
+
+    pub fn lang(&self) -> &'static str {
+        self.lang
+    }
+
+    /// Whether this language is written right to left, and the layout should be mirrored.
+    pub fn rtl(&self) -> bool {
+        RTL.contains(&self.lang)
+    }
+
+    /// The `dir` attribute for this language's layout.
+    pub fn dir(&self) -> &'static str {
+        if self.rtl() {
+            "rtl"
+        } else {
+            "ltr"
+        }
+    }
+
+    /// The text for message `id`: this catalog's, else English with a warning. An id
+    /// nobody knows is logged and shown as itself, so the gap is visible.
+    pub fn t<'a>(&self, id: &'a str) -> &'a str {
+        if let Some(text) = lookup(self.catalog, id) {
+            return text;
+        }
+        match lookup(EN, id) {
+            Some(text) => {
+                warn_once(
+                    self.lang,
+                    id,
+                    &format!("no {:?} text for {:?}, using English", self.lang, id),
+                );
+                text
+            }
+            None => {
+                warn_once(self.lang, id, &format!("unknown message id {:?}", id));
+                id
+            }
+        }
+    }
+
+    /// The text for message `id` with `{0}`, `{1}` and so on in it replaced by `args` in
+    /// that order, so each language can put them where its grammar wants them.
+    pub fn fill(&self, id: &str, args: &[&dyn fmt::Display]) -> String {
+        let mut rest = self.t(id);
+        let mut out = String::with_capacity(rest.len());
+        while let Some(open) = rest.find('{') {
+            out.push_str(&rest[..open]);
+            rest = &rest[open..];
+            let arg = rest.find('}').and_then(|close| {
+                let n: usize = rest[1..close].parse().ok()?;
+                Some((close, args.get(n)?))
+            });
+            match arg {
+                Some((close, arg)) => {
+                    let _ = write!(out, "{}", arg);
+                    rest = &rest[close + 1..];
+                }
+                None => {
+                    out.push('{');
+                    rest = &rest[1..];
+                }
+            }
+        }
+        out.push_str(rest);
+        out
+    }
+}
+
+/// The language the browser prefers, like `en-US`.
+fn browser_lang() -> Option<String> {
+    web_sys::window()?.navigator().language()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn languages_are_chosen_by_primary_subtag() {
+        assert_eq!(I18n::new("id-ID").lang(), "id");
+        assert_eq!(I18n::new("AR_eg").lang(), "ar");
+        assert_eq!(I18n::new("fr-FR").lang(), "en");
+        assert_eq!(I18n::new("").lang(), "en");
+        assert!(I18n::new("ar").rtl());
+        assert_eq!(I18n::new("id").dir(), "ltr");
+    }
+
+    #[test]
+    fn missing_text_falls_back_to_english() {
+        let partial = I18n::from_catalog("xx", &[("users", "Xusers")]);
+        assert_eq!(partial.t("users"), "Xusers");
+        assert_eq!(partial.t("online"), "Online");
+        assert_eq!(partial.t("no_such_id"), "no_such_id");
+        assert_eq!(I18n::new("id").t("users"), "Pengguna");
+    }
+
+    #[test]
+    fn every_catalog_has_the_same_ids() {
+        let ids = |catalog: Catalog| catalog.iter().map(|(id, _)| *id).collect::<Vec<_>>();
+        let english: HashSet<_> = ids(EN).into_iter().collect();
+        assert_eq!(english.len(), EN.len(), "an id is repeated");
+        for (lang, catalog) in CATALOGS {
+            assert_eq!(
+                ids(catalog).into_iter().collect::<HashSet<_>>(),
+                english,
+                "{}",
+                lang
+            );
+            assert_eq!(catalog.len(), EN.len(), "{}", lang);
+        }
+    }
+
+    #[test]
+    fn arguments_go_where_the_text_puts_them() {
+        let en = I18n::default();
+        assert_eq!(en.fill("match_count", &[&2, &5]), "2 of 5");
+        assert_eq!(
+            en.fill("typing_two", &[&"ann", &"bob"]),
+            "ann and bob are typing…"
+        );
+        let swapped = I18n::from_catalog("xx", &[("match_count", "{1}: {0}")]);
+        assert_eq!(swapped.fill("match_count", &[&2, &5]), "5: 2");
+        // Braces that aren't an argument stay as they are.
+        let odd = I18n::from_catalog("xx", &[("x", "{a} {9} {0}{")]);
+        assert_eq!(odd.fill("x", &[&"{1}"]), "{a} {9} {1}{");
+    }
+}
@@ -0,0 +1,91 @@
+//! Message ids: random (version 4) UUIDs made on the client, so a message has the same
+//! id from the moment it's typed, through the server's echo, to everyone's history.
+
+#[cfg(test)]
+use std::cell::RefCell;
+
+#[cfg(test)]
+type Source = Box<dyn FnMut() -> [u8; 16]>;
+
+#[cfg(test)]
+thread_local! {
+    static SOURCE: RefCell<Option<Source>> = const { RefCell::new(None) };
+}
+
+/// A new id for an outgoing message.
+pub fn new_id() -> String {
+    #[cfg(test)]
+    if let Some(bytes) = SOURCE.with(|s| s.borrow_mut().as_mut().map(|source| source())) {
+        return format_v4(bytes);
+    }
+    format_v4(random_bytes())
+}
+
+/// Replaces the random bytes behind `new_id` in tests, which have no Web Crypto to draw
+/// on, e.g. with a counter so ids are predictable. `None` goes back to random. Each test
+/// thread has its own.
+#[cfg(test)]
+pub fn set_source(source: Option<Source>) {
+    SOURCE.with(|s| *s.borrow_mut() = source);
+}
+
+/// Sets `new_id` counting up from 1, so a test's ids are the same in every run.
+#[cfg(test)]
+pub fn count_from_one() {
+    let mut n = 0u128;
+    set_source(Some(Box::new(move || {
+        n += 1;
+        n.to_be_bytes()
+    })));
+}
+
+/// Formats 16 bytes as a version 4 UUID, overwriting the bits that mark the version and
+/// variant.
+pub fn format_v4(mut bytes: [u8; 16]) -> String {
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+fn random_bytes() -> [u8; 16] {
+    let mut bytes = [0u8; 16];
+    let filled = web_sys::window()
+        .and_then(|w| w.crypto().ok())
+        .is_some_and(|c| c.get_random_values_with_u8_array(&mut bytes).is_ok());
+    // Without Web Crypto, `Math.random` is still plenty to keep ids from colliding.
+    if !filled {
+        for b in bytes.iter_mut() {
+            *b = (js_sys::Math::random() * 256.0) as u8;
+        }
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ids_are_version_4_uuids() {
+        assert_eq!(format_v4([0; 16]), "00000000-0000-4000-8000-000000000000");
+        assert_eq!(format_v4([0xff; 16]), "ffffffff-ffff-4fff-bfff-ffffffffffff");
+    }
+
+    #[test]
+    fn a_set_source_makes_ids_predictable() {
+        count_from_one();
+        assert_eq!(new_id(), "00000000-0000-4000-8000-000000000001");
+        assert_eq!(new_id(), "00000000-0000-4000-8000-000000000002");
+        count_from_one();
+        assert_eq!(new_id(), "00000000-0000-4000-8000-000000000001");
+        set_source(None);
+    }
+}
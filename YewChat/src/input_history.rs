@@ -0,0 +1,159 @@
+//! Recalling what we've sent into the message box, the way a shell does: Up steps back
+//! through recent sends, Down forward again, and Down past the newest brings back
+//! whatever was being typed before the recall started.
+
+use std::collections::VecDeque;
+
+/// How many sends are remembered; the oldest go first.
+pub const CAPACITY: usize = 50;
+
+#[derive(Debug, Default)]
+pub struct InputHistory {
+    sent: VecDeque<String>,
+    /// Which send is in the box while recalling, by index into `sent`.
+    at: Option<usize>,
+    /// What was in the box when the recall started.
+    draft: String,
+}
+
+impl InputHistory {
+    /// Remembers a send, newest last. Sending the same text twice in a row keeps one.
+    pub fn record(&mut self, text: &str) {
+        self.stop();
+        if text.trim().is_empty() || self.sent.back().is_some_and(|last| last == text) {
+            return;
+        }
+        if self.sent.len() == CAPACITY {
+            self.sent.pop_front();
+        }
+        self.sent.push_back(text.to_string());
+    }
+
+    /// Whether a send is being shown in place of the draft.
+    pub fn recalling(&self) -> bool {
+        self.at.is_some()
+    }
+
+    /// Leaves the text as it is, as the draft, and stops recalling; for when it's edited.
+    pub fn stop(&mut self) {
+        self.at = None;
+        self.draft.clear();
+    }
+
+    /// The send before the one shown, or the newest if `current`, the box's contents, is
+    /// the draft. `None`, changing nothing, at the oldest or with nothing sent yet.
+    pub fn older(&mut self, current: &str) -> Option<&str> {
+        let at = match self.at {
+            None if self.sent.is_empty() => return None,
+            None => {
+                self.draft = current.to_string();
+                self.sent.len() - 1
+            }
+            Some(0) => return None,
+            Some(at) => at - 1,
+        };
+        self.at = Some(at);
+        Some(&self.sent[at])
+    }
+
+    /// The send after the one shown, or the draft back after the newest. `None` when
+    /// not recalling.
+    pub fn newer(&mut self) -> Option<String> {
+        let at = self.at?;
+        if at + 1 < self.sent.len() {
+            self.at = Some(at + 1);
+            return Some(self.sent[at + 1].clone());
+        }
+        let draft = std::mem::take(&mut self.draft);
+        self.stop();
+        Some(draft)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sent(texts: &[&str]) -> InputHistory {
+        let mut history = InputHistory::default();
+        for text in texts {
+            history.record(text);
+        }
+        history
+    }
+
+    #[test]
+    fn up_steps_back_to_the_oldest_and_stops() {
+        let mut history = sent(&["one", "two", "three"]);
+        assert_eq!(history.older(""), Some("three"));
+        assert!(history.recalling());
+        assert_eq!(history.older("three"), Some("two"));
+        assert_eq!(history.older("two"), Some("one"));
+        assert_eq!(history.older("one"), None);
+        assert!(history.recalling());
+    }
+
+    #[test]
+    fn down_past_the_newest_restores_the_draft() {
+        let mut history = sent(&["one", "two"]);
+        assert_eq!(history.older("half typed"), Some("two"));
+        assert_eq!(history.older("two"), Some("one"));
+        assert_eq!(history.newer().as_deref(), Some("two"));
+        assert_eq!(history.newer().as_deref(), Some("half typed"));
+        assert!(!history.recalling());
+        assert_eq!(history.newer(), None);
+    }
+
+    #[test]
+    fn nothing_is_recalled_before_the_first_send() {
+        let mut history = InputHistory::default();
+        assert_eq!(history.older("draft"), None);
+        assert!(!history.recalling());
+        assert_eq!(history.newer(), None);
+    }
+
+    #[test]
+    fn editing_a_recalled_send_keeps_it_as_the_draft() {
+        let mut history = sent(&["one", "two"]);
+        history.older("draft");
+        history.stop();
+        assert!(!history.recalling());
+        assert_eq!(history.newer(), None);
+        // The next recall starts from the newest again.
+        assert_eq!(history.older("two, edited"), Some("two"));
+        assert_eq!(history.newer().as_deref(), Some("two, edited"));
+    }
+
+    #[test]
+    fn blank_sends_and_repeats_are_not_remembered() {
+        let mut history = sent(&["one", "  ", "", "two", "two", "one"]);
+        assert_eq!(history.older(""), Some("one"));
+        assert_eq!(history.older(""), Some("two"));
+        assert_eq!(history.older(""), Some("one"));
+        assert_eq!(history.older(""), None);
+    }
+
+    #[test]
+    fn sending_ends_a_recall() {
+        let mut history = sent(&["one"]);
+        history.older("draft");
+        history.record("two");
+        assert!(!history.recalling());
+        assert_eq!(history.older(""), Some("two"));
+    }
+
+    #[test]
+    fn only_the_latest_sends_are_kept() {
+        let texts: Vec<String> = (0..CAPACITY + 5).map(|i| i.to_string()).collect();
+        let mut history = InputHistory::default();
+        for text in &texts {
+            history.record(text);
+        }
+        let mut recalled = vec![];
+        while let Some(text) = history.older("") {
+            recalled.push(text.to_string());
+        }
+        recalled.reverse();
+        assert_eq!(recalled, texts[5..]);
+    }
+}
@@ -1,18 +1,41 @@
 #![recursion_limit = "512"]
 
+mod avatar;
+#[cfg(feature = "bench")]
+mod bench;
 mod components;
+mod emoji;
+mod hooks;
+mod i18n;
+mod ids;
+mod input_history;
+mod logger;
+mod markup;
+mod protocol;
+mod sanitize;
 mod services;
+mod settings;
+mod store;
+mod syntax;
+mod toasts;
+mod transport;
+mod username;
 
 use std::cell::RefCell;
 use std::rc::Rc;
 
+use gloo_events::EventListener;
 use wasm_bindgen::prelude::*;
 use yew::functional::*;
 use yew::prelude::*;
 use yew_router::prelude::*;
 
 use components::chat::Chat;
+use components::chat_fn::ChatFn;
+use components::log_panel::LogPanel;
 use components::login::Login;
+use components::toast_host::ToastHost;
+use settings::{Settings, SettingsContext, Theme};
 
 // When the `wee_alloc` feature is enabled, this uses `wee_alloc` as the global
 // allocator.
@@ -28,6 +51,12 @@ pub enum Route {
     Login,
     #[at("/chat")]
     Chat,
+    /// The same chat, written with hooks.
+    #[at("/chat-fn")]
+    ChatFn,
+    /// The rendering benchmark; not found unless built with the `bench` feature.
+    #[at("/bench")]
+    Bench,
     #[not_found]
     #[at("/404")]
     NotFound,
@@ -38,6 +67,8 @@ pub type User = Rc<UserInner>;
 #[derive(Debug, PartialEq)]
 pub struct UserInner {
     pub username: RefCell<String>,
+    /// Why the server last turned our username away, for the login screen to show.
+    pub register_error: RefCell<Option<String>>,
 }
 
 #[function_component(Main)]
@@ -45,16 +76,49 @@ fn main() -> Html {
     let ctx = use_state(|| {
         Rc::new(UserInner {
             username: RefCell::new("initial".into()),
+            register_error: RefCell::new(None),
         })
     });
+    let settings = use_state(settings::load);
+    let settings_ctx = SettingsContext {
+        settings: (*settings).clone(),
+        update: {
+            let settings = settings.clone();
+            Callback::from(move |next: Settings| {
+                settings::save(&next);
+                settings.set(next);
+            })
+        },
+    };
+
+    use_effect_with_deps(
+        |theme| {
+            let theme = *theme;
+            settings::apply_theme(theme);
+            // Left to the system, so follow it when it changes.
+            let follow = (theme == Theme::System)
+                .then(settings::dark_scheme_query)
+                .flatten()
+                .map(|query| {
+                    EventListener::new(&query, "change", |_| settings::apply_theme(Theme::System))
+                });
+            move || drop(follow)
+        },
+        settings.theme,
+    );
 
     html! {
         <ContextProvider<User> context={(*ctx).clone()}>
-            <BrowserRouter>
-                <div class="flex w-screen h-screen">
-                    <Switch<Route> render={Switch::render(switch)}/>
-                </div>
-            </BrowserRouter>
+            <ContextProvider<SettingsContext> context={settings_ctx}>
+                <BrowserRouter>
+                    <ToastHost>
+                        <div class="flex w-screen h-screen">
+                            <Switch<Route> render={Switch::render(switch)}/>
+                        </div>
+                        <LogPanel/>
+                    </ToastHost>
+                </BrowserRouter>
+            </ContextProvider<SettingsContext>>
         </ContextProvider<User>>
     }
 }
@@ -63,13 +127,29 @@ fn switch(selected_route: &Route) -> Html {
     match selected_route {
         Route::Login => html! {<Login />},
         Route::Chat => html! {<Chat/>},
-        Route::NotFound => html! {<h1>{"404 baby"}</h1>},
+        Route::ChatFn => html! {<ChatFn/>},
+        Route::Bench => bench(),
+        Route::NotFound => not_found(),
     }
 }
 
+#[cfg(feature = "bench")]
+fn bench() -> Html {
+    html! {<bench::Bench/>}
+}
+
+#[cfg(not(feature = "bench"))]
+fn bench() -> Html {
+    not_found()
+}
+
+fn not_found() -> Html {
+    html! {<h1>{"404 baby"}</h1>}
+}
+
 #[wasm_bindgen]
 pub fn run_app() -> Result<(), JsValue> {
-    wasm_logger::init(wasm_logger::Config::default());
+    logger::init();
     yew::start_app::<Main>();
     Ok(())
 }
@@ -0,0 +1,123 @@
+//! The app's logger: records go to the browser console, as they did with `wasm-logger`,
+//! and the most recent are also kept for the in-app log panel. The level can be changed
+//! while running and is remembered in localStorage, so it survives a reload.
+
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+
+use gloo_storage::{LocalStorage, Storage};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use wasm_bindgen::JsValue;
+
+const STORAGE_KEY: &str = "yewchat.log_level";
+/// How many records are kept for the panel; older ones are dropped.
+pub const CAPACITY: usize = 500;
+/// The level when none has been picked on this device.
+const DEFAULT_LEVEL: LevelFilter = LevelFilter::Debug;
+
+/// A record as kept for the panel.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Line {
+    /// When it was logged, in ms since the epoch.
+    pub time: f64,
+    pub level: Level,
+    /// The module it came from.
+    pub target: String,
+    pub message: String,
+}
+
+thread_local! {
+    static LINES: RefCell<VecDeque<Line>> = RefCell::new(VecDeque::with_capacity(CAPACITY));
+    // Bumped for every line kept or cleared, so the panel can tell when to redraw.
+    static GENERATION: Cell<u64> = const { Cell::new(0) };
+}
+
+struct Logger;
+
+static LOGGER: Logger = Logger;
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let message = record.args().to_string();
+        let text = JsValue::from_str(&format!(
+            "{} {}: {}",
+            record.level(),
+            record.target(),
+            message
+        ));
+        match record.level() {
+            Level::Error => web_sys::console::error_1(&text),
+            Level::Warn => web_sys::console::warn_1(&text),
+            Level::Info => web_sys::console::info_1(&text),
+            Level::Debug | Level::Trace => web_sys::console::debug_1(&text),
+        }
+        push(Line {
+            time: js_sys::Date::now(),
+            level: record.level(),
+            target: record.target().to_string(),
+            message,
+        });
+    }
+
+    fn flush(&self) {}
+}
+
+fn push(line: Line) {
+    LINES.with(|lines| {
+        let mut lines = lines.borrow_mut();
+        if lines.len() == CAPACITY {
+            lines.pop_front();
+        }
+        lines.push_back(line);
+    });
+    GENERATION.with(|g| g.set(g.get() + 1));
+}
+
+/// Installs the logger at the level saved on this device.
+pub fn init() {
+    match log::set_logger(&LOGGER) {
+        Ok(()) => log::set_max_level(saved_level()),
+        Err(e) => web_sys::console::error_1(&JsValue::from_str(&e.to_string())),
+    }
+}
+
+fn saved_level() -> LevelFilter {
+    LocalStorage::get::<String>(STORAGE_KEY)
+        .ok()
+        .and_then(|level| level.parse().ok())
+        .unwrap_or(DEFAULT_LEVEL)
+}
+
+pub fn level() -> LevelFilter {
+    log::max_level()
+}
+
+/// Logs everything at `level` and above from now on, here and after a reload.
+pub fn set_level(level: LevelFilter) {
+    log::set_max_level(level);
+    if let Err(e) = LocalStorage::set(STORAGE_KEY, level.to_string()) {
+        log::error!("couldn't save log level: {:?}", e);
+    }
+}
+
+/// The kept records, oldest first.
+pub fn lines() -> Vec<Line> {
+    LINES.with(|lines| lines.borrow().iter().cloned().collect())
+}
+
+/// Changes whenever the kept records do.
+pub fn generation() -> u64 {
+    GENERATION.with(Cell::get)
+}
+
+pub fn clear() {
+    LINES.with(|lines| lines.borrow_mut().clear());
+    GENERATION.with(|g| g.set(g.get() + 1));
+}
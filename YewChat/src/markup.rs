@@ -0,0 +1,571 @@
+//! The light structure we recognise in message bodies: fenced code blocks and inline code
+//! spans, following CommonMark's backtick rules, and Discord-style `||spoilers||`.
+//! Everything else is plain text. `fold` cuts a long message short without breaking any
+//! of that up.
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Block<'a> {
+    /// Ordinary text, possibly several lines, with any inline code picked out.
+    Text(Vec<Inline<'a>>),
+    /// A fenced block. `lang` is the first word of the info string after the opening fence.
+    Code {
+        lang: Option<&'a str>,
+        code: &'a str,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Inline<'a> {
+    Text(&'a str),
+    Code(&'a str),
+    /// Hidden until the reader asks; may contain code spans.
+    Spoiler(Vec<Inline<'a>>),
+}
+
+const SPOILER_MARKER: &str = "||";
+
+/// How much of a long message is shown until the reader asks for the rest.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Fold {
+    pub max_chars: usize,
+    pub max_lines: usize,
+}
+
+impl Default for Fold {
+    fn default() -> Self {
+        Self {
+            max_chars: 600,
+            max_lines: 12,
+        }
+    }
+}
+
+/// Where a message can be cut.
+#[derive(Clone, Copy)]
+enum Cut {
+    /// After the first this many blocks.
+    Blocks(usize),
+    /// In `blocks[block]`, after `inline` whole inlines and `len` bytes of the next,
+    /// which is plain text.
+    Within {
+        block: usize,
+        inline: usize,
+        len: usize,
+    },
+}
+
+/// A message's `blocks` cut down to `fold`, ending in an ellipsis, or `None` if it fits
+/// whole. Only text counts toward the limits and only text is cut, at a space or line
+/// break outside code spans and spoilers, so no word, link, span or spoiler is split.
+/// Code blocks before the cut are kept whole, as `CodeBlock` folds long ones itself. A
+/// message that can't be cut short of the limits, such as one long word, isn't.
+pub fn fold<'a>(blocks: &[Block<'a>], fold: Fold) -> Option<Vec<Block<'a>>> {
+    let (mut chars, mut lines) = (0, 1);
+    let mut cut = None;
+    for (b, block) in blocks.iter().enumerate() {
+        if let Block::Text(inlines) = block {
+            if b > 0 {
+                lines += 1;
+            }
+            for (i, inline) in inlines.iter().enumerate() {
+                let Inline::Text(text) = inline else {
+                    chars += inline_len(inline);
+                    if chars > fold.max_chars {
+                        return cut.map(|cut| cut_at(blocks, cut));
+                    }
+                    continue;
+                };
+                for (at, c) in text.char_indices() {
+                    if c.is_whitespace() && chars > 0 {
+                        cut = Some(Cut::Within {
+                            block: b,
+                            inline: i,
+                            len: at,
+                        });
+                    }
+                    chars += 1;
+                    if c == '\n' {
+                        lines += 1;
+                    }
+                    if chars > fold.max_chars || lines > fold.max_lines {
+                        return cut.map(|cut| cut_at(blocks, cut));
+                    }
+                }
+            }
+        }
+        // Between blocks is as good a place as any.
+        cut = Some(Cut::Blocks(b + 1));
+    }
+    None
+}
+
+fn inline_len(inline: &Inline) -> usize {
+    match inline {
+        Inline::Text(text) | Inline::Code(text) => text.chars().count(),
+        Inline::Spoiler(content) => content.iter().map(inline_len).sum(),
+    }
+}
+
+fn cut_at<'a>(blocks: &[Block<'a>], cut: Cut) -> Vec<Block<'a>> {
+    let (mut out, mut kept) = match cut {
+        Cut::Blocks(n) => match blocks[..n].split_last() {
+            Some((Block::Text(last), before)) => (before.to_vec(), last.clone()),
+            _ => (blocks[..n].to_vec(), vec![]),
+        },
+        Cut::Within { block, inline, len } => {
+            let Block::Text(inlines) = &blocks[block] else {
+                unreachable!("cuts are only made in text");
+            };
+            let mut kept = inlines[..inline].to_vec();
+            if let Some(Inline::Text(text)) = inlines.get(inline) {
+                kept.push(Inline::Text(&text[..len]));
+            }
+            (blocks[..block].to_vec(), kept)
+        }
+    };
+    // Drop what the cut leaves dangling at the end, so the ellipsis follows the last word.
+    while let Some(Inline::Text(text)) = kept.last_mut() {
+        *text = text.trim_end();
+        if !text.is_empty() {
+            break;
+        }
+        kept.pop();
+    }
+    kept.push(Inline::Text("…"));
+    out.push(Block::Text(kept));
+    out
+}
+
+/// Splits a message into text and fenced code blocks. A fence is a line of three or more
+/// backticks; it closes on a line of at least as many, or runs to the end of the message.
+pub fn classify_message(text: &str) -> Vec<Block<'_>> {
+    let mut blocks = Vec::new();
+    let mut text_start = 0;
+    let mut pos = 0;
+
+    while pos < text.len() {
+        let line = next_line(&text[pos..]);
+        let (fence, lang) = match opening_fence(line) {
+            Some(open) => open,
+            None => {
+                pos += line.len();
+                continue;
+            }
+        };
+
+        push_text(&mut blocks, &text[text_start..pos]);
+        let code_start = pos + line.len();
+        let (mut code_end, mut resume) = (text.len(), text.len());
+        let mut end = code_start;
+        while end < text.len() {
+            let line = next_line(&text[end..]);
+            if is_closing_fence(line, fence) {
+                code_end = end;
+                resume = end + line.len();
+                break;
+            }
+            end += line.len();
+        }
+        let code = &text[code_start..code_end];
+        blocks.push(Block::Code {
+            lang,
+            code: code.strip_suffix('\n').unwrap_or(code),
+        });
+        pos = resume;
+        text_start = resume;
+    }
+    push_text(&mut blocks, &text[text_start..]);
+    blocks
+}
+
+/// Splits a run of text into plain text, backtick code spans and spoilers. A code span
+/// closes on a run of backticks exactly as long as the one that opened it; an unclosed run
+/// is just text. Code binds tighter than spoilers, so `||` inside code is left alone.
+pub fn parse_inline(text: &str) -> Vec<Inline<'_>> {
+    group_spoilers(parse_code_spans(text))
+}
+
+fn parse_code_spans(text: &str) -> Vec<Inline<'_>> {
+    let mut inlines = Vec::new();
+    let mut plain_start = 0;
+    let mut pos = 0;
+
+    while let Some(i) = text[pos..].find('`') {
+        let open = pos + i;
+        let fence = text[open..].len() - text[open..].trim_start_matches('`').len();
+        let body_start = open + fence;
+        let close = text[body_start..]
+            .match_indices(&text[open..body_start])
+            .map(|(j, _)| body_start + j)
+            .find(|&j| !text[j + fence..].starts_with('`') && !text[..j].ends_with('`'));
+        match close {
+            Some(close) => {
+                if plain_start < open {
+                    inlines.push(Inline::Text(&text[plain_start..open]));
+                }
+                inlines.push(Inline::Code(strip_padding(&text[body_start..close])));
+                pos = close + fence;
+                plain_start = pos;
+            }
+            None => pos = body_start,
+        }
+    }
+    if plain_start < text.len() {
+        inlines.push(Inline::Text(&text[plain_start..]));
+    }
+    inlines
+}
+
+/// Gathers whatever lies between pairs of `||` in the plain text into spoilers. An empty
+/// pair, or a marker left unclosed, stays as text.
+fn group_spoilers(inlines: Vec<Inline<'_>>) -> Vec<Inline<'_>> {
+    let mut out = Vec::new();
+    // Where the open spoiler's content starts in `out`, and its marker.
+    let mut open: Option<(usize, &str)> = None;
+
+    for inline in inlines {
+        let mut text = match inline {
+            Inline::Text(text) => text,
+            other => {
+                out.push(other);
+                continue;
+            }
+        };
+        while let Some(i) = text.find(SPOILER_MARKER) {
+            if i > 0 {
+                out.push(Inline::Text(&text[..i]));
+            }
+            let marker = &text[i..i + SPOILER_MARKER.len()];
+            match open.take() {
+                None => open = Some((out.len(), marker)),
+                Some((start, open_marker)) if start == out.len() => {
+                    out.push(Inline::Text(open_marker));
+                    out.push(Inline::Text(marker));
+                }
+                Some((start, _)) => {
+                    let content = out.split_off(start);
+                    out.push(Inline::Spoiler(content));
+                }
+            }
+            text = &text[i + SPOILER_MARKER.len()..];
+        }
+        if !text.is_empty() {
+            out.push(Inline::Text(text));
+        }
+    }
+    if let Some((start, marker)) = open {
+        out.insert(start, Inline::Text(marker));
+    }
+    out
+}
+
+fn next_line(s: &str) -> &str {
+    &s[..s.find('\n').map_or(s.len(), |i| i + 1)]
+}
+
+/// Returns the fence length and language if `line` opens a code block.
+fn opening_fence(line: &str) -> Option<(usize, Option<&str>)> {
+    let line = line.trim_end_matches(['\n', '\r']);
+    let indent = line.len() - line.trim_start_matches(' ').len();
+    if indent > 3 {
+        return None;
+    }
+    let line = &line[indent..];
+    let fence = line.len() - line.trim_start_matches('`').len();
+    let info = line[fence..].trim();
+    // Backticks in the info string mean this is inline code, e.g. ```x```.
+    if fence < 3 || info.contains('`') {
+        return None;
+    }
+    Some((fence, info.split_whitespace().next()))
+}
+
+fn is_closing_fence(line: &str, fence: usize) -> bool {
+    let line = line.trim();
+    line.len() >= fence && line.chars().all(|c| c == '`')
+}
+
+/// Adds the text between fences, minus the line break that led into the next fence.
+fn push_text<'a>(blocks: &mut Vec<Block<'a>>, text: &'a str) {
+    let text = text.strip_suffix('\n').unwrap_or(text);
+    let text = text.strip_suffix('\r').unwrap_or(text);
+    if !text.trim().is_empty() {
+        blocks.push(Block::Text(parse_inline(text)));
+    }
+}
+
+/// CommonMark drops one space on each side of a span, so `` ` `x` ` `` can show backticks.
+fn strip_padding(code: &str) -> &str {
+    match code.strip_prefix(' ').and_then(|c| c.strip_suffix(' ')) {
+        Some(inner) if !inner.trim().is_empty() => inner,
+        _ => code,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(s: &str) -> Block<'_> {
+        Block::Text(vec![Inline::Text(s)])
+    }
+
+    fn code<'a>(lang: Option<&'a str>, code: &'a str) -> Block<'a> {
+        Block::Code { lang, code }
+    }
+
+    #[test]
+    fn fences_split_a_message_into_blocks() {
+        let cases: &[(&str, Vec<Block>)] = &[
+            ("plain", vec![text("plain")]),
+            ("two\nlines", vec![text("two\nlines")]),
+            ("```\nfn x() {}\n```", vec![code(None, "fn x() {}")]),
+            ("```rust\nlet a;\n```", vec![code(Some("rust"), "let a;")]),
+            (
+                "```rust ignore\nlet a;\n```",
+                vec![code(Some("rust"), "let a;")],
+            ),
+            (
+                "before\n```\ncode\n```\nafter",
+                vec![text("before"), code(None, "code"), text("after")],
+            ),
+            (
+                "```\n\n  indented\n\n```",
+                vec![code(None, "\n  indented\n")],
+            ),
+            ("```\n```", vec![code(None, "")]),
+            // Up to three spaces may lead a fence, and a closing fence may be longer.
+            ("   ```\nx\n`````", vec![code(None, "x")]),
+            ("    ```\nx\n```", vec![text("    ```\nx"), code(None, "")]),
+            // Only a fence at least as long closes one.
+            ("````\n```\n````", vec![code(None, "```")]),
+            // A fence nobody closes runs to the end.
+            (
+                "```\nnever closed\nstill code",
+                vec![code(None, "never closed\nstill code")],
+            ),
+            // Shorter runs are inline code, which may span lines.
+            (
+                "``\nnot a fence\n``",
+                vec![Block::Text(vec![Inline::Code("\nnot a fence\n")])],
+            ),
+            ("```x```", vec![Block::Text(vec![Inline::Code("x")])]),
+            (
+                "a\r\n```\r\nb\r\n```\r\nc",
+                vec![text("a"), code(None, "b\r"), text("c")],
+            ),
+            ("\n\n```\nx\n```\n\n", vec![code(None, "x")]),
+        ];
+        for (message, blocks) in cases {
+            assert_eq!(classify_message(message), *blocks, "{:?}", message);
+        }
+    }
+
+    #[test]
+    fn markup_inside_a_code_block_is_left_alone() {
+        let message = "```\n<b>`x`</b> ||no||\n```";
+        assert_eq!(classify_message(message), [code(None, "<b>`x`</b> ||no||")]);
+    }
+
+    #[test]
+    fn backticks_pick_out_code_spans() {
+        use Inline::{Code, Text};
+        let cases: &[(&str, Vec<Inline>)] = &[
+            ("no code", vec![Text("no code")]),
+            ("`x`", vec![Code("x")]),
+            ("a `b` c", vec![Text("a "), Code("b"), Text(" c")]),
+            ("`a` and `b`", vec![Code("a"), Text(" and "), Code("b")]),
+            // A span closes only on a run as long as its opener.
+            ("``a ` b``", vec![Code("a ` b")]),
+            ("`a``b`", vec![Code("a``b")]),
+            ("`` `x` ``", vec![Code("`x`")]),
+            // One space either side is dropped, unless that's all there is.
+            ("` x `", vec![Code("x")]),
+            ("`  x  `", vec![Code(" x ")]),
+            ("`   `", vec![Code("   ")]),
+            // Unclosed runs are text.
+            ("`open", vec![Text("`open")]),
+            ("a `b", vec![Text("a `b")]),
+            ("``a`", vec![Text("``a`")]),
+            ("`a`` b", vec![Text("`a`` b")]),
+            ("x ```` y", vec![Text("x ```` y")]),
+            ("é `ü` ñ", vec![Text("é "), Code("ü"), Text(" ñ")]),
+        ];
+        for (text, inlines) in cases {
+            assert_eq!(parse_inline(text), *inlines, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn bars_pick_out_spoilers() {
+        use Inline::{Code, Spoiler, Text};
+        let cases: &[(&str, Vec<Inline>)] = &[
+            ("||secret||", vec![Spoiler(vec![Text("secret")])]),
+            (
+                "it ||was|| him",
+                vec![Text("it "), Spoiler(vec![Text("was")]), Text(" him")],
+            ),
+            (
+                "||a|| and ||b||",
+                vec![
+                    Spoiler(vec![Text("a")]),
+                    Text(" and "),
+                    Spoiler(vec![Text("b")]),
+                ],
+            ),
+            // Spoilers hold code spans, but code holds no spoilers.
+            ("||see `x`||", vec![Spoiler(vec![Text("see "), Code("x")])]),
+            ("`||x||`", vec![Code("||x||")]),
+            (
+                "||a `||` b||",
+                vec![Spoiler(vec![Text("a "), Code("||"), Text(" b")])],
+            ),
+            // Unclosed and empty pairs are text.
+            ("||open", vec![Text("||"), Text("open")]),
+            ("a || b", vec![Text("a "), Text("||"), Text(" b")]),
+            ("||||", vec![Text("||"), Text("||")]),
+            (
+                "||||x||",
+                vec![Text("||"), Text("||"), Text("x"), Text("||")],
+            ),
+            ("|single|", vec![Text("|single|")]),
+            // Spoilers don't nest: the next pair closes the first.
+            (
+                "||a ||b|| c||",
+                vec![
+                    Spoiler(vec![Text("a ")]),
+                    Text("b"),
+                    Spoiler(vec![Text(" c")]),
+                ],
+            ),
+            ("|||x|||", vec![Spoiler(vec![Text("|x")]), Text("|")]),
+        ];
+        for (text, inlines) in cases {
+            assert_eq!(parse_inline(text), *inlines, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn spoilers_stay_within_their_text_block() {
+        let message = "||a\n```\ncode\n```\nb||";
+        assert_eq!(
+            classify_message(message),
+            [
+                Block::Text(vec![Inline::Text("||"), Inline::Text("a")]),
+                code(None, "code"),
+                Block::Text(vec![Inline::Text("b"), Inline::Text("||")]),
+            ]
+        );
+    }
+
+    fn folded(message: &str, max_chars: usize, max_lines: usize) -> Option<Vec<Block<'_>>> {
+        let fold_to = Fold {
+            max_chars,
+            max_lines,
+        };
+        fold(&classify_message(message), fold_to)
+    }
+
+    fn ellipsis_after<'a>(inlines: &[Inline<'a>]) -> Block<'a> {
+        let mut inlines = inlines.to_vec();
+        inlines.push(Inline::Text("…"));
+        Block::Text(inlines)
+    }
+
+    #[test]
+    fn messages_within_the_limits_are_not_folded() {
+        assert_eq!(folded("abcde", 5, 1), None);
+        assert_eq!(folded("a\nb", 5, 2), None);
+        assert_eq!(folded("", 5, 1), None);
+        assert_eq!(
+            folded("```\nlong code that is not counted\n```", 5, 1),
+            None
+        );
+    }
+
+    #[test]
+    fn long_text_is_cut_at_the_last_space_inside_the_limit() {
+        use Inline::Text;
+        assert_eq!(
+            folded("abc de", 5, 1),
+            Some(vec![ellipsis_after(&[Text("abc")])])
+        );
+        assert_eq!(
+            folded("hello world again", 10, 12),
+            Some(vec![ellipsis_after(&[Text("hello")])])
+        );
+        assert_eq!(
+            folded("hello   world", 8, 12),
+            Some(vec![ellipsis_after(&[Text("hello")])])
+        );
+        assert_eq!(
+            folded("ab cdé fg", 6, 1),
+            Some(vec![ellipsis_after(&[Text("ab cdé")])])
+        );
+    }
+
+    #[test]
+    fn too_many_lines_are_cut_at_a_line_break() {
+        use Inline::Text;
+        assert_eq!(
+            folded("a\nb\nc", 100, 2),
+            Some(vec![ellipsis_after(&[Text("a\nb")])])
+        );
+        // Code blocks count as no lines.
+        assert_eq!(
+            folded("one\n```\nx\n```\ntwo\nthree", 100, 2),
+            Some(vec![
+                text("one"),
+                code(None, "x"),
+                ellipsis_after(&[Text("two")])
+            ])
+        );
+    }
+
+    #[test]
+    fn what_cannot_be_cut_short_is_left_whole() {
+        assert_eq!(folded("abcdefghijklmnop", 5, 1), None);
+        assert_eq!(folded("`a long code span`", 5, 1), None);
+        assert_eq!(folded("||one long spoiler||", 5, 1), None);
+    }
+
+    #[test]
+    fn spans_and_spoilers_are_never_split() {
+        use Inline::{Code, Spoiler, Text};
+        assert_eq!(
+            folded("aa `bbbbbbbbbb` cc", 8, 1),
+            Some(vec![ellipsis_after(&[Text("aa")])])
+        );
+        assert_eq!(
+            folded("aa ||b b b b b b|| cc", 8, 1),
+            Some(vec![ellipsis_after(&[Text("aa")])])
+        );
+        assert_eq!(
+            folded("`x` ||y|| and more words", 9, 1),
+            Some(vec![ellipsis_after(&[
+                Code("x"),
+                Text(" "),
+                Spoiler(vec![Text("y")]),
+                Text(" and"),
+            ])])
+        );
+    }
+
+    #[test]
+    fn code_blocks_before_the_cut_are_kept_whole() {
+        use Inline::Text;
+        assert_eq!(
+            folded("intro\n```\ncode\n```\nlong text here", 10, 12),
+            Some(vec![
+                text("intro"),
+                code(None, "code"),
+                ellipsis_after(&[Text("long")]),
+            ])
+        );
+        // With no space to cut at in the text after it, the cut falls after the block.
+        assert_eq!(
+            folded("one\n```\nx\n```\ntoolongword", 5, 12),
+            Some(vec![text("one"), code(None, "x"), ellipsis_after(&[])])
+        );
+    }
+}
@@ -0,0 +1,423 @@
+//! The JSON frames exchanged with the chat server.
+
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+
+use crate::ids::new_id;
+
+/// Where one of our own messages is on its way to the server. Everyone else's
+/// messages are `Delivered` by definition.
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum DeliveryStatus {
+    /// Queued or sent, waiting for the server's echo.
+    Pending,
+    #[default]
+    Delivered,
+    /// No echo arrived in time; the bubble offers a retry.
+    Failed,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct MessageData {
+    /// Generated by the sender's client. Frames from servers that predate ids get a fresh
+    /// one, so every message can be told apart.
+    #[serde(default = "new_id")]
+    pub id: String,
+    pub from: String,
+    pub message: String,
+    /// Milliseconds since the epoch, as stamped by the server.
+    #[serde(default)]
+    pub time: u64,
+    /// Who reacted with each emoji, in the order they reacted.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub reactions: BTreeMap<String, Vec<String>>,
+    /// Who else has seen the message, in the order they saw it.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub read_by: Vec<String>,
+    /// The id of the message this one replies to, if any. That message may have been
+    /// deleted or be older than anything loaded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reply_to: Option<String>,
+    /// A file shared by the message. `message` still says what it is and links to it,
+    /// for anything that only reads the text. Boxed, as most messages have none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attachment: Option<Box<Attachment>>,
+    #[serde(skip)]
+    pub status: DeliveryStatus,
+}
+
+/// A file that isn't an image, uploaded and shared by a message.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Attachment {
+    pub name: String,
+    /// In bytes.
+    pub size: u64,
+    pub url: String,
+}
+
+/// `from` toggling their `emoji` reaction on the message with id `id`. The server fills
+/// in `from` itself, so what a client sends there is ignored.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Reaction {
+    pub id: String,
+    #[serde(default)]
+    pub from: String,
+    pub emoji: String,
+}
+
+/// `from` has seen the messages with these ids. As with reactions, the server fills in
+/// `from`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ReadReceipt {
+    pub ids: Vec<String>,
+    #[serde(default)]
+    pub from: String,
+}
+
+/// `from` started or stopped typing in `room`. The server fills in `from` here too, and
+/// passes the notice on to everyone but its sender.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct TypingNotice {
+    pub room: String,
+    #[serde(default)]
+    pub from: String,
+    pub typing: bool,
+}
+
+/// The server keeps at most this many pinned messages, shared by everyone.
+pub const PIN_LIMIT: usize = 10;
+
+/// `from` pinned or unpinned the message with id `id`. We send it without `from`, which
+/// the server fills in as usual.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct PinNotice {
+    pub id: String,
+    pub pinned: bool,
+    #[serde(default)]
+    pub from: String,
+}
+
+/// `by`, an admin, removed `nick` from the chat. Sent as just the nick, in `data`.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct KickNotice {
+    pub nick: String,
+    pub by: String,
+}
+
+/// Why the server didn't do what we asked. Sent as the `data` of an `Error` frame.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct ServerError {
+    /// What went wrong, for `text_id` to put in our own words.
+    pub code: String,
+    /// The server's own description, for codes `text_id` doesn't know.
+    #[serde(default)]
+    pub reason: Option<String>,
+    /// The message of ours it's about, if any.
+    #[serde(default)]
+    pub id: Option<String>,
+}
+
+impl ServerError {
+    /// The catalog id of what to tell the user: our own text for the codes we know, else
+    /// `None` if the server gave a `reason` to pass on, and a general apology if not.
+    pub fn text_id(&self) -> Option<&'static str> {
+        match self.code.as_str() {
+            "rate_limited" => Some("error_rate_limited"),
+            "banned_word" => Some("error_banned_word"),
+            "rejected" => Some("error_rejected"),
+            _ if self.reason_text().is_some() => None,
+            _ => Some("error_generic"),
+        }
+    }
+
+    /// The server's `reason`, if it says anything.
+    pub fn reason_text(&self) -> Option<&str> {
+        self.reason
+            .as_deref()
+            .map(str::trim)
+            .filter(|r| !r.is_empty())
+    }
+}
+
+/// The `data` of a users frame: each user's presence, which of them are admins, and
+/// their `Activity`. Servers from before roles sent the presence map alone.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct Roster {
+    pub statuses: HashMap<String, Presence>,
+    #[serde(default)]
+    pub admins: Vec<String>,
+    #[serde(default)]
+    pub activity: HashMap<String, Activity>,
+}
+
+/// When a user joined, and when they last sent a message or came back from away, in ms
+/// since the epoch by the server's clock.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Activity {
+    pub since: u64,
+    pub last_active: u64,
+}
+
+/// Written as the variant's name in lowercase, apart from `registerError` and `clearRoom`.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MsgTypes {
+    Users,
+    Register,
+    Message,
+    /// Asks for the page before the timestamp in `data`, or the latest without one. With an
+    /// `id`, asks for that message, a few before it and everything since instead.
+    History,
+    Status,
+    Delete,
+    Reaction,
+    Read,
+    Typing,
+    /// Sent with a `PinNotice`. The server also sends one on register with the ids of every
+    /// pinned message in `dataArray` instead.
+    Pin,
+    /// Empties the conversation for everyone. Only the room's owner, the first user the
+    /// server lists, may send it; the server passes it on with the owner's name in `data`.
+    #[serde(rename = "clearRoom")]
+    ClearRoom,
+    /// Removes a user. Only admins may send it, with the nick in `data`; everyone then gets
+    /// a `KickNotice`, before the server disconnects whoever it names.
+    Kick,
+    /// The server refused our `Register`; `data` says why.
+    #[serde(rename = "registerError")]
+    RegisterError,
+    /// The server refused something else we sent; `data` is a `ServerError`.
+    Error,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Presence {
+    #[default]
+    Active,
+    Away,
+}
+
+impl Presence {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Presence::Active => "active",
+            Presence::Away => "away",
+        }
+    }
+}
+
+/// Reply to a `History` request: the page of messages just before the requested cursor.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryPage {
+    pub messages: Vec<MessageData>,
+    pub has_more: bool,
+    /// The id the request asked for, if it did. The page is that message's surroundings
+    /// if the server still has it, and the latest page if not.
+    #[serde(default)]
+    pub around: Option<String>,
+}
+
+/// One frame, as camelCase JSON. What we write is canonical, and reads back as the same
+/// value: `data` and `dataArray` are written as `null` when they're `None`, and an empty
+/// `dataArray` as `[]`, which stays `Some` rather than collapsing to `None`; `id` is left
+/// out when it's `None`. When reading, a missing `data`, `dataArray` or `id` is `None`
+/// too, as the server omits fields freely.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebSocketMessage {
+    pub message_type: MsgTypes,
+    pub data_array: Option<Vec<String>>,
+    pub data: Option<String>,
+    /// Client-generated id the server echoes back, so a message can be matched to its
+    /// optimistic copy.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// Sent with a message that replies to the one with this id.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reply_to: Option<String>,
+    /// Sent with a message that shares a file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attachment: Option<Box<Attachment>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A small xorshift generator, so every run checks the same frames.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            (self.next() % n as u64) as usize
+        }
+
+        fn pick<T: Clone>(&mut self, items: &[T]) -> T {
+            items[self.below(items.len())].clone()
+        }
+
+        fn string(&mut self) -> String {
+            const PIECES: [&str; 14] = [
+                "",
+                "a",
+                "hello",
+                " ",
+                "\"",
+                "\\",
+                "\n",
+                "\u{0}",
+                "\u{200f}",
+                "😄",
+                "👩‍💻",
+                "聊天",
+                "</script>",
+                "{\"messageType\":\"users\"}",
+            ];
+            (0..self.below(6)).map(|_| self.pick(&PIECES)).collect()
+        }
+
+        fn maybe<T>(&mut self, f: impl FnOnce(&mut Self) -> T) -> Option<T> {
+            (self.below(2) == 0).then(|| f(self))
+        }
+    }
+
+    const TYPES: [MsgTypes; 14] = [
+        MsgTypes::Users,
+        MsgTypes::Register,
+        MsgTypes::Message,
+        MsgTypes::History,
+        MsgTypes::Status,
+        MsgTypes::Delete,
+        MsgTypes::Reaction,
+        MsgTypes::Read,
+        MsgTypes::Typing,
+        MsgTypes::Pin,
+        MsgTypes::ClearRoom,
+        MsgTypes::Kick,
+        MsgTypes::RegisterError,
+        MsgTypes::Error,
+    ];
+
+    fn frame(rng: &mut Rng) -> WebSocketMessage {
+        WebSocketMessage {
+            message_type: rng.pick(&TYPES),
+            // Empty as often as populated, so `Some(vec![])` is well covered.
+            data_array: rng.maybe(|rng| {
+                (0..rng.below(2) * rng.below(4))
+                    .map(|_| rng.string())
+                    .collect()
+            }),
+            data: rng.maybe(Rng::string),
+            id: rng.maybe(Rng::string),
+            reply_to: rng.maybe(Rng::string),
+            attachment: rng.maybe(|rng| {
+                Box::new(Attachment {
+                    name: rng.string(),
+                    size: rng.next(),
+                    url: rng.string(),
+                })
+            }),
+        }
+    }
+
+    #[test]
+    fn frames_survive_a_round_trip() {
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+        for _ in 0..5000 {
+            let frame = frame(&mut rng);
+            let json = serde_json::to_string(&frame).unwrap();
+            let back: WebSocketMessage = serde_json::from_str(&json).unwrap();
+            assert_eq!(back, frame, "{}", json);
+        }
+    }
+
+    #[test]
+    fn message_types_are_written_as_documented() {
+        let names: Vec<String> = TYPES
+            .iter()
+            .map(|t| serde_json::to_string(t).unwrap())
+            .collect();
+        assert_eq!(
+            names.join(" "),
+            r#""users" "register" "message" "history" "status" "delete" "reaction" "read" "typing" "pin" "clearRoom" "kick" "registerError" "error""#
+        );
+    }
+
+    #[test]
+    fn none_and_empty_data_arrays_stay_apart() {
+        let mut frame = WebSocketMessage {
+            message_type: MsgTypes::Users,
+            data_array: None,
+            data: None,
+            id: None,
+            reply_to: None,
+            attachment: None,
+        };
+        assert_eq!(
+            serde_json::to_string(&frame).unwrap(),
+            r#"{"messageType":"users","dataArray":null,"data":null}"#
+        );
+        frame.data_array = Some(vec![]);
+        let json = serde_json::to_string(&frame).unwrap();
+        assert_eq!(
+            json,
+            r#"{"messageType":"users","dataArray":[],"data":null}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<WebSocketMessage>(&json)
+                .unwrap()
+                .data_array,
+            Some(vec![])
+        );
+    }
+
+    #[test]
+    fn missing_optional_fields_read_as_none() {
+        let frame: WebSocketMessage = serde_json::from_str(r#"{"messageType":"message"}"#).unwrap();
+        assert_eq!(frame.data_array, None);
+        assert_eq!(frame.data, None);
+        assert_eq!(frame.id, None);
+    }
+
+    #[test]
+    fn server_errors_are_told_in_our_words_when_we_know_them() {
+        let error = |code: &str, reason: Option<&str>| ServerError {
+            code: code.into(),
+            reason: reason.map(String::from),
+            id: None,
+        };
+        let known = error("rate_limited", Some("slow down"));
+        assert_eq!(known.text_id(), Some("error_rate_limited"));
+        assert_eq!(
+            error("banned_word", None).text_id(),
+            Some("error_banned_word")
+        );
+        assert_eq!(error("rejected", None).text_id(), Some("error_rejected"));
+
+        let unknown = error(
+            "too_long",
+            Some("  Messages are capped at 2000 characters "),
+        );
+        assert_eq!(unknown.text_id(), None);
+        assert_eq!(
+            unknown.reason_text(),
+            Some("Messages are capped at 2000 characters")
+        );
+        assert_eq!(
+            error("too_long", Some("  ")).text_id(),
+            Some("error_generic")
+        );
+        assert_eq!(error("too_long", None).text_id(), Some("error_generic"));
+    }
+}
@@ -0,0 +1,180 @@
+//! Sanitising user-supplied content before it reaches the DOM.
+//!
+//! Yew escapes text nodes on its own, and message markup is only ever rendered as Yew
+//! nodes, never as raw HTML. Attribute values such as `src` and `href` are passed through
+//! untouched though, so any URL from a message goes through here first.
+
+const ALLOWED_SCHEMES: [&str; 2] = ["http", "https"];
+
+const IMAGE_EXTENSIONS: [&str; 5] = ["gif", "png", "jpg", "jpeg", "webp"];
+
+/// Hosts whose images are shown inline unless `ChatProps::image_hosts` says otherwise.
+/// Subdomains are included, so `giphy.com` covers GIPHY's `media*.giphy.com` CDN.
+pub const DEFAULT_IMAGE_HOSTS: [&str; 4] = [
+    "giphy.com",
+    "tenor.com",
+    "imgur.com",
+    "githubusercontent.com",
+];
+
+/// Returns `url` if it's an absolute http(s) URL that's safe to bind to `src` or `href`.
+/// Anything else, including `javascript:` and `data:` URLs, is rejected.
+pub fn safe_url(url: &str) -> Option<&str> {
+    let url = url.trim();
+    // Browsers ignore whitespace and control characters inside the scheme, which is how
+    // `java\tscript:` gets past a naive prefix check.
+    if url.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        return None;
+    }
+    let (scheme, rest) = url.split_once(':')?;
+    let allowed = ALLOWED_SCHEMES
+        .iter()
+        .any(|s| s.eq_ignore_ascii_case(scheme));
+    (allowed && rest.starts_with("//") && rest.len() > 2).then_some(url)
+}
+
+/// Whether the path of `url` ends in an image extension, ignoring any query or fragment.
+pub fn looks_like_image(url: &str) -> bool {
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    match path.rsplit_once('.') {
+        Some((_, ext)) => IMAGE_EXTENSIONS.iter().any(|e| e.eq_ignore_ascii_case(ext)),
+        None => false,
+    }
+}
+
+/// Returns `url` if it may be rendered as an `<img>`. It must be an https image URL on one
+/// of `allowed_hosts` or a subdomain of one, or on any host if the list is empty. Hosts
+/// are compared ignoring case.
+pub fn image_url<'a, S: AsRef<str>>(url: &'a str, allowed_hosts: &[S]) -> Option<&'a str> {
+    let url = safe_url(url)?;
+    let rest = url
+        .get(..8)?
+        .eq_ignore_ascii_case("https://")
+        .then(|| &url[8..])?;
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or(rest);
+    // `https://giphy.com@evil.com/x.gif` goes to evil.com, so credentials aren't allowed.
+    if authority.contains('@') {
+        return None;
+    }
+    let host = authority
+        .rsplit_once(':')
+        .map_or(authority, |(host, _)| host)
+        .to_ascii_lowercase();
+
+    let allowed = allowed_hosts.is_empty()
+        || allowed_hosts.iter().any(|a| {
+            let a = a.as_ref().to_ascii_lowercase();
+            host == a || host.strip_suffix(&a).is_some_and(|sub| sub.ends_with('.'))
+        });
+    (allowed && !host.is_empty() && looks_like_image(url)).then_some(url)
+}
+
+/// Escapes `text` for markup we write ourselves, such as the SVG behind an initials avatar.
+pub fn escape_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::markup::{classify_message, Block, Inline};
+
+    #[test]
+    fn script_urls_are_rejected() {
+        for url in [
+            "javascript:alert(1)",
+            "JavaScript:alert(1)",
+            "java\tscript:alert(1)",
+            " javascript:alert(1)",
+            "vbscript:msgbox(1)",
+            "data:text/html,<script>alert(1)</script>",
+            "data:image/gif;base64,R0lGODlhAQABAAAAACw=",
+            "//evil.example/x.gif",
+            "https:",
+            "https://",
+        ] {
+            assert_eq!(safe_url(url), None, "{:?}", url);
+        }
+    }
+
+    #[test]
+    fn web_urls_are_kept() {
+        assert_eq!(safe_url("https://example.com/a"), Some("https://example.com/a"));
+        assert_eq!(safe_url(" HTTP://example.com "), Some("HTTP://example.com"));
+    }
+
+    #[test]
+    fn markup_in_a_message_stays_text() {
+        for payload in [
+            "<script>alert(1)</script>",
+            "<img src=x onerror=alert(1)>",
+            "<a href=\"javascript:alert(1)\">click</a>",
+            "<b><i>unclosed",
+            "<div><script>alert(1)</div></script>",
+        ] {
+            assert_eq!(
+                classify_message(payload),
+                vec![Block::Text(vec![Inline::Text(payload)])],
+                "{:?}",
+                payload
+            );
+        }
+    }
+
+    #[test]
+    fn images_need_https_on_an_allowed_host() {
+        let hosts = DEFAULT_IMAGE_HOSTS;
+        for url in [
+            "https://media.giphy.com/media/abc/giphy.gif",
+            "https://giphy.com/x.gif",
+            "https://i.imgur.com/abc.PNG",
+            "https://MEDIA.GIPHY.COM/x.webp",
+            "https://i.imgur.com:443/abc.jpg?raw=1",
+        ] {
+            assert_eq!(image_url(url, &hosts), Some(url), "{:?}", url);
+        }
+        for url in [
+            "http://media.giphy.com/x.gif",
+            "data:image/gif;base64,R0lGODlhAQABAAAAACw=",
+            "javascript:alert(1).gif",
+            "https://evilgiphy.com/x.gif",
+            "https://giphy.com.evil.example/x.gif",
+            "https://giphy.com@evil.example/x.gif",
+            "https://example.com/x.gif",
+            "https://giphy.com/x.html",
+        ] {
+            assert_eq!(image_url(url, &hosts), None, "{:?}", url);
+        }
+    }
+
+    #[test]
+    fn allowed_hosts_are_matched_ignoring_case() {
+        assert!(image_url("https://media.giphy.com/x.gif", &["Giphy.COM"]).is_some());
+    }
+
+    #[test]
+    fn an_empty_allowlist_takes_any_https_host() {
+        let none: [&str; 0] = [];
+        assert!(image_url("https://example.com/x.gif", &none).is_some());
+        assert!(image_url("http://example.com/x.gif", &none).is_none());
+    }
+
+    #[test]
+    fn escaping_covers_markup_and_quotes() {
+        assert_eq!(
+            escape_html(r#"<img src='x' onerror="alert(1)">&"#),
+            "&lt;img src=&#39;x&#39; onerror=&quot;alert(1)&quot;&gt;&amp;"
+        );
+    }
+}
@@ -0,0 +1,53 @@
+use js_sys::{Function, Promise, Reflect};
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{HtmlDocument, HtmlTextAreaElement};
+
+/// Copies `text` to the system clipboard. If the async Clipboard API is missing or
+/// refuses (no permission, or the page isn't focused or served securely), falls back to
+/// selecting a hidden textarea and running the legacy copy command.
+pub async fn copy_text(text: &str) -> Result<(), JsValue> {
+    match write_text(text).await {
+        Ok(()) => Ok(()),
+        Err(e) => {
+            log::debug!("clipboard api failed, falling back to execCommand: {:?}", e);
+            copy_with_textarea(text)
+        }
+    }
+}
+
+/// The async Clipboard API isn't in web-sys's stable bindings yet, so it's called through
+/// reflection.
+async fn write_text(text: &str) -> Result<(), JsValue> {
+    let navigator = web_sys::window().ok_or("no window")?.navigator();
+    let clipboard = Reflect::get(&navigator, &"clipboard".into())?;
+    if clipboard.is_undefined() {
+        return Err("clipboard is unavailable".into());
+    }
+    let write_text: Function = Reflect::get(&clipboard, &"writeText".into())?.dyn_into()?;
+    let promise: Promise = write_text.call1(&clipboard, &text.into())?.dyn_into()?;
+    JsFuture::from(promise).await?;
+    Ok(())
+}
+
+fn copy_with_textarea(text: &str) -> Result<(), JsValue> {
+    let document = gloo_utils::document();
+    let body = document.body().ok_or("no body")?;
+    let textarea: HtmlTextAreaElement = document.create_element("textarea")?.dyn_into()?;
+    textarea.set_value(text);
+    // Kept out of sight and out of the layout, and read-only so mobile keyboards stay shut.
+    textarea.set_attribute("readonly", "")?;
+    textarea.set_attribute("style", "position:fixed;top:0;left:0;opacity:0")?;
+    body.append_child(&textarea)?;
+    textarea.select();
+
+    let copied = document
+        .unchecked_into::<HtmlDocument>()
+        .exec_command("copy");
+    body.remove_child(&textarea)?;
+    if copied? {
+        Ok(())
+    } else {
+        Err("copy command was refused".into())
+    }
+}
@@ -0,0 +1,149 @@
+//! Taking events that come in bursts a frame's worth at a time, so a flood of them costs
+//! one render rather than one each. `Coalescer` is the buffer, and runs without a
+//! browser; `next_frame` schedules its flush.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use gloo_timers::callback::Timeout;
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::JsCast;
+
+/// Items waiting for the next flush, in the order they arrived.
+#[derive(Debug)]
+pub struct Coalescer<T> {
+    pending: Vec<T>,
+}
+
+impl<T> Default for Coalescer<T> {
+    fn default() -> Self {
+        Self { pending: vec![] }
+    }
+}
+
+impl<T> Coalescer<T> {
+    /// Buffers `item`. Returns `true` for the first since the last `drain`, when a
+    /// flush has to be scheduled; the rest wait for that one.
+    pub fn push(&mut self, item: T) -> bool {
+        self.pending.push(item);
+        self.pending.len() == 1
+    }
+
+    /// Everything buffered, oldest first, leaving the buffer empty.
+    pub fn drain(&mut self) -> Vec<T> {
+        std::mem::take(&mut self.pending)
+    }
+}
+
+/// A callback waiting for the next frame. Dropping it first cancels the callback.
+pub enum NextFrame {
+    Frame {
+        id: i32,
+        _callback: Closure<dyn FnMut()>,
+    },
+    /// Hidden tabs get no frames, and what's waiting still has to be seen to, for
+    /// notifications if nothing else.
+    Timer { _timeout: Timeout },
+}
+
+impl Drop for NextFrame {
+    fn drop(&mut self) {
+        if let NextFrame::Frame { id, .. } = self {
+            let _ = gloo_utils::window().cancel_animation_frame(*id);
+        }
+    }
+}
+
+/// Calls `callback` just before the browser next paints, or as soon as it can while the
+/// tab is hidden.
+pub fn next_frame<F: FnOnce() + 'static>(callback: F) -> NextFrame {
+    // Shared with the fallback, in case the browser won't take the frame callback.
+    let callback = Rc::new(RefCell::new(Some(callback)));
+    let call = move |callback: &RefCell<Option<F>>| {
+        if let Some(callback) = callback.borrow_mut().take() {
+            callback();
+        }
+    };
+    if !gloo_utils::document().hidden() {
+        let on_frame = {
+            let callback = callback.clone();
+            Closure::wrap(Box::new(move || call(&callback)) as Box<dyn FnMut()>)
+        };
+        let requested =
+            gloo_utils::window().request_animation_frame(on_frame.as_ref().unchecked_ref());
+        if let Ok(id) = requested {
+            return NextFrame::Frame {
+                id,
+                _callback: on_frame,
+            };
+        }
+    }
+    NextFrame::Timer {
+        _timeout: Timeout::new(0, move || call(&callback)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::chat::{apply_incoming, ChatState};
+    use crate::protocol::MessageData;
+    use crate::services::event_bus::BusEvent;
+
+    /// Pushes each of `items` as `Chat` does on arrival, and counts the flushes it asks
+    /// to have scheduled.
+    fn burst<T>(coalescer: &mut Coalescer<T>, items: impl IntoIterator<Item = T>) -> usize {
+        items
+            .into_iter()
+            .map(|item| coalescer.push(item))
+            .filter(|&scheduled| scheduled)
+            .count()
+    }
+
+    fn message(n: u64) -> BusEvent {
+        BusEvent::MessageReceived(MessageData {
+            id: format!("m{}", n),
+            from: "ann".into(),
+            message: format!("message {}", n),
+            time: 1_000 + n,
+            reactions: Default::default(),
+            read_by: vec![],
+            reply_to: None,
+            attachment: None,
+            status: Default::default(),
+        })
+    }
+
+    #[test]
+    fn a_burst_asks_for_one_flush() {
+        let mut coalescer = Coalescer::default();
+        assert_eq!(burst(&mut coalescer, 0..100), 1);
+        assert_eq!(coalescer.drain(), (0..100).collect::<Vec<_>>());
+        assert!(coalescer.drain().is_empty());
+    }
+
+    #[test]
+    fn each_burst_after_a_flush_asks_for_another() {
+        let mut coalescer = Coalescer::default();
+        assert_eq!(burst(&mut coalescer, ["a", "b"]), 1);
+        assert_eq!(coalescer.drain(), ["a", "b"]);
+        assert_eq!(burst(&mut coalescer, ["c"]), 1);
+        assert_eq!(burst(&mut coalescer, ["d", "e"]), 0);
+        assert_eq!(coalescer.drain(), ["c", "d", "e"]);
+    }
+
+    #[test]
+    fn rapid_messages_are_applied_in_one_update() {
+        const N: u64 = 50;
+        let mut coalescer = Coalescer::default();
+        assert_eq!(burst(&mut coalescer, (0..N).map(message)), 1);
+
+        // The flush applies the whole burst, oldest first.
+        let mut state = ChatState::default();
+        for event in coalescer.drain() {
+            apply_incoming(&mut state, event);
+        }
+        let ids: Vec<_> = state.messages.iter().map(|m| m.id.clone()).collect();
+        assert_eq!(ids, (0..N).map(|n| format!("m{}", n)).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,39 @@
+//! How tightly the message list is laid out, remembered in localStorage.
+
+use gloo_storage::{LocalStorage, Storage};
+use serde::{Deserialize, Serialize};
+
+use crate::i18n::I18n;
+
+const STORAGE_KEY: &str = "yewchat.density";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Density {
+    /// Bubbles with avatars, one message to a block.
+    #[default]
+    Cozy,
+    /// One line per message, IRC style: time, sender, text.
+    Compact,
+}
+
+impl Density {
+    pub const ALL: [Density; 2] = [Density::Cozy, Density::Compact];
+
+    pub fn label(self, i18n: I18n) -> &'static str {
+        i18n.t(match self {
+            Density::Cozy => "density_cozy",
+            Density::Compact => "density_compact",
+        })
+    }
+}
+
+/// The setting saved on this device; nothing saved, or something unreadable, is cozy.
+pub fn load() -> Density {
+    LocalStorage::get(STORAGE_KEY).unwrap_or_default()
+}
+
+pub fn save(density: Density) {
+    if let Err(e) = LocalStorage::set(STORAGE_KEY, density) {
+        log::error!("couldn't save density setting: {:?}", e);
+    }
+}
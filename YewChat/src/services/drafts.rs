@@ -0,0 +1,52 @@
+//! What's been typed in the message box but not sent, kept in localStorage by user and
+//! room, so a reload, or coming back to the room, puts it back.
+
+use gloo_storage::{LocalStorage, Storage};
+
+const STORAGE_PREFIX: &str = "yewchat.draft";
+
+/// Where `user`'s draft for `room` is kept. Usernames can't contain `:`, so no two pairs
+/// share a key, whatever the room is called.
+pub fn storage_key(user: &str, room: &str) -> String {
+    format!("{}:{}:{}", STORAGE_PREFIX, user, room)
+}
+
+/// The draft `user` left in `room`, if there's anything in it.
+pub fn load(user: &str, room: &str) -> Option<String> {
+    LocalStorage::get::<String>(storage_key(user, room))
+        .ok()
+        .filter(|draft| !draft.trim().is_empty())
+}
+
+/// Keeps `text` as the draft; blank text clears it.
+pub fn save(user: &str, room: &str, text: &str) {
+    if text.trim().is_empty() {
+        clear(user, room);
+    } else if let Err(e) = LocalStorage::set(storage_key(user, room), text) {
+        log::error!("couldn't save the draft: {:?}", e);
+    }
+}
+
+pub fn clear(user: &str, room: &str) {
+    LocalStorage::delete(storage_key(user, room));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::username;
+
+    #[test]
+    fn drafts_are_kept_by_user_and_room() {
+        assert_eq!(storage_key("ann", "general"), "yewchat.draft:ann:general");
+        assert_ne!(storage_key("ann", "general"), storage_key("bob", "general"));
+        assert_ne!(storage_key("ann", "general"), storage_key("ann", "random"));
+    }
+
+    #[test]
+    fn a_colon_in_the_room_name_cant_collide() {
+        // Only a user called "ann:b" could reach ann's draft for "b:c", and there's none.
+        assert_eq!(storage_key("ann", "b:c"), storage_key("ann:b", "c"));
+        assert!(username::validate("ann:b").is_err());
+    }
+}
@@ -1,47 +1,528 @@
-use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::hash::Hash;
+
+use serde::de::DeserializeOwned;
 use yew_agent::{Agent, AgentLink, Context, HandlerId};
 
-#[derive(Serialize, Deserialize, Debug)]
+use crate::protocol::{
+    Activity, HistoryPage, KickNotice, MessageData, MsgTypes, PinNotice, Presence, Reaction,
+    ReadReceipt, Roster, ServerError, TypingNotice, WebSocketMessage,
+};
+
+/// The server only has the one room for now; every message belongs to it.
+pub const DEFAULT_ROOM: &str = "general";
+
+/// What a subscriber can listen to, so it only wakes for events it cares about.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Topic {
+    /// Who's connected and whether they're away.
+    Presence,
+    /// Messages in a room, including history and deletions.
+    Room(String),
+}
+
+/// A frame from the server, decoded once where it comes off the socket so subscribers
+/// get structured data instead of JSON to re-parse.
+#[derive(Clone, Debug)]
+pub enum BusEvent {
+    /// The full list of connected users, with the presence of any that reported one, and
+    /// the activity of those the server says.
+    UsersUpdated {
+        users: Vec<String>,
+        statuses: HashMap<String, Presence>,
+        admins: HashSet<String>,
+        activity: HashMap<String, Activity>,
+    },
+    MessageReceived(MessageData),
+    /// A reply to a history request. A page that couldn't be decoded still arrives, as
+    /// an error, so whoever asked for it isn't left waiting.
+    HistoryReceived(Result<HistoryPage, FrameError>),
+    /// The message with this id was deleted by its author.
+    MessageDeleted(String),
+    /// The room's owner, named here, cleared the conversation for everyone.
+    RoomCleared(String),
+    /// An admin removed someone, possibly us.
+    Kicked(KickNotice),
+    ReactionToggled(Reaction),
+    MessagesRead(ReadReceipt),
+    /// Someone started or stopped typing. Only subscribers to the notice's room hear it.
+    TypingChanged(TypingNotice),
+    /// Someone pinned or unpinned a message.
+    PinToggled(PinNotice),
+    /// Every pinned message's id, oldest pin first, replacing whatever we had.
+    PinsListed(Vec<String>),
+    /// The server refused our username, for this reason.
+    RegisterRejected(String),
+    /// The server refused something else we sent.
+    ServerError(ServerError),
+}
+
+/// Why a frame from the server was dropped instead of becoming a `BusEvent`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FrameError {
+    /// It isn't a `WebSocketMessage`: not JSON, cut short, of a type we don't know, or
+    /// with a field of the wrong kind.
+    Malformed(String),
+    /// A type the server never sends, like `Register`.
+    Unexpected(MsgTypes),
+    /// A frame of this type without the field it needs, named here.
+    Missing(MsgTypes, &'static str),
+    /// The payload doesn't decode as what the type carries, named here.
+    BadPayload(&'static str, String),
+    /// Something the event needs, named here, is blank.
+    Empty(&'static str),
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FrameError::Malformed(e) => write!(f, "bad frame: {}", e),
+            FrameError::Unexpected(message_type) => {
+                write!(f, "unexpected {:?} frame", message_type)
+            }
+            FrameError::Missing(message_type, field) => {
+                write!(f, "{:?} frame without {}", message_type, field)
+            }
+            FrameError::BadPayload(what, e) => write!(f, "bad {}: {}", what, e),
+            FrameError::Empty(what) => write!(f, "empty {}", what),
+        }
+    }
+}
+
+impl BusEvent {
+    pub fn topic(&self) -> Topic {
+        match self {
+            BusEvent::UsersUpdated { .. }
+            | BusEvent::RegisterRejected(_)
+            | BusEvent::Kicked(_)
+            | BusEvent::ServerError(_) => Topic::Presence,
+            BusEvent::MessageReceived(_)
+            | BusEvent::HistoryReceived(_)
+            | BusEvent::MessageDeleted(_)
+            | BusEvent::RoomCleared(_)
+            | BusEvent::ReactionToggled(_)
+            | BusEvent::MessagesRead(_)
+            | BusEvent::PinToggled(_)
+            | BusEvent::PinsListed(_) => Topic::Room(DEFAULT_ROOM.to_string()),
+            BusEvent::TypingChanged(notice) => Topic::Room(notice.room.clone()),
+        }
+    }
+
+    /// Decodes a text frame from the server. The server isn't trusted to be well
+    /// behaved: anything malformed, or missing what the event needs, is an error rather
+    /// than an event with made-up contents, so the caller can drop it.
+    pub fn from_frame(data: &str) -> Result<Self, FrameError> {
+        let frame: WebSocketMessage =
+            serde_json::from_str(data).map_err(|e| FrameError::Malformed(e.to_string()))?;
+        let message_type = frame.message_type;
+        let payload = |data: Option<String>| data.ok_or(FrameError::Missing(message_type, "data"));
+
+        match message_type {
+            MsgTypes::Users => {
+                let users = frame
+                    .data_array
+                    .ok_or(FrameError::Missing(message_type, "dataArray"))?;
+                // Presence is best effort; anyone missing from it counts as active.
+                let roster = frame
+                    .data
+                    .and_then(|d| {
+                        serde_json::from_str::<Roster>(&d).ok().or_else(|| {
+                            serde_json::from_str(&d).ok().map(|statuses| Roster {
+                                statuses,
+                                ..Roster::default()
+                            })
+                        })
+                    })
+                    .unwrap_or_default();
+                Ok(BusEvent::UsersUpdated {
+                    users: users.into_iter().filter(|u| !u.trim().is_empty()).collect(),
+                    statuses: roster.statuses,
+                    admins: roster.admins.into_iter().collect(),
+                    activity: roster.activity,
+                })
+            }
+            MsgTypes::Message => {
+                let message: MessageData = decode(&payload(frame.data)?, "message")?;
+                require(&message.from, "message sender")?;
+                Ok(BusEvent::MessageReceived(message))
+            }
+            MsgTypes::History => Ok(BusEvent::HistoryReceived(
+                payload(frame.data).and_then(|d| decode(&d, "history page")),
+            )),
+            MsgTypes::Delete => {
+                let id = payload(frame.data)?;
+                require(&id, "deleted message id")?;
+                Ok(BusEvent::MessageDeleted(id))
+            }
+            MsgTypes::Kick => {
+                let notice: KickNotice = decode(&payload(frame.data)?, "kick")?;
+                require(&notice.nick, "kicked user")?;
+                Ok(BusEvent::Kicked(notice))
+            }
+            MsgTypes::ClearRoom => {
+                let by = payload(frame.data)?;
+                require(&by, "who cleared the room")?;
+                Ok(BusEvent::RoomCleared(by))
+            }
+            MsgTypes::Reaction => {
+                let reaction: Reaction = decode(&payload(frame.data)?, "reaction")?;
+                require(&reaction.id, "reaction target")?;
+                require(&reaction.from, "reaction sender")?;
+                require(&reaction.emoji, "reaction emoji")?;
+                Ok(BusEvent::ReactionToggled(reaction))
+            }
+            MsgTypes::Read => {
+                let receipt: ReadReceipt = decode(&payload(frame.data)?, "read receipt")?;
+                require(&receipt.from, "reader")?;
+                Ok(BusEvent::MessagesRead(receipt))
+            }
+            MsgTypes::Typing => {
+                let notice: TypingNotice = decode(&payload(frame.data)?, "typing notice")?;
+                require(&notice.room, "typing room")?;
+                require(&notice.from, "typist")?;
+                Ok(BusEvent::TypingChanged(notice))
+            }
+            MsgTypes::Pin => {
+                if let Some(ids) = frame.data_array {
+                    return Ok(BusEvent::PinsListed(
+                        ids.into_iter().filter(|id| !id.trim().is_empty()).collect(),
+                    ));
+                }
+                let notice: PinNotice = decode(&payload(frame.data)?, "pin")?;
+                require(&notice.id, "pinned message id")?;
+                require(&notice.from, "pinner")?;
+                Ok(BusEvent::PinToggled(notice))
+            }
+            MsgTypes::RegisterError => {
+                Ok(BusEvent::RegisterRejected(frame.data.unwrap_or_else(
+                    || "That username can't be used".to_string(),
+                )))
+            }
+            MsgTypes::Error => {
+                // Servers that send a bare reason get it shown as is.
+                let data = payload(frame.data)?;
+                let error = serde_json::from_str(&data).unwrap_or(ServerError {
+                    code: String::new(),
+                    reason: Some(data),
+                    id: None,
+                });
+                Ok(BusEvent::ServerError(error))
+            }
+            MsgTypes::Register | MsgTypes::Status => Err(FrameError::Unexpected(message_type)),
+        }
+    }
+}
+
+fn decode<T: DeserializeOwned>(data: &str, what: &'static str) -> Result<T, FrameError> {
+    serde_json::from_str(data).map_err(|e| FrameError::BadPayload(what, e.to_string()))
+}
+
+fn require(value: &str, what: &'static str) -> Result<(), FrameError> {
+    if value.trim().is_empty() {
+        Err(FrameError::Empty(what))
+    } else {
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
 pub enum Request {
-    EventBusMsg(String),
+    EventBusMsg(BusEvent),
+    /// Limits the sending bridge to this topic, on top of any it already follows.
+    Subscribe(Topic),
+}
+
+/// Who gets which events, by topic `T`. A subscriber that hasn't picked any topics gets
+/// everything.
+#[derive(Debug)]
+pub(crate) struct Routes<K, T = Topic> {
+    subscribers: HashMap<K, Option<HashSet<T>>>,
+}
+
+impl<K: Copy + Eq + Hash, T: Eq + Hash> Routes<K, T> {
+    pub(crate) fn new() -> Self {
+        Self {
+            subscribers: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn connect(&mut self, id: K) {
+        self.subscribers.insert(id, None);
+    }
+
+    pub(crate) fn subscribe(&mut self, id: K, topic: T) {
+        self.subscribers
+            .entry(id)
+            .or_default()
+            .get_or_insert_with(HashSet::new)
+            .insert(topic);
+    }
+
+    /// Forgets `id` along with all its topics.
+    pub(crate) fn disconnect(&mut self, id: K) {
+        self.subscribers.remove(&id);
+    }
+
+    pub(crate) fn recipients<'a>(&'a self, topic: &'a T) -> impl Iterator<Item = K> + 'a {
+        self.subscribers
+            .iter()
+            .filter(move |(_, topics)| topics.as_ref().is_none_or(|t| t.contains(topic)))
+            .map(|(id, _)| *id)
+    }
 }
 
 pub struct EventBus {
     link: AgentLink<EventBus>,
-    subscribers: HashSet<HandlerId>,
+    routes: Routes<HandlerId>,
 }
 
 impl Agent for EventBus {
     type Reach = Context<Self>;
     type Message = ();
     type Input = Request;
-    type Output = String;
+    type Output = BusEvent;
 
     fn create(link: AgentLink<Self>) -> Self {
         Self {
             link,
-            subscribers: HashSet::new(),
+            routes: Routes::new(),
         }
     }
 
     fn update(&mut self, _msg: Self::Message) {}
 
-    fn handle_input(&mut self, msg: Self::Input, _id: HandlerId) {
+    fn handle_input(&mut self, msg: Self::Input, id: HandlerId) {
         match msg {
-            Request::EventBusMsg(s) => {
-                for sub in self.subscribers.iter() {
-                    self.link.respond(*sub, s.clone())
+            Request::EventBusMsg(event) => {
+                let topic = event.topic();
+                for sub in self.routes.recipients(&topic) {
+                    self.link.respond(sub, event.clone())
                 }
             }
+            Request::Subscribe(topic) => self.routes.subscribe(id, topic),
         }
     }
 
     fn connected(&mut self, id: HandlerId) {
-        self.subscribers.insert(id);
+        self.routes.connect(id);
     }
 
     fn disconnected(&mut self, id: HandlerId) {
-        self.subscribers.remove(&id);
+        self.routes.disconnect(id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MESSAGE: &str = r#"{"messageType":"message","data":"{\"id\":\"1\",\"from\":\"ann\",\"message\":\"hi\",\"time\":5}"}"#;
+
+    fn error(frame: &str) -> FrameError {
+        match BusEvent::from_frame(frame) {
+            Ok(event) => panic!("{} decoded as {:?}", frame, event),
+            Err(e) => e,
+        }
+    }
+
+    #[test]
+    fn a_good_frame_decodes() {
+        match BusEvent::from_frame(MESSAGE) {
+            Ok(BusEvent::MessageReceived(m)) => assert_eq!((m.from.as_str(), m.time), ("ann", 5)),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn truncated_frames_are_malformed() {
+        // Every proper prefix, down to nothing.
+        for end in 0..MESSAGE.len() {
+            let frame = &MESSAGE[..end];
+            assert!(
+                matches!(error(frame), FrameError::Malformed(_)),
+                "{:?}",
+                frame
+            );
+        }
+    }
+
+    #[test]
+    fn frames_that_are_not_messages_are_malformed() {
+        for frame in [
+            "",
+            "null",
+            "[]",
+            "\"message\"",
+            "{}",
+            r#"{"data":"hi"}"#,
+            r#"{"messageType":"bogus","data":"hi"}"#,
+            r#"{"messageType":"MESSAGE","data":"hi"}"#,
+            r#"{"messageType":7}"#,
+        ] {
+            assert!(
+                matches!(error(frame), FrameError::Malformed(_)),
+                "{:?}",
+                frame
+            );
+        }
+    }
+
+    #[test]
+    fn data_of_the_wrong_kind_is_malformed() {
+        for frame in [
+            r#"{"messageType":"message","data":{"from":"ann","message":"hi"}}"#,
+            r#"{"messageType":"delete","data":5}"#,
+            r#"{"messageType":"users","dataArray":"ann"}"#,
+            r#"{"messageType":"users","dataArray":[1,2]}"#,
+        ] {
+            assert!(
+                matches!(error(frame), FrameError::Malformed(_)),
+                "{:?}",
+                frame
+            );
+        }
+    }
+
+    #[test]
+    fn frames_without_their_data_are_missing_it() {
+        for (name, message_type) in [
+            ("message", MsgTypes::Message),
+            ("delete", MsgTypes::Delete),
+            ("kick", MsgTypes::Kick),
+            ("clearRoom", MsgTypes::ClearRoom),
+            ("reaction", MsgTypes::Reaction),
+            ("read", MsgTypes::Read),
+            ("typing", MsgTypes::Typing),
+            ("pin", MsgTypes::Pin),
+            ("error", MsgTypes::Error),
+        ] {
+            for frame in [
+                format!(r#"{{"messageType":"{}"}}"#, name),
+                format!(
+                    r#"{{"messageType":"{}","data":null,"dataArray":null}}"#,
+                    name
+                ),
+            ] {
+                assert_eq!(
+                    error(&frame),
+                    FrameError::Missing(message_type, "data"),
+                    "{}",
+                    frame
+                );
+            }
+        }
+        assert_eq!(
+            error(r#"{"messageType":"users","data":"{}"}"#),
+            FrameError::Missing(MsgTypes::Users, "dataArray")
+        );
+    }
+
+    #[test]
+    fn a_missing_history_page_still_answers_the_request() {
+        match BusEvent::from_frame(r#"{"messageType":"history"}"#) {
+            Ok(BusEvent::HistoryReceived(Err(e))) => {
+                assert_eq!(e, FrameError::Missing(MsgTypes::History, "data"))
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn payloads_that_do_not_decode_are_bad() {
+        // Messages without an id are given one before the rest is found wanting.
+        crate::ids::count_from_one();
+        for (frame, what) in [
+            (r#"{"messageType":"message","data":"hi"}"#, "message"),
+            (
+                r#"{"messageType":"message","data":"{\"from\":\"ann\"}"}"#,
+                "message",
+            ),
+            (r#"{"messageType":"reaction","data":"[]"}"#, "reaction"),
+            (
+                r#"{"messageType":"typing","data":"{\"room\":\"general\"}"}"#,
+                "typing notice",
+            ),
+        ] {
+            assert!(
+                matches!(error(frame), FrameError::BadPayload(w, _) if w == what),
+                "{:?}",
+                frame
+            );
+        }
+    }
+
+    #[test]
+    fn blank_fields_are_empty() {
+        for (frame, what) in [
+            (
+                r#"{"messageType":"message","data":"{\"id\":\"1\",\"from\":\" \",\"message\":\"hi\"}"}"#,
+                "message sender",
+            ),
+            (
+                r#"{"messageType":"delete","data":""}"#,
+                "deleted message id",
+            ),
+            (
+                r#"{"messageType":"reaction","data":"{\"id\":\"1\",\"from\":\"ann\",\"emoji\":\"\"}"}"#,
+                "reaction emoji",
+            ),
+        ] {
+            assert_eq!(error(frame), FrameError::Empty(what), "{}", frame);
+        }
+    }
+
+    #[test]
+    fn frames_only_clients_send_are_unexpected() {
+        for (name, message_type) in [
+            ("register", MsgTypes::Register),
+            ("status", MsgTypes::Status),
+        ] {
+            let frame = format!(r#"{{"messageType":"{}","data":"ann"}}"#, name);
+            assert_eq!(error(&frame), FrameError::Unexpected(message_type));
+        }
+    }
+
+    /// Who `routes` sends an event on `topic` to, in order.
+    fn recipients(routes: &Routes<u32>, topic: &Topic) -> Vec<u32> {
+        let mut ids: Vec<_> = routes.recipients(topic).collect();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn subscribers_get_only_the_topics_they_picked() {
+        let (lobby, other) = (
+            Topic::Room(DEFAULT_ROOM.to_string()),
+            Topic::Room("other".to_string()),
+        );
+        let mut routes = Routes::new();
+        for id in 1..=4 {
+            routes.connect(id);
+        }
+        routes.subscribe(1, Topic::Presence);
+        routes.subscribe(2, lobby.clone());
+        routes.subscribe(3, Topic::Presence);
+        routes.subscribe(3, lobby.clone());
+        // 4 picks nothing, so it hears everything.
+
+        assert_eq!(recipients(&routes, &Topic::Presence), [1, 3, 4]);
+        assert_eq!(recipients(&routes, &lobby), [2, 3, 4]);
+        assert_eq!(recipients(&routes, &other), [4]);
+    }
+
+    #[test]
+    fn disconnected_subscribers_get_nothing() {
+        let mut routes = Routes::new();
+        routes.connect(1);
+        routes.connect(2);
+        routes.subscribe(2, Topic::Presence);
+        routes.disconnect(1);
+        routes.disconnect(2);
+        assert!(recipients(&routes, &Topic::Presence).is_empty());
+
+        // Coming back starts over, following everything again.
+        routes.connect(2);
+        assert_eq!(recipients(&routes, &Topic::Room("other".to_string())), [2]);
     }
 }
@@ -0,0 +1,209 @@
+//! Saving the conversation to a file, built and downloaded entirely in the browser.
+
+use gloo_timers::callback::Timeout;
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::{Blob, BlobPropertyBag, HtmlAnchorElement, Url};
+
+use crate::protocol::MessageData;
+
+// Some browsers read the blob after `click` returns, so the URL outlives it for a bit.
+const REVOKE_DELAY_MS: u32 = 10_000;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExportFormat {
+    Text,
+    Json,
+}
+
+impl ExportFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Text => "txt",
+            ExportFormat::Json => "json",
+        }
+    }
+
+    fn mime_type(self) -> &'static str {
+        match self {
+            ExportFormat::Text => "text/plain;charset=utf-8",
+            ExportFormat::Json => "application/json",
+        }
+    }
+}
+
+/// The date and time in UTC of `ms` milliseconds since the epoch, as
+/// `(year, month, day, hour, minute, second)`.
+fn civil_time(ms: u64) -> (i64, u32, u32, u32, u32, u32) {
+    let secs = ms / 1000;
+    let (days, day_secs) = ((secs / 86_400) as i64, (secs % 86_400) as u32);
+    // Howard Hinnant's days-to-civil algorithm, on a calendar whose years start in March.
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (
+        year,
+        month,
+        day,
+        day_secs / 3600,
+        day_secs / 60 % 60,
+        day_secs % 60,
+    )
+}
+
+/// One line per message, `HH:MM sender: text` in UTC, with later lines of a multi-line
+/// message indented under the first. Each day starts with a `--- 2024-05-01 ---` line,
+/// since the times alone don't say which day.
+pub fn to_text(messages: &[MessageData]) -> String {
+    let mut out = String::new();
+    let mut day = None;
+    for m in messages {
+        let (y, mo, d, h, mi, _) = civil_time(m.time);
+        if day != Some((y, mo, d)) {
+            day = Some((y, mo, d));
+            out.push_str(&format!("--- {:04}-{:02}-{:02} ---\n", y, mo, d));
+        }
+        let mut lines = m.message.lines();
+        out.push_str(&format!(
+            "{:02}:{:02} {}: {}\n",
+            h,
+            mi,
+            m.from,
+            lines.next().unwrap_or_default()
+        ));
+        for line in lines {
+            out.push_str(&format!("    {}\n", line));
+        }
+    }
+    out
+}
+
+/// The messages as they're sent over the wire, ids, reactions and replies included.
+pub fn to_json(messages: &[MessageData]) -> String {
+    serde_json::to_string_pretty(messages).unwrap()
+}
+
+/// e.g. `yewchat-20240501-134509.txt`, stamped with `now` in UTC.
+pub fn filename(format: ExportFormat, now: u64) -> String {
+    let (y, mo, d, h, mi, s) = civil_time(now);
+    format!(
+        "yewchat-{:04}{:02}{:02}-{:02}{:02}{:02}.{}",
+        y,
+        mo,
+        d,
+        h,
+        mi,
+        s,
+        format.extension()
+    )
+}
+
+/// Offers `messages` as a file download in `format`.
+pub fn download(messages: &[MessageData], format: ExportFormat) -> Result<(), JsValue> {
+    let contents = match format {
+        ExportFormat::Text => to_text(messages),
+        ExportFormat::Json => to_json(messages),
+    };
+    let parts = js_sys::Array::of1(&contents.into());
+    let blob = Blob::new_with_str_sequence_and_options(
+        &parts,
+        BlobPropertyBag::new().type_(format.mime_type()),
+    )?;
+    let url = Url::create_object_url_with_blob(&blob)?;
+
+    let document = gloo_utils::document();
+    let link: HtmlAnchorElement = document.create_element("a")?.dyn_into()?;
+    link.set_href(&url);
+    link.set_download(&filename(format, js_sys::Date::now() as u64));
+    link.click();
+    Timeout::new(REVOKE_DELAY_MS, move || {
+        let _ = Url::revoke_object_url(&url);
+    })
+    .forget();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::protocol::DeliveryStatus;
+
+    /// 2024-05-01 00:00:00 UTC.
+    const MAY_DAY: u64 = 1_714_521_600_000;
+    const HOUR: u64 = 3_600_000;
+
+    fn message(from: &str, text: &str, time: u64) -> MessageData {
+        MessageData {
+            id: format!("{}-{}", from, time),
+            from: from.into(),
+            message: text.into(),
+            time,
+            reactions: BTreeMap::new(),
+            read_by: vec![],
+            reply_to: None,
+            attachment: None,
+            status: DeliveryStatus::Delivered,
+        }
+    }
+
+    #[test]
+    fn times_are_in_utc() {
+        assert_eq!(civil_time(0), (1970, 1, 1, 0, 0, 0));
+        assert_eq!(
+            civil_time(MAY_DAY + 13 * HOUR + 45 * 60_000 + 9_999),
+            (2024, 5, 1, 13, 45, 9)
+        );
+        // A leap day, and the last second of a year.
+        assert_eq!(civil_time(1_709_164_800_000), (2024, 2, 29, 0, 0, 0));
+        assert_eq!(civil_time(1_704_067_199_000), (2023, 12, 31, 23, 59, 59));
+    }
+
+    #[test]
+    fn text_has_a_line_per_message_and_a_heading_per_day() {
+        let messages = [
+            message("ann", "morning", MAY_DAY + 9 * HOUR),
+            message("bob", "first\nsecond", MAY_DAY + 23 * HOUR + 59 * 60_000),
+            message("ann", "", MAY_DAY + 24 * HOUR + 5 * 60_000),
+        ];
+        assert_eq!(
+            to_text(&messages),
+            "--- 2024-05-01 ---\n\
+             09:00 ann: morning\n\
+             23:59 bob: first\n    second\n\
+             --- 2024-05-02 ---\n\
+             00:05 ann: \n"
+        );
+        assert_eq!(to_text(&[]), "");
+    }
+
+    #[test]
+    fn json_round_trips() {
+        let messages = vec![message("ann", "hi", MAY_DAY)];
+        let back: Vec<MessageData> = serde_json::from_str(&to_json(&messages)).unwrap();
+        assert_eq!(back.len(), 1);
+        assert_eq!(
+            (back[0].id.as_str(), back[0].message.as_str()),
+            ("ann-1714521600000", "hi")
+        );
+    }
+
+    #[test]
+    fn filenames_are_stamped_and_typed() {
+        let now = MAY_DAY + 13 * HOUR + 45 * 60_000 + 9_000;
+        assert_eq!(
+            filename(ExportFormat::Text, now),
+            "yewchat-20240501-134509.txt"
+        );
+        assert_eq!(
+            filename(ExportFormat::Json, now),
+            "yewchat-20240501-134509.json"
+        );
+    }
+}
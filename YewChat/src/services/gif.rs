@@ -0,0 +1,198 @@
+use reqwasm::http::Request;
+use serde::Deserialize;
+
+const GIPHY_SEARCH_URL: &str = "https://api.giphy.com/v1/gifs/search";
+const TENOR_SEARCH_URL: &str = "https://tenor.googleapis.com/v2/search";
+pub const GIF_PAGE_SIZE: u32 = 24;
+
+/// Where GIF searches go. The default is picked at build time with `YEWCHAT_GIF_PROVIDER`
+/// (`giphy` or `tenor`). Each provider has its own API key, also baked in at build time
+/// through `YEWCHAT_GIPHY_API_KEY` or `YEWCHAT_TENOR_API_KEY`; `GifPicker` can be handed
+/// another.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GifProvider {
+    Giphy,
+    Tenor,
+}
+
+impl Default for GifProvider {
+    fn default() -> Self {
+        match option_env!("YEWCHAT_GIF_PROVIDER") {
+            Some(p) if p.eq_ignore_ascii_case("tenor") => GifProvider::Tenor,
+            _ => GifProvider::Giphy,
+        }
+    }
+}
+
+impl GifProvider {
+    pub fn default_api_key(self) -> Option<&'static str> {
+        match self {
+            GifProvider::Giphy => option_env!("YEWCHAT_GIPHY_API_KEY"),
+            GifProvider::Tenor => option_env!("YEWCHAT_TENOR_API_KEY"),
+        }
+    }
+
+    /// Attribution shown under the results, as both providers require.
+    pub fn attribution(self) -> &'static str {
+        match self {
+            GifProvider::Giphy => "Powered by GIPHY",
+            GifProvider::Tenor => "Via Tenor",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gif {
+    pub id: String,
+    pub title: String,
+    /// Small rendition for the picker grid.
+    pub preview_url: String,
+    /// Full rendition, which is what gets sent.
+    pub url: String,
+}
+
+/// One page of search results, and the cursor for the next page if there is one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GifPage {
+    pub gifs: Vec<Gif>,
+    pub next: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct GiphyResponse {
+    data: Vec<GiphyGif>,
+    pagination: GiphyPagination,
+}
+
+#[derive(Deserialize)]
+struct GiphyPagination {
+    offset: u32,
+    count: u32,
+    #[serde(default)]
+    total_count: u32,
+}
+
+#[derive(Deserialize)]
+struct GiphyGif {
+    id: String,
+    #[serde(default)]
+    title: String,
+    images: GiphyImages,
+}
+
+#[derive(Deserialize)]
+struct GiphyImages {
+    fixed_height_small: GiphyImage,
+    original: GiphyImage,
+}
+
+#[derive(Deserialize)]
+struct GiphyImage {
+    url: String,
+}
+
+#[derive(Deserialize)]
+struct TenorResponse {
+    results: Vec<TenorGif>,
+    #[serde(default)]
+    next: String,
+}
+
+#[derive(Deserialize)]
+struct TenorGif {
+    id: String,
+    #[serde(default)]
+    content_description: String,
+    media_formats: TenorFormats,
+}
+
+#[derive(Deserialize)]
+struct TenorFormats {
+    gif: TenorMedia,
+    tinygif: TenorMedia,
+}
+
+#[derive(Deserialize)]
+struct TenorMedia {
+    url: String,
+}
+
+// Giphy appends tracking parameters; dropping them keeps the `.gif` suffix that the chat
+// relies on to render the message inline.
+fn strip_query(url: &str) -> String {
+    url.split('?').next().unwrap_or(url).to_string()
+}
+
+fn encode(s: &str) -> String {
+    String::from(js_sys::encode_uri_component(s))
+}
+
+/// Searches `provider` for `query`, starting at the cursor returned with the previous
+/// page, or at the top if `None`.
+pub async fn search(
+    provider: GifProvider,
+    api_key: &str,
+    query: &str,
+    cursor: Option<&str>,
+) -> Result<GifPage, String> {
+    let url = match provider {
+        GifProvider::Giphy => format!(
+            "{}?api_key={}&q={}&limit={}&offset={}&rating=g",
+            GIPHY_SEARCH_URL,
+            encode(api_key),
+            encode(query),
+            GIF_PAGE_SIZE,
+            cursor.unwrap_or("0")
+        ),
+        GifProvider::Tenor => format!(
+            "{}?key={}&q={}&limit={}&pos={}&media_filter=gif,tinygif&contentfilter=high",
+            TENOR_SEARCH_URL,
+            encode(api_key),
+            encode(query),
+            GIF_PAGE_SIZE,
+            encode(cursor.unwrap_or(""))
+        ),
+    };
+
+    let resp = Request::get(&url).send().await.map_err(|e| e.to_string())?;
+    if !resp.ok() {
+        return Err(format!("GIF search failed (HTTP {})", resp.status()));
+    }
+
+    match provider {
+        GifProvider::Giphy => {
+            let body: GiphyResponse = resp.json().await.map_err(|e| e.to_string())?;
+            let end = body.pagination.offset + body.pagination.count;
+            Ok(GifPage {
+                next: (body.pagination.count > 0 && end < body.pagination.total_count)
+                    .then(|| end.to_string()),
+                gifs: body
+                    .data
+                    .into_iter()
+                    .map(|g| Gif {
+                        id: g.id,
+                        title: g.title,
+                        preview_url: g.images.fixed_height_small.url,
+                        url: strip_query(&g.images.original.url),
+                    })
+                    .collect(),
+            })
+        }
+        GifProvider::Tenor => {
+            let body: TenorResponse = resp.json().await.map_err(|e| e.to_string())?;
+            Ok(GifPage {
+                next: (!body.results.is_empty() && !body.next.is_empty()).then_some(body.next),
+                gifs: body
+                    .results
+                    .into_iter()
+                    .map(|g| Gif {
+                        id: g.id,
+                        title: g.content_description,
+                        preview_url: g.media_formats.tinygif.url,
+                        url: g.media_formats.gif.url,
+                    })
+                    .collect(),
+            })
+        }
+    }
+}
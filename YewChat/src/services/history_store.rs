@@ -0,0 +1,379 @@
+//! Message history kept in IndexedDB, so large histories survive reloads without the size
+//! limit and main-thread blocking of localStorage.
+//!
+//! Items are stored as JSON objects keyed by their string `id`, with a numeric `time` used
+//! for paging. Writes are queued and committed together in a single transaction.
+
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use futures::channel::oneshot;
+use gloo_timers::callback::Timeout;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::spawn_local;
+use web_sys::{
+    IdbCursorDirection, IdbCursorWithValue, IdbDatabase, IdbKeyRange, IdbObjectStoreParameters,
+    IdbOpenDbRequest, IdbRequest, IdbTransaction, IdbTransactionMode,
+};
+
+const DB_NAME: &str = "yewchat";
+const DB_VERSION: u32 = 1;
+const STORE: &str = "messages";
+const TIME_INDEX: &str = "time";
+// Writes queued within this window share one transaction.
+const WRITE_BATCH_MS: u32 = 250;
+
+thread_local! {
+    static DB: RefCell<Option<IdbDatabase>> = const { RefCell::new(None) };
+    static PENDING: RefCell<Vec<JsValue>> = const { RefCell::new(Vec::new()) };
+    static FLUSH_SCHEDULED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Queues `items` for the next batched write. Each must serialize to an object with a
+/// string `id` and a numeric `time`; an existing entry with the same id is replaced.
+pub fn persist<T: Serialize>(items: &[T]) {
+    let values: Vec<JsValue> = items
+        .iter()
+        .filter_map(|item| serde_json::to_string(item).ok())
+        .filter_map(|json| js_sys::JSON::parse(&json).ok())
+        .collect();
+    if values.is_empty() {
+        return;
+    }
+    PENDING.with(|p| p.borrow_mut().extend(values));
+
+    if !FLUSH_SCHEDULED.with(|s| s.replace(true)) {
+        Timeout::new(WRITE_BATCH_MS, || {
+            FLUSH_SCHEDULED.with(|s| s.set(false));
+            spawn_local(async {
+                let batch = PENDING.with(|p| std::mem::take(&mut *p.borrow_mut()));
+                if let Err(e) = write(batch).await {
+                    log::error!("history store: write failed: {:?}", e);
+                }
+            });
+        })
+        .forget();
+    }
+}
+
+/// Loads up to `limit` stored items older than `before`, or the newest ones if `None`,
+/// returned oldest first.
+pub async fn load_page<T: DeserializeOwned>(
+    before: Option<u64>,
+    limit: usize,
+) -> Result<Vec<T>, JsValue> {
+    let range = match before {
+        Some(before) => IdbKeyRange::upper_bound_with_open(&(before as f64).into(), true)?.into(),
+        None => JsValue::UNDEFINED,
+    };
+    let values = Rc::new(RefCell::new(Vec::new()));
+    {
+        let values = values.clone();
+        walk(&range, IdbCursorDirection::Prev, move |value| {
+            let mut values = values.borrow_mut();
+            values.push(value);
+            values.len() < limit
+        })
+        .await?;
+    }
+    let values = values.take();
+    Ok(values.iter().rev().filter_map(decode).collect())
+}
+
+/// Loads every stored item with a `time` from `from` on, up to but not including `to`
+/// if one is given, oldest first.
+pub async fn load_between<T: DeserializeOwned>(
+    from: u64,
+    to: Option<u64>,
+) -> Result<Vec<T>, JsValue> {
+    let from = JsValue::from_f64(from as f64);
+    let range = match to {
+        Some(to) => IdbKeyRange::bound_with_lower_open_and_upper_open(
+            &from,
+            &(to as f64).into(),
+            false,
+            true,
+        )?,
+        None => IdbKeyRange::lower_bound(&from)?,
+    };
+    let values = Rc::new(RefCell::new(Vec::new()));
+    {
+        let values = values.clone();
+        walk(&range.into(), IdbCursorDirection::Next, move |value| {
+            values.borrow_mut().push(value);
+            true
+        })
+        .await?;
+    }
+    let values = values.take();
+    Ok(values.iter().filter_map(decode).collect())
+}
+
+/// The stored item with this id, if there is one.
+pub async fn get<T: DeserializeOwned>(id: &str) -> Result<Option<T>, JsValue> {
+    let db = database().await?;
+    let tx = db.transaction_with_str(STORE)?;
+    let req = tx.object_store(STORE)?.get(&JsValue::from_str(id))?;
+    let value = done(&req).await?;
+    Ok(if value.is_undefined() {
+        None
+    } else {
+        decode(&value)
+    })
+}
+
+/// Looks through every stored item, newest first, for up to `limit` that `keep` picks.
+/// Returned newest first.
+pub async fn search<T: DeserializeOwned + 'static>(
+    keep: impl Fn(&T) -> bool + 'static,
+    limit: usize,
+) -> Result<Vec<T>, JsValue> {
+    let found = Rc::new(RefCell::new(Vec::new()));
+    {
+        let found = found.clone();
+        walk(
+            &JsValue::UNDEFINED,
+            IdbCursorDirection::Prev,
+            move |value| {
+                let mut found = found.borrow_mut();
+                if let Some(item) = decode(&value).filter(|item| keep(item)) {
+                    found.push(item);
+                }
+                found.len() < limit
+            },
+        )
+        .await?;
+    }
+    Ok(found.take())
+}
+
+/// Walks the stored items in `range` of the time index, in `direction`, handing each to
+/// `visit` for as long as it returns true.
+async fn walk(
+    range: &JsValue,
+    direction: IdbCursorDirection,
+    mut visit: impl FnMut(JsValue) -> bool + 'static,
+) -> Result<(), JsValue> {
+    let db = database().await?;
+    let tx = db.transaction_with_str(STORE)?;
+    let index = tx.object_store(STORE)?.index(TIME_INDEX)?;
+    let req = index.open_cursor_with_range_and_direction(range, direction)?;
+
+    // The cursor has to be advanced from inside its own success callback, before the
+    // transaction commits, so the whole walk happens there.
+    let (done_tx, done_rx) = oneshot::channel();
+    let done_tx = Rc::new(RefCell::new(Some(done_tx)));
+    let on_success = {
+        let (req, done_tx) = (req.clone(), done_tx.clone());
+        Closure::wrap(Box::new(move || {
+            let cursor = req
+                .result()
+                .ok()
+                .and_then(|r| r.dyn_into::<IdbCursorWithValue>().ok());
+            if let Some(cursor) = cursor {
+                let more = cursor.value().map(&mut visit).unwrap_or(true);
+                if more && cursor.continue_().is_ok() {
+                    return;
+                }
+            }
+            if let Some(done) = done_tx.borrow_mut().take() {
+                let _ = done.send(true);
+            }
+        }) as Box<dyn FnMut()>)
+    };
+    let on_error = Closure::wrap(Box::new(move || {
+        if let Some(done) = done_tx.borrow_mut().take() {
+            let _ = done.send(false);
+        }
+    }) as Box<dyn FnMut()>);
+    req.set_onsuccess(Some(on_success.as_ref().unchecked_ref()));
+    req.set_onerror(Some(on_error.as_ref().unchecked_ref()));
+
+    let ok = done_rx.await.unwrap_or(false);
+    req.set_onsuccess(None);
+    req.set_onerror(None);
+    if ok {
+        Ok(())
+    } else {
+        Err(request_error(&req))
+    }
+}
+
+fn decode<T: DeserializeOwned>(value: &JsValue) -> Option<T> {
+    let json = String::from(js_sys::JSON::stringify(value).ok()?);
+    serde_json::from_str(&json)
+        .map_err(|e| log::warn!("history store: skipping bad entry: {:?}", e))
+        .ok()
+}
+
+/// Deletes the item with this id, including any write of it still waiting for its batch.
+pub async fn remove(id: &str) -> Result<(), JsValue> {
+    let key = JsValue::from_str(id);
+    PENDING.with(|p| {
+        p.borrow_mut()
+            .retain(|v| js_sys::Reflect::get(v, &"id".into()).ok().as_ref() != Some(&key))
+    });
+    let db = database().await?;
+    let tx = db.transaction_with_str_and_mode(STORE, IdbTransactionMode::Readwrite)?;
+    tx.object_store(STORE)?.delete(&key)?;
+    committed(&tx).await
+}
+
+/// Deletes every item with a `time` before `cutoff`, including any writes of them still
+/// waiting for their batch.
+pub async fn remove_older_than(cutoff: u64) -> Result<(), JsValue> {
+    let cutoff = cutoff as f64;
+    PENDING.with(|p| {
+        p.borrow_mut().retain(|v| {
+            let time = js_sys::Reflect::get(v, &"time".into()).ok();
+            time.and_then(|t| t.as_f64()).is_none_or(|t| t >= cutoff)
+        })
+    });
+    let db = database().await?;
+    let tx = db.transaction_with_str_and_mode(STORE, IdbTransactionMode::Readwrite)?;
+    let index = tx.object_store(STORE)?.index(TIME_INDEX)?;
+    let range = IdbKeyRange::upper_bound_with_open(&cutoff.into(), true)?;
+    let req = index.open_cursor_with_range(&range)?;
+
+    // Like paging, each step has to be taken inside the cursor's own success callback.
+    let on_success = {
+        let req = req.clone();
+        Closure::wrap(Box::new(move || {
+            let cursor = req
+                .result()
+                .ok()
+                .and_then(|r| r.dyn_into::<IdbCursorWithValue>().ok());
+            if let Some(cursor) = cursor {
+                if cursor.delete().is_ok() {
+                    let _ = cursor.continue_();
+                }
+            }
+        }) as Box<dyn FnMut()>)
+    };
+    req.set_onsuccess(Some(on_success.as_ref().unchecked_ref()));
+    let result = committed(&tx).await;
+    req.set_onsuccess(None);
+    result
+}
+
+/// Empties the store, including any writes still waiting for their batch.
+pub async fn clear() -> Result<(), JsValue> {
+    PENDING.with(|p| p.borrow_mut().clear());
+    let db = database().await?;
+    let tx = db.transaction_with_str_and_mode(STORE, IdbTransactionMode::Readwrite)?;
+    tx.object_store(STORE)?.clear()?;
+    committed(&tx).await
+}
+
+async fn write(batch: Vec<JsValue>) -> Result<(), JsValue> {
+    if batch.is_empty() {
+        return Ok(());
+    }
+    let db = database().await?;
+    let tx = db.transaction_with_str_and_mode(STORE, IdbTransactionMode::Readwrite)?;
+    let store = tx.object_store(STORE)?;
+    for value in &batch {
+        store.put(value)?;
+    }
+    committed(&tx).await
+}
+
+async fn database() -> Result<IdbDatabase, JsValue> {
+    if let Some(db) = DB.with(|db| db.borrow().clone()) {
+        return Ok(db);
+    }
+
+    let factory = web_sys::window()
+        .ok_or("no window")?
+        .indexed_db()?
+        .ok_or("IndexedDB is unavailable")?;
+    let req = factory.open_with_u32(DB_NAME, DB_VERSION)?;
+    let on_upgrade = {
+        let req = req.clone();
+        Closure::wrap(Box::new(move || {
+            if let Err(e) = create_schema(&req) {
+                log::error!("history store: upgrade failed: {:?}", e);
+            }
+        }) as Box<dyn FnMut()>)
+    };
+    req.set_onupgradeneeded(Some(on_upgrade.as_ref().unchecked_ref()));
+    let result = done(&req).await;
+    req.set_onupgradeneeded(None);
+
+    let db: IdbDatabase = result?.dyn_into()?;
+    DB.with(|d| *d.borrow_mut() = Some(db.clone()));
+    Ok(db)
+}
+
+fn create_schema(req: &IdbOpenDbRequest) -> Result<(), JsValue> {
+    let db: IdbDatabase = req.result()?.dyn_into()?;
+    if !db.object_store_names().contains(STORE) {
+        let mut params = IdbObjectStoreParameters::new();
+        params.key_path(Some(&"id".into()));
+        let store = db.create_object_store_with_optional_parameters(STORE, &params)?;
+        store.create_index_with_str(TIME_INDEX, "time")?;
+    }
+    Ok(())
+}
+
+type Handler = Closure<dyn FnMut()>;
+
+/// Handlers for the success and failure events of a request or transaction, and a
+/// receiver saying which one fired.
+fn outcome() -> (Handler, Handler, oneshot::Receiver<bool>) {
+    let (tx, rx) = oneshot::channel();
+    let tx = Rc::new(RefCell::new(Some(tx)));
+    let settle = |ok: bool| {
+        let tx = tx.clone();
+        Closure::wrap(Box::new(move || {
+            if let Some(tx) = tx.borrow_mut().take() {
+                let _ = tx.send(ok);
+            }
+        }) as Box<dyn FnMut()>)
+    };
+    (settle(true), settle(false), rx)
+}
+
+async fn done(req: &IdbRequest) -> Result<JsValue, JsValue> {
+    let (on_success, on_error, rx) = outcome();
+    req.set_onsuccess(Some(on_success.as_ref().unchecked_ref()));
+    req.set_onerror(Some(on_error.as_ref().unchecked_ref()));
+    let ok = rx.await.unwrap_or(false);
+    req.set_onsuccess(None);
+    req.set_onerror(None);
+    if ok {
+        req.result()
+    } else {
+        Err(request_error(req))
+    }
+}
+
+async fn committed(tx: &IdbTransaction) -> Result<(), JsValue> {
+    let (on_complete, on_error, rx) = outcome();
+    tx.set_oncomplete(Some(on_complete.as_ref().unchecked_ref()));
+    tx.set_onerror(Some(on_error.as_ref().unchecked_ref()));
+    tx.set_onabort(Some(on_error.as_ref().unchecked_ref()));
+    let ok = rx.await.unwrap_or(false);
+    tx.set_oncomplete(None);
+    tx.set_onerror(None);
+    tx.set_onabort(None);
+    if ok {
+        Ok(())
+    } else {
+        Err(tx
+            .error()
+            .map(JsValue::from)
+            .unwrap_or_else(|| "transaction aborted".into()))
+    }
+}
+
+fn request_error(req: &IdbRequest) -> JsValue {
+    req.error()
+        .ok()
+        .flatten()
+        .map(JsValue::from)
+        .unwrap_or_else(|| "request failed".into())
+}
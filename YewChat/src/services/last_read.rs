@@ -0,0 +1,18 @@
+//! The newest message read on this device, remembered by id in localStorage so the
+//! "New messages" divider knows where to go after a reload.
+
+use gloo_storage::{LocalStorage, Storage};
+
+const STORAGE_KEY: &str = "yewchat.last_read";
+
+/// The id last read here. Nothing saved, or something unreadable, is none: everything
+/// counts as seen, so a first visit shows no divider.
+pub fn load() -> Option<String> {
+    LocalStorage::get(STORAGE_KEY).ok()
+}
+
+pub fn save(id: &str) {
+    if let Err(e) = LocalStorage::set(STORAGE_KEY, id) {
+        log::error!("couldn't save the last read message: {:?}", e);
+    }
+}
@@ -0,0 +1,79 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use futures::future::{FutureExt, LocalBoxFuture, Shared};
+use reqwasm::http::Request;
+use serde::Deserialize;
+
+/// Browsers can't read arbitrary pages cross-origin, so OpenGraph metadata is fetched
+/// through an unfurl proxy. The target URL is appended to its endpoint, percent-encoded.
+/// This one is used unless `ChatProps::unfurl_endpoint` says otherwise.
+pub const DEFAULT_UNFURL_ENDPOINT: &str = "http://127.0.0.1:8081/unfurl?url=";
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct LinkPreview {
+    pub title: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Whatever the page declared, as the proxy relays it, so `LinkPreviewCard` checks it
+    /// like any other image link before showing it.
+    #[serde(default)]
+    pub image: Option<String>,
+}
+
+type PendingPreview = Shared<LocalBoxFuture<'static, Option<Rc<LinkPreview>>>>;
+
+thread_local! {
+    // Each link's lookup, shared by every card showing it, whether it's still on its way
+    // or done. Failed lookups end in `None` and stay, so a dead link isn't refetched on
+    // every render.
+    static CACHE: RefCell<HashMap<String, PendingPreview>> = RefCell::new(HashMap::new());
+}
+
+/// Returns the first http(s) URL in `text`, which is the only one we unfurl.
+pub fn first_url(text: &str) -> Option<&str> {
+    text.split_whitespace()
+        .find(|w| w.starts_with("https://") || w.starts_with("http://"))
+}
+
+/// The preview for `url`, asked of the proxy at `endpoint` unless it's cached. Results
+/// are cached by `url` alone, whichever proxy gave them.
+pub async fn fetch_preview(endpoint: &str, url: &str) -> Option<Rc<LinkPreview>> {
+    let lookup = CACHE.with(|c| {
+        c.borrow_mut()
+            .entry(url.to_string())
+            .or_insert_with(|| {
+                unfurl(endpoint.to_string(), url.to_string())
+                    .boxed_local()
+                    .shared()
+            })
+            .clone()
+    });
+    lookup.await
+}
+
+async fn unfurl(endpoint: String, url: String) -> Option<Rc<LinkPreview>> {
+    let endpoint = format!(
+        "{}{}",
+        endpoint,
+        String::from(js_sys::encode_uri_component(&url))
+    );
+    match Request::get(&endpoint).send().await {
+        Ok(resp) if resp.ok() => match resp.json::<LinkPreview>().await {
+            Ok(p) => Some(Rc::new(p)),
+            Err(e) => {
+                log::debug!("unfurl {}: {:?}", url, e);
+                None
+            }
+        },
+        Ok(resp) => {
+            log::debug!("unfurl {}: HTTP {}", url, resp.status());
+            None
+        }
+        Err(e) => {
+            log::debug!("unfurl {}: {:?}", url, e);
+            None
+        }
+    }
+}
@@ -1,2 +1,19 @@
-pub mod websocket;
+pub mod clipboard;
+pub mod coalesce;
+pub mod density;
+pub mod drafts;
 pub mod event_bus;
+pub mod export;
+pub mod gif;
+pub mod history_store;
+pub mod last_read;
+pub mod link_preview;
+pub mod mutes;
+pub mod notify;
+pub mod outbox;
+pub mod receipts;
+pub mod retention;
+pub mod sidebar;
+pub mod stars;
+pub mod upload;
+pub mod websocket;
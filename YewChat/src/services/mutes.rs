@@ -0,0 +1,19 @@
+//! Users muted on this device, remembered by name in localStorage. The server is never
+//! told: their messages still arrive, they just aren't shown.
+
+use std::collections::HashSet;
+
+use gloo_storage::{LocalStorage, Storage};
+
+const STORAGE_KEY: &str = "yewchat.muted";
+
+/// The names muted on this device. Nothing saved, or something unreadable, is nobody.
+pub fn load() -> HashSet<String> {
+    LocalStorage::get(STORAGE_KEY).unwrap_or_default()
+}
+
+pub fn save(muted: &HashSet<String>) {
+    if let Err(e) = LocalStorage::set(STORAGE_KEY, muted) {
+        log::error!("couldn't save muted users: {:?}", e);
+    }
+}
@@ -0,0 +1,95 @@
+//! Getting the user's attention for a new message: a short chime, and a desktop
+//! notification. Both quietly do nothing where the browser can't or won't.
+
+use std::cell::RefCell;
+
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{
+    AudioContext, Notification, NotificationOptions, NotificationPermission, OscillatorType,
+};
+
+thread_local! {
+    // One for the page: browsers limit how many may be open.
+    static AUDIO: RefCell<Option<AudioContext>> = const { RefCell::new(None) };
+}
+
+/// Plays a soft two-note chime.
+pub fn chime() {
+    AUDIO.with(|audio| {
+        let mut audio = audio.borrow_mut();
+        if audio.is_none() {
+            *audio = AudioContext::new().ok();
+        }
+        if let Some(audio) = audio.as_ref() {
+            if let Err(err) = play_chime(audio) {
+                log::debug!("couldn't play the chime: {:?}", err);
+            }
+        }
+    });
+}
+
+fn play_chime(audio: &AudioContext) -> Result<(), wasm_bindgen::JsValue> {
+    let start = audio.current_time();
+    for (i, frequency) in [880.0, 1320.0].into_iter().enumerate() {
+        let at = start + i as f64 * 0.12;
+        let oscillator = audio.create_oscillator()?;
+        oscillator.set_type(OscillatorType::Sine);
+        oscillator.frequency().set_value(frequency);
+        let gain = audio.create_gain()?;
+        gain.gain().set_value_at_time(0.15, at)?;
+        gain.gain()
+            .exponential_ramp_to_value_at_time(0.001, at + 0.3)?;
+        oscillator.connect_with_audio_node(&gain)?;
+        gain.connect_with_audio_node(&audio.destination())?;
+        oscillator.start_with_when(at)?;
+        oscillator.stop_with_when(at + 0.3)?;
+    }
+    Ok(())
+}
+
+fn supported() -> bool {
+    js_sys::Reflect::has(&gloo_utils::window(), &"Notification".into()).unwrap_or(false)
+}
+
+/// Whether desktop notifications may be shown.
+pub fn permitted() -> bool {
+    supported() && Notification::permission() == NotificationPermission::Granted
+}
+
+/// Asks for permission to show desktop notifications, if it hasn't been given, and
+/// returns whether it now has.
+pub async fn request_permission() -> bool {
+    if !supported() {
+        return false;
+    }
+    if Notification::permission() == NotificationPermission::Granted {
+        return true;
+    }
+    match Notification::request_permission() {
+        Ok(promise) => JsFuture::from(promise)
+            .await
+            .ok()
+            .and_then(|answer| answer.as_string())
+            .is_some_and(|answer| answer == "granted"),
+        Err(_) => false,
+    }
+}
+
+/// Shows a desktop notification, if they're permitted. Clicking it brings the tab back.
+pub fn show(title: &str, body: &str) {
+    if !permitted() {
+        return;
+    }
+    let mut options = NotificationOptions::new();
+    options.body(body).tag("yewchat-message");
+    match Notification::new_with_options(title, &options) {
+        Ok(notification) => {
+            let onclick = wasm_bindgen::closure::Closure::once_into_js(move || {
+                let _ = gloo_utils::window().focus();
+            });
+            notification.set_onclick(Some(onclick.unchecked_ref()));
+        }
+        Err(err) => log::debug!("couldn't show a notification: {:?}", err),
+    }
+}
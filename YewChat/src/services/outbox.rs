@@ -0,0 +1,28 @@
+//! Messages typed while offline, kept in localStorage until the server has them so a
+//! reload mid-disconnect doesn't lose them.
+
+use gloo_storage::{LocalStorage, Storage};
+
+use crate::protocol::{DeliveryStatus, MessageData};
+
+const STORAGE_KEY: &str = "yewchat.outbox";
+
+/// The messages still queued when the page was last open, in the order they were typed.
+pub fn load() -> Vec<MessageData> {
+    let queued: Vec<MessageData> = LocalStorage::get(STORAGE_KEY).unwrap_or_default();
+    queued
+        .into_iter()
+        .map(|m| MessageData {
+            status: DeliveryStatus::Pending,
+            ..m
+        })
+        .collect()
+}
+
+pub fn save(queued: &[&MessageData]) {
+    if queued.is_empty() {
+        LocalStorage::delete(STORAGE_KEY);
+    } else if let Err(e) = LocalStorage::set(STORAGE_KEY, queued) {
+        log::error!("couldn't save queued messages: {:?}", e);
+    }
+}
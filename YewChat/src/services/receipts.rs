@@ -0,0 +1,120 @@
+//! Read receipts: which messages have been on screen while we were looking, and which of
+//! those the server still has to hear about.
+
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::rc::Rc;
+
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::{Element, IntersectionObserver, IntersectionObserverEntry, IntersectionObserverInit};
+use yew::Callback;
+
+// How much of a message has to be on screen for it to count as seen.
+const VISIBLE_THRESHOLD: f64 = 0.5;
+
+/// Receipts waiting to go to the server. Each id is handed out by `take` at most once, so
+/// the observer and the focus handler can both report a message without it being sent
+/// twice.
+#[derive(Default)]
+pub struct ReceiptQueue {
+    sent: HashSet<String>,
+    pending: Vec<String>,
+}
+
+impl ReceiptQueue {
+    /// Queues a receipt for `id` unless one is already queued or sent. Returns whether it
+    /// was queued.
+    pub fn mark(&mut self, id: &str) -> bool {
+        if self.sent.contains(id) || self.pending.iter().any(|p| p == id) {
+            return false;
+        }
+        self.pending.push(id.to_string());
+        true
+    }
+
+    /// Everything queued, now counted as sent.
+    pub fn take(&mut self) -> Vec<String> {
+        let ids = std::mem::take(&mut self.pending);
+        self.sent.extend(ids.iter().cloned());
+        ids
+    }
+
+    /// Puts back receipts that `take` handed out but couldn't be delivered, so the next
+    /// flush tries them again.
+    pub fn restore(&mut self, ids: Vec<String>) {
+        for id in &ids {
+            self.sent.remove(id);
+        }
+        self.pending.splice(0..0, ids);
+    }
+}
+
+/// Tracks which message rows are on screen, by the `data-msg-id` attribute of the
+/// elements it's asked to watch.
+pub struct VisibleMessages {
+    observer: IntersectionObserver,
+    visible: Rc<RefCell<HashSet<String>>>,
+    _callback: Closure<dyn FnMut(js_sys::Array)>,
+}
+
+impl VisibleMessages {
+    /// `on_visible` is called whenever a watched message comes into view.
+    pub fn new(on_visible: Callback<()>) -> Result<Self, JsValue> {
+        let visible = Rc::new(RefCell::new(HashSet::new()));
+        let callback = {
+            let visible = visible.clone();
+            Closure::wrap(Box::new(move |entries: js_sys::Array| {
+                let mut appeared = false;
+                let mut visible = visible.borrow_mut();
+                for entry in entries.iter() {
+                    let entry: IntersectionObserverEntry = entry.unchecked_into();
+                    let id = match entry.target().get_attribute("data-msg-id") {
+                        Some(id) => id,
+                        None => continue,
+                    };
+                    if entry.is_intersecting() {
+                        appeared |= visible.insert(id);
+                    } else {
+                        visible.remove(&id);
+                    }
+                }
+                if appeared {
+                    on_visible.emit(());
+                }
+            }) as Box<dyn FnMut(js_sys::Array)>)
+        };
+        let mut options = IntersectionObserverInit::new();
+        options.threshold(&VISIBLE_THRESHOLD.into());
+        let observer =
+            IntersectionObserver::new_with_options(callback.as_ref().unchecked_ref(), &options)?;
+        Ok(Self {
+            observer,
+            visible,
+            _callback: callback,
+        })
+    }
+
+    /// Watches every element under `root` with a `data-msg-id`. Elements already watched
+    /// are unaffected, so this can run after every render.
+    pub fn watch_all(&self, root: &Element) {
+        if let Ok(rows) = root.query_selector_all("[data-msg-id]") {
+            for i in 0..rows.length() {
+                if let Some(row) = rows.get(i).and_then(|n| n.dyn_into::<Element>().ok()) {
+                    self.observer.observe(&row);
+                }
+            }
+        }
+    }
+
+    /// The ids of the watched messages on screen right now.
+    pub fn ids(&self) -> Vec<String> {
+        self.visible.borrow().iter().cloned().collect()
+    }
+}
+
+impl Drop for VisibleMessages {
+    fn drop(&mut self) {
+        self.observer.disconnect();
+    }
+}
@@ -0,0 +1,64 @@
+//! How long messages are kept on this device before they're cleared automatically,
+//! remembered in localStorage.
+
+use gloo_storage::{LocalStorage, Storage};
+use serde::{Deserialize, Serialize};
+
+use crate::i18n::I18n;
+
+const STORAGE_KEY: &str = "yewchat.retention";
+
+const HOUR_MS: u64 = 60 * 60 * 1000;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Retention {
+    #[default]
+    Forever,
+    Hour,
+    Day,
+    Week,
+}
+
+impl Retention {
+    pub const ALL: [Retention; 4] = [
+        Retention::Forever,
+        Retention::Hour,
+        Retention::Day,
+        Retention::Week,
+    ];
+
+    pub fn max_age_ms(self) -> Option<u64> {
+        match self {
+            Retention::Forever => None,
+            Retention::Hour => Some(HOUR_MS),
+            Retention::Day => Some(24 * HOUR_MS),
+            Retention::Week => Some(7 * 24 * HOUR_MS),
+        }
+    }
+
+    pub fn label(self, i18n: I18n) -> &'static str {
+        i18n.t(match self {
+            Retention::Forever => "retention_forever",
+            Retention::Hour => "retention_hour",
+            Retention::Day => "retention_day",
+            Retention::Week => "retention_week",
+        })
+    }
+
+    /// Messages sent before this time (ms since the epoch) are due to be cleared, or
+    /// `None` if everything is kept.
+    pub fn cutoff(self, now: u64) -> Option<u64> {
+        self.max_age_ms().map(|age| now.saturating_sub(age))
+    }
+}
+
+/// The setting saved on this device; nothing saved, or something unreadable, keeps everything.
+pub fn load() -> Retention {
+    LocalStorage::get(STORAGE_KEY).unwrap_or_default()
+}
+
+pub fn save(retention: Retention) {
+    if let Err(e) = LocalStorage::set(STORAGE_KEY, retention) {
+        log::error!("couldn't save retention setting: {:?}", e);
+    }
+}
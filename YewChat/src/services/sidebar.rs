@@ -0,0 +1,28 @@
+//! How wide the user sidebar is, remembered in localStorage.
+
+use gloo_storage::{LocalStorage, Storage};
+
+const STORAGE_KEY: &str = "yewchat.sidebar_width";
+
+/// The width in CSS pixels before it's been dragged, Tailwind's `w-64`.
+pub const DEFAULT_WIDTH: i32 = 256;
+/// Narrow enough to give the messages room, wide enough for an avatar and a short name.
+pub const MIN_WIDTH: i32 = 160;
+pub const MAX_WIDTH: i32 = 480;
+
+/// `width` brought within `MIN_WIDTH..=MAX_WIDTH`.
+pub fn clamp(width: i32) -> i32 {
+    width.clamp(MIN_WIDTH, MAX_WIDTH)
+}
+
+/// The width saved on this device, in bounds even if they've changed since; nothing
+/// saved, or something unreadable, is the default.
+pub fn load() -> i32 {
+    clamp(LocalStorage::get(STORAGE_KEY).unwrap_or(DEFAULT_WIDTH))
+}
+
+pub fn save(width: i32) {
+    if let Err(e) = LocalStorage::set(STORAGE_KEY, width) {
+        log::error!("couldn't save sidebar width: {:?}", e);
+    }
+}
@@ -0,0 +1,71 @@
+//! Starred messages, remembered by id in localStorage so they survive reloads and
+//! reconnects.
+
+use std::collections::HashSet;
+
+use gloo_storage::{LocalStorage, Storage};
+
+const STORAGE_KEY: &str = "yewchat.starred";
+
+/// The ids starred on this device. Nothing saved, or something unreadable, is no stars.
+pub fn load() -> HashSet<String> {
+    let saved = LocalStorage::raw().get_item(STORAGE_KEY).ok().flatten();
+    saved.map(|saved| decode(&saved)).unwrap_or_default()
+}
+
+pub fn save(starred: &HashSet<String>) {
+    if let Err(e) = LocalStorage::raw().set_item(STORAGE_KEY, &encode(starred)) {
+        log::error!("couldn't save starred messages: {:?}", e);
+    }
+}
+
+/// The stars as they're kept: a JSON list of ids, sorted so the same stars are always
+/// saved the same.
+fn encode(starred: &HashSet<String>) -> String {
+    let mut ids: Vec<&String> = starred.iter().collect();
+    ids.sort();
+    serde_json::to_string(&ids).expect("a list of strings to serialize")
+}
+
+/// What `encode` saved, as stars again.
+fn decode(saved: &str) -> HashSet<String> {
+    serde_json::from_str(saved).unwrap_or_default()
+}
+
+/// Stars `id`, or unstars it if it already was. Returns whether it's starred now.
+pub fn toggle(starred: &mut HashSet<String>, id: &str) -> bool {
+    if starred.remove(id) {
+        false
+    } else {
+        starred.insert(id.to_string());
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn toggled_stars_come_back_after_saving() {
+        let mut starred = HashSet::new();
+        assert!(toggle(&mut starred, "b"));
+        assert!(toggle(&mut starred, "a"));
+        assert!(toggle(&mut starred, "c"));
+        assert!(!toggle(&mut starred, "c"));
+        let saved = encode(&starred);
+        assert_eq!(saved, r#"["a","b"]"#);
+        assert_eq!(decode(&saved), starred);
+
+        assert!(!toggle(&mut starred, "a"));
+        assert!(!toggle(&mut starred, "b"));
+        assert!(decode(&encode(&starred)).is_empty());
+    }
+
+    #[test]
+    fn unreadable_stars_are_none() {
+        for saved in ["", "null", "{}", "[1]", r#"["a""#] {
+            assert!(decode(saved).is_empty(), "{:?}", saved);
+        }
+    }
+}
@@ -0,0 +1,214 @@
+//! Uploading files pasted or dropped into the chat. The file goes to an upload service as
+//! the `file` field of a multipart POST, and the service answers with JSON like
+//! `{"url": "https://…"}`: where the file can now be had. An image's link is sent as the
+//! message; anything else goes as an `Attachment`, with a `file_message` for its text.
+
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+use futures::channel::oneshot;
+use serde::Deserialize;
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::JsCast;
+use web_sys::{File, FormData, ProgressEvent, XmlHttpRequest};
+use yew::Callback;
+
+use crate::protocol::Attachment;
+use crate::sanitize::safe_url;
+
+/// Used unless `ChatProps::upload_endpoint` says otherwise.
+pub const DEFAULT_UPLOAD_ENDPOINT: &str = "http://127.0.0.1:8081/upload";
+/// Larger files are turned down before any of them is sent.
+pub const MAX_UPLOAD_BYTES: f64 = 5.0 * 1024.0 * 1024.0;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum UploadError {
+    /// The file's size, in bytes.
+    TooLarge(f64),
+    /// The service answered with this HTTP status.
+    Status(u16),
+    /// The service couldn't be reached.
+    Network,
+    /// The service's answer had no usable link in it.
+    BadResponse,
+}
+
+impl fmt::Display for UploadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UploadError::TooLarge(size) => write!(
+                f,
+                "too large ({}, the limit is {})",
+                human_size(*size as u64),
+                human_size(MAX_UPLOAD_BYTES as u64)
+            ),
+            UploadError::Status(status) => write!(f, "the upload service answered {}", status),
+            UploadError::Network => write!(f, "couldn't reach the upload service"),
+            UploadError::BadResponse => write!(f, "the upload service sent back no link"),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Uploaded {
+    url: String,
+}
+
+/// A size in bytes the way people write it: `512 B`, `1.5 KB`, `12.3 MB`, `2.0 GB`.
+pub fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    // Moves up a unit on what's shown, so 1023.99 KB reads as 1.0 MB, not 1024.0 KB.
+    while (size * 10.0).round() >= 10240.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// The text of a message sharing `attachment`, for whatever shows only the text: its
+/// name and size on the first line, and the link to it on the second.
+pub fn file_message(attachment: &Attachment) -> String {
+    let name = attachment.name.trim().replace(['\r', '\n'], " ");
+    format!(
+        "📎 {} ({})\n{}",
+        name,
+        human_size(attachment.size),
+        attachment.url
+    )
+}
+
+/// Reads the link to the uploaded file out of the service's answer.
+pub fn parse_response(body: &str) -> Result<String, UploadError> {
+    let uploaded: Uploaded = serde_json::from_str(body).map_err(|_| UploadError::BadResponse)?;
+    safe_url(uploaded.url.trim())
+        .map(str::to_string)
+        .ok_or(UploadError::BadResponse)
+}
+
+/// Uploads `file` to `endpoint`, reporting how much of it has gone, from 0 to 1, to
+/// `on_progress` along the way. Returns the link the service gave it.
+pub async fn upload(
+    endpoint: &str,
+    file: File,
+    on_progress: Callback<f64>,
+) -> Result<String, UploadError> {
+    if file.size() > MAX_UPLOAD_BYTES {
+        return Err(UploadError::TooLarge(file.size()));
+    }
+    // Only XMLHttpRequest reports progress on what's being sent.
+    let xhr = XmlHttpRequest::new().map_err(|_| UploadError::Network)?;
+    xhr.open_with_async("POST", endpoint, true)
+        .map_err(|_| UploadError::Network)?;
+    let form = FormData::new().map_err(|_| UploadError::Network)?;
+    form.append_with_blob_and_filename("file", &file, &file.name())
+        .map_err(|_| UploadError::Network)?;
+
+    let on_progress = Closure::wrap(Box::new(move |e: ProgressEvent| {
+        if e.length_computable() && e.total() > 0.0 {
+            on_progress.emit(e.loaded() / e.total());
+        }
+    }) as Box<dyn FnMut(ProgressEvent)>);
+    if let Ok(upload) = xhr.upload() {
+        upload.set_onprogress(Some(on_progress.as_ref().unchecked_ref()));
+    }
+
+    let (tx, rx) = oneshot::channel();
+    let tx = Rc::new(RefCell::new(Some(tx)));
+    let settle = |loaded: bool| {
+        let tx = tx.clone();
+        Closure::wrap(Box::new(move || {
+            if let Some(tx) = tx.borrow_mut().take() {
+                let _ = tx.send(loaded);
+            }
+        }) as Box<dyn FnMut()>)
+    };
+    let (on_load, on_error) = (settle(true), settle(false));
+    xhr.set_onload(Some(on_load.as_ref().unchecked_ref()));
+    xhr.set_onerror(Some(on_error.as_ref().unchecked_ref()));
+    xhr.set_onabort(Some(on_error.as_ref().unchecked_ref()));
+
+    xhr.send_with_opt_form_data(Some(&form))
+        .map_err(|_| UploadError::Network)?;
+    let loaded = rx.await.unwrap_or(false);
+    xhr.set_onload(None);
+    xhr.set_onerror(None);
+    xhr.set_onabort(None);
+    if !loaded {
+        return Err(UploadError::Network);
+    }
+
+    match xhr.status() {
+        Ok(status) if (200..300).contains(&status) => {
+            let body = xhr.response_text().ok().flatten().unwrap_or_default();
+            parse_response(&body)
+        }
+        Ok(status) => Err(UploadError::Status(status)),
+        Err(_) => Err(UploadError::Network),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes_are_written_in_the_largest_unit_that_fits() {
+        for (bytes, shown) in [
+            (0, "0 B"),
+            (1023, "1023 B"),
+            (1024, "1.0 KB"),
+            (1536, "1.5 KB"),
+            (1024 * 1024 - 1, "1.0 MB"),
+            (12_900_000, "12.3 MB"),
+            (5 * 1024 * 1024, "5.0 MB"),
+            (2 * 1024 * 1024 * 1024, "2.0 GB"),
+            (3 * 1024_u64.pow(4), "3.0 TB"),
+            (2048 * 1024_u64.pow(4), "2048.0 TB"),
+        ] {
+            assert_eq!(human_size(bytes), shown, "{}", bytes);
+        }
+    }
+
+    #[test]
+    fn a_file_message_names_sizes_and_links_the_file() {
+        let attachment = Attachment {
+            name: " notes\r\nfinal.txt ".into(),
+            size: 1536,
+            url: "https://files.example/abc".into(),
+        };
+        assert_eq!(
+            file_message(&attachment),
+            "📎 notes  final.txt (1.5 KB)\nhttps://files.example/abc"
+        );
+    }
+
+    #[test]
+    fn only_a_safe_link_is_taken_from_the_answer() {
+        assert_eq!(
+            parse_response(r#"{"url": " https://files.example/abc "}"#),
+            Ok("https://files.example/abc".into())
+        );
+        for body in [
+            r#"{"url": "javascript:alert(1)"}"#,
+            r#"{"link": "https://files.example/abc"}"#,
+            "not json",
+        ] {
+            assert_eq!(
+                parse_response(body),
+                Err(UploadError::BadResponse),
+                "{}",
+                body
+            );
+        }
+        assert_eq!(
+            UploadError::TooLarge(MAX_UPLOAD_BYTES * 2.0).to_string(),
+            "too large (10.0 MB, the limit is 5.0 MB)"
+        );
+    }
+}
@@ -1,53 +1,1282 @@
-use futures::{channel::mpsc::Sender, SinkExt, StreamExt};
-use reqwasm::websocket::{futures::WebSocket, Message};
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+use futures::channel::mpsc::{self, Receiver, Sender, UnboundedReceiver, UnboundedSender};
+use futures::channel::oneshot;
+use futures::future::LocalBoxFuture;
+use futures::{pin_mut, select, Future, FutureExt, StreamExt};
+use gloo_events::EventListener;
+use gloo_timers::future::TimeoutFuture;
+use serde::Deserialize;
 
 use wasm_bindgen_futures::spawn_local;
-use yew_agent::Dispatched;
+use yew::Callback;
+use yew_agent::{Bridge, Bridged, Dispatched, Dispatcher};
+
+use crate::services::event_bus::{BusEvent, EventBus, Request, Topic};
+use crate::store::{Slice, Store, StoreAction, StoreRequest, StoreUpdate};
+use crate::transport::{BrowserTransport, Transport, TransportError};
+
+pub const WS_URL: &str = "ws://127.0.0.1:8080";
+
+// Reconnect delays double after every failed attempt, up to the cap.
+const MIN_BACKOFF_MS: u32 = 500;
+const MAX_BACKOFF_MS: u32 = 30_000;
+// Failed attempts in a row before reconnecting is left to the user; about a minute of
+// backoff.
+pub const MAX_RECONNECT_ATTEMPTS: u32 = 8;
+
+/// Frames that can wait to be written before `WebsocketService::send` turns more away,
+/// unless `ServiceConfig::queue_capacity` says otherwise.
+pub const DEFAULT_QUEUE_CAPACITY: usize = 1000;
+
+// Number of round trips averaged into the reported latency, to smooth out jitter.
+const RTT_WINDOW: usize = 5;
+// Number of recent pongs the clock offset is picked from.
+const CLOCK_WINDOW: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConnState {
+    Connecting,
+    Open,
+    Closed,
+    /// Automatic reconnection gave up; only `WebsocketService::reconnect` tries again.
+    Stopped,
+    /// The browser can't make WebSocket connections, so none is tried again.
+    Unsupported,
+    /// The browser says it's offline; nothing is tried until it says it's back.
+    Offline,
+}
+
+/// How often to ping the server, and how long to wait for the pong before deciding a
+/// silently half-open connection is dead.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HeartbeatConfig {
+    pub interval_ms: u32,
+    /// Used instead of `interval_ms` while the tab is hidden, to spare the battery.
+    pub hidden_interval_ms: u32,
+    pub timeout_ms: u32,
+}
+
+impl Default for HeartbeatConfig {
+    fn default() -> Self {
+        Self {
+            interval_ms: 25_000,
+            hidden_interval_ms: 60_000,
+            timeout_ms: 10_000,
+        }
+    }
+}
+
+/// How a `WebsocketService` is set up, besides where it connects and whom it tells.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ServiceConfig {
+    pub heartbeat: HeartbeatConfig,
+    /// How many frames may wait to be written, at least one. Frames wait while the socket
+    /// is connecting or reconnecting, and while it's slower than what's sent, so this
+    /// bounds how much is held in memory then, and how long a burst can be. A larger
+    /// queue rides out longer outages at the cost of sending a backlog all at once when
+    /// the socket's back; a smaller one gives up sooner, leaving the frame with the
+    /// sender, whose own retry (`Chat`'s outbox) can try it again.
+    pub queue_capacity: usize,
+}
+
+impl Default for ServiceConfig {
+    fn default() -> Self {
+        Self {
+            heartbeat: HeartbeatConfig::default(),
+            queue_capacity: DEFAULT_QUEUE_CAPACITY,
+        }
+    }
+}
+
+/// Why `WebsocketService::send` didn't take a frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SendError {
+    /// The queue is at capacity. It empties as the socket writes, so it's worth trying
+    /// again shortly.
+    Full,
+    /// The service was closed, so nothing will be sent again.
+    Closed,
+}
+
+/// The frames waiting for the connection task to write them.
+struct Outgoing {
+    tx: RefCell<Sender<String>>,
+}
+
+impl Outgoing {
+    /// A queue holding `capacity` frames, and where the connection task reads them.
+    fn new(capacity: usize) -> (Self, Receiver<String>) {
+        // The channel holds one frame for every sender on top of its buffer, so a capacity
+        // of 1 is a channel with no buffer at all, which still holds that one frame. A
+        // capacity of 0 is taken as 1, as a queue that holds nothing couldn't send.
+        let (tx, rx) = mpsc::channel(capacity.max(1) - 1);
+        (
+            Self {
+                tx: RefCell::new(tx),
+            },
+            rx,
+        )
+    }
+
+    fn send(&self, frame: String) -> Result<(), SendError> {
+        self.tx.borrow_mut().try_send(frame).map_err(|e| {
+            if e.is_full() {
+                SendError::Full
+            } else {
+                SendError::Closed
+            }
+        })
+    }
+}
+
+enum Beat {
+    Ping,
+    Dead,
+    Wait(u32),
+}
+
+/// Estimates how far the server's clock is ahead of ours from pongs stamped with the
+/// server's time. Each sample assumes the server read its clock halfway through the round
+/// trip, which is least wrong for the quickest one, so the quickest recent sample wins.
+/// Every ping re-samples, which keeps correcting for drift.
+#[derive(Default)]
+struct ClockSync {
+    /// `(round trip, offset)` in milliseconds, oldest first.
+    samples: VecDeque<(f64, f64)>,
+}
+
+impl ClockSync {
+    /// Records a ping sent at `sent` and answered at `received`, both by our clock, that
+    /// the server stamped `server_time` by its own. Returns the best offset so far.
+    fn sample(&mut self, sent: f64, received: f64, server_time: f64) -> f64 {
+        if self.samples.len() == CLOCK_WINDOW {
+            self.samples.pop_front();
+        }
+        self.samples
+            .push_back((received - sent, server_time - (sent + received) / 2.0));
+        self.samples
+            .iter()
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+            .map_or(0.0, |&(_, offset)| offset)
+    }
+}
 
-use crate::services::event_bus::{EventBus, Request};
+/// What a pong carries.
+#[derive(Debug, PartialEq)]
+struct Pong {
+    /// The send time of the ping it answers, echoed back.
+    sent: Option<f64>,
+    /// When the server sent it, by the server's clock. Older servers leave it out.
+    server_time: Option<f64>,
+}
+
+/// What a pong told us.
+#[derive(Debug, PartialEq)]
+struct RoundTrip {
+    /// Smoothed round-trip time in milliseconds.
+    rtt: u32,
+    /// The server clock's lead on ours in milliseconds, if the pong carried its time.
+    clock_offset: Option<f64>,
+}
+
+/// Ping/pong bookkeeping for a single connection. The current time is always passed in,
+/// so none of this depends on the browser clock.
+struct Heartbeat {
+    config: HeartbeatConfig,
+    last_ping: f64,
+    awaiting_pong: bool,
+    rtts: VecDeque<f64>,
+    clock: ClockSync,
+}
+
+impl Heartbeat {
+    fn new(config: HeartbeatConfig, now: f64) -> Self {
+        Self {
+            config,
+            // Backdated so the first ping goes out right away, hidden or not, and latency
+            // shows up early.
+            last_ping: now - config.interval_ms.max(config.hidden_interval_ms) as f64,
+            awaiting_pong: false,
+            rtts: VecDeque::with_capacity(RTT_WINDOW),
+            clock: ClockSync::default(),
+        }
+    }
+
+    /// Decides what to do at `now`, with the tab `hidden` or not: send a ping, give up on
+    /// the connection, or wait.
+    fn poll(&mut self, now: f64, hidden: bool) -> Beat {
+        if self.awaiting_pong {
+            let deadline = self.last_ping + self.config.timeout_ms as f64;
+            if now >= deadline {
+                Beat::Dead
+            } else {
+                Beat::Wait((deadline - now).ceil() as u32)
+            }
+        } else {
+            let interval = if hidden {
+                self.config.hidden_interval_ms
+            } else {
+                self.config.interval_ms
+            };
+            let due = self.last_ping + interval as f64;
+            if now >= due {
+                self.last_ping = now;
+                self.awaiting_pong = true;
+                Beat::Ping
+            } else {
+                Beat::Wait((due - now).ceil() as u32)
+            }
+        }
+    }
+
+    /// Records a pong, which proves the connection is alive. If it echoes the send time
+    /// of the outstanding ping, returns what the round trip measured.
+    fn pong(&mut self, pong: &Pong, now: f64) -> Option<RoundTrip> {
+        let awaiting = std::mem::replace(&mut self.awaiting_pong, false);
+        let sent = match pong.sent {
+            Some(sent) if awaiting && sent == self.last_ping => sent,
+            _ => return None,
+        };
+
+        if self.rtts.len() == RTT_WINDOW {
+            self.rtts.pop_front();
+        }
+        self.rtts.push_back(now - sent);
+        let average = self.rtts.iter().sum::<f64>() / self.rtts.len() as f64;
+        Some(RoundTrip {
+            rtt: average.round() as u32,
+            clock_offset: pong
+                .server_time
+                .map(|server_time| self.clock.sample(sent, now, server_time)),
+        })
+    }
+}
+
+fn ping_frame(sent: f64) -> String {
+    format!(r#"{{"messageType":"ping","data":"{}"}}"#, sent)
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FrameKind {
+    message_type: String,
+    data: Option<String>,
+    time: Option<f64>,
+}
+
+/// Returns `Some` if `data` is a pong.
+fn parse_pong(data: &str) -> Option<Pong> {
+    match serde_json::from_str::<FrameKind>(data) {
+        Ok(FrameKind {
+            message_type,
+            data,
+            time,
+        }) if message_type == "pong" => Some(Pong {
+            sent: data.and_then(|d| d.parse().ok()),
+            server_time: time,
+        }),
+        _ => None,
+    }
+}
+
+/// Hands a frame to the event bus, or logs it if it can't be decoded.
+fn publish(event_bus: &mut Dispatcher<EventBus>, data: &str) {
+    match BusEvent::from_frame(data) {
+        Ok(event) => event_bus.send(Request::EventBusMsg(event)),
+        Err(e) => log::error!("ws: dropping frame: {}", e),
+    }
+}
+
+/// What a `Subscription` hears about the connection.
+#[derive(Clone, Debug)]
+pub enum WsEvent {
+    /// The connection's state, once as it is on subscribing and then on every change.
+    State(ConnState),
+    /// A frame from the server on one of the subscribed topics, decoded.
+    Frame(BusEvent),
+}
+
+/// A component's window on the connection, whoever owns the socket. Dropping it
+/// unsubscribes.
+pub struct Subscription {
+    _state: Box<dyn Bridge<Store>>,
+    _events: Option<Box<dyn Bridge<EventBus>>>,
+}
+
+/// What the service asks of the connection task.
+enum Control {
+    /// Connect now, unless connected.
+    Retry,
+    /// Drop the connection, and try nothing until the next `Retry`.
+    Offline,
+}
+
+/// Whether the tab is hidden, as the connection task reads it.
+#[derive(Clone)]
+struct Visibility {
+    hidden: Rc<Cell<bool>>,
+    // Wakes the heartbeat, so a shorter interval takes effect straight away.
+    wake: UnboundedSender<()>,
+}
+
+impl Visibility {
+    fn set_hidden(&self, hidden: bool) {
+        if self.hidden.replace(hidden) != hidden {
+            let _ = self.wake.unbounded_send(());
+        }
+    }
+}
+
+/// What the connection task hears from the service, besides frames to send.
+struct Controls {
+    control: UnboundedReceiver<Control>,
+    /// Read by the heartbeat; `wake` is nudged when it changes.
+    hidden: Rc<Cell<bool>>,
+    wake: UnboundedReceiver<()>,
+}
+
+/// Opens one connection attempt.
+type Connector = Box<dyn Fn() -> Result<Box<dyn Transport>, TransportError>>;
+
+/// Where the connection task tells the time and waits, so it can run on a clock other
+/// than the browser's.
+trait Clock {
+    /// Milliseconds since the epoch.
+    fn now(&self) -> f64;
+    fn sleep(&self, ms: u32) -> LocalBoxFuture<'static, ()>;
+}
+
+struct BrowserClock;
+
+impl Clock for BrowserClock {
+    fn now(&self) -> f64 {
+        js_sys::Date::now()
+    }
+
+    fn sleep(&self, ms: u32) -> LocalBoxFuture<'static, ()> {
+        TimeoutFuture::new(ms).boxed_local()
+    }
+}
+
+/// A clock that never moves, for tests that don't wait on one.
+#[cfg(test)]
+struct StoppedClock;
+
+#[cfg(test)]
+impl Clock for StoppedClock {
+    fn now(&self) -> f64 {
+        0.0
+    }
+
+    fn sleep(&self, _ms: u32) -> LocalBoxFuture<'static, ()> {
+        futures::future::pending().boxed_local()
+    }
+}
+
+/// How the connection task keeps time.
+struct Timing {
+    heartbeat: HeartbeatConfig,
+    clock: Box<dyn Clock>,
+}
 
 pub struct WebsocketService {
-    pub tx: Sender<String>,
+    outgoing: Outgoing,
+    control: UnboundedSender<Control>,
+    attempts: Rc<Cell<u32>>,
+    clock_offset: Rc<Cell<f64>>,
+    visibility: Visibility,
+    // The page's `visibilitychange`, for connections `connect` opened.
+    _visibility_listener: Option<EventListener>,
+    // Dropping this ends the connection task, so the socket doesn't outlive its owner.
+    shutdown: RefCell<Option<oneshot::Sender<()>>>,
+}
+
+/// Where the connection task reports to.
+struct Listeners {
+    on_state: Callback<ConnState>,
+    on_rtt: Callback<u32>,
+    on_frame: Callback<String>,
+    on_clock_offset: Callback<f64>,
 }
 
 impl WebsocketService {
-    pub fn new() -> Self {
-        let ws = WebSocket::open("ws://127.0.0.1:8080").unwrap();
+    /// Connects to `WS_URL`. Publishes every frame on the event bus, and the connection's
+    /// state in the store, for `subscribe` to pass on, as well as telling `on_state`.
+    /// `on_rtt` receives the smoothed ping round-trip time in milliseconds after every pong.
+    pub fn with_config(
+        on_state: Callback<ConnState>,
+        on_rtt: Callback<u32>,
+        config: ServiceConfig,
+    ) -> Self {
+        let event_bus = RefCell::new(EventBus::dispatcher());
+        let on_frame =
+            Callback::from(move |data: String| publish(&mut event_bus.borrow_mut(), &data));
+        let store = RefCell::new(Store::dispatcher());
+        let on_state = Callback::from(move |state| {
+            let action = StoreAction::Connection(state);
+            store.borrow_mut().send(StoreRequest::Dispatch(action));
+            on_state.emit(state);
+        });
+        Self::connect(WS_URL, on_state, on_rtt, on_frame, config)
+    }
+
+    /// Follows the connection opened by `with_config`, from any component:
+    /// `callback` gets its state, and the frames on `topics`. With no topics it gets the
+    /// state alone.
+    pub fn subscribe(topics: &[Topic], callback: Callback<WsEvent>) -> Subscription {
+        let on_update = callback.clone();
+        let state = Store::bridge_slice(
+            Slice::Connection,
+            Callback::from(move |update| {
+                if let StoreUpdate::Connection(state) = update {
+                    on_update.emit(WsEvent::State(state));
+                }
+            }),
+        );
+        let events = (!topics.is_empty()).then(|| {
+            let mut bridge = EventBus::bridge(callback.reform(WsEvent::Frame));
+            for topic in topics {
+                bridge.send(Request::Subscribe(topic.clone()));
+            }
+            bridge
+        });
+        Subscription {
+            _state: state,
+            _events: events,
+        }
+    }
+
+    /// Connects to `url` and hands every frame other than a pong to `on_frame` as it
+    /// arrives, undecoded, instead of publishing it on the event bus.
+    pub fn connect(
+        url: &str,
+        on_state: Callback<ConnState>,
+        on_rtt: Callback<u32>,
+        on_frame: Callback<String>,
+        config: ServiceConfig,
+    ) -> Self {
+        let url = url.to_string();
+        let simulate_unsupported = simulating_unsupported();
+        let connect = move || {
+            if simulate_unsupported {
+                return Err(TransportError::Unsupported(
+                    "simulated by ?no-websocket".to_string(),
+                ));
+            }
+            BrowserTransport::connect(&url).map(|t| Box::new(t) as Box<dyn Transport>)
+        };
+        let mut service = Self::with_transport(connect, on_state, on_rtt, on_frame, config);
+        // Pings slow down while the tab is hidden, and pick up again once it's shown.
+        let document = gloo_utils::document();
+        service.visibility.set_hidden(document.hidden());
+        let visibility = service.visibility.clone();
+        service._visibility_listener = Some(EventListener::new(
+            &document,
+            "visibilitychange",
+            move |_| visibility.set_hidden(gloo_utils::document().hidden()),
+        ));
+        service
+    }
+
+    /// Like `connect`, but each connection attempt, the first and every reconnect, comes
+    /// from calling `connect`.
+    pub fn with_transport(
+        connect: impl Fn() -> Result<Box<dyn Transport>, TransportError> + 'static,
+        on_state: Callback<ConnState>,
+        on_rtt: Callback<u32>,
+        on_frame: Callback<String>,
+        config: ServiceConfig,
+    ) -> Self {
+        let listeners = (on_state, on_rtt, on_frame);
+        let (service, task) = Self::with_clock(connect, listeners, config, Box::new(BrowserClock));
+        spawn_local(task);
+        service
+    }
+
+    /// The service, and the task that keeps its connection, timed by `clock`, for the
+    /// caller to run.
+    fn with_clock(
+        connect: impl Fn() -> Result<Box<dyn Transport>, TransportError> + 'static,
+        (on_state, on_rtt, on_frame): (Callback<ConnState>, Callback<u32>, Callback<String>),
+        config: ServiceConfig,
+        clock: Box<dyn Clock>,
+    ) -> (Self, impl Future<Output = ()>) {
+        let (outgoing, in_rx) = Outgoing::new(config.queue_capacity);
+        let (shutdown_tx, shutdown_rx) = oneshot::channel();
+        let (control_tx, control_rx) = mpsc::unbounded();
+        let (wake_tx, wake_rx) = mpsc::unbounded();
+        let attempts = Rc::new(Cell::new(0));
+        let clock_offset = Rc::new(Cell::new(0.0));
+        let visibility = Visibility {
+            hidden: Rc::new(Cell::new(false)),
+            wake: wake_tx,
+        };
+
+        let task = run(
+            Box::new(connect),
+            in_rx,
+            shutdown_rx,
+            Controls {
+                control: control_rx,
+                hidden: visibility.hidden.clone(),
+                wake: wake_rx,
+            },
+            attempts.clone(),
+            Listeners {
+                on_state,
+                on_rtt,
+                on_frame,
+                on_clock_offset: {
+                    let clock_offset = clock_offset.clone();
+                    Callback::from(move |offset| clock_offset.set(offset))
+                },
+            },
+            Timing {
+                heartbeat: config.heartbeat,
+                clock,
+            },
+        );
+
+        let service = Self {
+            outgoing,
+            control: control_tx,
+            attempts,
+            clock_offset,
+            visibility,
+            _visibility_listener: None,
+            shutdown: RefCell::new(Some(shutdown_tx)),
+        };
+        (service, task)
+    }
+
+    /// Like `with_transport`, but on a clock that never moves, and with the connection
+    /// task handed back for the test to run instead of spawned.
+    #[cfg(test)]
+    pub(crate) fn unspawned(
+        connect: impl Fn() -> Result<Box<dyn Transport>, TransportError> + 'static,
+        on_state: Callback<ConnState>,
+    ) -> (Self, impl Future<Output = ()>) {
+        let listeners = (on_state, Callback::noop(), Callback::noop());
+        Self::with_clock(
+            connect,
+            listeners,
+            ServiceConfig::default(),
+            Box::new(StoppedClock),
+        )
+    }
+
+    /// Queues `frame` to be written to the socket: now if it's open, or once it's back
+    /// if it's connecting or reconnecting.
+    pub fn send(&self, frame: String) -> Result<(), SendError> {
+        self.outgoing.send(frame)
+    }
+
+    /// Tries to connect again right away with a fresh backoff sequence, whether
+    /// reconnecting gave up, is waiting out a delay, or is waiting for the network. Does
+    /// nothing while connected.
+    pub fn reconnect(&self) {
+        self.attempts.set(0);
+        let _ = self.control.unbounded_send(Control::Retry);
+    }
+
+    /// Passes on what the browser says about the network. Going offline closes the
+    /// socket and stops reconnecting, in `ConnState::Offline`; coming back online
+    /// reconnects at once, as `reconnect` does, rather than waiting out the backoff.
+    pub fn set_online(&self, online: bool) {
+        if online {
+            self.reconnect();
+        } else {
+            let _ = self.control.unbounded_send(Control::Offline);
+        }
+    }
+
+    /// Closes the connection for good, as dropping the service would: nothing is sent or
+    /// received afterwards, and neither reconnecting nor `reconnect` opens another.
+    pub fn close(&self) {
+        self.shutdown.borrow_mut().take();
+    }
+
+    /// Failed connection attempts since the last successful one or `reconnect`.
+    pub fn attempts(&self) -> u32 {
+        self.attempts.get()
+    }
 
-        let (mut write, mut read) = ws.split();
+    /// The time by the server's clock, in milliseconds since the epoch, so what we stamp
+    /// and compare against lines up with what the server stamps. Until the first pong
+    /// that carries the server's time this is our own clock; afterwards it's the last
+    /// estimate, across reconnects.
+    pub fn server_now(&self) -> f64 {
+        js_sys::Date::now() + self.clock_offset.get()
+    }
+}
 
-        let (in_tx, mut in_rx) = futures::channel::mpsc::channel::<String>(1000);
-        let mut event_bus = EventBus::dispatcher();
+/// Debug builds opened with `?no-websocket` in the URL act as if the browser had no
+/// WebSocket, to try out how the app copes without a connection.
+fn simulating_unsupported() -> bool {
+    cfg!(debug_assertions)
+        && web_sys::window()
+            .and_then(|w| w.location().search().ok())
+            .is_some_and(|query| {
+                query
+                    .trim_start_matches('?')
+                    .split('&')
+                    .any(|pair| pair == "no-websocket")
+            })
+}
 
-        spawn_local(async move {
-            while let Some(s) = in_rx.next().await {
-                log::debug!("got event from channel! {}", s);
-                write.send(Message::Text(s)).await.unwrap();
+/// What a transport reported, in the order it happened.
+enum Event {
+    Open,
+    Frame(String),
+    Closed,
+}
+
+/// Feeds everything `transport` reports into one stream.
+fn events(transport: &dyn Transport) -> UnboundedReceiver<Event> {
+    let (tx, rx) = mpsc::unbounded();
+    let (on_open, on_close) = (tx.clone(), tx.clone());
+    transport.on_open(Callback::from(move |_| {
+        let _ = on_open.unbounded_send(Event::Open);
+    }));
+    transport.on_message(Callback::from(move |frame| {
+        let _ = tx.unbounded_send(Event::Frame(frame));
+    }));
+    transport.on_close(Callback::from(move |_| {
+        let _ = on_close.unbounded_send(Event::Closed);
+    }));
+    rx
+}
+
+/// Keeps a connection open for as long as the service is alive, reconnecting with
+/// exponential backoff whenever the socket closes or fails to open, until
+/// `MAX_RECONNECT_ATTEMPTS` fail in a row. Then it waits for a `Control::Retry`. While
+/// the browser is offline it waits for one without trying.
+async fn run(
+    connect: Connector,
+    mut in_rx: Receiver<String>,
+    shutdown: oneshot::Receiver<()>,
+    controls: Controls,
+    attempts: Rc<Cell<u32>>,
+    listeners: Listeners,
+    timing: Timing,
+) {
+    let Controls {
+        mut control,
+        hidden,
+        mut wake,
+    } = controls;
+    let Listeners {
+        on_state,
+        on_rtt,
+        on_frame,
+        on_clock_offset,
+    } = listeners;
+    let Timing {
+        heartbeat: heartbeat_config,
+        clock,
+    } = timing;
+    let mut shutdown = shutdown.fuse();
+    let mut backoff = MIN_BACKOFF_MS;
+    let mut offline = false;
+
+    loop {
+        if offline {
+            on_state.emit(ConnState::Offline);
+            loop {
+                select! {
+                    c = control.next() => match c {
+                        Some(Control::Retry) => break,
+                        Some(Control::Offline) => {}
+                        None => return,
+                    },
+                    _ = shutdown => return,
+                }
             }
-        });
+            offline = false;
+            backoff = MIN_BACKOFF_MS;
+        }
+        on_state.emit(ConnState::Connecting);
 
-        spawn_local(async move {
-            while let Some(msg) = read.next().await {
-                match msg {
-                    Ok(Message::Text(data)) => {
-                        log::debug!("from websocket: {}", data);
-                        event_bus.send(Request::EventBusMsg(data));
+        match connect() {
+            Ok(transport) => {
+                let mut events = events(transport.as_ref());
+
+                let opened = loop {
+                    select! {
+                        event = events.next() => match event {
+                            Some(Event::Open) => break true,
+                            Some(Event::Closed) | None => break false,
+                            Some(Event::Frame(_)) => {}
+                        },
+                        c = control.next() => match c {
+                            Some(Control::Offline) => {
+                                offline = true;
+                                break false;
+                            }
+                            // Already under way.
+                            Some(Control::Retry) => {}
+                            None => return,
+                        },
+                        _ = shutdown => return,
+                    }
+                };
+
+                if opened {
+                    on_state.emit(ConnState::Open);
+                    backoff = MIN_BACKOFF_MS;
+                    attempts.set(0);
+
+                    let heartbeat = RefCell::new(Heartbeat::new(heartbeat_config, clock.now()));
+
+                    // Resolves to `true` once the service is dropped, or `false` if the
+                    // heartbeat gave up on the connection.
+                    let writer = async {
+                        loop {
+                            let now = clock.now();
+                            let beat = heartbeat.borrow_mut().poll(now, hidden.get());
+                            let wait = match beat {
+                                Beat::Ping => {
+                                    if let Err(e) = transport.send(&ping_frame(now)) {
+                                        log::error!("ws: {}", e)
+                                    }
+                                    continue;
+                                }
+                                Beat::Dead => {
+                                    log::warn!(
+                                        "no pong within {}ms, reconnecting",
+                                        heartbeat_config.timeout_ms
+                                    );
+                                    return false;
+                                }
+                                Beat::Wait(ms) => ms,
+                            };
+
+                            select! {
+                                s = in_rx.next() => match s {
+                                    Some(s) => {
+                                        log::debug!("got event from channel! {}", s);
+                                        if let Err(e) = transport.send(&s) {
+                                            log::error!("ws: {}", e)
+                                        }
+                                    }
+                                    None => return true,
+                                },
+                                _ = clock.sleep(wait).fuse() => {},
+                                _ = wake.next() => {},
+                            }
+                        }
                     }
-                    Ok(Message::Bytes(b)) => {
-                        let decoded = std::str::from_utf8(&b);
-                        if let Ok(val) = decoded {
-                            log::debug!("from websocket: {}", val);
-                            event_bus.send(Request::EventBusMsg(val.into()));
+                    .fuse();
+
+                    let reader = async {
+                        while let Some(event) = events.next().await {
+                            let data = match event {
+                                Event::Frame(data) => data,
+                                Event::Closed => break,
+                                Event::Open => continue,
+                            };
+                            if let Some(pong) = parse_pong(&data) {
+                                let round_trip = heartbeat.borrow_mut().pong(&pong, clock.now());
+                                if let Some(round_trip) = round_trip {
+                                    on_rtt.emit(round_trip.rtt);
+                                    if let Some(offset) = round_trip.clock_offset {
+                                        on_clock_offset.emit(offset);
+                                    }
+                                }
+                                continue;
+                            }
+                            log::debug!("from websocket: {}", data);
+                            on_frame.emit(data);
+                        }
+                        log::debug!("WebSocket Closed");
+                    }
+                    .fuse();
+
+                    pin_mut!(writer, reader);
+                    loop {
+                        select! {
+                            owner_gone = writer => if owner_gone { return } else { break },
+                            _ = reader => break,
+                            c = control.next() => match c {
+                                Some(Control::Offline) => {
+                                    offline = true;
+                                    break;
+                                }
+                                Some(Control::Retry) => {}
+                                None => return,
+                            },
+                            _ = shutdown => return,
                         }
                     }
-                    Err(e) => {
-                        log::error!("ws: {:?}", e)
+                }
+            }
+            Err(TransportError::Unsupported(reason)) => {
+                log::error!("ws: unsupported, not retrying: {}", reason);
+                on_state.emit(ConnState::Unsupported);
+                // Nothing will change that while the page is open.
+                let _ = shutdown.await;
+                return;
+            }
+            Err(e) => {
+                log::error!("ws: {}", e)
+            }
+        }
+
+        if offline {
+            // Dropped along with the transport; that's no failed attempt.
+            on_state.emit(ConnState::Closed);
+            continue;
+        }
+        attempts.set(attempts.get() + 1);
+        on_state.emit(ConnState::Closed);
+
+        let retried = if attempts.get() >= MAX_RECONNECT_ATTEMPTS {
+            log::warn!("ws: giving up after {} attempts", attempts.get());
+            on_state.emit(ConnState::Stopped);
+            select! {
+                c = control.next() => match c {
+                    Some(c) => {
+                        offline = matches!(c, Control::Offline);
+                        true
+                    }
+                    None => return,
+                },
+                _ = shutdown => return,
+            }
+        } else {
+            select! {
+                _ = clock.sleep(backoff).fuse() => false,
+                c = control.next() => match c {
+                    Some(c) => {
+                        offline = matches!(c, Control::Offline);
+                        true
                     }
+                    None => return,
+                },
+                _ = shutdown => return,
+            }
+        };
+        backoff = if retried {
+            MIN_BACKOFF_MS
+        } else {
+            (backoff * 2).min(MAX_BACKOFF_MS)
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::LocalPool;
+    use futures::task::LocalSpawnExt;
+
+    use crate::transport::MockTransport;
+
+    const START: f64 = 1_000_000.0;
+    const REGISTER: &str = r#"{"messageType":"register","dataArray":null,"data":"ann"}"#;
+
+    /// Time that only passes when a test says so.
+    #[derive(Clone, Default)]
+    struct ManualClock(Rc<ClockState>);
+
+    #[derive(Default)]
+    struct ClockState {
+        now: Cell<f64>,
+        sleepers: RefCell<Vec<(f64, oneshot::Sender<()>)>>,
+    }
+
+    impl ManualClock {
+        /// Moves time on by `ms`, waking whatever was sleeping until then.
+        fn advance(&self, ms: u32) {
+            let now = self.0.now.get() + ms as f64;
+            self.0.now.set(now);
+            let sleepers = std::mem::take(&mut *self.0.sleepers.borrow_mut());
+            for (until, wake) in sleepers {
+                if until <= now {
+                    let _ = wake.send(());
+                } else {
+                    self.0.sleepers.borrow_mut().push((until, wake));
                 }
             }
-            log::debug!("WebSocket Closed");
+        }
+    }
+
+    impl Clock for ManualClock {
+        fn now(&self) -> f64 {
+            self.0.now.get()
+        }
+
+        fn sleep(&self, ms: u32) -> LocalBoxFuture<'static, ()> {
+            let (wake, woken) = oneshot::channel();
+            let until = self.0.now.get() + ms as f64;
+            self.0.sleepers.borrow_mut().push((until, wake));
+            woken.map(|_| ()).boxed_local()
+        }
+    }
+
+    /// A service on mock connections and a manual clock, with everything it reports.
+    struct Harness {
+        pool: LocalPool,
+        clock: ManualClock,
+        service: WebsocketService,
+        /// Every connection the service opened, oldest first.
+        transports: Rc<RefCell<Vec<MockTransport>>>,
+        states: Rc<RefCell<Vec<ConnState>>>,
+        rtts: Rc<RefCell<Vec<u32>>>,
+        frames: Rc<RefCell<Vec<String>>>,
+    }
+
+    fn record<T: 'static>(into: &Rc<RefCell<Vec<T>>>) -> Callback<T> {
+        let into = into.clone();
+        Callback::from(move |t| into.borrow_mut().push(t))
+    }
+
+    impl Harness {
+        fn new(config: ServiceConfig) -> Self {
+            Self::connecting(config, Ok)
+        }
+
+        /// Like `new`, but each connection goes through `open` first, which can fail it.
+        fn connecting(
+            config: ServiceConfig,
+            open: impl Fn(MockTransport) -> Result<MockTransport, TransportError> + 'static,
+        ) -> Self {
+            let clock = ManualClock::default();
+            clock.0.now.set(START);
+            let transports = Rc::new(RefCell::new(Vec::new()));
+            let (states, rtts, frames) = Default::default();
+            let connect = {
+                let transports = transports.clone();
+                move || {
+                    let transport = MockTransport::default();
+                    transports.borrow_mut().push(transport.clone());
+                    open(transport).map(|t| Box::new(t) as Box<dyn Transport>)
+                }
+            };
+            let listeners = (record(&states), record(&rtts), record(&frames));
+            let (service, task) =
+                WebsocketService::with_clock(connect, listeners, config, Box::new(clock.clone()));
+            let pool = LocalPool::new();
+            pool.spawner().spawn_local(task).unwrap();
+            let mut harness = Self {
+                pool,
+                clock,
+                service,
+                transports,
+                states,
+                rtts,
+                frames,
+            };
+            harness.settle();
+            harness
+        }
+
+        /// Runs the connection task until it waits on something.
+        fn settle(&mut self) {
+            self.pool.run_until_stalled();
+        }
+
+        fn advance(&mut self, ms: u32) {
+            self.clock.advance(ms);
+            self.settle();
+        }
+
+        /// The latest connection.
+        fn transport(&self) -> MockTransport {
+            self.transports.borrow().last().cloned().unwrap()
+        }
+
+        fn open(&mut self) {
+            self.transport().open();
+            self.settle();
+        }
+
+        /// What's been sent on the latest connection, besides pings.
+        fn sent(&self) -> Vec<String> {
+            let sent = self.transport().sent();
+            sent.into_iter()
+                .filter(|f| parse_ping(f).is_none())
+                .collect()
+        }
+
+        /// When the latest connection's pings were sent, oldest first.
+        fn pings(&self) -> Vec<f64> {
+            let sent = self.transport().sent();
+            sent.iter().filter_map(|f| parse_ping(f)).collect()
+        }
+    }
+
+    fn parse_ping(frame: &str) -> Option<f64> {
+        let kind: FrameKind = serde_json::from_str(frame).ok()?;
+        (kind.message_type == "ping").then(|| kind.data?.parse().ok())?
+    }
+
+    fn pong(sent: f64, server_time: Option<f64>) -> String {
+        match server_time {
+            Some(time) => format!(
+                r#"{{"messageType":"pong","data":"{}","time":{}}}"#,
+                sent, time
+            ),
+            None => format!(r#"{{"messageType":"pong","data":"{}"}}"#, sent),
+        }
+    }
+
+    #[test]
+    fn frames_sent_while_connecting_go_out_once_open() {
+        let mut h = Harness::new(ServiceConfig::default());
+        assert_eq!(*h.states.borrow(), [ConnState::Connecting]);
+
+        h.service.send(REGISTER.to_string()).unwrap();
+        h.service.send("hello".to_string()).unwrap();
+        h.settle();
+        assert!(h.transport().sent().is_empty());
+
+        h.open();
+        assert_eq!(*h.states.borrow(), [ConnState::Connecting, ConnState::Open]);
+        assert_eq!(h.sent(), [REGISTER, "hello"]);
+    }
+
+    #[test]
+    fn frames_sent_while_open_go_out_in_order() {
+        let mut h = Harness::new(ServiceConfig::default());
+        h.open();
+        for frame in [REGISTER, "one", "two"] {
+            h.service.send(frame.to_string()).unwrap();
+        }
+        h.settle();
+        assert_eq!(h.sent(), [REGISTER, "one", "two"]);
+    }
+
+    #[test]
+    fn received_frames_are_handed_on() {
+        let mut h = Harness::new(ServiceConfig::default());
+        h.open();
+        h.transport().receive("first");
+        h.transport().receive("second");
+        h.settle();
+        assert_eq!(*h.frames.borrow(), ["first", "second"]);
+    }
+
+    #[test]
+    fn pongs_report_the_round_trip_instead() {
+        let mut h = Harness::new(ServiceConfig::default());
+        h.open();
+        assert_eq!(h.pings(), [START]);
+
+        h.clock.advance(40);
+        h.transport().receive(&pong(START, Some(START + 5_000.0)));
+        h.settle();
+        assert!(h.frames.borrow().is_empty());
+        assert_eq!(*h.rtts.borrow(), [40]);
+        // The server's clock read halfway through the round trip, 20ms in.
+        assert_eq!(h.service.clock_offset.get(), 4_980.0);
+    }
+
+    #[test]
+    fn closing_the_service_stops_the_connection() {
+        let mut h = Harness::new(ServiceConfig::default());
+        h.open();
+        h.service.close();
+        h.settle();
+        h.transport().close();
+        h.advance(MAX_BACKOFF_MS);
+        assert_eq!(h.transports.borrow().len(), 1);
+        assert_eq!(h.service.send("late".to_string()), Err(SendError::Closed));
+    }
+
+    const HEARTBEAT: HeartbeatConfig = HeartbeatConfig {
+        interval_ms: 1_000,
+        hidden_interval_ms: 4_000,
+        timeout_ms: 500,
+    };
+
+    #[test]
+    fn the_heartbeat_pings_at_once_then_every_interval() {
+        let mut beat = Heartbeat::new(HEARTBEAT, START);
+        assert!(matches!(beat.poll(START, false), Beat::Ping));
+        assert!(matches!(beat.poll(START, false), Beat::Wait(500)));
+        beat.pong(
+            &Pong {
+                sent: Some(START),
+                server_time: None,
+            },
+            START + 100.0,
+        );
+        assert!(matches!(beat.poll(START + 100.0, false), Beat::Wait(900)));
+        assert!(matches!(beat.poll(START + 100.0, true), Beat::Wait(3_900)));
+        assert!(matches!(beat.poll(START + 1_000.0, false), Beat::Ping));
+    }
+
+    #[test]
+    fn the_heartbeat_gives_up_once_the_pong_is_overdue() {
+        let mut beat = Heartbeat::new(HEARTBEAT, START);
+        beat.poll(START, false);
+        assert!(matches!(beat.poll(START + 499.0, false), Beat::Wait(1)));
+        assert!(matches!(beat.poll(START + 500.0, false), Beat::Dead));
+    }
+
+    #[test]
+    fn pongs_to_other_pings_measure_nothing() {
+        let mut beat = Heartbeat::new(HEARTBEAT, START);
+        beat.poll(START, false);
+        let stale = Pong {
+            sent: Some(START - 1.0),
+            server_time: None,
+        };
+        assert_eq!(beat.pong(&stale, START + 10.0), None);
+        let unsolicited = Pong {
+            sent: Some(START),
+            server_time: None,
+        };
+        assert_eq!(beat.pong(&unsolicited, START + 10.0), None);
+    }
+
+    #[test]
+    fn a_missed_pong_reconnects() {
+        let mut h = Harness::new(ServiceConfig {
+            heartbeat: HEARTBEAT,
+            ..ServiceConfig::default()
+        });
+        h.open();
+        assert_eq!(h.pings(), [START]);
+
+        h.advance(HEARTBEAT.timeout_ms - 1);
+        assert_eq!(h.states.borrow().last(), Some(&ConnState::Open));
+        h.advance(1);
+        assert_eq!(h.states.borrow().last(), Some(&ConnState::Closed));
+        assert_eq!(h.service.attempts(), 1);
+
+        h.advance(MIN_BACKOFF_MS);
+        assert_eq!(h.transports.borrow().len(), 2);
+        assert_eq!(h.states.borrow().last(), Some(&ConnState::Connecting));
+        h.open();
+        assert_eq!(h.service.attempts(), 0);
+        assert_eq!(h.pings(), [h.clock.now()]);
+    }
+
+    #[test]
+    fn a_pong_in_time_keeps_the_connection() {
+        let mut h = Harness::new(ServiceConfig {
+            heartbeat: HEARTBEAT,
+            ..ServiceConfig::default()
+        });
+        h.open();
+        h.advance(HEARTBEAT.timeout_ms - 1);
+        h.transport().receive(&pong(START, None));
+        h.settle();
+        h.advance(HEARTBEAT.interval_ms - HEARTBEAT.timeout_ms + 1);
+        assert_eq!(h.states.borrow().last(), Some(&ConnState::Open));
+        assert_eq!(h.pings(), [START, START + HEARTBEAT.interval_ms as f64]);
+        assert_eq!(h.transports.borrow().len(), 1);
+    }
+
+    fn with_capacity(queue_capacity: usize) -> Harness {
+        Harness::new(ServiceConfig {
+            queue_capacity,
+            ..ServiceConfig::default()
+        })
+    }
+
+    /// Queues frames until the service turns one away, and returns how many it took.
+    fn fill(service: &WebsocketService) -> usize {
+        (0..)
+            .find(|i| match service.send(format!("frame {}", i)) {
+                Ok(()) => false,
+                Err(e) => {
+                    assert_eq!(e, SendError::Full);
+                    true
+                }
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn the_queue_holds_its_capacity_until_the_socket_opens() {
+        for capacity in [1, 2, 10, DEFAULT_QUEUE_CAPACITY] {
+            let h = with_capacity(capacity);
+            assert_eq!(fill(&h.service), capacity, "capacity {}", capacity);
+        }
+    }
+
+    #[test]
+    fn a_capacity_of_zero_still_holds_one_frame() {
+        let mut h = with_capacity(0);
+        assert_eq!(fill(&h.service), 1);
+        h.open();
+        assert_eq!(h.sent(), ["frame 0"]);
+    }
+
+    #[test]
+    fn a_full_queue_takes_frames_again_once_written() {
+        let mut h = with_capacity(3);
+        assert_eq!(fill(&h.service), 3);
+        h.open();
+        assert_eq!(h.sent(), ["frame 0", "frame 1", "frame 2"]);
+        assert_eq!(fill(&h.service), 3);
+    }
+
+    #[test]
+    fn a_closed_service_turns_frames_away_as_closed() {
+        for capacity in [0, 1, 5] {
+            let mut h = with_capacity(capacity);
+            h.service.close();
+            h.settle();
+            assert_eq!(h.service.send("late".to_string()), Err(SendError::Closed));
+        }
+    }
+
+    #[test]
+    fn a_dropped_connection_is_reopened_after_a_backoff() {
+        let mut h = Harness::new(ServiceConfig::default());
+        h.open();
+        h.transport().close();
+        h.settle();
+        assert_eq!(h.states.borrow().last(), Some(&ConnState::Closed));
+        assert_eq!(h.transports.borrow().len(), 1);
+
+        h.advance(MIN_BACKOFF_MS);
+        assert_eq!(h.transports.borrow().len(), 2);
+        h.service.send("again".to_string()).unwrap();
+        h.open();
+        assert_eq!(h.sent(), ["again"]);
+    }
+
+    #[test]
+    fn going_offline_waits_for_the_network_then_reconnects_at_once() {
+        let mut h = Harness::new(ServiceConfig::default());
+        h.open();
+        h.service.set_online(false);
+        h.settle();
+        assert_eq!(h.states.borrow().last(), Some(&ConnState::Offline));
+        assert_eq!(h.service.attempts(), 0);
+
+        h.advance(MAX_BACKOFF_MS);
+        assert_eq!(h.transports.borrow().len(), 1);
+        assert_eq!(h.states.borrow().last(), Some(&ConnState::Offline));
+
+        h.service.set_online(true);
+        h.settle();
+        assert_eq!(h.transports.borrow().len(), 2);
+        assert_eq!(h.states.borrow().last(), Some(&ConnState::Connecting));
+    }
+
+    #[test]
+    fn an_unsupported_browser_is_reported_once_and_never_retried() {
+        let mut h = Harness::connecting(ServiceConfig::default(), |_| {
+            Err(TransportError::Unsupported("no WebSocket".to_string()))
         });
+        assert_eq!(
+            *h.states.borrow(),
+            [ConnState::Connecting, ConnState::Unsupported]
+        );
 
-        Self { tx: in_tx }
+        h.advance(MAX_BACKOFF_MS);
+        h.service.reconnect();
+        h.settle();
+        assert_eq!(h.transports.borrow().len(), 1);
+        assert_eq!(h.states.borrow().len(), 2);
     }
 }
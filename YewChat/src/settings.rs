@@ -0,0 +1,240 @@
+//! The user's preferences, kept together in localStorage and handed down from `Main` as
+//! a `SettingsContext`, so every component reads the same copy and sees a change as
+//! soon as it's made. `Settings::from_value` is what reading them back comes down to,
+//! and runs without a browser.
+
+use gloo_storage::{LocalStorage, Storage};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use web_sys::MediaQueryList;
+use yew::Callback;
+
+use crate::i18n::I18n;
+
+const STORAGE_KEY: &str = "yewchat.settings";
+
+/// The range the idle threshold may be set in, in minutes.
+pub const MIN_IDLE_MINUTES: u32 = 1;
+pub const MAX_IDLE_MINUTES: u32 = 120;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    /// Whichever the operating system is set to, following it when it changes.
+    #[default]
+    System,
+    Light,
+    Dark,
+}
+
+impl Theme {
+    pub const ALL: [Theme; 3] = [Theme::System, Theme::Light, Theme::Dark];
+
+    pub fn label(self, i18n: I18n) -> &'static str {
+        i18n.t(match self {
+            Theme::System => "theme_system",
+            Theme::Light => "theme_light",
+            Theme::Dark => "theme_dark",
+        })
+    }
+}
+
+/// How times of day are written.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Clock {
+    /// `21:41`
+    #[default]
+    #[serde(rename = "24h")]
+    H24,
+    /// `9:41 PM`
+    #[serde(rename = "12h")]
+    H12,
+}
+
+impl Clock {
+    pub const ALL: [Clock; 2] = [Clock::H24, Clock::H12];
+
+    pub fn label(self, i18n: I18n) -> &'static str {
+        i18n.t(match self {
+            Clock::H24 => "clock_24h",
+            Clock::H12 => "clock_12h",
+        })
+    }
+
+    /// A time of day, like `21:41` or `9:41 PM`.
+    pub fn format(self, hours: u32, minutes: u32) -> String {
+        match self {
+            Clock::H24 => format!("{:02}:{:02}", hours, minutes),
+            Clock::H12 => {
+                let suffix = if hours < 12 { "AM" } else { "PM" };
+                let hours = match hours % 12 {
+                    0 => 12,
+                    h => h,
+                };
+                format!("{}:{:02} {}", hours, minutes, suffix)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Settings {
+    pub theme: Theme,
+    /// A chime when someone else's message comes in.
+    pub sound: bool,
+    /// A desktop notification for messages that come in while the tab is hidden.
+    pub notifications: bool,
+    pub clock: Clock,
+    /// How long without mouse or keyboard input before we report ourselves as away.
+    pub idle_minutes: u32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            theme: Theme::default(),
+            sound: false,
+            notifications: false,
+            clock: Clock::default(),
+            idle_minutes: 5,
+        }
+    }
+}
+
+// Overwrites `into` with the field `key` of `fields`, if it's there and makes sense.
+fn read_field<T: DeserializeOwned>(
+    fields: &serde_json::Map<String, Value>,
+    key: &str,
+    into: &mut T,
+) {
+    if let Some(value) = fields.get(key).and_then(|v| T::deserialize(v).ok()) {
+        *into = value;
+    }
+}
+
+impl Settings {
+    /// Settings as saved by this or any other version: each field that's missing or
+    /// can't be read keeps its default, without costing the others, and fields this
+    /// version doesn't know are ignored.
+    pub fn from_value(value: &Value) -> Self {
+        let mut settings = Settings::default();
+        if let Some(fields) = value.as_object() {
+            read_field(fields, "theme", &mut settings.theme);
+            read_field(fields, "sound", &mut settings.sound);
+            read_field(fields, "notifications", &mut settings.notifications);
+            read_field(fields, "clock", &mut settings.clock);
+            read_field(fields, "idle_minutes", &mut settings.idle_minutes);
+        }
+        settings.idle_minutes = settings
+            .idle_minutes
+            .clamp(MIN_IDLE_MINUTES, MAX_IDLE_MINUTES);
+        settings
+    }
+
+    pub fn idle_threshold_ms(&self) -> u32 {
+        self.idle_minutes * 60 * 1000
+    }
+}
+
+pub fn load() -> Settings {
+    LocalStorage::get::<Value>(STORAGE_KEY)
+        .map(|value| Settings::from_value(&value))
+        .unwrap_or_default()
+}
+
+pub fn save(settings: &Settings) {
+    if let Err(err) = LocalStorage::set(STORAGE_KEY, settings) {
+        log::warn!("couldn't save settings: {:?}", err);
+    }
+}
+
+/// What `Main` provides: the settings in force, and how to change them. A change is
+/// saved and takes effect everywhere at once.
+#[derive(Clone, PartialEq)]
+pub struct SettingsContext {
+    pub settings: Settings,
+    pub update: Callback<Settings>,
+}
+
+/// The media query for a dark operating system theme.
+pub fn dark_scheme_query() -> Option<MediaQueryList> {
+    gloo_utils::window()
+        .match_media("(prefers-color-scheme: dark)")
+        .ok()
+        .flatten()
+}
+
+/// Switches the page between light and dark, by the `dark` class on the root element
+/// that the stylesheet and Tailwind's `dark:` variants key off.
+pub fn apply_theme(theme: Theme) {
+    let dark = match theme {
+        Theme::System => dark_scheme_query().is_some_and(|query| query.matches()),
+        Theme::Light => false,
+        Theme::Dark => true,
+    };
+    if let Some(root) = gloo_utils::document().document_element() {
+        let _ = root.class_list().toggle_with_force("dark", dark);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn missing_settings_are_the_defaults() {
+        assert_eq!(Settings::from_value(&json!({})), Settings::default());
+        assert_eq!(Settings::from_value(&json!(null)), Settings::default());
+        assert_eq!(Settings::from_value(&json!([1, 2])), Settings::default());
+    }
+
+    #[test]
+    fn saved_settings_read_back() {
+        let settings = Settings {
+            theme: Theme::Dark,
+            sound: true,
+            notifications: true,
+            clock: Clock::H12,
+            idle_minutes: 30,
+        };
+        let saved = serde_json::to_value(&settings).unwrap();
+        assert_eq!(Settings::from_value(&saved), settings);
+    }
+
+    #[test]
+    fn a_bad_field_costs_only_itself() {
+        let settings = Settings::from_value(&json!({
+            "theme": "purple",
+            "sound": "yes",
+            "clock": "12h",
+            "from_a_later_version": true,
+        }));
+        assert_eq!(
+            settings,
+            Settings {
+                clock: Clock::H12,
+                ..Settings::default()
+            }
+        );
+    }
+
+    #[test]
+    fn the_idle_threshold_is_kept_in_range() {
+        let idle = |minutes: Value| Settings::from_value(&json!({ "idle_minutes": minutes }));
+        assert_eq!(idle(json!(0)).idle_minutes, MIN_IDLE_MINUTES);
+        assert_eq!(idle(json!(10_000)).idle_minutes, MAX_IDLE_MINUTES);
+        assert_eq!(idle(json!(-3)).idle_minutes, 5);
+        assert_eq!(idle(json!(2)).idle_threshold_ms(), 120_000);
+    }
+
+    #[test]
+    fn clocks_format_both_ways() {
+        assert_eq!(Clock::H24.format(9, 5), "09:05");
+        assert_eq!(Clock::H12.format(0, 0), "12:00 AM");
+        assert_eq!(Clock::H12.format(12, 30), "12:30 PM");
+        assert_eq!(Clock::H12.format(21, 41), "9:41 PM");
+    }
+}
@@ -0,0 +1,645 @@
+//! `Store`: the state more than one component reads — each room's conversation and who's
+//! in it, and the connection — kept in one place and changed only through
+//! `StoreAction`s. Components follow the `Slice`s they show and get a fresh copy of one
+//! whenever it changes, without waking for the rest. `AppState::apply` is the reducer
+//! proper, and runs without Yew or a browser.
+
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use yew::Callback;
+use yew_agent::{Agent, AgentLink, Bridge, Bridged, Context, HandlerId};
+
+use crate::components::chat::{
+    apply_incoming, clear_delivered, is_expired, merge_history, remove_message, Applied, ChatState,
+    UserProfile,
+};
+use crate::protocol::{DeliveryStatus, MessageData};
+use crate::services::event_bus::{BusEvent, Routes, Topic, DEFAULT_ROOM};
+use crate::services::websocket::ConnState;
+
+/// A part of the state that can be followed on its own.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Slice {
+    /// Who's connected, with their presence. The server has one list for everyone.
+    Users,
+    /// A room's conversation.
+    Room(String),
+    Connection,
+}
+
+/// Everything that can happen to the shared state.
+#[derive(Clone, Debug)]
+pub enum StoreAction {
+    /// A decoded server frame, applied to the room it's for.
+    Incoming(BusEvent),
+    /// This room was opened afresh, with these messages still waiting to be sent. What
+    /// else it had comes back from the local store and the server.
+    Opened(String, Vec<MessageData>),
+    /// A page of this room's history, read back from the local store.
+    Loaded(String, Vec<MessageData>),
+    /// We sent this message to this room, and show it right away ahead of the server's
+    /// echo.
+    Sent(String, MessageData),
+    /// Our message in this room with this id couldn't be sent, or its echo never came.
+    Failed(String, String),
+    /// The failed message in this room with this id is being sent again.
+    Retried(String, String),
+    /// We deleted our message in this room with this id, or the fade out of one the
+    /// server deleted has finished.
+    Deleted(String, String),
+    /// We cleared this room on this device: all but our undelivered messages go.
+    Cleared(String),
+    /// The retention setting drops this room's delivered messages from before this time.
+    Expired(String, u64),
+    /// This user's typing notice in this room ran out, which counts as their stopping.
+    StoppedTyping(String, String),
+    /// These, in order, as one change, so followers hear about each slice once.
+    Batch(Vec<StoreAction>),
+    Connection(ConnState),
+}
+
+/// The state itself. A follower's copy has only the slices it follows filled in.
+#[derive(Clone)]
+pub struct AppState {
+    // Shared with every follower's copy until the next change.
+    rooms: HashMap<String, Rc<ChatState>>,
+    users: Vec<UserProfile>,
+    conn: ConnState,
+}
+
+impl Default for AppState {
+    fn default() -> Self {
+        Self {
+            rooms: HashMap::new(),
+            users: vec![],
+            conn: ConnState::Connecting,
+        }
+    }
+}
+
+impl AppState {
+    /// `room`'s conversation; empty for a room nothing has happened in yet.
+    pub fn room(&self, room: &str) -> Rc<ChatState> {
+        self.rooms.get(room).cloned().unwrap_or_default()
+    }
+
+    pub fn users(&self) -> &[UserProfile] {
+        &self.users
+    }
+
+    pub fn conn(&self) -> ConnState {
+        self.conn
+    }
+
+    /// `room`'s conversation, borrowed in place; `None` for a room nothing has happened in
+    /// yet.
+    pub(crate) fn get(&self, room: &str) -> Option<&ChatState> {
+        self.rooms.get(room).map(Rc::as_ref)
+    }
+
+    fn room_mut(&mut self, room: &str) -> &mut ChatState {
+        Rc::make_mut(self.rooms.entry(room.to_string()).or_default())
+    }
+
+    /// Applies a server frame, returning what it did along with the slices that changed,
+    /// for a caller with follow-up work of its own.
+    pub(crate) fn apply_incoming(&mut self, event: BusEvent) -> (Applied, Vec<Slice>) {
+        // The user list lives with the room everyone's in.
+        let room = match event.topic() {
+            Topic::Room(room) => room,
+            Topic::Presence => DEFAULT_ROOM.to_string(),
+        };
+        let applied = apply_incoming(self.room_mut(&room), event);
+        let changed = match &applied {
+            Applied::Users => {
+                self.users = self.rooms[&room].users.clone();
+                vec![Slice::Users, Slice::Room(room)]
+            }
+            Applied::Duplicate
+            | Applied::RegisterRejected(_)
+            | Applied::Kicked(_)
+            | Applied::Reacted(None) => vec![],
+            Applied::Read(ids) if ids.is_empty() => vec![],
+            _ => vec![Slice::Room(room)],
+        };
+        (applied, changed)
+    }
+
+    /// Applies `action`, returning the slices that changed.
+    pub fn apply(&mut self, action: StoreAction) -> Vec<Slice> {
+        match action {
+            StoreAction::Incoming(event) => self.apply_incoming(event).1,
+            StoreAction::Opened(room, queued) => {
+                let mut state = ChatState::default();
+                merge_history(&mut state, queued);
+                self.rooms.insert(room.clone(), Rc::new(state));
+                vec![Slice::Room(room)]
+            }
+            StoreAction::Loaded(room, page) => {
+                let loaded = self.rooms.get(&room);
+                if loaded.is_some_and(|state| page.iter().all(|m| state.has(&m.id))) {
+                    return vec![];
+                }
+                merge_history(self.room_mut(&room), page);
+                vec![Slice::Room(room)]
+            }
+            StoreAction::Sent(room, message) => {
+                let added = self.room_mut(&room).push_message(message);
+                if added {
+                    vec![Slice::Room(room)]
+                } else {
+                    vec![]
+                }
+            }
+            StoreAction::Failed(room, id) => {
+                self.set_status(room, &id, DeliveryStatus::Pending, DeliveryStatus::Failed)
+            }
+            StoreAction::Retried(room, id) => {
+                self.set_status(room, &id, DeliveryStatus::Failed, DeliveryStatus::Pending)
+            }
+            StoreAction::Deleted(room, id) => {
+                if !self.has(&room, |m| m.id == id) {
+                    return vec![];
+                }
+                remove_message(self.room_mut(&room), &id);
+                vec![Slice::Room(room)]
+            }
+            StoreAction::Cleared(room) => {
+                if !self.has(&room, |m| m.status == DeliveryStatus::Delivered) {
+                    return vec![];
+                }
+                clear_delivered(self.room_mut(&room));
+                vec![Slice::Room(room)]
+            }
+            StoreAction::Expired(room, cutoff) => {
+                if !self.has(&room, |m| is_expired(m, cutoff)) {
+                    return vec![];
+                }
+                self.room_mut(&room)
+                    .retain_messages(|m| !is_expired(m, cutoff));
+                vec![Slice::Room(room)]
+            }
+            StoreAction::StoppedTyping(room, who) => {
+                let typing = self.rooms.get(&room);
+                if !typing.is_some_and(|state| state.typers(&room).contains(&who.as_str())) {
+                    return vec![];
+                }
+                self.room_mut(&room).set_typing(&room, &who, false);
+                vec![Slice::Room(room)]
+            }
+            StoreAction::Batch(actions) => {
+                let mut changed: Vec<Slice> = vec![];
+                for slice in actions.into_iter().flat_map(|action| self.apply(action)) {
+                    if !changed.contains(&slice) {
+                        changed.push(slice);
+                    }
+                }
+                changed
+            }
+            StoreAction::Connection(conn) => {
+                if conn == self.conn {
+                    return vec![];
+                }
+                self.conn = conn;
+                vec![Slice::Connection]
+            }
+        }
+    }
+
+    /// Moves our message with this id to `to`, if it's still at `from`: an echo that
+    /// beat its timeout leaves the message delivered.
+    fn set_status(
+        &mut self,
+        room: String,
+        id: &str,
+        from: DeliveryStatus,
+        to: DeliveryStatus,
+    ) -> Vec<Slice> {
+        if !self.has(&room, |m| m.id == id && m.status == from) {
+            return vec![];
+        }
+        let state = self.room_mut(&room);
+        if let Some(m) = state.messages.iter_mut().find(|m| m.id == id) {
+            m.status = to;
+        }
+        vec![Slice::Room(room)]
+    }
+
+    /// Whether `room` has a message that `test` picks out. Checked before changing
+    /// anything, so a room that stays as it is isn't copied away from its followers.
+    fn has(&self, room: &str, test: impl Fn(&MessageData) -> bool) -> bool {
+        self.rooms
+            .get(room)
+            .is_some_and(|state| state.messages.iter().any(test))
+    }
+
+    /// Takes in an update to a followed slice.
+    pub fn merge(&mut self, update: StoreUpdate) {
+        match update {
+            StoreUpdate::Users(users) => self.users = users,
+            StoreUpdate::Room(room, state) => {
+                self.rooms.insert(room, state);
+            }
+            StoreUpdate::Connection(conn) => self.conn = conn,
+        }
+    }
+
+    fn snapshot(&self, slice: &Slice) -> StoreUpdate {
+        match slice {
+            Slice::Users => StoreUpdate::Users(self.users.clone()),
+            Slice::Room(room) => StoreUpdate::Room(room.clone(), self.room(room)),
+            Slice::Connection => StoreUpdate::Connection(self.conn),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum StoreRequest {
+    Dispatch(StoreAction),
+    /// Limits the sending bridge to this slice, on top of any it already follows, and
+    /// sends it the slice as it is now.
+    Follow(Slice),
+}
+
+/// A followed slice, as it is after a change.
+#[derive(Clone)]
+pub enum StoreUpdate {
+    Users(Vec<UserProfile>),
+    Room(String, Rc<ChatState>),
+    Connection(ConnState),
+}
+
+pub struct Store {
+    link: AgentLink<Store>,
+    state: AppState,
+    routes: Routes<HandlerId, Slice>,
+}
+
+impl Store {
+    /// Connects `callback` to `slice` alone, starting with its current value. Dropping
+    /// the bridge unsubscribes it.
+    pub fn bridge_slice(slice: Slice, callback: Callback<StoreUpdate>) -> Box<dyn Bridge<Store>> {
+        let mut bridge = Store::bridge(callback);
+        bridge.send(StoreRequest::Follow(slice));
+        bridge
+    }
+}
+
+impl Agent for Store {
+    type Reach = Context<Self>;
+    type Message = ();
+    type Input = StoreRequest;
+    type Output = StoreUpdate;
+
+    fn create(link: AgentLink<Self>) -> Self {
+        Self {
+            link,
+            state: AppState::default(),
+            routes: Routes::new(),
+        }
+    }
+
+    fn update(&mut self, _msg: Self::Message) {}
+
+    fn handle_input(&mut self, msg: Self::Input, id: HandlerId) {
+        match msg {
+            StoreRequest::Dispatch(action) => {
+                for slice in self.state.apply(action) {
+                    let update = self.state.snapshot(&slice);
+                    for sub in self.routes.recipients(&slice) {
+                        self.link.respond(sub, update.clone());
+                    }
+                }
+            }
+            StoreRequest::Follow(slice) => {
+                self.link.respond(id, self.state.snapshot(&slice));
+                self.routes.subscribe(id, slice);
+            }
+        }
+    }
+
+    fn connected(&mut self, id: HandlerId) {
+        // Dispatchers only send.
+        if id.is_respondable() {
+            self.routes.connect(id);
+        }
+    }
+
+    fn disconnected(&mut self, id: HandlerId) {
+        self.routes.disconnect(id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{PinNotice, Reaction, ReadReceipt, ServerError, TypingNotice};
+    use std::collections::{BTreeMap, HashSet};
+
+    fn message(id: &str, time: u64, status: DeliveryStatus) -> MessageData {
+        MessageData {
+            id: id.into(),
+            from: "ann".into(),
+            message: "hi".into(),
+            time,
+            reactions: BTreeMap::new(),
+            read_by: vec![],
+            reply_to: None,
+            attachment: None,
+            status,
+        }
+    }
+
+    fn room() -> String {
+        DEFAULT_ROOM.to_string()
+    }
+
+    fn statuses(state: &AppState) -> Vec<(String, DeliveryStatus, u64)> {
+        let room = state.room(DEFAULT_ROOM);
+        room.messages
+            .iter()
+            .map(|m| (m.id.clone(), m.status, m.time))
+            .collect()
+    }
+
+    #[test]
+    fn an_echo_settles_a_sent_message() {
+        let mut state = AppState::default();
+        let sent = message("a", 10, DeliveryStatus::Pending);
+        assert_eq!(
+            state.apply(StoreAction::Sent(room(), sent)),
+            [Slice::Room(room())]
+        );
+        let echo = message("a", 12, DeliveryStatus::Delivered);
+        let echoed = StoreAction::Incoming(BusEvent::MessageReceived(echo));
+        assert_eq!(state.apply(echoed), [Slice::Room(room())]);
+        // The server's time wins.
+        assert_eq!(
+            statuses(&state),
+            [("a".to_string(), DeliveryStatus::Delivered, 12)]
+        );
+
+        // A timeout that fires after all fails nothing.
+        assert!(state
+            .apply(StoreAction::Failed(room(), "a".into()))
+            .is_empty());
+        assert_eq!(statuses(&state)[0].1, DeliveryStatus::Delivered);
+    }
+
+    #[test]
+    fn a_message_without_an_echo_fails_and_can_be_retried() {
+        let mut state = AppState::default();
+        state.apply(StoreAction::Sent(
+            room(),
+            message("a", 10, DeliveryStatus::Pending),
+        ));
+        state.apply(StoreAction::Failed(room(), "a".into()));
+        assert_eq!(statuses(&state)[0].1, DeliveryStatus::Failed);
+        // Failing twice changes nothing more.
+        assert!(state
+            .apply(StoreAction::Failed(room(), "a".into()))
+            .is_empty());
+
+        state.apply(StoreAction::Retried(room(), "a".into()));
+        assert_eq!(statuses(&state)[0].1, DeliveryStatus::Pending);
+        let echo = message("a", 20, DeliveryStatus::Delivered);
+        state.apply(StoreAction::Incoming(BusEvent::MessageReceived(echo)));
+        assert_eq!(statuses(&state)[0].1, DeliveryStatus::Delivered);
+    }
+
+    #[test]
+    fn repeats_change_nothing() {
+        let mut state = AppState::default();
+        let delivered = || message("a", 10, DeliveryStatus::Delivered);
+        let received = || StoreAction::Incoming(BusEvent::MessageReceived(delivered()));
+        assert_eq!(state.apply(received()), [Slice::Room(room())]);
+        assert!(state.apply(received()).is_empty());
+        assert!(state
+            .apply(StoreAction::Sent(room(), delivered()))
+            .is_empty());
+        assert_eq!(statuses(&state).len(), 1);
+    }
+
+    #[test]
+    fn the_user_list_is_its_own_slice() {
+        let mut state = AppState::default();
+        let users = BusEvent::UsersUpdated {
+            users: vec!["ann".into(), "bob".into()],
+            statuses: HashMap::new(),
+            admins: HashSet::new(),
+            activity: HashMap::new(),
+        };
+        assert_eq!(
+            state.apply(StoreAction::Incoming(users)),
+            [Slice::Users, Slice::Room(room())]
+        );
+        let names: Vec<_> = state.users().iter().map(|u| u.name.as_str()).collect();
+        assert_eq!(names, ["ann", "bob"]);
+    }
+
+    #[test]
+    fn the_connection_changes_only_on_a_new_state() {
+        let mut state = AppState::default();
+        assert!(state
+            .apply(StoreAction::Connection(ConnState::Connecting))
+            .is_empty());
+        assert_eq!(
+            state.apply(StoreAction::Connection(ConnState::Open)),
+            [Slice::Connection]
+        );
+        assert_eq!(state.conn(), ConnState::Open);
+    }
+
+    #[test]
+    fn followers_keep_their_copy_until_the_next_update() {
+        let mut state = AppState::default();
+        state.apply(StoreAction::Sent(
+            room(),
+            message("a", 10, DeliveryStatus::Pending),
+        ));
+        let mut follower = AppState::default();
+        follower.merge(state.snapshot(&Slice::Room(room())));
+        state.apply(StoreAction::Failed(room(), "a".into()));
+        assert_eq!(statuses(&follower)[0].1, DeliveryStatus::Pending);
+        follower.merge(state.snapshot(&Slice::Room(room())));
+        assert_eq!(statuses(&follower)[0].1, DeliveryStatus::Failed);
+    }
+
+    fn ids(state: &AppState) -> Vec<String> {
+        statuses(state).into_iter().map(|(id, ..)| id).collect()
+    }
+
+    fn incoming(event: BusEvent) -> StoreAction {
+        StoreAction::Incoming(event)
+    }
+
+    #[test]
+    fn opening_a_room_starts_it_over() {
+        let mut state = AppState::default();
+        let old = message("old", 10, DeliveryStatus::Delivered);
+        state.apply(incoming(BusEvent::MessageReceived(old)));
+        let queued = vec![message("queued", 20, DeliveryStatus::Pending)];
+        assert_eq!(
+            state.apply(StoreAction::Opened(room(), queued)),
+            [Slice::Room(room())]
+        );
+        assert_eq!(ids(&state), ["queued"]);
+    }
+
+    #[test]
+    fn a_stored_page_is_taken_in_once() {
+        let mut state = AppState::default();
+        let page = || {
+            vec![
+                message("b", 20, DeliveryStatus::Delivered),
+                message("a", 10, DeliveryStatus::Delivered),
+            ]
+        };
+        assert_eq!(
+            state.apply(StoreAction::Loaded(room(), page())),
+            [Slice::Room(room())]
+        );
+        assert!(state.apply(StoreAction::Loaded(room(), page())).is_empty());
+        assert_eq!(ids(&state), ["a", "b"]);
+    }
+
+    #[test]
+    fn deleting_a_message_unpins_it() {
+        let mut state = AppState::default();
+        let a = message("a", 10, DeliveryStatus::Delivered);
+        state.apply(incoming(BusEvent::MessageReceived(a)));
+        state.apply(incoming(BusEvent::PinToggled(PinNotice {
+            id: "a".into(),
+            pinned: true,
+            from: "ann".into(),
+        })));
+        assert_eq!(
+            state.apply(StoreAction::Deleted(room(), "a".into())),
+            [Slice::Room(room())]
+        );
+        assert!(ids(&state).is_empty());
+        assert!(state.room(DEFAULT_ROOM).pinned.is_empty());
+        assert!(state
+            .apply(StoreAction::Deleted(room(), "a".into()))
+            .is_empty());
+    }
+
+    #[test]
+    fn clearing_and_expiry_keep_undelivered_messages() {
+        let mut state = AppState::default();
+        state.apply(StoreAction::Batch(vec![
+            StoreAction::Loaded(
+                room(),
+                vec![
+                    message("old", 10, DeliveryStatus::Delivered),
+                    message("new", 30, DeliveryStatus::Delivered),
+                ],
+            ),
+            StoreAction::Sent(room(), message("ours", 5, DeliveryStatus::Pending)),
+        ]));
+
+        assert_eq!(
+            state.apply(StoreAction::Expired(room(), 20)),
+            [Slice::Room(room())]
+        );
+        assert_eq!(ids(&state), ["ours", "new"]);
+        assert!(state.apply(StoreAction::Expired(room(), 20)).is_empty());
+
+        assert_eq!(
+            state.apply(StoreAction::Cleared(room())),
+            [Slice::Room(room())]
+        );
+        assert_eq!(ids(&state), ["ours"]);
+        assert!(state.apply(StoreAction::Cleared(room())).is_empty());
+    }
+
+    #[test]
+    fn reactions_and_receipts_on_unloaded_messages_change_nothing() {
+        let mut state = AppState::default();
+        let reaction = |id: &str| {
+            incoming(BusEvent::ReactionToggled(Reaction {
+                id: id.into(),
+                from: "bob".into(),
+                emoji: "👍".into(),
+            }))
+        };
+        assert!(state.apply(reaction("a")).is_empty());
+
+        let a = message("a", 10, DeliveryStatus::Delivered);
+        state.apply(incoming(BusEvent::MessageReceived(a)));
+        assert_eq!(state.apply(reaction("a")), [Slice::Room(room())]);
+        let (applied, _) = state.apply_incoming(BusEvent::MessagesRead(ReadReceipt {
+            ids: vec!["a".into(), "missing".into()],
+            from: "bob".into(),
+        }));
+        assert_eq!(applied, Applied::Read(vec!["a".into()]));
+        let room = state.room(DEFAULT_ROOM);
+        assert_eq!(room.messages[0].reactions["👍"], ["bob"]);
+        assert_eq!(room.messages[0].read_by, ["bob"]);
+    }
+
+    #[test]
+    fn a_typing_notice_runs_out() {
+        let mut state = AppState::default();
+        state.apply(incoming(BusEvent::TypingChanged(TypingNotice {
+            room: room(),
+            from: "bob".into(),
+            typing: true,
+        })));
+        assert_eq!(state.room(DEFAULT_ROOM).typers(DEFAULT_ROOM), ["bob"]);
+        let stopped = || StoreAction::StoppedTyping(room(), "bob".into());
+        assert_eq!(state.apply(stopped()), [Slice::Room(room())]);
+        assert!(state.apply(stopped()).is_empty());
+    }
+
+    #[test]
+    fn a_batch_reports_each_slice_once() {
+        let mut state = AppState::default();
+        let batch = StoreAction::Batch(vec![
+            StoreAction::Sent(room(), message("a", 10, DeliveryStatus::Pending)),
+            StoreAction::Failed(room(), "a".into()),
+            StoreAction::Connection(ConnState::Open),
+            StoreAction::Retried(room(), "a".into()),
+        ]);
+        assert_eq!(state.apply(batch), [Slice::Room(room()), Slice::Connection]);
+        assert_eq!(statuses(&state)[0].1, DeliveryStatus::Pending);
+    }
+
+    #[test]
+    fn delivery_status_transitions() {
+        use DeliveryStatus::*;
+        let echo = || incoming(BusEvent::MessageReceived(message("a", 20, Delivered)));
+        let refused = || {
+            incoming(BusEvent::ServerError(ServerError {
+                code: "rejected".into(),
+                reason: None,
+                id: Some("a".into()),
+            }))
+        };
+        let failed = || StoreAction::Failed(room(), "a".into());
+        let retried = || StoreAction::Retried(room(), "a".into());
+        let cases: [(DeliveryStatus, &dyn Fn() -> StoreAction, DeliveryStatus); 12] = [
+            (Pending, &echo, Delivered),
+            (Pending, &refused, Failed),
+            (Pending, &failed, Failed),
+            (Pending, &retried, Pending),
+            // A retry's echo, or a late one for the first try.
+            (Failed, &echo, Delivered),
+            (Failed, &refused, Failed),
+            (Failed, &failed, Failed),
+            (Failed, &retried, Pending),
+            // Nothing undoes a delivery.
+            (Delivered, &echo, Delivered),
+            (Delivered, &refused, Delivered),
+            (Delivered, &failed, Delivered),
+            (Delivered, &retried, Delivered),
+        ];
+        for (from, action, to) in cases {
+            let mut state = AppState::default();
+            state.apply(StoreAction::Sent(room(), message("a", 10, from)));
+            state.apply(action());
+            assert_eq!(statuses(&state)[0].1, to, "from {:?}", from);
+        }
+    }
+}
@@ -0,0 +1,393 @@
+//! Colouring for the code blocks in messages. Each language we know is described by a
+//! small `Grammar`: its keywords and literals, how its comments and strings look. That's
+//! enough to pick those out, and numbers, in a single pass, without a parser. Code in any
+//! other language, or none, is shown as it is.
+
+use yew::prelude::*;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Token {
+    Plain,
+    Keyword,
+    /// Built-in values, such as `true` and `null`.
+    Literal,
+    String,
+    Number,
+    Comment,
+}
+
+impl Token {
+    fn class(self) -> &'static str {
+        match self {
+            Token::Plain => "",
+            Token::Keyword => "tok-keyword",
+            Token::Literal => "tok-literal",
+            Token::String => "tok-string",
+            Token::Number => "tok-number",
+            Token::Comment => "tok-comment",
+        }
+    }
+}
+
+struct Grammar {
+    /// What the language is called after a code fence, in lower case.
+    names: &'static [&'static str],
+    keywords: &'static [&'static str],
+    literals: &'static [&'static str],
+    line_comments: &'static [&'static str],
+    block_comment: Option<(&'static str, &'static str)>,
+    /// Strings open and close on these. Only backtick strings may run over a line break.
+    quotes: &'static [char],
+}
+
+const GRAMMARS: &[Grammar] = &[
+    Grammar {
+        names: &["rust", "rs"],
+        keywords: &[
+            "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum",
+            "extern", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move",
+            "mut", "pub", "ref", "return", "self", "Self", "static", "struct", "super", "trait",
+            "type", "unsafe", "use", "where", "while",
+        ],
+        literals: &["true", "false", "None", "Some", "Ok", "Err"],
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        // Not `'`, which is as often a lifetime as a character.
+        quotes: &['"'],
+    },
+    Grammar {
+        names: &["javascript", "js", "jsx", "typescript", "ts", "tsx"],
+        keywords: &[
+            "async",
+            "await",
+            "break",
+            "case",
+            "catch",
+            "class",
+            "const",
+            "continue",
+            "default",
+            "delete",
+            "do",
+            "else",
+            "export",
+            "extends",
+            "finally",
+            "for",
+            "from",
+            "function",
+            "if",
+            "import",
+            "in",
+            "instanceof",
+            "interface",
+            "let",
+            "new",
+            "of",
+            "return",
+            "static",
+            "super",
+            "switch",
+            "this",
+            "throw",
+            "try",
+            "type",
+            "typeof",
+            "var",
+            "void",
+            "while",
+            "yield",
+        ],
+        literals: &["true", "false", "null", "undefined"],
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        quotes: &['"', '\'', '`'],
+    },
+    Grammar {
+        names: &["python", "py"],
+        keywords: &[
+            "and", "as", "assert", "async", "await", "break", "class", "continue", "def", "del",
+            "elif", "else", "except", "finally", "for", "from", "global", "if", "import", "in",
+            "is", "lambda", "nonlocal", "not", "or", "pass", "raise", "return", "try", "while",
+            "with", "yield",
+        ],
+        literals: &["True", "False", "None"],
+        line_comments: &["#"],
+        block_comment: None,
+        quotes: &['"', '\''],
+    },
+    Grammar {
+        names: &["json"],
+        keywords: &[],
+        literals: &["true", "false", "null"],
+        line_comments: &[],
+        block_comment: None,
+        quotes: &['"'],
+    },
+    Grammar {
+        names: &["bash", "sh", "shell", "zsh"],
+        keywords: &[
+            "case", "do", "done", "elif", "else", "esac", "export", "fi", "for", "function", "if",
+            "in", "local", "return", "then", "while",
+        ],
+        literals: &[],
+        line_comments: &["#"],
+        block_comment: None,
+        quotes: &['"', '\''],
+    },
+];
+
+fn grammar(lang: &str) -> Option<&'static Grammar> {
+    let lang = lang.to_lowercase();
+    GRAMMARS.iter().find(|g| g.names.contains(&lang.as_str()))
+}
+
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// How long the string opening `rest` with `quote` is, up to and including its closing
+/// quote, or to the end of the line or code if it isn't closed.
+fn string_len(rest: &str, quote: char) -> usize {
+    let mut chars = rest.char_indices().skip(1);
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '\n' if quote != '`' => return i,
+            c if c == quote => return i + c.len_utf8(),
+            _ => {}
+        }
+    }
+    rest.len()
+}
+
+/// Splits `code` into runs of one kind of token, as `lang` colours them; `None` if we
+/// don't know `lang`. Joined back up, the runs are `code` again.
+pub fn tokenize<'a>(code: &'a str, lang: &str) -> Option<Vec<(Token, &'a str)>> {
+    let grammar = grammar(lang)?;
+    let mut tokens: Vec<(Token, &str)> = Vec::new();
+    let mut pos = 0;
+    while let Some(c) = code[pos..].chars().next() {
+        let rest = &code[pos..];
+        let (token, len) = if grammar.line_comments.iter().any(|m| rest.starts_with(m)) {
+            (Token::Comment, rest.find('\n').unwrap_or(rest.len()))
+        } else if let Some((open, close)) = grammar
+            .block_comment
+            .filter(|(open, _)| rest.starts_with(open))
+        {
+            let end = rest[open.len()..]
+                .find(close)
+                .map_or(rest.len(), |i| open.len() + i + close.len());
+            (Token::Comment, end)
+        } else if grammar.quotes.contains(&c) {
+            (Token::String, string_len(rest, c))
+        } else if is_word(c) {
+            let len = rest.find(|c| !is_word(c)).unwrap_or(rest.len());
+            let word = &rest[..len];
+            let token = if c.is_ascii_digit() {
+                // Takes in a fraction, and suffixes like `u32` or `n`.
+                Token::Number
+            } else if grammar.keywords.contains(&word) {
+                Token::Keyword
+            } else if grammar.literals.contains(&word) {
+                Token::Literal
+            } else {
+                Token::Plain
+            };
+            let len = match token {
+                Token::Number => rest
+                    .find(|c: char| !is_word(c) && c != '.')
+                    .unwrap_or(rest.len()),
+                _ => len,
+            };
+            (token, len)
+        } else {
+            (Token::Plain, c.len_utf8())
+        };
+        match tokens.last_mut() {
+            Some((last, run)) if *last == token && token == Token::Plain => {
+                *run = &code[pos - run.len()..pos + len];
+            }
+            _ => tokens.push((token, &rest[..len])),
+        }
+        pos += len;
+    }
+    Some(tokens)
+}
+
+/// `code` with its keywords, literals, strings, numbers and comments in spans classed
+/// `tok-keyword` and so on, as `styles.css` colours them, if we know `lang`; otherwise
+/// plain text.
+pub fn highlight(code: &str, lang: Option<&str>) -> Html {
+    let Some(tokens) = lang.and_then(|lang| tokenize(code, lang)) else {
+        return html! { {code} };
+    };
+    tokens
+        .into_iter()
+        .map(|(token, text)| match token {
+            Token::Plain => html! { {text} },
+            token => html! { <span class={token.class()}>{text}</span> },
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Token::*;
+
+    fn tokens<'a>(code: &'a str, lang: &str) -> Vec<(Token, &'a str)> {
+        let tokens = tokenize(code, lang).unwrap();
+        let joined: std::string::String = tokens.iter().map(|(_, text)| *text).collect();
+        assert_eq!(joined, code);
+        tokens
+    }
+
+    #[test]
+    fn rust_is_coloured() {
+        assert_eq!(
+            tokens(r#"let s = "fn"; // if"#, "rust"),
+            [
+                (Keyword, "let"),
+                (Plain, " s = "),
+                (String, r#""fn""#),
+                (Plain, "; "),
+                (Comment, "// if"),
+            ]
+        );
+        assert_eq!(
+            tokens("/* match */ Some(0x1f)", "rs"),
+            [
+                (Comment, "/* match */"),
+                (Plain, " "),
+                (Literal, "Some"),
+                (Plain, "("),
+                (Number, "0x1f"),
+                (Plain, ")"),
+            ]
+        );
+        // Not a character, but a lifetime.
+        assert_eq!(tokens("&'a str", "rust"), [(Plain, "&'a str")]);
+    }
+
+    #[test]
+    fn javascript_is_coloured() {
+        assert_eq!(
+            tokens("const a = 'if' + `for\nwhile`;", "js"),
+            [
+                (Keyword, "const"),
+                (Plain, " a = "),
+                (String, "'if'"),
+                (Plain, " + "),
+                (String, "`for\nwhile`"),
+                (Plain, ";"),
+            ]
+        );
+        assert_eq!(
+            tokens("return null; /* new", "ts"),
+            [
+                (Keyword, "return"),
+                (Plain, " "),
+                (Literal, "null"),
+                (Plain, "; "),
+                (Comment, "/* new"),
+            ]
+        );
+    }
+
+    #[test]
+    fn python_is_coloured() {
+        assert_eq!(
+            tokens("def f(): # return\n    return 'def' or None", "python"),
+            [
+                (Keyword, "def"),
+                (Plain, " f(): "),
+                (Comment, "# return"),
+                (Plain, "\n    "),
+                (Keyword, "return"),
+                (Plain, " "),
+                (String, "'def'"),
+                (Plain, " "),
+                (Keyword, "or"),
+                (Plain, " "),
+                (Literal, "None"),
+            ]
+        );
+    }
+
+    #[test]
+    fn json_is_coloured() {
+        assert_eq!(
+            tokens(r#"{"true": [1.5, null]}"#, "json"),
+            [
+                (Plain, "{"),
+                (String, r#""true""#),
+                (Plain, ": ["),
+                (Number, "1.5"),
+                (Plain, ", "),
+                (Literal, "null"),
+                (Plain, "]}"),
+            ]
+        );
+    }
+
+    #[test]
+    fn shell_is_coloured() {
+        assert_eq!(
+            tokens("if [ \"$x\" ]; then echo 'fi' # done\nfi", "bash"),
+            [
+                (Keyword, "if"),
+                (Plain, " [ "),
+                (String, "\"$x\""),
+                (Plain, " ]; "),
+                (Keyword, "then"),
+                (Plain, " echo "),
+                (String, "'fi'"),
+                (Plain, " "),
+                (Comment, "# done"),
+                (Plain, "\n"),
+                (Keyword, "fi"),
+            ]
+        );
+    }
+
+    #[test]
+    fn escapes_and_line_ends_bound_strings() {
+        assert_eq!(
+            tokens(r#""a\"if" x"#, "rust"),
+            [(String, r#""a\"if""#), (Plain, " x")]
+        );
+        assert_eq!(
+            tokens("'open\nelse", "js"),
+            [(String, "'open"), (Plain, "\n"), (Keyword, "else")]
+        );
+    }
+
+    #[test]
+    fn languages_are_named_in_any_case() {
+        assert_eq!(tokens("let", "Rust"), [(Keyword, "let")]);
+        assert_eq!(tokens("let", "JS"), [(Keyword, "let")]);
+    }
+
+    #[test]
+    fn unknown_languages_are_not_coloured() {
+        assert_eq!(tokenize("let x", "cobol"), None);
+        assert_eq!(highlight("let x", Some("cobol")), html! { {"let x"} });
+        assert_eq!(highlight("let x", None), html! { {"let x"} });
+    }
+
+    #[test]
+    fn highlighting_wraps_tokens_in_classed_spans() {
+        let (keyword, number) = (Keyword.class(), Number.class());
+        let expected = html! {
+            <>
+                <span class={keyword}>{"let"}</span>
+                {" x = "}
+                <span class={number}>{"1"}</span>
+            </>
+        };
+        assert_eq!(highlight("let x = 1", Some("rust")), expected);
+    }
+}
@@ -0,0 +1,199 @@
+//! Short notices that come and go at the foot of the page. `ToastHost` shows them, and
+//! provides a `ToastContext` that anything under it pushes them through: `use_toasts` in
+//! a function component, or `ctx.link().context` in a struct one. `Toasts`, the queue
+//! itself, is plain data with the time passed in, and runs without a browser.
+
+use yew::Callback;
+
+/// How long a toast stays up.
+pub const TOAST_MS: f64 = 4_000.0;
+/// Errors stay up longer, as there's usually something to do about them.
+pub const ERROR_TOAST_MS: f64 = 8_000.0;
+/// At most this many are up at once; a new one past that takes down the oldest.
+pub const MAX_TOASTS: usize = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Info,
+    Success,
+    Error,
+}
+
+/// A button on a toast. Pressing it dismisses the toast as well.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ToastAction {
+    pub label: String,
+    pub onclick: Callback<()>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Toast {
+    pub text: String,
+    pub severity: Severity,
+    pub action: Option<ToastAction>,
+}
+
+impl Toast {
+    pub fn new(severity: Severity, text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            severity,
+            action: None,
+        }
+    }
+
+    pub fn info(text: impl Into<String>) -> Self {
+        Self::new(Severity::Info, text)
+    }
+
+    pub fn success(text: impl Into<String>) -> Self {
+        Self::new(Severity::Success, text)
+    }
+
+    pub fn error(text: impl Into<String>) -> Self {
+        Self::new(Severity::Error, text)
+    }
+
+    /// Adds a button, such as "Retry", that calls `onclick`.
+    pub fn with_action(self, label: impl Into<String>, onclick: Callback<()>) -> Self {
+        Self {
+            action: Some(ToastAction {
+                label: label.into(),
+                onclick,
+            }),
+            ..self
+        }
+    }
+
+    fn lifetime(&self) -> f64 {
+        match self.severity {
+            Severity::Error => ERROR_TOAST_MS,
+            Severity::Info | Severity::Success => TOAST_MS,
+        }
+    }
+}
+
+/// A toast that's up, with what it's known by and when it goes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Shown {
+    pub id: u32,
+    pub toast: Toast,
+    pub expires_at: f64,
+}
+
+/// The toasts that are up, oldest first.
+#[derive(Debug, Default)]
+pub struct Toasts {
+    shown: Vec<Shown>,
+    next_id: u32,
+}
+
+impl Toasts {
+    /// Puts up `toast` at `now`, below any already up, taking down the oldest if that
+    /// makes too many. Returns its id.
+    pub fn push(&mut self, toast: Toast, now: f64) -> u32 {
+        let id = self.next_id;
+        self.next_id += 1;
+        let expires_at = now + toast.lifetime();
+        self.shown.push(Shown {
+            id,
+            toast,
+            expires_at,
+        });
+        if self.shown.len() > MAX_TOASTS {
+            self.shown.drain(..self.shown.len() - MAX_TOASTS);
+        }
+        id
+    }
+
+    /// Takes down the toast with this id, returning whether it was up.
+    pub fn dismiss(&mut self, id: u32) -> bool {
+        let before = self.shown.len();
+        self.shown.retain(|s| s.id != id);
+        self.shown.len() != before
+    }
+
+    /// Takes down every toast whose time is up at `now`, returning whether any was.
+    pub fn expire(&mut self, now: f64) -> bool {
+        let before = self.shown.len();
+        self.shown.retain(|s| s.expires_at > now);
+        self.shown.len() != before
+    }
+
+    /// When the next toast goes, if any is up.
+    pub fn next_expiry(&self) -> Option<f64> {
+        self.shown
+            .iter()
+            .map(|s| s.expires_at)
+            .min_by(f64::total_cmp)
+    }
+
+    pub fn shown(&self) -> &[Shown] {
+        &self.shown
+    }
+}
+
+/// What `ToastHost` provides: how to put up a toast.
+#[derive(Clone, PartialEq)]
+pub struct ToastContext {
+    pub push: Callback<Toast>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(toasts: &Toasts) -> Vec<&str> {
+        toasts
+            .shown()
+            .iter()
+            .map(|s| s.toast.text.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn toasts_stack_in_order_and_expire() {
+        let mut toasts = Toasts::default();
+        assert_eq!(toasts.next_expiry(), None);
+        let first = toasts.push(Toast::info("copied"), 0.0);
+        let second = toasts.push(Toast::error("send failed"), 1_000.0);
+        assert_ne!(first, second);
+        assert_eq!(texts(&toasts), ["copied", "send failed"]);
+        assert_eq!(toasts.next_expiry(), Some(TOAST_MS));
+
+        assert!(!toasts.expire(TOAST_MS - 1.0));
+        assert!(toasts.expire(TOAST_MS));
+        // The error stays up longer.
+        assert_eq!(texts(&toasts), ["send failed"]);
+        assert_eq!(toasts.next_expiry(), Some(1_000.0 + ERROR_TOAST_MS));
+        assert!(toasts.expire(1_000.0 + ERROR_TOAST_MS));
+        assert!(toasts.shown().is_empty());
+        assert!(!toasts.expire(f64::INFINITY));
+    }
+
+    #[test]
+    fn toasts_are_limited_and_dismissible() {
+        let mut toasts = Toasts::default();
+        let ids: Vec<u32> = (0..MAX_TOASTS + 2)
+            .map(|i| toasts.push(Toast::info(i.to_string()), i as f64))
+            .collect();
+        // The oldest two made way.
+        assert_eq!(toasts.shown().len(), MAX_TOASTS);
+        assert_eq!(toasts.shown()[0].id, ids[2]);
+        assert_eq!(toasts.next_expiry(), Some(2.0 + TOAST_MS));
+
+        assert!(!toasts.dismiss(ids[0]));
+        assert!(toasts.dismiss(ids[3]));
+        assert!(!toasts.dismiss(ids[3]));
+        let left: Vec<u32> = toasts.shown().iter().map(|s| s.id).collect();
+        assert_eq!(left, [ids[2], ids[4]]);
+    }
+
+    #[test]
+    fn an_action_rides_along() {
+        let toast = Toast::error("send failed").with_action("Retry", Callback::noop());
+        assert_eq!(toast.severity, Severity::Error);
+        assert_eq!(toast.action.map(|a| a.label), Some("Retry".to_string()));
+        assert_eq!(Toast::success("done").action, None);
+    }
+}
@@ -0,0 +1,202 @@
+//! The connection under `WebsocketService`, behind a trait so the service can run
+//! against `MockTransport` instead of a real socket.
+
+#[cfg(test)]
+use std::cell::Cell;
+use std::cell::RefCell;
+use std::fmt;
+#[cfg(test)]
+use std::rc::Rc;
+
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::{BinaryType, MessageEvent, WebSocket};
+use yew::Callback;
+
+#[derive(Debug)]
+pub enum TransportError {
+    /// There's no working WebSocket here: the browser lacks one, or refused to create one.
+    /// Trying again won't help.
+    Unsupported(String),
+    Failed(String),
+}
+
+impl fmt::Display for TransportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TransportError::Unsupported(reason) => write!(f, "WebSocket unsupported: {}", reason),
+            TransportError::Failed(reason) => f.write_str(reason),
+        }
+    }
+}
+
+impl From<JsValue> for TransportError {
+    fn from(e: JsValue) -> Self {
+        TransportError::Failed(format!("{:?}", e))
+    }
+}
+
+/// Whether this browser has a `WebSocket` at all.
+pub fn supported() -> bool {
+    js_sys::Reflect::has(&js_sys::global(), &JsValue::from_str("WebSocket")).unwrap_or(false)
+}
+
+/// One connection attempt. The callbacks are set right after it's created, before it
+/// can have opened; dropping it closes the connection.
+pub trait Transport {
+    /// Sends a text frame. Fails if the connection isn't open.
+    fn send(&self, frame: &str) -> Result<(), TransportError>;
+    /// `callback` receives every text frame, and every binary frame that's valid UTF-8.
+    fn on_message(&self, callback: Callback<String>);
+    /// `callback` is called once the connection is open.
+    fn on_open(&self, callback: Callback<()>);
+    /// `callback` is called when the connection closes, or fails to open.
+    fn on_close(&self, callback: Callback<()>);
+}
+
+type Handler = Closure<dyn FnMut(JsValue)>;
+
+/// The browser's WebSocket.
+pub struct BrowserTransport {
+    socket: WebSocket,
+    // Kept alive for as long as the socket can call them.
+    handlers: RefCell<Vec<Handler>>,
+}
+
+impl BrowserTransport {
+    /// Fails with `Unsupported` if the browser has no WebSocket or its constructor throws,
+    /// as it can when a policy blocks sockets.
+    pub fn connect(url: &str) -> Result<Self, TransportError> {
+        if !supported() {
+            return Err(TransportError::Unsupported(
+                "not available in this browser".to_string(),
+            ));
+        }
+        let socket =
+            WebSocket::new(url).map_err(|e| TransportError::Unsupported(format!("{:?}", e)))?;
+        socket.set_binary_type(BinaryType::Arraybuffer);
+        Ok(Self {
+            socket,
+            handlers: RefCell::new(Vec::new()),
+        })
+    }
+
+    fn handler(&self, f: impl FnMut(JsValue) + 'static) -> js_sys::Function {
+        let closure: Handler = Closure::wrap(Box::new(f));
+        let function = closure.as_ref().unchecked_ref::<js_sys::Function>().clone();
+        self.handlers.borrow_mut().push(closure);
+        function
+    }
+}
+
+impl Transport for BrowserTransport {
+    fn send(&self, frame: &str) -> Result<(), TransportError> {
+        Ok(self.socket.send_with_str(frame)?)
+    }
+
+    fn on_message(&self, callback: Callback<String>) {
+        let handler = self.handler(move |e| {
+            let data = e.unchecked_into::<MessageEvent>().data();
+            if let Some(text) = data.as_string() {
+                callback.emit(text);
+            } else if data.is_instance_of::<js_sys::ArrayBuffer>() {
+                let bytes = js_sys::Uint8Array::new(&data).to_vec();
+                if let Ok(text) = String::from_utf8(bytes) {
+                    callback.emit(text);
+                }
+            }
+        });
+        self.socket.set_onmessage(Some(&handler));
+    }
+
+    fn on_open(&self, callback: Callback<()>) {
+        let handler = self.handler(move |_| callback.emit(()));
+        self.socket.set_onopen(Some(&handler));
+    }
+
+    fn on_close(&self, callback: Callback<()>) {
+        let handler = self.handler(move |_| callback.emit(()));
+        self.socket.set_onclose(Some(&handler));
+    }
+}
+
+impl Drop for BrowserTransport {
+    fn drop(&mut self) {
+        self.socket.set_onmessage(None);
+        self.socket.set_onopen(None);
+        self.socket.set_onclose(None);
+        let _ = self.socket.close();
+    }
+}
+
+#[cfg(test)]
+#[derive(Default)]
+struct MockState {
+    open: Cell<bool>,
+    sent: RefCell<Vec<String>>,
+    on_message: RefCell<Option<Callback<String>>>,
+    on_open: RefCell<Option<Callback<()>>>,
+    on_close: RefCell<Option<Callback<()>>>,
+}
+
+/// A connection driven by hand, for tests. Clones share one connection: give one to the
+/// service and keep one to play the server.
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub struct MockTransport {
+    state: Rc<MockState>,
+}
+
+#[cfg(test)]
+impl MockTransport {
+    /// Completes the handshake.
+    pub fn open(&self) {
+        self.state.open.set(true);
+        if let Some(callback) = self.state.on_open.borrow().clone() {
+            callback.emit(());
+        }
+    }
+
+    /// Delivers `frame` as if the server had sent it.
+    pub fn receive(&self, frame: &str) {
+        if let Some(callback) = self.state.on_message.borrow().clone() {
+            callback.emit(frame.to_string());
+        }
+    }
+
+    /// Drops the connection from the server's side.
+    pub fn close(&self) {
+        self.state.open.set(false);
+        if let Some(callback) = self.state.on_close.borrow().clone() {
+            callback.emit(());
+        }
+    }
+
+    /// Every frame sent so far, oldest first.
+    pub fn sent(&self) -> Vec<String> {
+        self.state.sent.borrow().clone()
+    }
+}
+
+#[cfg(test)]
+impl Transport for MockTransport {
+    fn send(&self, frame: &str) -> Result<(), TransportError> {
+        if !self.state.open.get() {
+            return Err(TransportError::Failed("not open".to_string()));
+        }
+        self.state.sent.borrow_mut().push(frame.to_string());
+        Ok(())
+    }
+
+    fn on_message(&self, callback: Callback<String>) {
+        *self.state.on_message.borrow_mut() = Some(callback);
+    }
+
+    fn on_open(&self, callback: Callback<()>) {
+        *self.state.on_open.borrow_mut() = Some(callback);
+    }
+
+    fn on_close(&self, callback: Callback<()>) {
+        *self.state.on_close.borrow_mut() = Some(callback);
+    }
+}
@@ -0,0 +1,91 @@
+//! What counts as a username. The server applies the same rules on register, and also
+//! turns away names that are already taken.
+
+use std::fmt;
+
+pub const MIN_LEN: usize = 2;
+pub const MAX_LEN: usize = 24;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum UsernameError {
+    Empty,
+    TooShort,
+    TooLong,
+    /// The first character that isn't allowed.
+    InvalidChar(char),
+}
+
+impl fmt::Display for UsernameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UsernameError::Empty => write!(f, "Pick a username"),
+            UsernameError::TooShort => write!(f, "Usernames need at least {} characters", MIN_LEN),
+            UsernameError::TooLong => write!(f, "Usernames can be at most {} characters", MAX_LEN),
+            UsernameError::InvalidChar(' ') => write!(f, "Usernames can't contain spaces"),
+            UsernameError::InvalidChar(c) => write!(
+                f,
+                "Usernames can't contain \"{}\"; use letters, digits, '.', '-' or '_'",
+                c
+            ),
+        }
+    }
+}
+
+fn allowed(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '.' | '-' | '_')
+}
+
+/// Checks `name` once surrounding whitespace is trimmed, returning the trimmed name.
+/// Length is counted in characters, so names in any script get the same room.
+pub fn validate(name: &str) -> Result<&str, UsernameError> {
+    let name = name.trim();
+    let len = name.chars().count();
+    if len == 0 {
+        return Err(UsernameError::Empty);
+    }
+    if let Some(c) = name.chars().find(|&c| !allowed(c)) {
+        return Err(UsernameError::InvalidChar(c));
+    }
+    if len < MIN_LEN {
+        return Err(UsernameError::TooShort);
+    }
+    if len > MAX_LEN {
+        return Err(UsernameError::TooLong);
+    }
+    Ok(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_are_trimmed() {
+        assert_eq!(validate("  ann.b-c_1 "), Ok("ann.b-c_1"));
+        assert_eq!(validate(" \t"), Err(UsernameError::Empty));
+    }
+
+    #[test]
+    fn length_is_counted_in_characters() {
+        assert_eq!(validate("a"), Err(UsernameError::TooShort));
+        assert_eq!(validate(&"a".repeat(MIN_LEN)), Ok("aa"));
+        let longest = "é".repeat(MAX_LEN);
+        assert_eq!(validate(&longest), Ok(longest.as_str()));
+        assert_eq!(
+            validate(&"é".repeat(MAX_LEN + 1)),
+            Err(UsernameError::TooLong)
+        );
+    }
+
+    #[test]
+    fn the_first_bad_character_is_named() {
+        assert_eq!(validate("ann smith"), Err(UsernameError::InvalidChar(' ')));
+        assert_eq!(validate("a@b!"), Err(UsernameError::InvalidChar('@')));
+        // Before the length, so a one-character name says what's wrong with it.
+        assert_eq!(validate("@"), Err(UsernameError::InvalidChar('@')));
+        assert_eq!(
+            UsernameError::InvalidChar(' ').to_string(),
+            "Usernames can't contain spaces"
+        );
+    }
+}
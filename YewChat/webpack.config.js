@@ -3,11 +3,15 @@ const CopyWebpackPlugin = require('copy-webpack-plugin');
 const WasmPackPlugin = require('@wasm-tool/wasm-pack-plugin');
 
 const distPath = path.resolve(__dirname, 'dist');
+// Extra cargo features, comma-separated, e.g. `YEWCHAT_FEATURES=bench`.
+const features = ['wee_alloc', ...(process.env.YEWCHAT_FEATURES || '').split(',').filter(Boolean)];
 
 module.exports = {
     mode: 'production',
     devServer: {
         port: 8000,
+        // Serve the app for any route, so one like /bench can be opened directly.
+        historyApiFallback: true,
     },
     entry: './bootstrap.js',
     output: {
@@ -21,7 +25,7 @@ module.exports = {
         }),
         new WasmPackPlugin({
             crateDirectory: '.',
-            extraArgs: '-- --features wee_alloc',
+            extraArgs: `-- --features ${features.join(',')}`,
             outName: 'yewchat',
         }),
     ],